@@ -0,0 +1,98 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+// #[derive(Digestible)] hashes the digests of all fields in order. For enums,
+// the index of the variant goes first.
+#[proc_macro_derive(Digestible)]
+pub fn derive_digestible(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(::vchain::Digestible));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let fields = match &data.fields {
+                Fields::Named(fields) => fields
+                    .named
+                    .iter()
+                    .map(|f| {
+                        let ident = f.ident.as_ref().unwrap();
+                        quote!(&self.#ident)
+                    })
+                    .collect::<Vec<_>>(),
+                Fields::Unnamed(fields) => (0..fields.unnamed.len())
+                    .map(|i| {
+                        let idx = Index::from(i);
+                        quote!(&self.#idx)
+                    })
+                    .collect(),
+                Fields::Unit => Vec::new(),
+            };
+            concat_fields(quote!(), &fields)
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(i, v)| {
+                let ident = &v.ident;
+                let tag = i as u32;
+                let tag = quote!(::vchain::Digestible::to_digest(&#tag),);
+                match &v.fields {
+                    Fields::Named(fields) => {
+                        let names: Vec<_> = fields
+                            .named
+                            .iter()
+                            .map(|f| f.ident.clone().unwrap())
+                            .collect();
+                        let refs: Vec<_> = names.iter().map(|n| quote!(#n)).collect();
+                        let digest = concat_fields(tag, &refs);
+                        quote!(Self::#ident { #(#names),* } => #digest,)
+                    }
+                    Fields::Unnamed(fields) => {
+                        let names: Vec<_> = (0..fields.unnamed.len())
+                            .map(|i| format_ident!("f{}", i))
+                            .collect();
+                        let refs: Vec<_> = names.iter().map(|n| quote!(#n)).collect();
+                        let digest = concat_fields(tag, &refs);
+                        quote!(Self::#ident ( #(#names),* ) => #digest,)
+                    }
+                    Fields::Unit => {
+                        let digest = concat_fields(tag, &[]);
+                        quote!(Self::#ident => #digest,)
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(&input.ident, "unions are not supported")
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::vchain::Digestible for #name #ty_generics #where_clause {
+            fn to_digest(&self) -> ::vchain::Digest {
+                #body
+            }
+        }
+    };
+    expanded.into()
+}
+
+fn concat_fields(prefix: TokenStream2, fields: &[TokenStream2]) -> TokenStream2 {
+    quote! {
+        ::vchain::concat_digest(
+            ::std::vec![#prefix #(::vchain::Digestible::to_digest(#fields)),*].into_iter()
+        )
+    }
+}
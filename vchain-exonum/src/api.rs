@@ -1,7 +1,31 @@
-use crate::schema::VChainSchema;
+use crate::{
+    proof::{block_headers_coordinate, BlockHeaderProof},
+    schema::VChainSchema,
+};
 use exonum::runtime::rust::api::{self, ServiceApiBuilder, ServiceApiState};
 use serde_json::json;
-use vchain::{acc, historical_query, IdType, OverallResult, ReadInterface};
+use std::sync::{Arc, Mutex};
+use vchain::{
+    acc, historical_query_cached, parse_id_list, CacheCapacity, CachedChain, IdType, OverallResult,
+    QueryCache, ReadCache, ReadInterface, MAX_BATCH_IDS,
+};
+
+// the dashboards issue the same queries as the new blocks arrive
+const QUERY_CACHE_SIZE: usize = 64;
+// the blocks and the nodes of each kind read by the queries, kept across the
+// snapshots since they never change once written
+const READ_CACHE_SIZE: usize = 10_000;
+
+lazy_static::lazy_static! {
+    static ref ACC1_QUERY_CACHE: Mutex<QueryCache<acc::Acc1Proof>> =
+        Mutex::new(QueryCache::new(QUERY_CACHE_SIZE));
+    static ref ACC2_QUERY_CACHE: Mutex<QueryCache<acc::Acc2Proof>> =
+        Mutex::new(QueryCache::new(QUERY_CACHE_SIZE));
+    static ref ACC3_QUERY_CACHE: Mutex<QueryCache<acc::Acc3Proof>> =
+        Mutex::new(QueryCache::new(QUERY_CACHE_SIZE));
+    static ref READ_CACHE: Arc<ReadCache> =
+        Arc::new(ReadCache::new(CacheCapacity::uniform(READ_CACHE_SIZE)));
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct VChainApi;
@@ -11,8 +35,60 @@ pub struct QueryInput {
     pub id: IdType,
 }
 
+// e.g. `ids=1,2,3`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchQueryInput {
+    pub ids: String,
+}
+
+// Either `ids=1,2,3` or the inclusive range `start=1&end=3`, with the headers
+// in a BlockHeaderProof if `proof=true`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlockHeadersInput {
+    pub ids: Option<String>,
+    pub start: Option<IdType>,
+    pub end: Option<IdType>,
+    #[serde(default)]
+    pub proof: bool,
+}
+
+impl BlockHeadersInput {
+    fn ids(&self) -> anyhow::Result<Vec<IdType>> {
+        match (&self.ids, self.start, self.end) {
+            (Some(ids), None, None) => parse_id_list(ids),
+            (None, Some(start), Some(end)) => {
+                anyhow::ensure!(
+                    start <= end,
+                    vchain::Error::Parameter(format!("start {} is after end {}", start, end))
+                );
+                anyhow::ensure!(
+                    (end - start) as usize < MAX_BATCH_IDS,
+                    vchain::Error::Parameter(format!(
+                        "at most {} ids can be read at once",
+                        MAX_BATCH_IDS
+                    ))
+                );
+                Ok((start..=end).collect())
+            }
+            _ => anyhow::bail!(vchain::Error::Parameter(
+                "either ids or both start and end should be given".to_owned()
+            )),
+        }
+    }
+}
+
+// The kind of the error, see vchain::Error, or an internal error for the
+// others. Exonum has no status for the results that cannot be proved or
+// verified, so they are bad requests as the invalid parameters.
 fn handle_err(e: anyhow::Error) -> api::Error {
-    api::Error::InternalError(failure::format_err!("{:?}", e))
+    match vchain::Error::find(&e) {
+        Some(vchain::Error::NotFound(_)) => api::Error::NotFound(format!("{:#}", e)),
+        Some(vchain::Error::Storage(_)) => api::Error::Storage(failure::format_err!("{:?}", e)),
+        Some(vchain::Error::Parameter(_))
+        | Some(vchain::Error::Proof(_))
+        | Some(vchain::Error::Verification(_)) => api::Error::BadRequest(format!("{:#}", e)),
+        None => api::Error::InternalError(failure::format_err!("{:?}", e)),
+    }
 }
 
 impl VChainApi {
@@ -21,6 +97,37 @@ impl VChainApi {
         schema.get_parameter().map_err(handle_err)
     }
 
+    // The parameters set by TxSetParam, with the first blocks they are used
+    // for.
+    pub fn get_param_changes(
+        self,
+        state: &ServiceApiState<'_>,
+    ) -> api::Result<Vec<serde_json::Value>> {
+        let schema = VChainSchema::new(state.service_data());
+        schema
+            .param_changes
+            .iter()
+            .map(|(block_id, param)| {
+                let param = param.to_vchain_type().map_err(handle_err)?;
+                Ok(json!({ "from_block": block_id, "param": param }))
+            })
+            .collect()
+    }
+
+    // The service keys allowed to add objects, or any key if empty, and those
+    // allowed to change them.
+    pub fn get_senders(self, state: &ServiceApiState<'_>) -> api::Result<serde_json::Value> {
+        let schema = VChainSchema::new(state.service_data());
+        let senders: Vec<_> = schema.senders.iter().collect();
+        let admins: Vec<_> = schema.admins.iter().collect();
+        let open_senders = schema.open_senders.get().unwrap_or(false);
+        Ok(json!({
+            "senders": senders,
+            "admins": admins,
+            "open_senders": open_senders,
+        }))
+    }
+
     pub fn get_object(
         self,
         state: &ServiceApiState<'_>,
@@ -39,6 +146,96 @@ impl VChainApi {
         schema.read_block_header(query.id).map_err(handle_err)
     }
 
+    // The proof of the header from the latest block, see BlockHeaderProof.
+    pub fn get_block_header_proof(
+        self,
+        state: &ServiceApiState<'_>,
+        query: QueryInput,
+    ) -> api::Result<BlockHeaderProof> {
+        self.block_headers_proof(state, &[query.id])
+    }
+
+    fn block_headers_proof(
+        self,
+        state: &ServiceApiState<'_>,
+        ids: &[IdType],
+    ) -> api::Result<BlockHeaderProof> {
+        let data = state.data();
+        let core = data.for_core();
+        let block_proof = core
+            .block_and_precommits(core.height())
+            .ok_or_else(|| api::Error::NotFound("no block committed".to_owned()))?;
+        let to_table = core
+            .state_hash_aggregator()
+            .get_proof(block_headers_coordinate(state.instance().id));
+        let schema = VChainSchema::new(state.service_data());
+        if let Some(id) = ids.iter().find(|id| !schema.block_headers.contains(id)) {
+            return Err(api::Error::NotFound(format!(
+                "no block header for id: {}",
+                id
+            )));
+        }
+        let to_header = schema.block_headers.get_multiproof(ids.iter().copied());
+        Ok(BlockHeaderProof {
+            block_proof,
+            to_table,
+            to_header,
+        })
+    }
+
+    pub fn get_block_headers(
+        self,
+        state: &ServiceApiState<'_>,
+        query: BlockHeadersInput,
+    ) -> api::Result<serde_json::Value> {
+        let ids = query.ids().map_err(handle_err)?;
+        if query.proof {
+            return Ok(json!(self.block_headers_proof(state, &ids)?));
+        }
+        let schema = VChainSchema::new(state.service_data());
+        let headers = ids
+            .into_iter()
+            .map(|id| schema.read_block_header(id).map_err(handle_err))
+            .collect::<api::Result<Vec<_>>>()?;
+        Ok(json!(headers))
+    }
+
+    pub fn get_objects(
+        self,
+        state: &ServiceApiState<'_>,
+        query: BatchQueryInput,
+    ) -> api::Result<Vec<vchain::Object>> {
+        let schema = VChainSchema::new(state.service_data());
+        let ids = parse_id_list(&query.ids).map_err(handle_err)?;
+        ids.into_iter()
+            .map(|id| schema.read_object(id).map_err(handle_err))
+            .collect()
+    }
+
+    pub fn get_intra_index_nodes(
+        self,
+        state: &ServiceApiState<'_>,
+        query: BatchQueryInput,
+    ) -> api::Result<Vec<vchain::IntraIndexNode>> {
+        let schema = VChainSchema::new(state.service_data());
+        let ids = parse_id_list(&query.ids).map_err(handle_err)?;
+        ids.into_iter()
+            .map(|id| schema.read_intra_index_node(id).map_err(handle_err))
+            .collect()
+    }
+
+    pub fn get_skip_list_nodes(
+        self,
+        state: &ServiceApiState<'_>,
+        query: BatchQueryInput,
+    ) -> api::Result<Vec<vchain::SkipListNode>> {
+        let schema = VChainSchema::new(state.service_data());
+        let ids = parse_id_list(&query.ids).map_err(handle_err)?;
+        ids.into_iter()
+            .map(|id| schema.read_skip_list_node(id).map_err(handle_err))
+            .collect()
+    }
+
     pub fn get_block_data(
         self,
         state: &ServiceApiState<'_>,
@@ -91,15 +288,24 @@ impl VChainApi {
         let param = schema
             .get_parameter()
             .map_err(|e| api::Error::NotFound(format!("{:?}", e)))?;
+        let chain = CachedChain::with_cache(schema, READ_CACHE.clone());
         match param.acc_type {
             acc::Type::ACC1 => {
                 let res: OverallResult<acc::Acc1Proof> =
-                    historical_query(&query, &schema).map_err(handle_err)?;
+                    historical_query_cached(&query, &mut ACC1_QUERY_CACHE.lock().unwrap(), &chain)
+                        .map_err(handle_err)?;
                 Ok(json!(res))
             }
             acc::Type::ACC2 => {
                 let res: OverallResult<acc::Acc2Proof> =
-                    historical_query(&query, &schema).map_err(handle_err)?;
+                    historical_query_cached(&query, &mut ACC2_QUERY_CACHE.lock().unwrap(), &chain)
+                        .map_err(handle_err)?;
+                Ok(json!(res))
+            }
+            acc::Type::ACC3 => {
+                let res: OverallResult<acc::Acc3Proof> =
+                    historical_query_cached(&query, &mut ACC3_QUERY_CACHE.lock().unwrap(), &chain)
+                        .map_err(handle_err)?;
                 Ok(json!(res))
             }
         }
@@ -112,6 +318,14 @@ impl VChainApi {
                 "get/param",
                 move |state: &ServiceApiState<'_>, _query: ()| self.get_param(state),
             )
+            .endpoint(
+                "get/param_changes",
+                move |state: &ServiceApiState<'_>, _query: ()| self.get_param_changes(state),
+            )
+            .endpoint(
+                "get/senders",
+                move |state: &ServiceApiState<'_>, _query: ()| self.get_senders(state),
+            )
             .endpoint(
                 "get/obj",
                 move |state: &ServiceApiState<'_>, query: QueryInput| self.get_object(state, query),
@@ -122,6 +336,36 @@ impl VChainApi {
                     self.get_block_header(state, query)
                 },
             )
+            .endpoint(
+                "get/blk_header_proof",
+                move |state: &ServiceApiState<'_>, query: QueryInput| {
+                    self.get_block_header_proof(state, query)
+                },
+            )
+            .endpoint(
+                "get/blk_headers",
+                move |state: &ServiceApiState<'_>, query: BlockHeadersInput| {
+                    self.get_block_headers(state, query)
+                },
+            )
+            .endpoint(
+                "get/objs",
+                move |state: &ServiceApiState<'_>, query: BatchQueryInput| {
+                    self.get_objects(state, query)
+                },
+            )
+            .endpoint(
+                "get/intraindexes",
+                move |state: &ServiceApiState<'_>, query: BatchQueryInput| {
+                    self.get_intra_index_nodes(state, query)
+                },
+            )
+            .endpoint(
+                "get/skiplists",
+                move |state: &ServiceApiState<'_>, query: BatchQueryInput| {
+                    self.get_skip_list_nodes(state, query)
+                },
+            )
             .endpoint(
                 "get/blk_data",
                 move |state: &ServiceApiState<'_>, query: QueryInput| {
@@ -1,22 +1,24 @@
 #[macro_use]
 extern crate log;
 
-use anyhow::{bail, Error, Result};
+use anyhow::{bail, ensure, Context, Error, Result};
 use exonum::{
     api::backends::actix::AllowOrigin,
     blockchain::{config::GenesisConfigBuilder, ConsensusConfig, ValidatorKeys},
     crypto::{self, PublicKey, SecretKey},
     keys::Keys,
-    node::{Node, NodeApiConfig, NodeConfig},
+    node::{ConnectInfo, ConnectListConfig, Node, NodeApiConfig, NodeConfig},
     runtime::{rust::ServiceFactory, RuntimeInstance},
 };
 use exonum_merkledb::{DbOptions, RocksDB};
+use hex::FromHex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use vchain::acc;
-use vchain_exonum::{service::VChainService, transactions::InitParam};
+use vchain_exonum::{service::VChainService, transactions::InitParam, INSTANCE_ID, INSTANCE_NAME};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct NodeKeys {
@@ -42,22 +44,119 @@ impl NodeKeys {
         fs::write(path, data)?;
         Ok(())
     }
+
+    fn load_or_create(db: &Path) -> Result<Self> {
+        match Self::load_from_file(&db.join(KEYS_FILE)) {
+            Ok(key) => {
+                info!("found old key");
+                Ok(key)
+            }
+            _ => {
+                warn!("create new key");
+                let key = Self::new();
+                key.save_to_file(&db.join(KEYS_FILE))?;
+                Ok(key)
+            }
+        }
+    }
+
+    fn validator_info(&self, peer_address: String) -> ValidatorInfo {
+        ValidatorInfo {
+            peer_address,
+            consensus_key: self.consensus_key.0,
+            service_key: self.service_key.0,
+        }
+    }
 }
 
-fn node_config(api_address: String, peer_address: String, keys: NodeKeys) -> Result<NodeConfig> {
+const KEYS_FILE: &str = "keys.json";
+const NETWORK_FILE: &str = "network.json";
+
+// The public keys and the peer address of a validator, written by
+// generate-config for the other validators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ValidatorInfo {
+    peer_address: String,
+    consensus_key: PublicKey,
+    service_key: PublicKey,
+}
+
+// The validators of the network, written by finalize next to the keys. They
+// are in the order of the consensus keys, so that every node gets the same
+// genesis config whatever the order of the files given to finalize.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NetworkConfig {
+    validators: Vec<ValidatorInfo>,
+}
+
+impl NetworkConfig {
+    fn load_from_file(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(path)?;
+        let network = serde_json::from_str::<Self>(&data)
+            .with_context(|| format!("failed to parse {:?}", path))?;
+        Ok(Some(network))
+    }
+
+    fn save_to_file(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+// Without a network, the node is the only validator, with no peers.
+fn node_config(
+    api_address: String,
+    peer_address: String,
+    keys: NodeKeys,
+    network: Option<NetworkConfig>,
+) -> Result<NodeConfig> {
     info!("api address: {}", &api_address);
     info!("peer address: {}", &peer_address);
 
+    let own = keys.validator_info(peer_address.clone());
     let (consensus_public_key, consensus_secret_key) = keys.consensus_key;
     let (service_public_key, service_secret_key) = keys.service_key;
 
+    let validators = match network {
+        Some(network) => {
+            ensure!(
+                network
+                    .validators
+                    .iter()
+                    .any(|v| v.consensus_key == consensus_public_key),
+                "the node is not one of the validators in {}",
+                NETWORK_FILE
+            );
+            network.validators
+        }
+        None => vec![own],
+    };
+    info!("{} validators", validators.len());
+
     let consensus = ConsensusConfig {
-        validator_keys: vec![ValidatorKeys {
-            consensus_key: consensus_public_key,
-            service_key: service_public_key,
-        }],
+        validator_keys: validators
+            .iter()
+            .map(|v| ValidatorKeys {
+                consensus_key: v.consensus_key,
+                service_key: v.service_key,
+            })
+            .collect(),
         ..ConsensusConfig::default()
     };
+    let connect_list = ConnectListConfig {
+        peers: validators
+            .iter()
+            .filter(|v| v.consensus_key != consensus_public_key)
+            .map(|v| ConnectInfo {
+                address: v.peer_address.clone(),
+                public_key: v.consensus_key,
+            })
+            .collect(),
+    };
 
     let api_cfg = NodeApiConfig {
         public_api_address: Some(api_address.parse()?),
@@ -70,7 +169,7 @@ fn node_config(api_address: String, peer_address: String, keys: NodeKeys) -> Res
         consensus,
         external_address: peer_address.to_owned(),
         network: Default::default(),
-        connect_list: Default::default(),
+        connect_list,
         api: api_cfg,
         mempool: Default::default(),
         services_configs: Default::default(),
@@ -92,11 +191,17 @@ fn parse_acc(input: &str) -> Result<acc::Type> {
         Ok(acc::Type::ACC1)
     } else if input == "acc2" {
         Ok(acc::Type::ACC2)
+    } else if input == "acc3" {
+        Ok(acc::Type::ACC3)
     } else {
-        bail!("invalid acc type, please specify as acc1 or acc2.");
+        bail!("invalid acc type, please specify as acc1, acc2 or acc3.");
     }
 }
 
+fn parse_public_key(input: &str) -> Result<PublicKey> {
+    PublicKey::from_hex(input).map_err(|_| anyhow::anyhow!("invalid public key {}", input))
+}
+
 #[allow(clippy::box_vec)]
 fn parse_v_bit_len(input: &str) -> Result<Box<Vec<u32>>> {
     let x = input
@@ -125,7 +230,7 @@ struct Opts {
     #[structopt(long, default_value = "127.0.0.1:2000")]
     peer_address: String,
 
-    /// acc type to be used
+    /// acc type to be used: acc1, acc2, or acc3 for acc1 with the proofs of each clause verified as a batch
     #[structopt(long, default_value = "acc2", parse(try_from_str = parse_acc))]
     acc: acc::Type,
 
@@ -141,6 +246,126 @@ struct Opts {
     /// max skip list level, 0 means no skip list.
     #[structopt(long, default_value = "0")]
     skip_list_max_level: u32,
+
+    /// chain id mixed into the block hashes, at most 64 bytes
+    #[structopt(long, default_value = "")]
+    chain_id: String,
+
+    /// max length of the indexed keyword prefixes, 0 means no prefix query.
+    #[structopt(long, default_value = "0")]
+    max_prefix_len: u32,
+
+    /// max number of children of the intra index nodes.
+    #[structopt(long, default_value = "2")]
+    intra_index_fanout: u32,
+
+    /// max level of the B-tree over the blocks, instead of the skip list. 0 means no B-tree.
+    #[structopt(long, default_value = "0")]
+    btree_max_level: u32,
+
+    /// max number of objects in a block, the rest are carried over to the next block. 0 means unlimited.
+    #[structopt(long, default_value = "0")]
+    max_objs_per_block: u32,
+
+    /// service key in hex allowed to add objects, repeat it for several. No key can add objects if none is given, unless --open-senders.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_public_key))]
+    sender: Vec<PublicKey>,
+
+    /// service key in hex allowed to change the senders by TxSetSenders, repeat it for several.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_public_key))]
+    admin: Vec<PublicKey>,
+
+    /// allow any key to add objects, whatever the senders.
+    #[structopt(long)]
+    open_senders: bool,
+
+    /// number of threads building the blocks, 0 means the global pool shared with the queries.
+    #[structopt(long, default_value = "0")]
+    build_threads: usize,
+
+    /// number of threads answering the queries, 0 means the global pool shared with the builds.
+    #[structopt(long, default_value = "0")]
+    query_threads: usize,
+}
+
+// The two steps to set up a network of several validators, before running
+// each node with the same parameter.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "vchain-node")]
+enum Bootstrap {
+    /// create the keys of a validator, and write its public keys and peer address for the others
+    GenerateConfig {
+        /// db path, should be a directory
+        #[structopt(short = "-i", long, parse(from_os_str))]
+        db: PathBuf,
+
+        /// Peer Address, reachable by the other validators
+        #[structopt(long, default_value = "127.0.0.1:2000")]
+        peer_address: String,
+
+        /// output path of the public config
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// set up the network from the public configs of all the validators, including this one
+    Finalize {
+        /// db path, should be a directory
+        #[structopt(short = "-i", long, parse(from_os_str))]
+        db: PathBuf,
+
+        /// public configs written by generate-config
+        #[structopt(required = true, parse(from_os_str))]
+        validators: Vec<PathBuf>,
+    },
+}
+
+fn bootstrap(cmd: Bootstrap) -> Result<()> {
+    match cmd {
+        Bootstrap::GenerateConfig {
+            db,
+            peer_address,
+            output,
+        } => {
+            fs::create_dir_all(&db)?;
+            let keys = NodeKeys::load_or_create(&db)?;
+            let info = keys.validator_info(peer_address);
+            fs::write(&output, serde_json::to_string_pretty(&info)?)?;
+            info!("public config written to {:?}", output);
+        }
+        Bootstrap::Finalize { db, validators } => {
+            let keys = NodeKeys::load_from_file(&db.join(KEYS_FILE))
+                .context("failed to load the keys, run generate-config first")?;
+            let mut validators = validators
+                .iter()
+                .map(|path| {
+                    let data = fs::read_to_string(path)?;
+                    serde_json::from_str::<ValidatorInfo>(&data)
+                        .with_context(|| format!("failed to parse {:?}", path))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            validators.sort_by_key(|v| v.consensus_key);
+            let mut keys_seen = HashSet::new();
+            for v in &validators {
+                ensure!(
+                    keys_seen.insert(v.consensus_key),
+                    "duplicated validator {}",
+                    v.peer_address
+                );
+            }
+            ensure!(
+                keys_seen.contains(&keys.consensus_key.0),
+                "the public config of this node is missing"
+            );
+            let network = NetworkConfig { validators };
+            network.save_to_file(&db.join(NETWORK_FILE))?;
+            info!(
+                "network of {} validators written to {:?}",
+                network.validators.len(),
+                db.join(NETWORK_FILE)
+            );
+        }
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -148,46 +373,55 @@ fn main() -> Result<()> {
         env_logger::Env::default().filter_or("RUST_LOG", "vchain=info,vchain_exonum=info"),
     );
 
+    // the other command lines run the node
+    if let Some("generate-config") | Some("finalize") = std::env::args().nth(1).as_deref() {
+        return bootstrap(Bootstrap::from_args());
+    }
     let opts = Opts::from_args();
+    vchain::init_thread_pools(opts.build_threads, opts.query_threads)?;
 
     let param = InitParam {
         v_bit_len: opts.bit_len.to_vec(),
         is_acc2: opts.acc == acc::Type::ACC2,
+        is_acc3: opts.acc == acc::Type::ACC3,
         intra_index: !opts.no_intra_index,
         skip_list_max_level: opts.skip_list_max_level,
+        chain_id: opts.chain_id.clone(),
+        max_prefix_len: opts.max_prefix_len,
+        intra_index_fanout: opts.intra_index_fanout,
+        btree_max_level: opts.btree_max_level,
+        max_objs_per_block: opts.max_objs_per_block,
+        senders: opts.sender,
+        admins: opts.admin,
+        open_senders: opts.open_senders,
     };
     info!("param: {:?}", param);
 
     info!("db path: {:?}", opts.db);
+    let network = NetworkConfig::load_from_file(&opts.db.join(NETWORK_FILE))?;
     if opts.create_new && opts.db.exists() {
+        ensure!(
+            network.is_none(),
+            "cannot discard the database of a network, remove it by hand"
+        );
         fs::remove_dir_all(&opts.db)?;
     }
     fs::create_dir_all(&opts.db)?;
 
-    let key = match NodeKeys::load_from_file(&opts.db.join("keys.json")) {
-        Ok(key) => {
-            info!("found old key");
-            key
-        }
-        _ => {
-            warn!("create new key");
-            let key = NodeKeys::new();
-            key.save_to_file(&opts.db.join("keys.json"))?;
-            key
-        }
-    };
+    let key = NodeKeys::load_or_create(&opts.db)?;
     let db = RocksDB::open(opts.db, &DbOptions::default()).map_err(anyhow::Error::msg)?;
 
     let external_runtimes: Vec<RuntimeInstance> = vec![];
     let service = VChainService;
     let artifact_id = service.artifact_id();
     let services = vec![service.into()];
-    let node_config = node_config(opts.api_address, opts.peer_address, key)?;
+    let single = network.is_none();
+    let node_config = node_config(opts.api_address, opts.peer_address, key, network)?;
     let genesis_config = GenesisConfigBuilder::with_consensus_config(node_config.consensus.clone())
         .with_artifact(artifact_id.clone())
         .with_instance(
             artifact_id
-                .into_default_instance(1, "vchain")
+                .into_default_instance(INSTANCE_ID, INSTANCE_NAME)
                 .with_constructor(param),
         )
         .build();
@@ -200,7 +434,11 @@ fn main() -> Result<()> {
         genesis_config,
         None,
     );
-    info!("Starting a single node...");
+    if single {
+        info!("Starting a single node...");
+    } else {
+        info!("Starting a node of the network...");
+    }
     info!("Blockchain is ready for transactions!");
     node.run().map_err(anyhow::Error::msg)
 }
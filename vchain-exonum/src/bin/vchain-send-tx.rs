@@ -1,17 +1,23 @@
 #[macro_use]
 extern crate log;
 
-use anyhow::Result;
-use exonum::{crypto, runtime::rust::Transaction};
+use anyhow::{ensure, Result};
+use exonum::{
+    crypto::{self, PublicKey, SecretKey},
+    runtime::rust::Transaction,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
 use std::time::Duration;
 use structopt::StructOpt;
-use vchain::{load_raw_obj_from_file, IdType};
-use vchain_exonum::transactions::{RawObject, TxAddObjs};
+use vchain::load_raw_obj_from_file;
+use vchain_exonum::{
+    transactions::{RawObject, TxAddObjs},
+    INSTANCE_ID,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "vchain-send-tx")]
@@ -23,6 +29,39 @@ struct Opts {
     /// api address
     #[structopt(short, long, default_value = "http://127.0.0.1:5000")]
     api_address: String,
+
+    /// keys to sign the txs, e.g. keys.json of vchain-node, created if missing [default: a new key for each run]
+    #[structopt(short, long, parse(from_os_str))]
+    keys: Option<PathBuf>,
+
+    /// max objects per tx [default: the objects of a blk in the data file]
+    #[structopt(short, long)]
+    batch_size: Option<usize>,
+
+    /// txs sent before waiting for them to be committed
+    #[structopt(short, long, default_value = "1")]
+    txs_per_blk: usize,
+}
+
+// The service key of keys.json of vchain-node, so that the senders allowed
+// by the chain can sign with a known key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SenderKeys {
+    service_key: (PublicKey, SecretKey),
+}
+
+impl SenderKeys {
+    fn load_or_create(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let data = fs::read_to_string(path)?;
+            return Ok(serde_json::from_str(&data)?);
+        }
+        let keys = Self {
+            service_key: crypto::gen_keypair(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&keys)?)?;
+        Ok(keys)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,40 +78,76 @@ async fn main() -> Result<()> {
     info!("read data from {:?}", opts.input);
     warn!("blk id from data file will be ignored");
 
+    ensure!(opts.batch_size != Some(0), "batch size should be positive");
+    ensure!(opts.txs_per_blk > 0, "txs per blk should be positive");
     let raw_objs = load_raw_obj_from_file(&opts.input)?;
-    let mut txs: BTreeMap<IdType, TxAddObjs> = BTreeMap::new();
-    for (&id, objs) in raw_objs.iter() {
+    let mut txs: Vec<TxAddObjs> = Vec::new();
+    for objs in raw_objs.values() {
         let tx_objs: Vec<_> = objs.iter().map(|o| RawObject::create(o)).collect();
-        txs.insert(id, TxAddObjs { objs: tx_objs });
+        let batch_size = opts.batch_size.unwrap_or_else(|| tx_objs.len().max(1));
+        for chunk in tx_objs.chunks(batch_size) {
+            txs.push(TxAddObjs {
+                objs: chunk.to_vec(),
+            });
+        }
     }
+    info!(
+        "send {} objects in {} txs",
+        raw_objs.values().map(|objs| objs.len()).sum::<usize>(),
+        txs.len()
+    );
 
-    let keypair = crypto::gen_keypair();
+    let keypair = match &opts.keys {
+        Some(path) => SenderKeys::load_or_create(path)?.service_key,
+        None => crypto::gen_keypair(),
+    };
+    info!("sender key: {}", hex::encode(&keypair.0));
     let client = reqwest::Client::new();
-    for (_, tx) in txs.into_iter() {
-        let tx_message = tx.sign(1, keypair.0, &keypair.1).into_raw();
-        let res = client
-            .post(&tx_url)
-            .json(&json!({ "tx_body": tx_message }))
-            .send()
-            .await?;
-        debug!("response: {:?}", &res);
-        let tx_res = res.json::<TxResponse>().await?;
-        info!("tx_hash={:?}", tx_res.tx_hash);
-
-        loop {
-            let res2 = client
-                .get(&tx_url)
-                .query(&[("hash", tx_res.tx_hash.clone())])
+    let (mut accepted, mut rejected) = (0, 0);
+    for batch in txs.chunks(opts.txs_per_blk) {
+        let mut tx_hashes = Vec::with_capacity(batch.len());
+        for tx in batch {
+            let tx_message = tx
+                .clone()
+                .sign(INSTANCE_ID, keypair.0, &keypair.1)
+                .into_raw();
+            let res = client
+                .post(&tx_url)
+                .json(&json!({ "tx_body": tx_message }))
                 .send()
                 .await?;
-            debug!("response: {:?}", &res2);
-            let tx_info = res2.json::<serde_json::Value>().await?;
-            if tx_info.get("type").unwrap() == &json!("committed") {
-                break;
+            debug!("response: {:?}", &res);
+            let tx_res = res.json::<TxResponse>().await?;
+            info!("tx_hash={:?}", tx_res.tx_hash);
+            tx_hashes.push((tx_res.tx_hash, tx.objs.len()));
+        }
+
+        for (tx_hash, num_objs) in tx_hashes {
+            let status = loop {
+                let res2 = client
+                    .get(&tx_url)
+                    .query(&[("hash", tx_hash.clone())])
+                    .send()
+                    .await?;
+                debug!("response: {:?}", &res2);
+                let tx_info = res2.json::<serde_json::Value>().await?;
+                if tx_info.get("type") == Some(&json!("committed")) {
+                    break tx_info.get("status").cloned().unwrap_or_default();
+                }
+                sleep(Duration::from_millis(100));
+            };
+            if status.get("type") == Some(&json!("success")) {
+                accepted += num_objs;
+            } else {
+                rejected += num_objs;
+                warn!(
+                    "tx_hash={:?} with {} objects failed: {}",
+                    tx_hash, num_objs, status
+                );
             }
-            sleep(Duration::from_millis(100));
         }
     }
+    info!("{} objects accepted, {} rejected", accepted, rejected);
 
     Ok(())
 }
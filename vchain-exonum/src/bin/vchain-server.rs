@@ -3,16 +3,28 @@ extern crate lazy_static;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use anyhow::Context;
+use exonum::crypto::PublicKey;
 use futures::{lock::Mutex, StreamExt};
+use hex::FromHex;
 use lru::LruCache;
-use serde::Serialize;
+use rustls::internal::pemfile;
+use rustls::PrivateKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use vchain::acc;
 use vchain::chain::*;
+use vchain::openapi::{openapi_document, ApiParam, ApiRoute};
+use vchain_exonum::{proof::BlockHeaderProof, INSTANCE_ID};
 
 static mut API_ADDRESS: Option<String> = None;
 static mut PARAM: Option<Parameter> = None;
+static mut VALIDATOR_KEYS: Vec<PublicKey> = Vec::new();
 
 lazy_static! {
     static ref BLK_HEAD_CACHE: Mutex<LruCache<IdType, BlockHeader>> =
@@ -27,6 +39,10 @@ fn get_param() -> &'static Parameter {
     unsafe { PARAM.as_ref().unwrap() }
 }
 
+fn get_validator_keys() -> &'static [PublicKey] {
+    unsafe { &VALIDATOR_KEYS }
+}
+
 #[derive(Debug)]
 struct MyErr(anyhow::Error);
 
@@ -67,6 +83,29 @@ impl_get_info!(web_get_index_node, "index");
 impl_get_info!(web_get_skip_list_node, "skiplist");
 impl_get_info!(web_get_object, "obj");
 
+#[derive(Deserialize)]
+struct IdList {
+    ids: String,
+}
+
+macro_rules! impl_get_batch {
+    ($name: ident, $url: expr) => {
+        async fn $name(req: web::Query<IdList>) -> impl Responder {
+            HttpResponse::TemporaryRedirect()
+                .header(
+                    "Location",
+                    format!("{}/get/{}?ids={}", get_api_address(), $url, req.ids),
+                )
+                .finish()
+        }
+    };
+}
+
+impl_get_batch!(web_get_blk_headers, "blk_headers");
+impl_get_batch!(web_get_intra_index_nodes, "intraindexes");
+impl_get_batch!(web_get_skip_list_nodes, "skiplists");
+impl_get_batch!(web_get_objects, "objs");
+
 async fn web_query() -> impl Responder {
     HttpResponse::TemporaryRedirect()
         .header("Location", format!("{}/query", get_api_address()))
@@ -80,30 +119,64 @@ struct VerifyResponse {
     verify_time_in_ms: u64,
 }
 
+// The headers are checked against the proofs from the blocks signed by the
+// validators, instead of trusting the api node.
 #[derive(Debug, Clone)]
 struct LightChain {
     param: Parameter,
-    blk_header_api: String,
+    validator_keys: &'static [PublicKey],
+    blk_headers_api: String,
+    blk_header_proof_api: String,
 }
 
 impl LightChain {
-    fn new(param: Parameter, api_address: &str) -> Self {
+    fn new(param: Parameter, api_address: &str, validator_keys: &'static [PublicKey]) -> Self {
         Self {
             param,
-            blk_header_api: format!("{}/get/blk_header", api_address),
+            validator_keys,
+            blk_headers_api: format!("{}/get/blk_headers", api_address),
+            blk_header_proof_api: format!("{}/get/blk_header_proof", api_address),
         }
     }
 
     async fn get_block_header(&self, id: IdType) -> anyhow::Result<BlockHeader> {
-        let client = reqwest::Client::new();
-        client
-            .get(&self.blk_header_api)
+        let proof = reqwest::Client::new()
+            .get(&self.blk_header_proof_api)
             .query(&[("id", id)])
             .send()
             .await?
-            .json::<BlockHeader>()
+            .json::<BlockHeaderProof>()
+            .await
+            .map_err(anyhow::Error::msg)?;
+        proof.verify(id, INSTANCE_ID, self.validator_keys)
+    }
+
+    // The ids, sorted and without duplicates, are read as a range if they
+    // are contiguous, with the headers in a single proof.
+    async fn get_block_headers(&self, ids: &[IdType]) -> anyhow::Result<Vec<BlockHeader>> {
+        let mut query = match (ids.first(), ids.last()) {
+            (Some(&start), Some(&end)) if (end - start) as usize + 1 == ids.len() => {
+                vec![("start", start.to_string()), ("end", end.to_string())]
+            }
+            _ => {
+                let ids = ids
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                vec![("ids", ids)]
+            }
+        };
+        query.push(("proof", "true".to_owned()));
+        let proof = reqwest::Client::new()
+            .get(&self.blk_headers_api)
+            .query(&query)
+            .send()
+            .await?
+            .json::<BlockHeaderProof>()
             .await
-            .map_err(anyhow::Error::msg)
+            .map_err(anyhow::Error::msg)?;
+        proof.verify_headers(ids, INSTANCE_ID, self.validator_keys)
     }
 }
 
@@ -113,6 +186,7 @@ impl LightNodeInterface for LightChain {
         Ok(self.param.clone())
     }
 
+    // the headers are only cached once verified
     async fn lightnode_read_block_header(&self, id: IdType) -> anyhow::Result<BlockHeader> {
         if let Some(header) = BLK_HEAD_CACHE.lock().await.get(&id).cloned() {
             return Ok(header);
@@ -121,6 +195,43 @@ impl LightNodeInterface for LightChain {
         BLK_HEAD_CACHE.lock().await.put(id, header.clone());
         Ok(header)
     }
+
+    // only the headers not in the cache are fetched, at most MAX_BATCH_IDS
+    // at a time
+    async fn lightnode_read_block_headers(
+        &self,
+        ids: &[IdType],
+    ) -> anyhow::Result<Vec<BlockHeader>> {
+        let mut headers = Vec::with_capacity(ids.len());
+        let mut missing = Vec::new();
+        {
+            let mut cache = BLK_HEAD_CACHE.lock().await;
+            for &id in ids {
+                let header = cache.get(&id).cloned();
+                if header.is_none() {
+                    missing.push(id);
+                }
+                headers.push(header);
+            }
+        }
+        missing.sort_unstable();
+        missing.dedup();
+        let mut fetched = HashMap::new();
+        for chunk in missing.chunks(MAX_BATCH_IDS) {
+            for header in self.get_block_headers(chunk).await? {
+                BLK_HEAD_CACHE.lock().await.put(header.block_id, header);
+                fetched.insert(header.block_id, header);
+            }
+        }
+        ids.iter()
+            .zip(headers)
+            .map(|(id, header)| {
+                header
+                    .or_else(|| fetched.get(id).copied())
+                    .with_context(|| format!("failed to read blk #{}", id))
+            })
+            .collect()
+    }
 }
 
 async fn web_verify(mut body: web::Payload) -> actix_web::Result<impl Responder> {
@@ -129,7 +240,7 @@ async fn web_verify(mut body: web::Payload) -> actix_web::Result<impl Responder>
         bytes.extend_from_slice(&item?);
     }
 
-    let lightnode = LightChain::new(get_param().clone(), get_api_address());
+    let lightnode = LightChain::new(get_param().clone(), get_api_address(), get_validator_keys());
     let (verify_result, time) = match lightnode.param.acc_type {
         acc::Type::ACC1 => {
             let res: OverallResult<acc::Acc1Proof> =
@@ -141,6 +252,11 @@ async fn web_verify(mut body: web::Payload) -> actix_web::Result<impl Responder>
                 serde_json::from_slice(&bytes).map_err(handle_err)?;
             res.verify(&lightnode).await
         }
+        acc::Type::ACC3 => {
+            let res: OverallResult<acc::Acc3Proof> =
+                serde_json::from_slice(&bytes).map_err(handle_err)?;
+            res.verify(&lightnode).await
+        }
     }
     .map_err(handle_err)?;
     let response = VerifyResponse {
@@ -151,23 +267,224 @@ async fn web_verify(mut body: web::Payload) -> actix_web::Result<impl Responder>
     Ok(HttpResponse::Ok().json(response))
 }
 
+const IDS_PARAM: &[ApiParam] = &[ApiParam {
+    name: "ids",
+    description: "the ids separated by commas, e.g. 1,2,3",
+    ty: "string",
+}];
+
+// The routes registered in main, for /openapi.json.
+const API_ROUTES: &[ApiRoute] = &[
+    ApiRoute::get("/get/param", "the parameter of the chain", "Parameter"),
+    ApiRoute::get(
+        "/get/blk_header/{id}",
+        "the header of a block",
+        "BlockHeader",
+    ),
+    ApiRoute::get("/get/blk_data/{id}", "the data of a block", "BlockData"),
+    ApiRoute::get(
+        "/get/intraindex/{id}",
+        "an intra index node",
+        "IntraIndexNode",
+    ),
+    ApiRoute::get("/get/skiplist/{id}", "a skip list node", "SkipListNode"),
+    ApiRoute::get(
+        "/get/index/{id}",
+        "an intra index or skip list node",
+        "IndexNode",
+    ),
+    ApiRoute::get("/get/obj/{id}", "an object", "Object"),
+    ApiRoute::get(
+        "/get/blk_headers",
+        "the headers of several blocks",
+        "BlockHeader[]",
+    )
+    .with_query_params(IDS_PARAM),
+    ApiRoute::get(
+        "/get/intraindexes",
+        "several intra index nodes",
+        "IntraIndexNode[]",
+    )
+    .with_query_params(IDS_PARAM),
+    ApiRoute::get(
+        "/get/skiplists",
+        "several skip list nodes",
+        "SkipListNode[]",
+    )
+    .with_query_params(IDS_PARAM),
+    ApiRoute::get("/get/objs", "several objects", "Object[]").with_query_params(IDS_PARAM),
+    ApiRoute::post("/query", "answer a query", "Query", "OverallResult"),
+    ApiRoute::post(
+        "/verify",
+        "verify the response of a query",
+        "OverallResult",
+        "VerifyResponse",
+    ),
+];
+
+async fn web_get_openapi() -> impl Responder {
+    HttpResponse::Ok().json(openapi_document("vchain-server", API_ROUTES))
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "vchain-server")]
 struct Opts {
-    /// api address
-    #[structopt(short, long, default_value = "http://127.0.0.1:5000")]
-    api_address: String,
+    /// config file in TOML, with the options below as keys, e.g. `api_address = "http://127.0.0.1:5000"`, overridden by the options given
+    #[structopt(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// api address [default: http://127.0.0.1:5000]
+    #[structopt(short, long)]
+    api_address: Option<String>,
+
+    /// server binding address [default: 127.0.0.1:8000]
+    #[structopt(short, long)]
+    binding: Option<String>,
 
-    /// server binding address
-    #[structopt(short, long, default_value = "127.0.0.1:8000")]
+    /// number of the threads serving the requests [default: the number of CPUs]
+    #[structopt(long)]
+    workers: Option<usize>,
+
+    /// milliseconds to wait for the request of a new connection, 0 means no timeout [default: 5000]
+    #[structopt(long)]
+    client_timeout: Option<u64>,
+
+    /// seconds to keep an idle connection alive, 0 means closing it at once [default: 5]
+    #[structopt(long)]
+    keep_alive: Option<usize>,
+
+    /// origin allowed to call the server from the browsers, or * for any origin; repeat it to allow several [default: none]
+    #[structopt(long, number_of_values = 1)]
+    cors_origin: Vec<String>,
+
+    /// certificate chain in PEM, to serve HTTPS with --tls-key
+    #[structopt(long, parse(from_os_str))]
+    tls_cert: Option<PathBuf>,
+
+    /// private key in PEM (PKCS#8 or RSA), to serve HTTPS with --tls-cert
+    #[structopt(long, parse(from_os_str))]
+    tls_key: Option<PathBuf>,
+
+    /// consensus key of a validator in hex, to verify the block headers against the signed blocks; repeat it for each validator, at least one is required
+    #[structopt(long, number_of_values = 1)]
+    validator_key: Vec<String>,
+}
+
+// The options of the server, read from the config file and then overridden by
+// those given on the command line.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    api_address: String,
     binding: String,
+    workers: Option<usize>,
+    client_timeout: u64,
+    keep_alive: usize,
+    cors_origin: Vec<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    validator_key: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            api_address: "http://127.0.0.1:5000".to_owned(),
+            binding: "127.0.0.1:8000".to_owned(),
+            workers: None,
+            client_timeout: 5000,
+            keep_alive: 5,
+            cors_origin: Vec::new(),
+            tls_cert: None,
+            tls_key: None,
+            validator_key: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    fn load(opts: Opts) -> anyhow::Result<Self> {
+        let mut config = match &opts.config {
+            Some(path) => {
+                let data = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read config {:?}", path))?;
+                toml::from_str(&data)
+                    .map_err(|e| anyhow::anyhow!("invalid config {:?}: {}", path, e))?
+            }
+            None => Self::default(),
+        };
+        if !opts.cors_origin.is_empty() {
+            config.cors_origin = opts.cors_origin;
+        }
+        if !opts.validator_key.is_empty() {
+            config.validator_key = opts.validator_key;
+        }
+        config.api_address = opts.api_address.unwrap_or(config.api_address);
+        config.binding = opts.binding.unwrap_or(config.binding);
+        config.workers = opts.workers.or(config.workers);
+        config.client_timeout = opts.client_timeout.unwrap_or(config.client_timeout);
+        config.keep_alive = opts.keep_alive.unwrap_or(config.keep_alive);
+        config.tls_cert = opts.tls_cert.or(config.tls_cert);
+        config.tls_key = opts.tls_key.or(config.tls_key);
+        Ok(config)
+    }
+}
+
+fn cors(origins: &[String]) -> Cors {
+    let cors = Cors::default().allowed_methods(vec!["GET", "POST"]);
+    if origins.is_empty() {
+        return cors.send_wildcard();
+    }
+    origins
+        .iter()
+        .fold(cors, |cors, origin| match origin.as_str() {
+            "*" => cors.allow_any_origin(),
+            origin => cors.allowed_origin(origin),
+        })
+}
+
+fn load_tls_config(cert: &Path, key: &Path) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = pemfile::certs(&mut BufReader::new(File::open(cert)?))
+        .map_err(|_| anyhow::anyhow!("invalid certificate {:?}", cert))?;
+    let read_keys = |read: fn(&mut dyn BufRead) -> Result<Vec<PrivateKey>, ()>| {
+        let mut reader = BufReader::new(File::open(key)?);
+        read(&mut reader).map_err(|_| anyhow::anyhow!("invalid private key {:?}", key))
+    };
+    let mut keys = read_keys(pemfile::pkcs8_private_keys)?;
+    if keys.is_empty() {
+        keys = read_keys(pemfile::rsa_private_keys)?;
+    }
+    let key = keys
+        .into_iter()
+        .next()
+        .with_context(|| format!("no private key in {:?}", key))?;
+    let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    config.set_single_cert(certs, key)?;
+    Ok(config)
 }
 
 #[actix_rt::main]
 async fn main() -> actix_web::Result<()> {
     env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
-    let opts = Opts::from_args();
-    let api_address = format!("{}/api/services/vchain", opts.api_address);
+    let config = Config::load(Opts::from_args()).map_err(handle_err)?;
+    let tls_config = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(load_tls_config(cert, key).map_err(handle_err)?),
+        (None, None) => None,
+        _ => return Err(handle_err("--tls-cert and --tls-key should be given together").into()),
+    };
+    let validator_keys = config
+        .validator_key
+        .iter()
+        .map(|key| {
+            PublicKey::from_hex(key)
+                .map_err(|_| handle_err(format!("invalid validator key {}", key)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // otherwise the headers from the api node could not be verified
+    if validator_keys.is_empty() {
+        return Err(handle_err("no validator key given, use --validator-key").into());
+    }
+    let api_address = format!("{}/api/services/vchain", config.api_address);
     let param = reqwest::get(&format!("{}/get/param", api_address))
         .await
         .map_err(handle_err)?
@@ -177,15 +494,13 @@ async fn main() -> actix_web::Result<()> {
     unsafe {
         API_ADDRESS = Some(api_address);
         PARAM = Some(param);
+        VALIDATOR_KEYS = validator_keys;
     }
 
-    HttpServer::new(|| {
+    let origins = config.cors_origin.clone();
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(
-                Cors::default()
-                    .send_wildcard()
-                    .allowed_methods(vec!["GET", "POST"]),
-            )
+            .wrap(cors(&origins))
             .route("/get/param", web::get().to(web_get_param))
             .route("/get/blk_header/{id}", web::get().to(web_get_blk_header))
             .route("/get/blk_data/{id}", web::get().to(web_get_blk_data))
@@ -196,12 +511,28 @@ async fn main() -> actix_web::Result<()> {
             .route("/get/skiplist/{id}", web::get().to(web_get_skip_list_node))
             .route("/get/index/{id}", web::get().to(web_get_index_node))
             .route("/get/obj/{id}", web::get().to(web_get_object))
+            .route("/get/blk_headers", web::get().to(web_get_blk_headers))
+            .route(
+                "/get/intraindexes",
+                web::get().to(web_get_intra_index_nodes),
+            )
+            .route("/get/skiplists", web::get().to(web_get_skip_list_nodes))
+            .route("/get/objs", web::get().to(web_get_objects))
             .route("/query", web::post().to(web_query))
             .route("/verify", web::post().to(web_verify))
+            .route("/openapi.json", web::get().to(web_get_openapi))
     })
-    .bind(opts.binding)?
-    .run()
-    .await?;
+    .client_timeout(config.client_timeout)
+    .keep_alive(config.keep_alive);
+    let server = match config.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    let server = match tls_config {
+        Some(tls) => server.bind_rustls(&config.binding, tls)?,
+        None => server.bind(&config.binding)?,
+    };
+    server.run().await?;
 
     Ok(())
 }
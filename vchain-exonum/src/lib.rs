@@ -6,15 +6,24 @@ extern crate exonum_derive;
 extern crate log;
 
 pub mod api;
+pub mod migration;
+pub mod proof;
 pub mod proto;
 pub mod schema;
 pub mod service;
 pub mod transactions;
 
+// the service instance started by vchain-node
+pub const INSTANCE_ID: exonum::runtime::InstanceId = 1;
+pub const INSTANCE_NAME: &str = "vchain";
+
 pub mod errors {
     #[derive(Debug, IntoExecutionError)]
     pub enum Error {
         Unknown = 1,
+        InvalidParam = 2,
+        UnknownObject = 3,
+        NotAllowed = 4,
     }
 }
 
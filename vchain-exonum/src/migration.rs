@@ -0,0 +1,399 @@
+// The upgrades of the data stored by the earlier versions of the service. The
+// exonum version used here (0.13) has no hooks to migrate the data of a service
+// to a new version of its artifact, so the service upgrades its data in place
+// before it is read or written, when the service is initialized, at the start
+// of every transaction and before every commit. The upgrades are in the same
+// fork as the rest of the block, so that every node upgrades its data at the
+// same height.
+use crate::schema::{
+    BTreeNode, BlockData, BlockHeader, IntraIndexNode, Object, Parameter, SkipListNode,
+    VChainSchema,
+};
+use anyhow::{ensure, Context, Result};
+use exonum_merkledb::{
+    access::{Access, AccessExt, RawAccessMut},
+    BinaryValue,
+};
+use std::collections::HashMap;
+use vchain::{acc::G1Affine, Digest, IdType, MultiSet, SetElementType, SetFilter, SkipLstLvlType};
+
+// The version of the layout of the data, kept in VChainSchema::schema_version.
+//
+// 1: the protobuf messages, with the ids in the keys of the indexes as u32.
+// 2: the ids in the keys as u64.
+// 3: the keywords in the bincode of the B-tree nodes as the words rather than
+//    the symbols of the keyword table.
+// 4: the open_senders entry, rather than any key allowed to add objects when
+//    there is no sender.
+//
+// The data of the versions before 1, stored in bincode, cannot be upgraded.
+pub const SCHEMA_VERSION: u32 = 4;
+
+// The indexes with the ids as the keys, by their names in VChainSchema.
+const PARAM_CHANGES: &str = "param_changes";
+const OBJECTS: &str = "objects";
+const BLOCK_HEADERS: &str = "block_headers";
+const BLOCK_DATA: &str = "block_data";
+const INTRA_INDEX_NODES: &str = "intra_index_nodes";
+const SKIP_LIST_NODES: &str = "skip_list_nodes";
+const BTREE_NODES: &str = "btree_nodes";
+const TOMBSTONES_IN_THIS_ROUND: &str = "tombstones_in_this_round";
+
+// Upgrade the data to SCHEMA_VERSION, if not yet.
+pub fn migrate<T>(access: T) -> Result<()>
+where
+    T: Access,
+    T::Base: RawAccessMut,
+{
+    let version = stored_version(access.clone());
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+    ensure!(
+        version < SCHEMA_VERSION,
+        "the data is of schema version {}, newer than {} of this service",
+        version,
+        SCHEMA_VERSION
+    );
+    info!(
+        "migrate the data from schema version {} to {}",
+        version, SCHEMA_VERSION
+    );
+    if version < 2 {
+        widen_id_keys(access.clone());
+    }
+    if version < 3 {
+        decode_btree_keywords(access.clone())?;
+    }
+    if version < 4 {
+        keep_open_senders(access.clone());
+    }
+    VChainSchema::new(access).schema_version.set(SCHEMA_VERSION);
+    Ok(())
+}
+
+// The version of the data, told by the width of the keys for the data written
+// before the version was kept, or SCHEMA_VERSION if there is no block yet.
+fn stored_version<T: Access>(access: T) -> u32 {
+    if let Some(version) = VChainSchema::new(access.clone()).schema_version.get() {
+        return version;
+    }
+    let block_data = access.get_map::<_, Vec<u8>, Vec<u8>>(BLOCK_DATA);
+    let key = block_data.keys().next();
+    match key {
+        Some(key) if key.len() == 4 => 1,
+        Some(_) => 2,
+        None => SCHEMA_VERSION,
+    }
+}
+
+fn widen_map_keys<T, V>(access: T, name: &str)
+where
+    T: Access,
+    T::Base: RawAccessMut,
+    V: BinaryValue,
+{
+    let entries: Vec<(u32, V)> = access.clone().get_map::<_, u32, V>(name).iter().collect();
+    let mut map = access.get_map::<_, IdType, V>(name);
+    map.clear();
+    for (id, value) in entries {
+        map.put(&IdType::from(id), value);
+    }
+}
+
+// The ids in the messages are read as they are, since uint32 and uint64 are
+// encoded the same in protobuf, so only the keys are rewritten.
+fn widen_id_keys<T>(access: T)
+where
+    T: Access,
+    T::Base: RawAccessMut,
+{
+    widen_map_keys::<_, Parameter>(access.clone(), PARAM_CHANGES);
+    widen_map_keys::<_, Object>(access.clone(), OBJECTS);
+    widen_map_keys::<_, BlockData>(access.clone(), BLOCK_DATA);
+    widen_map_keys::<_, IntraIndexNode>(access.clone(), INTRA_INDEX_NODES);
+    widen_map_keys::<_, SkipListNode>(access.clone(), SKIP_LIST_NODES);
+    widen_map_keys::<_, BTreeNode>(access.clone(), BTREE_NODES);
+
+    let headers: Vec<(u32, BlockHeader)> = access
+        .clone()
+        .get_proof_map::<_, u32, BlockHeader>(BLOCK_HEADERS)
+        .iter()
+        .collect();
+    let mut map = access
+        .clone()
+        .get_proof_map::<_, IdType, BlockHeader>(BLOCK_HEADERS);
+    map.clear();
+    for (id, header) in headers {
+        map.put(&IdType::from(id), header);
+    }
+
+    let tombstones: Vec<u32> = access
+        .clone()
+        .get_key_set::<_, u32>(TOMBSTONES_IN_THIS_ROUND)
+        .iter()
+        .collect();
+    let mut set = access.get_key_set::<_, IdType>(TOMBSTONES_IN_THIS_ROUND);
+    set.clear();
+    for id in tombstones {
+        set.insert(IdType::from(id));
+    }
+}
+
+// SetElementType with the keywords in the symbols of the keyword table, as the
+// earlier versions stored them in bincode.
+#[derive(Serialize, Deserialize)]
+enum LegacySetElement {
+    V { dim: u32, val: u64, mask: u64 },
+    W(u32),
+    WPrefix(u32),
+}
+
+// vchain::BTreeNode in the bincode of the earlier versions, where the set data
+// is the map of the elements to their counts.
+#[derive(Serialize, Deserialize)]
+struct LegacyBTreeNode {
+    #[serde(with = "vchain::chain::id::serde_impl")]
+    id: IdType,
+    #[serde(with = "vchain::chain::id::serde_impl")]
+    block_id: IdType,
+    level: SkipLstLvlType,
+    set_data: Vec<(LegacySetElement, u64)>,
+    set_filter: Option<SetFilter>,
+    #[serde(with = "vchain::acc::serde_impl")]
+    acc_value: G1Affine,
+    pre_skipped_hash: Digest,
+    child_hash_digest: Digest,
+    #[serde(with = "vchain::chain::id::serde_impl::vec")]
+    tombstones: Vec<IdType>,
+    digest: Digest,
+}
+
+impl LegacyBTreeNode {
+    fn into_vchain_type(self, keywords: &HashMap<u32, String>) -> Result<vchain::BTreeNode> {
+        let keyword = |sym| {
+            keywords
+                .get(&sym)
+                .map(|w| vchain::Keyword::new(w))
+                .with_context(|| format!("unknown keyword symbol {}", sym))
+        };
+        let set_data = self
+            .set_data
+            .into_iter()
+            .map(|(elem, count)| {
+                let elem = match elem {
+                    LegacySetElement::V { dim, val, mask } => SetElementType::V { dim, val, mask },
+                    LegacySetElement::W(sym) => SetElementType::W(keyword(sym)?),
+                    LegacySetElement::WPrefix(sym) => SetElementType::WPrefix(keyword(sym)?),
+                };
+                Ok((elem, count))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(vchain::BTreeNode {
+            id: self.id,
+            block_id: self.block_id,
+            level: self.level,
+            set_data: MultiSet::from_tuple_vec(set_data),
+            set_filter: self.set_filter,
+            acc_value: self.acc_value,
+            pre_skipped_hash: self.pre_skipped_hash,
+            child_hash_digest: self.child_hash_digest,
+            tombstones: self.tombstones,
+            digest: self.digest,
+        })
+    }
+}
+
+// The B-tree nodes are the only data kept in bincode with the keywords, which
+// were the symbols of the keyword table before.
+fn decode_btree_keywords<T>(access: T) -> Result<()>
+where
+    T: Access,
+    T::Base: RawAccessMut,
+{
+    let mut schema = VChainSchema::new(access);
+    let keywords: HashMap<u32, String> = schema.keywords.iter().collect();
+    let nodes: Vec<(IdType, BTreeNode)> = schema.btree_nodes.iter().collect();
+    for (id, node) in nodes {
+        let legacy: LegacyBTreeNode = bincode::deserialize(&node.data)
+            .with_context(|| format!("invalid b-tree node #{}", id))?;
+        let node = legacy.into_vchain_type(&keywords)?;
+        schema.btree_nodes.put(&id, BTreeNode::create(&node)?);
+    }
+    Ok(())
+}
+
+// Any key could add objects to the chains of the earlier versions without a
+// sender, which is kept by opening them.
+fn keep_open_senders<T>(access: T)
+where
+    T: Access,
+    T::Base: RawAccessMut,
+{
+    let mut schema = VChainSchema::new(access);
+    let open = schema.senders.iter().next().is_none();
+    schema.open_senders.set(open);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exonum_merkledb::{access::Prefixed, Database, TemporaryDB};
+    use vchain::{ReadInterface, WriteInterface};
+
+    fn legacy_btree_node(keywords: &HashMap<String, u32>, node: &vchain::BTreeNode) -> BTreeNode {
+        let set_data = node
+            .set_data
+            .iter()
+            .map(|(elem, &count)| {
+                let elem = match elem {
+                    SetElementType::V { dim, val, mask } => LegacySetElement::V {
+                        dim: *dim,
+                        val: *val,
+                        mask: *mask,
+                    },
+                    SetElementType::W(k) => LegacySetElement::W(keywords[k.word()]),
+                    SetElementType::WPrefix(k) => LegacySetElement::WPrefix(keywords[k.word()]),
+                };
+                (elem, count)
+            })
+            .collect();
+        let legacy = LegacyBTreeNode {
+            id: node.id,
+            block_id: node.block_id,
+            level: node.level,
+            set_data,
+            set_filter: node.set_filter,
+            acc_value: node.acc_value,
+            pre_skipped_hash: node.pre_skipped_hash,
+            child_hash_digest: node.child_hash_digest,
+            tombstones: node.tombstones.clone(),
+            digest: node.digest,
+        };
+        BTreeNode {
+            data: bincode::serialize(&legacy).unwrap(),
+        }
+    }
+
+    fn flat_obj_ids(data: &vchain::BlockData) -> &[IdType] {
+        match &data.data {
+            vchain::IntraData::Flat(ids) => ids,
+            vchain::IntraData::Index(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_migrate_from_u32_keys() {
+        let param = vchain::Parameter {
+            v_bit_len: vec![3],
+            acc_type: vchain::acc::Type::ACC2,
+            use_sk: true,
+            intra_index: false,
+            skip_list_max_level: 0,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 2,
+            btree_max_level: 2,
+            max_objs_per_block: 0,
+        };
+        let mut chain = vchain::MemChain::new();
+        chain.set_parameter(param.clone()).unwrap();
+        {
+            let mut builder = vchain::ChainBuilder::new(&mut chain, None).unwrap();
+            let data = "1 [ 1 ] { a }\n2 [ 2 ] { b }\n3 [ 1 ] { a }\n";
+            for (id, objs) in vchain::load_raw_obj_from_str(data).unwrap().iter() {
+                builder.append_block(*id, *id * 10, objs.iter()).unwrap();
+            }
+        }
+        let latest = chain.latest_block_id().unwrap().unwrap();
+
+        // the data as written by the version 1, which kept no version
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let access = Prefixed::new("vchain", &fork);
+        let symbols: HashMap<String, u32> = [("a", 0), ("b", 1)]
+            .iter()
+            .map(|&(w, s)| (w.to_owned(), s))
+            .collect();
+        {
+            let mut schema = VChainSchema::new(access.clone());
+            schema.set_parameter(param).unwrap();
+            for (word, &sym) in &symbols {
+                schema.keywords.put(&sym, word.clone());
+            }
+        }
+        let mut btree_ids = Vec::new();
+        for id in 1..=latest {
+            let header = chain.read_block_header(id).unwrap();
+            access
+                .clone()
+                .get_proof_map::<_, u32, BlockHeader>(BLOCK_HEADERS)
+                .put(&(id as u32), BlockHeader::create(&header).unwrap());
+            let data = chain.read_block_data(id).unwrap();
+            for &obj_id in flat_obj_ids(&data) {
+                let obj = chain.read_object(obj_id).unwrap();
+                access
+                    .clone()
+                    .get_map::<_, u32, Object>(OBJECTS)
+                    .put(&(obj_id as u32), Object::create(&obj).unwrap());
+            }
+            for &node_id in &data.btree_ids {
+                let node = chain.read_btree_node(node_id).unwrap();
+                access
+                    .clone()
+                    .get_map::<_, u32, BTreeNode>(BTREE_NODES)
+                    .put(&(node_id as u32), legacy_btree_node(&symbols, &node));
+                btree_ids.push(node_id);
+            }
+            access
+                .clone()
+                .get_map::<_, u32, BlockData>(BLOCK_DATA)
+                .put(&(id as u32), BlockData::create(&data).unwrap());
+        }
+        assert!(!btree_ids.is_empty());
+        assert_eq!(stored_version(access.clone()), 1);
+
+        migrate(access.clone()).unwrap();
+        let schema = VChainSchema::new(access.clone());
+        assert_eq!(schema.schema_version.get(), Some(SCHEMA_VERSION));
+        // any key could add objects without a sender
+        assert_eq!(schema.open_senders.get(), Some(true));
+        for id in 1..=latest {
+            assert_eq!(
+                schema.read_block_header(id).unwrap(),
+                chain.read_block_header(id).unwrap()
+            );
+            let data = schema.read_block_data(id).unwrap();
+            assert_eq!(data, chain.read_block_data(id).unwrap());
+            for &obj_id in flat_obj_ids(&data) {
+                assert_eq!(
+                    schema.read_object(obj_id).unwrap(),
+                    chain.read_object(obj_id).unwrap()
+                );
+            }
+        }
+        for id in btree_ids {
+            assert_eq!(
+                schema.read_btree_node(id).unwrap(),
+                chain.read_btree_node(id).unwrap()
+            );
+        }
+        drop(schema);
+        // and only once
+        migrate(access).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_new_chain() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let access = Prefixed::new("vchain", &fork);
+        migrate(access.clone()).unwrap();
+        let mut schema = VChainSchema::new(access.clone());
+        assert_eq!(schema.schema_version.get(), Some(SCHEMA_VERSION));
+
+        schema.schema_version.set(SCHEMA_VERSION + 1);
+        drop(schema);
+        assert!(migrate(access).is_err());
+    }
+}
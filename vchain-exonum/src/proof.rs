@@ -0,0 +1,99 @@
+use crate::schema::BlockHeader;
+use anyhow::{anyhow, ensure, Context, Result};
+use exonum::{
+    blockchain::{BlockProof, IndexCoordinates, SchemaOrigin},
+    crypto::{Hash, PublicKey},
+    runtime::InstanceId,
+};
+use exonum_merkledb::{MapProof, ObjectHash};
+use std::collections::{HashMap, HashSet};
+use vchain::IdType;
+
+// The proof of some block headers of the service, from the latest exonum
+// block signed by the validators, through the state hash of the block, to the
+// headers in `block_headers`, which is the first index of the state hash of
+// the service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockHeaderProof {
+    pub block_proof: BlockProof,
+    pub to_table: MapProof<IndexCoordinates, Hash>,
+    pub to_header: MapProof<IdType, BlockHeader>,
+}
+
+pub fn block_headers_coordinate(instance_id: InstanceId) -> IndexCoordinates {
+    SchemaOrigin::Service(instance_id).coordinate_for(0)
+}
+
+impl BlockHeaderProof {
+    // Check the proof against the consensus keys of the validators, which
+    // should come from the genesis config rather than the node giving the
+    // proof, and return the header of block `id`.
+    pub fn verify(
+        &self,
+        id: IdType,
+        instance_id: InstanceId,
+        validator_keys: &[PublicKey],
+    ) -> Result<vchain::BlockHeader> {
+        let mut headers = self.verify_headers(&[id], instance_id, validator_keys)?;
+        Ok(headers.remove(0))
+    }
+
+    // Like verify, for the headers of the blocks `ids` in the same order.
+    pub fn verify_headers(
+        &self,
+        ids: &[IdType],
+        instance_id: InstanceId,
+        validator_keys: &[PublicKey],
+    ) -> Result<Vec<vchain::BlockHeader>> {
+        let block = &self.block_proof.block;
+        let block_hash = block.object_hash();
+        // the signatures are checked as the precommits are deserialized
+        let mut signers = HashSet::new();
+        for precommit in &self.block_proof.precommits {
+            let author = precommit.author();
+            ensure!(
+                validator_keys.contains(&author),
+                "precommit by unknown validator {:?}",
+                author
+            );
+            ensure!(
+                precommit.payload().block_hash == block_hash
+                    && precommit.payload().height == block.height(),
+                "precommit of another block"
+            );
+            signers.insert(author);
+        }
+        // the same majority as the consensus
+        ensure!(
+            signers.len() > validator_keys.len() * 2 / 3,
+            "block signed by {} of {} validators",
+            signers.len(),
+            validator_keys.len()
+        );
+
+        let to_table = self
+            .to_table
+            .check_against_hash(*block.state_hash())
+            .map_err(|e| anyhow!("invalid proof of the headers table: {:?}", e))?;
+        let coordinate = block_headers_coordinate(instance_id);
+        let table_hash = to_table
+            .entries()
+            .find(|(k, _)| **k == coordinate)
+            .map(|(_, h)| *h)
+            .context("the headers table is missing from the proof")?;
+        let to_header = self
+            .to_header
+            .check_against_hash(table_hash)
+            .map_err(|e| anyhow!("invalid proof of the headers: {:?}", e))?;
+        let headers: HashMap<IdType, &BlockHeader> =
+            to_header.entries().map(|(&k, h)| (k, h)).collect();
+        ids.iter()
+            .map(|id| {
+                headers
+                    .get(id)
+                    .with_context(|| format!("blk #{} is missing from the proof", id))?
+                    .to_vchain_type()
+            })
+            .collect()
+    }
+}
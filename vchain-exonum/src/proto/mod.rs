@@ -1,6 +1,6 @@
 pub use self::service::{
-    BlockData, BlockHeader, InitParam, IntraIndexNode, Object, Parameter, RawObject, SkipListNode,
-    TxAddObjs,
+    BlockData, BlockHeader, InitParam, IntraIndexNode, Object, Parameter, RawObject, SetElement,
+    SkipListNode, TxAddObjs, TxRemoveObjs, TxSetParam, TxSetSenders,
 };
 
 include!(concat!(env!("OUT_DIR"), "/protobuf_mod.rs"));
@@ -1,19 +1,26 @@
 use crate::transactions::RawObject;
-use anyhow::{Context, Error, Result};
-use exonum::crypto::Hash;
+use anyhow::{anyhow, bail, ensure, Error, Result};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use exonum::crypto::{Hash, PublicKey};
 use exonum_derive::{BinaryValue, FromAccess, ObjectHash};
 use exonum_merkledb::{
     access::{Access, RawAccessMut},
-    Entry, ListIndex, MapIndex, ObjectHash as _, ProofMapIndex,
+    Entry, KeySetIndex, ListIndex, MapIndex, ObjectHash as _, ProofMapIndex,
 };
 use exonum_proto::ProtobufConvert;
-use vchain::IdType;
+use std::convert::TryFrom;
+use vchain::{
+    acc::Fr, Digest, IdType, Keyword, MultiSet, SetElementType, SetFilter, SkipLstLvlType,
+    SymbolType, DIGEST_LEN,
+};
 
 use super::proto;
 
 macro_rules! impl_schema_from_proto {
     ($type:ident) => {
-        #[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+        #[derive(
+            Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash,
+        )]
         #[protobuf_convert(source = "proto::Parameter")]
         pub struct $type {
             pub data: Vec<u8>,
@@ -27,28 +34,401 @@ macro_rules! impl_schema_from_proto {
             }
 
             pub fn to_vchain_type(&self) -> Result<vchain::$type> {
-                bincode::deserialize::<vchain::$type>(&self.data).map_err(Error::msg)
+                bincode::deserialize::<vchain::$type>(&self.data)
+                    .map_err(|e| vchain::Error::Storage(e.to_string()).into())
             }
         }
     };
 }
 
 impl_schema_from_proto!(Parameter);
-impl_schema_from_proto!(Object);
-impl_schema_from_proto!(BlockHeader);
-impl_schema_from_proto!(BlockData);
-impl_schema_from_proto!(IntraIndexNode);
-impl_schema_from_proto!(SkipListNode);
+impl_schema_from_proto!(BTreeNode);
+impl_schema_from_proto!(IdCounter);
+
+const SET_ELEMENT_V: u32 = 0;
+const SET_ELEMENT_W: u32 = 1;
+const SET_ELEMENT_W_PREFIX: u32 = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert)]
+#[protobuf_convert(source = "proto::SetElement")]
+pub struct SetElement {
+    pub kind: u32,
+    pub dim: u32,
+    pub val: u64,
+    pub mask: u64,
+    pub word: String,
+    pub count: u64,
+}
+
+// in the order of the digests, so that every node stores the same bytes
+fn set_data_to_pb(set: &MultiSet<SetElementType>) -> Vec<SetElement> {
+    set.sorted_iter()
+        .map(|(elem, &count)| {
+            let (kind, dim, val, mask, word) = match elem {
+                SetElementType::V { dim, val, mask } => {
+                    (SET_ELEMENT_V, *dim, *val, *mask, String::new())
+                }
+                SetElementType::W(w) => (SET_ELEMENT_W, 0, 0, 0, w.word().to_string()),
+                SetElementType::WPrefix(w) => (SET_ELEMENT_W_PREFIX, 0, 0, 0, w.word().to_string()),
+            };
+            SetElement {
+                kind,
+                dim,
+                val,
+                mask,
+                word,
+                count,
+            }
+        })
+        .collect()
+}
+
+fn set_data_from_pb(set: &[SetElement]) -> Result<MultiSet<SetElementType>> {
+    let items = set
+        .iter()
+        .map(|pb| {
+            let elem = match pb.kind {
+                SET_ELEMENT_V => SetElementType::V {
+                    dim: pb.dim,
+                    val: pb.val,
+                    mask: pb.mask,
+                },
+                SET_ELEMENT_W => SetElementType::W(Keyword::new(&pb.word)),
+                SET_ELEMENT_W_PREFIX => SetElementType::WPrefix(Keyword::new(&pb.word)),
+                kind => bail!("invalid set element kind {}", kind),
+            };
+            Ok((elem, pb.count))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(MultiSet::from_tuple_vec(items))
+}
+
+fn set_filter_to_pb(filter: Option<&SetFilter>) -> Vec<u64> {
+    filter.map(|f| f.bits().to_vec()).unwrap_or_default()
+}
+
+fn set_filter_from_pb(bits: &[u64]) -> Result<Option<SetFilter>> {
+    if bits.is_empty() {
+        return Ok(None);
+    }
+    let bits = <[u64; 4]>::try_from(bits)
+        .map_err(|_| anyhow!("invalid set filter of {} words", bits.len()))?;
+    Ok(Some(SetFilter::from_bits(bits)))
+}
+
+fn opt_digest_to_pb(digest: Option<Digest>) -> Vec<u8> {
+    digest.map(|d| d.0.to_vec()).unwrap_or_default()
+}
+
+fn digest_from_pb(data: &[u8]) -> Result<Digest> {
+    let mut digest = Digest::default();
+    ensure!(
+        data.len() == DIGEST_LEN,
+        "invalid digest of {} bytes",
+        data.len()
+    );
+    digest.0.copy_from_slice(data);
+    Ok(digest)
+}
+
+fn opt_digest_from_pb(data: &[u8]) -> Result<Option<Digest>> {
+    if data.is_empty() {
+        Ok(None)
+    } else {
+        digest_from_pb(data).map(Some)
+    }
+}
+
+fn canonical_to_pb(c: &impl CanonicalSerialize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    c.serialize(&mut buf).map_err(Error::msg)?;
+    Ok(buf)
+}
+
+fn canonical_from_pb<C: CanonicalDeserialize>(data: &[u8]) -> Result<C> {
+    C::deserialize(data).map_err(Error::msg)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::Object")]
+pub struct Object {
+    pub id: IdType,
+    pub block_id: IdType,
+    pub v_data: Vec<u64>,
+    pub w_data: Vec<String>,
+    pub set_data: Vec<SetElement>,
+    pub acc_value: Vec<u8>,
+}
+
+impl Object {
+    pub fn create(input: &vchain::Object) -> Result<Self> {
+        let mut w_data: Vec<_> = input.w_data.iter().cloned().collect();
+        w_data.sort();
+        Ok(Self {
+            id: input.id,
+            block_id: input.block_id,
+            v_data: input.v_data.clone(),
+            w_data,
+            set_data: set_data_to_pb(&input.set_data),
+            acc_value: canonical_to_pb(&input.acc_value)?,
+        })
+    }
+
+    pub fn to_vchain_type(&self) -> Result<vchain::Object> {
+        Ok(vchain::Object {
+            id: self.id,
+            block_id: self.block_id,
+            v_data: self.v_data.clone(),
+            w_data: self.w_data.iter().cloned().collect(),
+            set_data: set_data_from_pb(&self.set_data)?,
+            acc_value: canonical_from_pb(&self.acc_value)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::BlockHeader")]
+pub struct BlockHeader {
+    pub block_id: IdType,
+    pub prev_hash: [u8; DIGEST_LEN],
+    pub data_root: [u8; DIGEST_LEN],
+    pub skip_list_root: Vec<u8>,
+    pub timestamp: u64,
+    pub tombstone_root: Vec<u8>,
+}
+
+impl BlockHeader {
+    pub fn create(input: &vchain::BlockHeader) -> Result<Self> {
+        Ok(Self {
+            block_id: input.block_id,
+            prev_hash: input.prev_hash.0,
+            data_root: input.data_root.0,
+            skip_list_root: opt_digest_to_pb(input.skip_list_root),
+            timestamp: input.timestamp,
+            tombstone_root: opt_digest_to_pb(input.tombstone_root),
+        })
+    }
+
+    pub fn to_vchain_type(&self) -> Result<vchain::BlockHeader> {
+        Ok(vchain::BlockHeader {
+            block_id: self.block_id,
+            prev_hash: Digest(self.prev_hash),
+            data_root: Digest(self.data_root),
+            skip_list_root: opt_digest_from_pb(&self.skip_list_root)?,
+            timestamp: self.timestamp,
+            tombstone_root: opt_digest_from_pb(&self.tombstone_root)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::BlockData")]
+pub struct BlockData {
+    pub block_id: IdType,
+    pub intra_index: bool,
+    pub intra_index_root: IdType,
+    pub obj_ids: Vec<IdType>,
+    pub set_data: Vec<SetElement>,
+    pub acc_value: Vec<u8>,
+    pub skip_list_ids: Vec<IdType>,
+    pub timestamp: u64,
+    pub tombstones: Vec<IdType>,
+    pub btree_ids: Vec<IdType>,
+}
+
+impl BlockData {
+    pub fn create(input: &vchain::BlockData) -> Result<Self> {
+        let (intra_index, intra_index_root, obj_ids) = match &input.data {
+            vchain::IntraData::Index(root) => (true, *root, Vec::new()),
+            vchain::IntraData::Flat(ids) => (false, 0, ids.clone()),
+        };
+        Ok(Self {
+            block_id: input.block_id,
+            intra_index,
+            intra_index_root,
+            obj_ids,
+            set_data: set_data_to_pb(&input.set_data),
+            acc_value: canonical_to_pb(&input.acc_value)?,
+            skip_list_ids: input.skip_list_ids.clone(),
+            timestamp: input.timestamp,
+            tombstones: input.tombstones.clone(),
+            btree_ids: input.btree_ids.clone(),
+        })
+    }
+
+    pub fn to_vchain_type(&self) -> Result<vchain::BlockData> {
+        Ok(vchain::BlockData {
+            block_id: self.block_id,
+            data: if self.intra_index {
+                vchain::IntraData::Index(self.intra_index_root)
+            } else {
+                vchain::IntraData::Flat(self.obj_ids.clone())
+            },
+            set_data: set_data_from_pb(&self.set_data)?,
+            acc_value: canonical_from_pb(&self.acc_value)?,
+            skip_list_ids: self.skip_list_ids.clone(),
+            timestamp: self.timestamp,
+            tombstones: self.tombstones.clone(),
+            btree_ids: self.btree_ids.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::IntraIndexNode")]
+pub struct IntraIndexNode {
+    pub is_leaf: bool,
+    pub id: IdType,
+    pub block_id: IdType,
+    pub set_data: Vec<SetElement>,
+    pub set_filter: Vec<u64>,
+    pub acc_value: Vec<u8>,
+    pub child_hash_digest: Vec<u8>,
+    pub child_hashes: Vec<[u8; DIGEST_LEN]>,
+    pub child_ids: Vec<IdType>,
+    pub obj_id: IdType,
+    pub obj_hash: Vec<u8>,
+}
+
+impl IntraIndexNode {
+    pub fn create(input: &vchain::IntraIndexNode) -> Result<Self> {
+        let mut node = Self {
+            is_leaf: false,
+            id: input.id(),
+            block_id: input.block_id(),
+            set_data: set_data_to_pb(input.set_data()),
+            set_filter: set_filter_to_pb(input.set_filter()),
+            acc_value: canonical_to_pb(input.acc_value())?,
+            child_hash_digest: Vec::new(),
+            child_hashes: Vec::new(),
+            child_ids: Vec::new(),
+            obj_id: 0,
+            obj_hash: Vec::new(),
+        };
+        match input {
+            vchain::IntraIndexNode::NonLeaf(n) => {
+                node.child_hash_digest = n.child_hash_digest.0.to_vec();
+                node.child_hashes = n.child_hashes.iter().map(|h| h.0).collect();
+                node.child_ids = n.child_ids.to_vec();
+            }
+            vchain::IntraIndexNode::Leaf(n) => {
+                node.is_leaf = true;
+                node.obj_id = n.obj_id;
+                node.obj_hash = n.obj_hash.0.to_vec();
+            }
+        }
+        Ok(node)
+    }
+
+    pub fn to_vchain_type(&self) -> Result<vchain::IntraIndexNode> {
+        let set_data = set_data_from_pb(&self.set_data)?;
+        let set_filter = set_filter_from_pb(&self.set_filter)?;
+        let acc_value = canonical_from_pb(&self.acc_value)?;
+        Ok(if self.is_leaf {
+            vchain::IntraIndexNode::Leaf(Box::new(vchain::IntraIndexLeaf {
+                id: self.id,
+                block_id: self.block_id,
+                set_data,
+                set_filter,
+                acc_value,
+                obj_id: self.obj_id,
+                obj_hash: digest_from_pb(&self.obj_hash)?,
+            }))
+        } else {
+            vchain::IntraIndexNode::NonLeaf(Box::new(vchain::IntraIndexNonLeaf {
+                id: self.id,
+                block_id: self.block_id,
+                set_data,
+                set_filter,
+                acc_value,
+                child_hash_digest: digest_from_pb(&self.child_hash_digest)?,
+                child_hashes: self.child_hashes.iter().map(|&h| Digest(h)).collect(),
+                child_ids: self.child_ids.iter().copied().collect(),
+            }))
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::SkipListNode")]
+pub struct SkipListNode {
+    pub id: IdType,
+    pub block_id: IdType,
+    pub level: u32,
+    pub set_data: Vec<SetElement>,
+    pub set_filter: Vec<u64>,
+    pub acc_value: Vec<u8>,
+    pub pre_skipped_hash: [u8; DIGEST_LEN],
+    pub tombstones: Vec<IdType>,
+    pub digest: [u8; DIGEST_LEN],
+    pub set_poly: Vec<Vec<u8>>,
+}
+
+impl SkipListNode {
+    pub fn create(input: &vchain::SkipListNode) -> Result<Self> {
+        Ok(Self {
+            id: input.id,
+            block_id: input.block_id,
+            level: u32::from(input.level),
+            set_data: set_data_to_pb(&input.set_data),
+            set_filter: set_filter_to_pb(input.set_filter.as_ref()),
+            acc_value: canonical_to_pb(&input.acc_value)?,
+            pre_skipped_hash: input.pre_skipped_hash.0,
+            tombstones: input.tombstones.clone(),
+            digest: input.digest.0,
+            set_poly: input
+                .set_poly
+                .iter()
+                .map(canonical_to_pb)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn to_vchain_type(&self) -> Result<vchain::SkipListNode> {
+        Ok(vchain::SkipListNode {
+            id: self.id,
+            block_id: self.block_id,
+            level: SkipLstLvlType::try_from(self.level)?,
+            set_data: set_data_from_pb(&self.set_data)?,
+            set_filter: set_filter_from_pb(&self.set_filter)?,
+            acc_value: canonical_from_pb(&self.acc_value)?,
+            pre_skipped_hash: Digest(self.pre_skipped_hash),
+            tombstones: self.tombstones.clone(),
+            digest: Digest(self.digest),
+            set_poly: self
+                .set_poly
+                .iter()
+                .map(|c| canonical_from_pb::<Fr>(c))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
 
 #[derive(Debug, FromAccess)]
 pub(crate) struct VChainSchema<T: Access> {
     pub param: Entry<T::Base, Parameter>,
+    // set by TxSetParam, and used from the next block on
+    pub pending_param: Entry<T::Base, Parameter>,
+    // the parameters by the first blocks they are used for, from the second
+    pub param_changes: MapIndex<T::Base, IdType, Parameter>,
     pub objects: MapIndex<T::Base, IdType, Object>,
     pub block_headers: ProofMapIndex<T::Base, IdType, BlockHeader>,
     pub block_data: MapIndex<T::Base, IdType, BlockData>,
     pub intra_index_nodes: MapIndex<T::Base, IdType, IntraIndexNode>,
     pub skip_list_nodes: MapIndex<T::Base, IdType, SkipListNode>,
+    pub btree_nodes: MapIndex<T::Base, IdType, BTreeNode>,
+    pub keywords: MapIndex<T::Base, SymbolType, String>,
+    // the service keys allowed to add objects, none if empty
+    pub senders: KeySetIndex<T::Base, PublicKey>,
+    // any key is allowed to add objects, whatever the senders
+    pub open_senders: Entry<T::Base, bool>,
+    pub admins: KeySetIndex<T::Base, PublicKey>,
+    pub id_counter: Entry<T::Base, IdCounter>,
     pub objs_in_this_round: ListIndex<T::Base, RawObject>,
+    // the objects removed by TxRemoveObjs, as the tombstones of the next block
+    pub tombstones_in_this_round: KeySetIndex<T::Base, IdType>,
+    // the version of the layout of the data, see migration::SCHEMA_VERSION
+    pub schema_version: Entry<T::Base, u32>,
 }
 
 impl<T: Access> VChainSchema<T> {
@@ -61,39 +441,61 @@ impl<T: Access> vchain::ReadInterface for VChainSchema<T> {
     fn get_parameter(&self) -> Result<vchain::Parameter> {
         self.param
             .get()
-            .context("failed to get parameter")?
+            .ok_or_else(|| vchain::Error::NotFound("failed to get parameter".to_owned()))?
             .to_vchain_type()
     }
     fn read_block_header(&self, id: IdType) -> Result<vchain::BlockHeader> {
         self.block_headers
             .get(&id)
-            .context("failed to read block header")?
+            .ok_or_else(|| vchain::Error::NotFound(format!("failed to read block header #{}", id)))?
             .to_vchain_type()
     }
     fn read_block_data(&self, id: IdType) -> Result<vchain::BlockData> {
         self.block_data
             .get(&id)
-            .context("failed to read block data")?
+            .ok_or_else(|| vchain::Error::NotFound(format!("failed to read block data #{}", id)))?
             .to_vchain_type()
     }
     fn read_intra_index_node(&self, id: IdType) -> Result<vchain::IntraIndexNode> {
         self.intra_index_nodes
             .get(&id)
-            .context("failed to read intra index node")?
+            .ok_or_else(|| {
+                vchain::Error::NotFound(format!("failed to read intra index node #{}", id))
+            })?
             .to_vchain_type()
     }
     fn read_skip_list_node(&self, id: IdType) -> Result<vchain::SkipListNode> {
         self.skip_list_nodes
             .get(&id)
-            .context("failed to read skip list node")?
+            .ok_or_else(|| {
+                vchain::Error::NotFound(format!("failed to read skip list node #{}", id))
+            })?
+            .to_vchain_type()
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<vchain::BTreeNode> {
+        self.btree_nodes
+            .get(&id)
+            .ok_or_else(|| vchain::Error::NotFound(format!("failed to read b-tree node #{}", id)))?
             .to_vchain_type()
     }
     fn read_object(&self, id: IdType) -> Result<vchain::Object> {
         self.objects
             .get(&id)
-            .context("failed to read object")?
+            .ok_or_else(|| vchain::Error::NotFound(format!("failed to read object #{}", id)))?
             .to_vchain_type()
     }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        Ok(self.keywords.get(&sym))
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        Ok(self.keywords.iter().collect())
+    }
+    fn read_id_counter(&self) -> Result<vchain::IdCounter> {
+        match self.id_counter.get() {
+            Some(counter) => counter.to_vchain_type(),
+            None => Ok(vchain::IdCounter::default()),
+        }
+    }
 }
 
 impl<T: Access> vchain::WriteInterface for VChainSchema<T>
@@ -125,9 +527,22 @@ where
         self.skip_list_nodes.put(&id, SkipListNode::create(&node)?);
         Ok(())
     }
+    fn write_btree_node(&mut self, node: vchain::BTreeNode) -> Result<()> {
+        let id = node.id;
+        self.btree_nodes.put(&id, BTreeNode::create(&node)?);
+        Ok(())
+    }
     fn write_object(&mut self, obj: vchain::Object) -> Result<()> {
         let id = obj.id;
         self.objects.put(&id, Object::create(&obj)?);
         Ok(())
     }
+    fn write_keyword(&mut self, sym: SymbolType, word: String) -> Result<()> {
+        self.keywords.put(&sym, word);
+        Ok(())
+    }
+    fn write_id_counter(&mut self, counter: vchain::IdCounter) -> Result<()> {
+        self.id_counter.set(IdCounter::create(&counter)?);
+        Ok(())
+    }
 }
@@ -1,8 +1,9 @@
 use crate::{
     api::VChainApi,
     errors::Error,
-    schema::VChainSchema,
-    transactions::{InitParam, TxAddObjs},
+    migration,
+    schema::{Parameter, VChainSchema},
+    transactions::{InitParam, RawObject, TxAddObjs, TxRemoveObjs, TxSetParam, TxSetSenders},
 };
 use exonum::{
     crypto::Hash,
@@ -12,11 +13,23 @@ use exonum::{
     },
 };
 use exonum_merkledb::{BinaryValue, Snapshot};
-use vchain::{Digest, Digestible, IdType, ReadInterface, WriteInterface};
+use std::time::{SystemTime, UNIX_EPOCH};
+use vchain::{ChainBuilder, IdType, ReadInterface, WriteInterface};
 
 #[exonum_interface]
 pub trait VChainInterface {
     fn add_objs(&self, ctx: CallContext<'_>, arg: TxAddObjs) -> Result<(), Error>;
+    fn set_param(&self, ctx: CallContext<'_>, arg: TxSetParam) -> Result<(), Error>;
+    fn remove_objs(&self, ctx: CallContext<'_>, arg: TxRemoveObjs) -> Result<(), Error>;
+    fn set_senders(&self, ctx: CallContext<'_>, arg: TxSetSenders) -> Result<(), Error>;
+}
+
+// Upgrade the data of the earlier versions of the service before it is used.
+fn migrate(ctx: &CallContext<'_>) -> Result<(), Error> {
+    migration::migrate(ctx.service_data()).map_err(|e| {
+        error!("failed to migrate the service data: {:?}", e);
+        Error::Unknown
+    })
 }
 
 #[derive(Debug, ServiceFactory, ServiceDispatcher)]
@@ -26,26 +39,119 @@ pub struct VChainService;
 
 impl VChainInterface for VChainService {
     fn add_objs(&self, ctx: CallContext<'_>, arg: TxAddObjs) -> Result<(), Error> {
+        migrate(&ctx)?;
         let core = ctx.data().for_core();
         let block_id = core.height().0;
+        let author = ctx.caller().author().ok_or(Error::NotAllowed)?;
+        let mut schema = VChainSchema::new(ctx.service_data());
+        let open = schema.open_senders.get().unwrap_or(false);
+        if !open && !schema.senders.contains(&author) {
+            warn!("reject objects from {:?} at blk #{}", author, block_id);
+            return Err(Error::NotAllowed);
+        }
         warn!(
             "receive tx at blk #{} with {} objects",
             block_id,
             arg.objs.len()
         );
-        let mut schema = VChainSchema::new(ctx.service_data());
         schema.objs_in_this_round.extend(arg.objs.iter().cloned());
         Ok(())
     }
+
+    fn set_param(&self, ctx: CallContext<'_>, arg: TxSetParam) -> Result<(), Error> {
+        migrate(&ctx)?;
+        let core = ctx.data().for_core();
+        let block_id = core.height().0;
+        let author = ctx.caller().author().ok_or(Error::NotAllowed)?;
+        let mut schema = VChainSchema::new(ctx.service_data());
+        if !schema.admins.contains(&author) {
+            warn!("reject param from {:?} at blk #{}", author, block_id);
+            return Err(Error::NotAllowed);
+        }
+        let param = arg.param.into_vchain_type();
+        let current = schema.get_parameter().map_err(|_| Error::Unknown)?;
+        if let Err(e) = current.check_update(&param) {
+            warn!("reject param at blk #{}: {}", block_id, e);
+            return Err(Error::InvalidParam);
+        }
+        warn!("receive param at blk #{}: {:?}", block_id, param);
+        let param = Parameter::create(&param).map_err(|_| Error::Unknown)?;
+        schema.pending_param.set(param);
+        Ok(())
+    }
+
+    // Only the objects in the earlier blocks can be removed, not those of the
+    // transactions in this block, which have no ids yet.
+    fn remove_objs(&self, ctx: CallContext<'_>, arg: TxRemoveObjs) -> Result<(), Error> {
+        migrate(&ctx)?;
+        let core = ctx.data().for_core();
+        let block_id = core.height().0;
+        let author = ctx.caller().author().ok_or(Error::NotAllowed)?;
+        let mut schema = VChainSchema::new(ctx.service_data());
+        // otherwise any key could hide the objects of the others
+        if !schema.admins.contains(&author) {
+            warn!(
+                "reject removing objects by {:?} at blk #{}",
+                author, block_id
+            );
+            return Err(Error::NotAllowed);
+        }
+        if let Some(&id) = arg.obj_ids.iter().find(|id| !schema.objects.contains(id)) {
+            warn!("reject removing unknown obj #{} at blk #{}", id, block_id);
+            return Err(Error::UnknownObject);
+        }
+        warn!(
+            "receive tx at blk #{} removing {} objects",
+            block_id,
+            arg.obj_ids.len()
+        );
+        for id in arg.obj_ids {
+            schema.tombstones_in_this_round.insert(id);
+        }
+        Ok(())
+    }
+
+    fn set_senders(&self, ctx: CallContext<'_>, arg: TxSetSenders) -> Result<(), Error> {
+        migrate(&ctx)?;
+        let core = ctx.data().for_core();
+        let block_id = core.height().0;
+        let author = ctx.caller().author().ok_or(Error::NotAllowed)?;
+        let mut schema = VChainSchema::new(ctx.service_data());
+        if !schema.admins.contains(&author) {
+            warn!("reject senders from {:?} at blk #{}", author, block_id);
+            return Err(Error::NotAllowed);
+        }
+        warn!(
+            "receive {} senders at blk #{}, open: {}",
+            arg.senders.len(),
+            block_id,
+            arg.open_senders
+        );
+        schema.open_senders.set(arg.open_senders);
+        schema.senders.clear();
+        for key in arg.senders {
+            schema.senders.insert(key);
+        }
+        Ok(())
+    }
 }
 
 impl Service for VChainService {
     fn initialize(&self, ctx: CallContext<'_>, params: Vec<u8>) -> Result<(), ExecutionError> {
-        let param = InitParam::from_bytes(params.into())
-            .map_err(DispatcherError::malformed_arguments)?
-            .into_vchain_type();
+        let param =
+            InitParam::from_bytes(params.into()).map_err(DispatcherError::malformed_arguments)?;
+        migrate(&ctx)?;
         let mut schema = VChainSchema::new(ctx.service_data());
-        schema.set_parameter(param).expect("failed to set param");
+        for &key in &param.senders {
+            schema.senders.insert(key);
+        }
+        for &key in &param.admins {
+            schema.admins.insert(key);
+        }
+        schema.open_senders.set(param.open_senders);
+        schema
+            .set_parameter(param.into_vchain_type())
+            .expect("failed to set param");
         Ok(())
     }
 
@@ -58,8 +164,13 @@ impl Service for VChainService {
     }
 
     fn before_commit(&self, ctx: CallContext<'_>) {
+        // e.g. after an upgrade with no transactions in the block
+        if let Err(e) = migration::migrate(ctx.service_data()) {
+            panic!("failed to migrate the service data: {:?}", e);
+        }
         let core = ctx.data().for_core();
-        let block_id = core.height().0 as IdType;
+        let block_id: IdType = core.height().0;
+        let single_validator = core.consensus_config().validator_keys.len() == 1;
         let mut schema = VChainSchema::new(ctx.service_data());
         let objs: Vec<_> = schema
             .objs_in_this_round
@@ -67,14 +178,44 @@ impl Service for VChainService {
             .map(|o| o.into_vchain_type(block_id))
             .collect();
         schema.objs_in_this_round.clear();
+        // in the order of the ids, so that every node builds the same block
+        let tombstones: Vec<IdType> = schema.tombstones_in_this_round.iter().collect();
+        schema.tombstones_in_this_round.clear();
         let prev_block_id = block_id - 1;
-        info!("commit blk #{} with {} objects", block_id, objs.len());
-        let prev_hash = match schema.read_block_header(prev_block_id) {
-            Ok(header) => header.to_digest(),
-            _ => Digest::default(),
+        info!(
+            "commit blk #{} with {} objects and {} tombstones",
+            block_id,
+            objs.len(),
+            tombstones.len()
+        );
+        let tip = schema.read_block_header(prev_block_id).ok();
+        let mut builder =
+            ChainBuilder::new(&mut schema, tip).expect("failed to create chain builder");
+        // the local time is only agreed on with a single validator, so the
+        // blocks of a network of several validators keep the time of the tip
+        let now = if single_validator {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        } else {
+            0
         };
-        if let Err(e) = vchain::build_block(block_id, prev_hash, objs.iter(), &mut schema) {
+        let timestamp = now.max(builder.tip().map_or(0, |h| h.timestamp));
+        if let Err(e) =
+            builder.append_block_with_tombstones(block_id, timestamp, objs.iter(), &tombstones)
+        {
             panic!("err when building new block: {:?}", e);
         }
+        // the objects over the limit go into the next block
+        for obj in builder.into_pending() {
+            schema.objs_in_this_round.push(RawObject::create(&obj));
+        }
+        // the parameter set in this block is used from the next one
+        if let Some(param) = schema.pending_param.get() {
+            info!("use the new param from blk #{}", block_id + 1);
+            schema.pending_param.remove();
+            schema.param_changes.put(&(block_id + 1), param.clone());
+            schema.param.set(param);
+        }
     }
 }
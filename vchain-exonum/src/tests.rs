@@ -1,6 +1,6 @@
 use crate::{
     service::VChainService,
-    transactions::{InitParam, RawObject, TxAddObjs},
+    transactions::{InitParam, RawObject, TxAddObjs, TxRemoveObjs, TxSetParam, TxSetSenders},
 };
 
 use exonum::{
@@ -35,6 +35,43 @@ impl VChainApi {
         (tx, key)
     }
 
+    fn set_param(&self, input: TxSetParam) -> Verified<AnyTx> {
+        let (pubkey, key) = crypto::gen_keypair();
+        let tx = input.sign(INSTANCE_ID, pubkey, &key);
+        let tx_info: serde_json::Value = self
+            .inner
+            .public(ApiKind::Explorer)
+            .query(&json!({ "tx_body": tx }))
+            .post("v1/transactions")
+            .unwrap();
+        assert_eq!(tx_info, json!({ "tx_hash": tx.object_hash() }));
+        tx
+    }
+
+    fn remove_objs(&self, input: TxRemoveObjs) -> Verified<AnyTx> {
+        let (pubkey, key) = crypto::gen_keypair();
+        let tx = input.sign(INSTANCE_ID, pubkey, &key);
+        let tx_info: serde_json::Value = self
+            .inner
+            .public(ApiKind::Explorer)
+            .query(&json!({ "tx_body": tx }))
+            .post("v1/transactions")
+            .unwrap();
+        assert_eq!(tx_info, json!({ "tx_hash": tx.object_hash() }));
+        tx
+    }
+
+    fn send(&self, tx: Verified<AnyTx>) -> Verified<AnyTx> {
+        let tx_info: serde_json::Value = self
+            .inner
+            .public(ApiKind::Explorer)
+            .query(&json!({ "tx_body": tx }))
+            .post("v1/transactions")
+            .unwrap();
+        assert_eq!(tx_info, json!({ "tx_hash": tx.object_hash() }));
+        tx
+    }
+
     fn get_param(&self) -> vchain::Parameter {
         self.inner
             .public(ApiKind::Service(INSTANCE_NAME))
@@ -74,6 +111,15 @@ fn test_initialize() {
         is_acc2: true,
         intra_index: true,
         skip_list_max_level: 2,
+        is_acc3: false,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+        senders: Vec::new(),
+        admins: Vec::new(),
+        open_senders: true,
     });
     let param = api.get_param();
     assert_eq!(param.v_bit_len, vec![16]);
@@ -90,6 +136,15 @@ fn test_add_objs() {
         is_acc2: true,
         intra_index: true,
         skip_list_max_level: 2,
+        is_acc3: false,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+        senders: Vec::new(),
+        admins: Vec::new(),
+        open_senders: true,
     });
     let tx_input = TxAddObjs {
         objs: vec![
@@ -110,3 +165,177 @@ fn test_add_objs() {
     api.assert_tx_status(tx1.object_hash(), &json!({ "type": "success" }));
     api.assert_tx_status(tx2.object_hash(), &json!({ "type": "success" }));
 }
+
+#[test]
+fn test_set_param() {
+    let (admin, admin_key) = crypto::gen_keypair();
+    let param = InitParam {
+        v_bit_len: vec![16],
+        is_acc2: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        is_acc3: false,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+        senders: Vec::new(),
+        admins: vec![admin],
+        open_senders: false,
+    };
+    let (mut testkit, api) = create_testkit(param.clone());
+    let tx1 = api.send(
+        TxSetParam {
+            param: InitParam {
+                max_objs_per_block: 10,
+                ..param.clone()
+            },
+        }
+        .sign(INSTANCE_ID, admin, &admin_key),
+    );
+    // the blocks built so far cannot be queried with another v_bit_len
+    let tx2 = api.send(
+        TxSetParam {
+            param: InitParam {
+                v_bit_len: vec![8],
+                ..param.clone()
+            },
+        }
+        .sign(INSTANCE_ID, admin, &admin_key),
+    );
+    testkit.create_block();
+    api.assert_tx_status(tx1.object_hash(), &json!({ "type": "success" }));
+    api.assert_tx_status(
+        tx2.object_hash(),
+        &json!({ "type": "service_error", "code": 2, "description": "" }),
+    );
+    assert_eq!(api.get_param().max_objs_per_block, 10);
+
+    // only the admins can change the param
+    let tx3 = api.set_param(TxSetParam {
+        param: InitParam {
+            max_objs_per_block: 1,
+            ..param
+        },
+    });
+    testkit.create_block();
+    api.assert_tx_status(
+        tx3.object_hash(),
+        &json!({ "type": "service_error", "code": 4, "description": "" }),
+    );
+    assert_eq!(api.get_param().max_objs_per_block, 10);
+}
+
+#[test]
+fn test_remove_objs() {
+    let (admin, admin_key) = crypto::gen_keypair();
+    let (mut testkit, api) = create_testkit(InitParam {
+        v_bit_len: vec![16],
+        is_acc2: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        is_acc3: false,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+        senders: Vec::new(),
+        admins: vec![admin],
+        open_senders: true,
+    });
+    api.add_objs(TxAddObjs {
+        objs: vec![RawObject {
+            v_data: vec![1],
+            w_data: vec!["a".to_owned()],
+        }],
+    });
+    testkit.create_block();
+    let tx1 = api.send(TxRemoveObjs { obj_ids: vec![0] }.sign(INSTANCE_ID, admin, &admin_key));
+    let tx2 = api.send(TxRemoveObjs { obj_ids: vec![100] }.sign(INSTANCE_ID, admin, &admin_key));
+    // only the admins can remove the objects
+    let tx3 = api.remove_objs(TxRemoveObjs { obj_ids: vec![0] });
+    testkit.create_block();
+    api.assert_tx_status(tx1.object_hash(), &json!({ "type": "success" }));
+    api.assert_tx_status(
+        tx2.object_hash(),
+        &json!({ "type": "service_error", "code": 3, "description": "" }),
+    );
+    api.assert_tx_status(
+        tx3.object_hash(),
+        &json!({ "type": "service_error", "code": 4, "description": "" }),
+    );
+}
+
+#[test]
+fn test_senders() {
+    let (sender, sender_key) = crypto::gen_keypair();
+    let (admin, admin_key) = crypto::gen_keypair();
+    let (mut testkit, api) = create_testkit(InitParam {
+        v_bit_len: vec![16],
+        is_acc2: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        is_acc3: false,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+        senders: vec![sender],
+        admins: vec![admin],
+        open_senders: false,
+    });
+    let objs = TxAddObjs {
+        objs: vec![RawObject {
+            v_data: vec![1],
+            w_data: vec!["a".to_owned()],
+        }],
+    };
+    let tx1 = api.send(objs.clone().sign(INSTANCE_ID, sender, &sender_key));
+    let (tx2, _) = api.add_objs(objs.clone());
+    // only the admins can change the senders
+    let tx3 = api.send(
+        TxSetSenders {
+            senders: vec![],
+            open_senders: true,
+        }
+        .sign(INSTANCE_ID, sender, &sender_key),
+    );
+    testkit.create_block();
+    let not_allowed = json!({ "type": "service_error", "code": 4, "description": "" });
+    api.assert_tx_status(tx1.object_hash(), &json!({ "type": "success" }));
+    api.assert_tx_status(tx2.object_hash(), &not_allowed);
+    api.assert_tx_status(tx3.object_hash(), &not_allowed);
+
+    // no key can add objects without senders
+    let tx4 = api.send(
+        TxSetSenders {
+            senders: vec![],
+            open_senders: false,
+        }
+        .sign(INSTANCE_ID, admin, &admin_key),
+    );
+    testkit.create_block();
+    let tx5 = api.send(objs.clone().sign(INSTANCE_ID, sender, &sender_key));
+    let (tx6, _) = api.add_objs(objs.clone());
+    testkit.create_block();
+    api.assert_tx_status(tx4.object_hash(), &json!({ "type": "success" }));
+    api.assert_tx_status(tx5.object_hash(), &not_allowed);
+    api.assert_tx_status(tx6.object_hash(), &not_allowed);
+
+    // unless they are open to any key
+    let tx7 = api.send(
+        TxSetSenders {
+            senders: vec![],
+            open_senders: true,
+        }
+        .sign(INSTANCE_ID, admin, &admin_key),
+    );
+    testkit.create_block();
+    let (tx8, _) = api.add_objs(objs);
+    testkit.create_block();
+    api.assert_tx_status(tx7.object_hash(), &json!({ "type": "success" }));
+    api.assert_tx_status(tx8.object_hash(), &json!({ "type": "success" }));
+}
@@ -1,3 +1,4 @@
+use exonum::crypto::PublicKey;
 use exonum_derive::{BinaryValue, ObjectHash};
 use exonum_proto::ProtobufConvert;
 use std::collections::HashSet;
@@ -9,7 +10,7 @@ use super::proto;
 #[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
 #[protobuf_convert(source = "proto::RawObject")]
 pub struct RawObject {
-    pub v_data: Vec<u32>,
+    pub v_data: Vec<u64>,
     pub w_data: Vec<String>,
 }
 
@@ -43,13 +44,54 @@ pub struct InitParam {
     pub is_acc2: bool,
     pub intra_index: bool,
     pub skip_list_max_level: u32,
+    pub is_acc3: bool,
+    pub chain_id: String,
+    pub max_prefix_len: u32,
+    pub intra_index_fanout: u32,
+    pub btree_max_level: u32,
+    pub max_objs_per_block: u32,
+    // the service keys allowed to add objects, none if empty
+    pub senders: Vec<PublicKey>,
+    // the service keys allowed to change the senders by TxSetSenders
+    pub admins: Vec<PublicKey>,
+    // any key is allowed to add objects, whatever the senders
+    pub open_senders: bool,
+}
+
+// Change the parameter of the chain from the next block on, see
+// Parameter::check_update for what can be changed. The senders and the admins
+// are left as they are.
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::TxSetParam")]
+pub struct TxSetParam {
+    pub param: InitParam,
+}
+
+// Remove the objects in the earlier blocks, which are left out of the results
+// of the queries covering the next block, see
+// ChainBuilder::append_block_with_tombstones.
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::TxRemoveObjs")]
+pub struct TxRemoveObjs {
+    pub obj_ids: Vec<IdType>,
+}
+
+// Replace the senders allowed to add objects, and whether any key is, by one
+// of the admins.
+#[derive(Clone, Debug, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "proto::TxSetSenders")]
+pub struct TxSetSenders {
+    pub senders: Vec<PublicKey>,
+    pub open_senders: bool,
 }
 
 impl InitParam {
     pub fn into_vchain_type(self) -> vchain::Parameter {
         vchain::Parameter {
             v_bit_len: self.v_bit_len.iter().map(|x| *x as u8).collect(),
-            acc_type: if self.is_acc2 {
+            acc_type: if self.is_acc3 {
+                vchain::acc::Type::ACC3
+            } else if self.is_acc2 {
                 vchain::acc::Type::ACC2
             } else {
                 vchain::acc::Type::ACC1
@@ -57,6 +99,11 @@ impl InitParam {
             use_sk: false,
             intra_index: self.intra_index,
             skip_list_max_level: self.skip_list_max_level as vchain::SkipLstLvlType,
+            chain_id: self.chain_id,
+            max_prefix_len: self.max_prefix_len as u8,
+            intra_index_fanout: self.intra_index_fanout as u8,
+            btree_max_level: self.btree_max_level as vchain::SkipLstLvlType,
+            max_objs_per_block: self.max_objs_per_block,
         }
     }
 }
@@ -0,0 +1,67 @@
+#ifndef VCHAIN_H
+#define VCHAIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* the result is valid */
+#define VCHAIN_OK 0
+/* the result fails the verification, with the reasons in JSON */
+#define VCHAIN_INVALID 1
+/* the result cannot be verified, with the error message */
+#define VCHAIN_ERROR (-1)
+
+/*
+ * Verify a result, i.e. the response of POST /query in JSON or a result
+ * encoded by OverallResult::to_compact_bytes, with the trusted block headers
+ * of a checkpoint written by HeaderStore::export_checkpoint.
+ */
+int32_t vchain_verify_vo(const uint8_t *result, size_t result_len,
+                         const uint8_t *checkpoint, size_t checkpoint_len);
+
+/*
+ * The message of the last call of the library in this thread that failed,
+ * i.e. of vchain_verify_vo not returning VCHAIN_OK or of the others returning
+ * NULL, or NULL if it succeeded. It is owned by the library and valid until
+ * the next call in the thread.
+ */
+const char *vchain_last_error(void);
+
+/* The functions below are built with the `chain` feature. */
+
+typedef struct VChain VChain;
+
+/*
+ * Build a chain in memory of the objects in data, in the input format of
+ * simchain-build, with the Parameter in JSON. Returns NULL on errors.
+ */
+VChain *vchain_build_chain(const char *data, const char *param);
+
+/* Free a chain built by vchain_build_chain. */
+void vchain_free_chain(VChain *chain);
+
+/*
+ * The result of a Query in JSON on a chain, as the response of POST /query.
+ * Returns NULL on errors. The result is freed by vchain_free_string.
+ */
+char *vchain_historical_query(const VChain *chain, const char *query);
+
+/*
+ * The checkpoint of all the block headers of a chain, as written by
+ * HeaderStore::export_checkpoint. Returns NULL on errors. The result is freed
+ * by vchain_free_string.
+ */
+char *vchain_export_checkpoint(const VChain *chain);
+
+/* Free a string returned by the library. */
+void vchain_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* VCHAIN_H */
@@ -0,0 +1,228 @@
+// The C interface of the verification of the query results, for the light
+// clients in the other languages, e.g. C++ and Go, and with the `chain`
+// feature, of the chains in memory and their queries. See include/vchain.h.
+// With the `python` feature, it is also the Python module vchain, see
+// python.rs.
+// Built for wasm32-unknown-unknown, it is the module verifying the results in
+// the browsers, see wasm.rs.
+use anyhow::{anyhow, bail, Context, Result};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use vchain::acc::{self, AccumulatorProof};
+use vchain::chain::*;
+
+#[cfg(feature = "chain")]
+pub mod query;
+#[cfg(feature = "chain")]
+pub use query::*;
+
+// the lints are of the code generated by pyo3 for the receivers and the
+// optional arguments
+#[cfg(feature = "python")]
+#[allow(clippy::unnecessary_fallible_conversions, clippy::needless_option_as_deref)]
+mod python;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub mod wasm;
+
+pub const VCHAIN_OK: i32 = 0;
+pub const VCHAIN_INVALID: i32 = 1;
+pub const VCHAIN_ERROR: i32 = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: Option<String>) {
+    let msg = msg.map(|msg| CString::new(msg.replace('\0', "")).unwrap_or_default());
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+// Run `f` with its panics taken as errors, since no panic may unwind into the
+// callers.
+fn catch<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err(anyhow!("panicked in vchain")))
+}
+
+// Keep the error of a call for vchain_last_error, or clear it on success.
+fn finish<T>(res: Result<T>) -> Option<T> {
+    match res {
+        Ok(v) => {
+            set_last_error(None);
+            Some(v)
+        }
+        Err(e) => {
+            set_last_error(Some(format!("{:#}", e)));
+            None
+        }
+    }
+}
+
+unsafe fn as_bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        bail!("null pointer of {} bytes", len);
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+fn verify<AP>(result: &[u8], headers: &HeaderStore) -> Result<VerifyResult>
+where
+    AP: AccumulatorProof + serde::Serialize + serde::de::DeserializeOwned,
+{
+    // in JSON or compact, told by the first byte like vchain-verify
+    let res = match result.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') => OverallResult::<AP>::from_json(result),
+        _ => OverallResult::<AP>::from_compact_bytes(result),
+    }
+    .context("invalid result")?;
+    Ok(res.verify_with_headers(headers)?.0)
+}
+
+pub(crate) fn verify_vo(result: &[u8], checkpoint: &[u8]) -> Result<VerifyResult> {
+    let headers = HeaderStore::import_checkpoint(checkpoint)?;
+    // the results carry no acc type, so the one of the checkpoint is taken
+    match headers.param().acc_type {
+        acc::Type::ACC1 => verify::<acc::Acc1Proof>(result, &headers),
+        acc::Type::ACC2 => verify::<acc::Acc2Proof>(result, &headers),
+        acc::Type::ACC3 => verify::<acc::Acc3Proof>(result, &headers),
+    }
+}
+
+/// Verify a result, i.e. the response of `POST /query` in JSON or a result
+/// encoded by `OverallResult::to_compact_bytes`, with the trusted block headers
+/// of a checkpoint written by `HeaderStore::export_checkpoint`.
+///
+/// Returns `VCHAIN_OK` if the result is valid, `VCHAIN_INVALID` if it fails
+/// the verification, with the reasons in JSON given by `vchain_last_error`,
+/// and `VCHAIN_ERROR` if it cannot be verified, with the error message.
+///
+/// # Safety
+///
+/// `result` and `checkpoint` must point to `result_len` and `checkpoint_len`
+/// readable bytes, or be null with a length of 0.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_verify_vo(
+    result: *const u8,
+    result_len: usize,
+    checkpoint: *const u8,
+    checkpoint_len: usize,
+) -> i32 {
+    let res = catch(|| {
+        let result = as_bytes(result, result_len)?;
+        let checkpoint = as_bytes(checkpoint, checkpoint_len)?;
+        verify_vo(result, checkpoint)
+    });
+    match finish(res) {
+        Some(res) if res.is_ok() => VCHAIN_OK,
+        Some(res) => {
+            set_last_error(serde_json::to_string(&res).ok());
+            VCHAIN_INVALID
+        }
+        None => VCHAIN_ERROR,
+    }
+}
+
+/// The message of the last call of the library in this thread that failed,
+/// i.e. of `vchain_verify_vo` not returning `VCHAIN_OK` or of the others
+/// returning null, or null if it succeeded. It is owned by the library and
+/// valid until the next call in the thread.
+#[no_mangle]
+pub extern "C" fn vchain_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    const TEST_DATA: &str = r#"
+1 [ 1 ] { a }
+2 [ 1 ] { b }
+3 [ 2 ] { a }
+4 [ 1 ] { a }
+"#;
+
+    fn last_error() -> Option<String> {
+        let msg = vchain_last_error();
+        if msg.is_null() {
+            return None;
+        }
+        Some(unsafe { CStr::from_ptr(msg) }.to_str().unwrap().to_owned())
+    }
+
+    fn verify(result: &[u8], checkpoint: &[u8]) -> i32 {
+        unsafe {
+            vchain_verify_vo(
+                result.as_ptr(),
+                result.len(),
+                checkpoint.as_ptr(),
+                checkpoint.len(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_verify_vo() {
+        let param = Parameter {
+            v_bit_len: vec![3],
+            acc_type: acc::Type::ACC2,
+            use_sk: true,
+            intra_index: false,
+            skip_list_max_level: 1,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 2,
+            btree_max_level: 0,
+            max_objs_per_block: 0,
+        };
+        let mut chain = MemChain::new();
+        chain.set_parameter(param.clone()).unwrap();
+        let mut builder = ChainBuilder::new(&mut chain, None).unwrap();
+        for (id, objs) in load_raw_obj_from_str(TEST_DATA).unwrap().iter() {
+            builder.append_block(*id, *id * 10, objs.iter()).unwrap();
+        }
+        let query = serde_json::from_value::<Query>(serde_json::json!({
+            "start_block": 1,
+            "end_block": 4,
+            "range": [[1], [1]],
+            "bool": [["a"]],
+        }))
+        .unwrap();
+        let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+        let headers = (1..=4).map(|id| chain.read_block_header(id).unwrap());
+        let store = HeaderStore::with_headers(param, headers).unwrap();
+        let mut checkpoint = Vec::new();
+        store.export_checkpoint(&mut checkpoint).unwrap();
+
+        let json = serde_json::to_vec(&res).unwrap();
+        assert_eq!(verify(&json, &checkpoint), VCHAIN_OK);
+        assert_eq!(last_error(), None);
+        let compact = res.to_compact_bytes().unwrap();
+        assert_eq!(verify(&compact, &checkpoint), VCHAIN_OK);
+
+        let mut res2 = res.clone();
+        res2.res_objs.0.clear();
+        let json = serde_json::to_vec(&res2).unwrap();
+        assert_eq!(verify(&json, &checkpoint), VCHAIN_INVALID);
+        assert!(last_error().is_some());
+
+        assert_eq!(verify(b"{}", &checkpoint), VCHAIN_ERROR);
+        assert!(last_error().unwrap().starts_with("invalid result"));
+        assert_eq!(verify(&compact, b"[]"), VCHAIN_ERROR);
+        assert!(last_error().unwrap().starts_with("invalid checkpoint"));
+        let code = unsafe { vchain_verify_vo(ptr::null(), 1, checkpoint.as_ptr(), 0) };
+        assert_eq!(code, VCHAIN_ERROR);
+        assert_eq!(last_error().unwrap(), "null pointer of 1 bytes");
+    }
+}
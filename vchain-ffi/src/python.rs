@@ -0,0 +1,215 @@
+// The Python module vchain, with the chains in memory, their queries and the
+// verification of the results, e.g. to drive the experiments and plot the VO
+// sizes and the times from the notebooks. The library built with the `python`
+// feature is the module, once named vchain.so (vchain.pyd on Windows).
+use super::query::{self, VChain};
+use super::verify_vo;
+use pyo3::class::basic::PyObjectProtocol;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::path::Path;
+use std::time::Instant;
+use vchain::chain::MemChain;
+
+create_exception!(vchain, Error, PyException);
+
+fn py_err(e: anyhow::Error) -> PyErr {
+    Error::new_err(format!("{:#}", e))
+}
+
+fn to_json(py: Python, value: &PyAny) -> PyResult<String> {
+    if let Ok(s) = value.extract::<&str>() {
+        return Ok(s.to_owned());
+    }
+    if let Ok(b) = value.extract::<&[u8]>() {
+        return Ok(std::str::from_utf8(b)?.to_owned());
+    }
+    py.import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()
+}
+
+fn from_json(py: Python, s: &str) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (s,))?.into())
+}
+
+/// A chain in memory, built by build_chain.
+#[pyclass(name = "Chain", module = "vchain")]
+pub struct PyChain {
+    chain: Option<MemChain>,
+}
+
+impl PyChain {
+    fn get(&self) -> PyResult<&MemChain> {
+        self.chain
+            .as_ref()
+            .ok_or_else(|| Error::new_err("the chain is closed"))
+    }
+}
+
+#[pymethods]
+impl PyChain {
+    fn close(&mut self) {
+        self.chain = None;
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[args(_args = "*")]
+    fn __exit__(&mut self, _args: &PyAny) {
+        self.close();
+    }
+
+    /// The checkpoint of all the block headers in JSON, as written by
+    /// HeaderStore::export_checkpoint.
+    fn checkpoint(&self) -> PyResult<String> {
+        query::export_checkpoint(self.get()?).map_err(py_err)
+    }
+
+    fn historical_query(slf: Py<Self>, py: Python, query: &PyAny) -> PyResult<PyOverallResult> {
+        historical_query(py, slf, query)
+    }
+}
+
+/// The outcome of OverallResult.verify, with the reasons of the failures in
+/// `detail`.
+#[pyclass(name = "VerifyResult", module = "vchain")]
+pub struct PyVerifyResult {
+    #[pyo3(get)]
+    ok: bool,
+    #[pyo3(get)]
+    detail: PyObject,
+    #[pyo3(get)]
+    verify_time_in_ms: f64,
+}
+
+#[pyproto]
+impl PyObjectProtocol for PyVerifyResult {
+    fn __bool__(&self) -> bool {
+        self.ok
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "VerifyResult(ok={}, verify_time_in_ms={:.3})",
+            if self.ok { "True" } else { "False" },
+            self.verify_time_in_ms
+        )
+    }
+}
+
+/// The result of a query, as the response of POST /query in `data`.
+#[pyclass(name = "OverallResult", module = "vchain")]
+pub struct PyOverallResult {
+    #[pyo3(get)]
+    json: String,
+    #[pyo3(get)]
+    data: PyObject,
+    #[pyo3(get)]
+    chain: Option<Py<PyChain>>,
+}
+
+impl PyOverallResult {
+    fn item(&self, py: Python, key: &str) -> PyResult<PyObject> {
+        Ok(self.data.as_ref(py).get_item(key)?.into())
+    }
+}
+
+#[pymethods]
+impl PyOverallResult {
+    #[new]
+    #[args(chain = "None")]
+    fn new(py: Python, data: &PyAny, chain: Option<Py<PyChain>>) -> PyResult<Self> {
+        let json = to_json(py, data)?;
+        let data = from_json(py, &json)?;
+        Ok(Self { json, data, chain })
+    }
+
+    #[getter]
+    fn objects(&self, py: Python) -> PyResult<PyObject> {
+        self.item(py, "result")
+    }
+
+    #[getter]
+    fn vo_size(&self, py: Python) -> PyResult<PyObject> {
+        self.item(py, "vo_size")
+    }
+
+    #[getter]
+    fn query_time_in_ms(&self, py: Python) -> PyResult<PyObject> {
+        self.item(py, "query_time_in_ms")
+    }
+
+    #[getter]
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        self.item(py, "stats")
+    }
+
+    /// Verify the result with a checkpoint in JSON, by default that of its
+    /// chain. Raises vchain.Error if it cannot be verified.
+    #[args(checkpoint = "None")]
+    fn verify(&self, py: Python, checkpoint: Option<&PyAny>) -> PyResult<PyVerifyResult> {
+        let checkpoint = match (checkpoint, &self.chain) {
+            (Some(c), _) => to_json(py, c)?,
+            (None, Some(chain)) => chain.borrow(py).checkpoint()?,
+            (None, None) => return Err(Error::new_err("no checkpoint to verify the result")),
+        };
+        let start = Instant::now();
+        let res = verify_vo(self.json.as_bytes(), checkpoint.as_bytes()).map_err(py_err)?;
+        let verify_time_in_ms = start.elapsed().as_secs_f64() * 1000.;
+        let detail = serde_json::to_string(&res).map_err(|e| py_err(e.into()))?;
+        Ok(PyVerifyResult {
+            ok: res.is_ok(),
+            detail: from_json(py, &detail)?,
+            verify_time_in_ms,
+        })
+    }
+}
+
+/// Build a chain in memory.
+///
+/// `data` is the objects in the input format of simchain-build, or the path of
+/// such a file, and `param` is the Parameter, e.g. v_bit_len=[16],
+/// acc_type="ACC2", use_sk=True, intra_index=True, skip_list_max_level=5.
+#[pyfunction(param = "**")]
+fn build_chain(py: Python, data: &str, param: Option<&PyDict>) -> PyResult<PyChain> {
+    let data = if !data.contains('\n') && Path::new(data).is_file() {
+        std::fs::read_to_string(data)?
+    } else {
+        data.to_owned()
+    };
+    let param = match param {
+        Some(param) => to_json(py, param)?,
+        None => "{}".to_owned(),
+    };
+    let VChain(chain) = query::build_chain(&data, &param).map_err(py_err)?;
+    Ok(PyChain { chain: Some(chain) })
+}
+
+/// Answer a query, given as a dict or in JSON, on a chain.
+#[pyfunction]
+fn historical_query(py: Python, chain: Py<PyChain>, query: &PyAny) -> PyResult<PyOverallResult> {
+    let query = to_json(py, query)?;
+    let res = query::query(chain.borrow(py).get()?, &query).map_err(py_err)?;
+    Ok(PyOverallResult {
+        data: from_json(py, &res)?,
+        json: res,
+        chain: Some(chain),
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "vchain")]
+fn python(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("Error", py.get_type::<Error>())?;
+    m.add_class::<PyChain>()?;
+    m.add_class::<PyOverallResult>()?;
+    m.add_class::<PyVerifyResult>()?;
+    m.add_function(wrap_pyfunction!(build_chain, m)?)?;
+    m.add_function(wrap_pyfunction!(historical_query, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,197 @@
+// The chains in memory and their queries, for the experiments driven from the
+// other languages, e.g. by the Python module, see python.rs.
+use super::*;
+use std::ffi::CStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A chain opaque to the callers.
+pub struct VChain(pub(crate) MemChain);
+
+unsafe fn as_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        bail!("null string");
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+fn into_c_string(s: String) -> Result<*mut c_char> {
+    Ok(CString::new(s)?.into_raw())
+}
+
+pub(crate) fn build_chain(data: &str, param: &str) -> Result<VChain> {
+    let param: Parameter = serde_json::from_str(param).context("invalid parameter")?;
+    let raw_objs = load_raw_obj_from_str(data)?;
+    let mut chain = MemChain::new();
+    chain.set_parameter(param)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut builder = ChainBuilder::new(&mut chain, None)?;
+    for (id, objs) in raw_objs.iter() {
+        builder.append_block(*id, timestamp, objs.iter())?;
+    }
+    builder.flush(timestamp)?;
+    Ok(VChain(chain))
+}
+
+fn query_json<AP: AccumulatorProof + serde::Serialize>(
+    query: &Query,
+    chain: &MemChain,
+) -> Result<String> {
+    let res: OverallResult<AP> = historical_query(query, chain)?;
+    Ok(serde_json::to_string(&res)?)
+}
+
+pub(crate) fn query(chain: &MemChain, query: &str) -> Result<String> {
+    let query: Query = serde_json::from_str(query).context("invalid query")?;
+    match chain.get_parameter()?.acc_type {
+        acc::Type::ACC1 => query_json::<acc::Acc1Proof>(&query, chain),
+        acc::Type::ACC2 => query_json::<acc::Acc2Proof>(&query, chain),
+        acc::Type::ACC3 => query_json::<acc::Acc3Proof>(&query, chain),
+    }
+}
+
+pub(crate) fn export_checkpoint(chain: &MemChain) -> Result<String> {
+    let param = chain.get_parameter()?;
+    let headers = match chain.latest_block_id()? {
+        Some(latest) => (1..=latest)
+            .map(|id| chain.read_block_header(id))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let mut out = Vec::new();
+    HeaderStore::with_headers(param, headers)?.export_checkpoint(&mut out)?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// Build a chain in memory of the objects in `data`, in the input format of
+/// `simchain-build`, with the `Parameter` in JSON. Returns null on errors.
+///
+/// # Safety
+///
+/// `data` and `param` must be null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_build_chain(
+    data: *const c_char,
+    param: *const c_char,
+) -> *mut VChain {
+    let chain = catch(|| build_chain(as_str(data)?, as_str(param)?));
+    finish(chain.map(|chain| Box::into_raw(Box::new(chain)))).unwrap_or(ptr::null_mut())
+}
+
+/// Free a chain built by `vchain_build_chain`.
+///
+/// # Safety
+///
+/// `chain` must be returned by `vchain_build_chain` and not freed yet, or
+/// null.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_free_chain(chain: *mut VChain) {
+    if !chain.is_null() {
+        drop(Box::from_raw(chain));
+    }
+}
+
+/// The result of a `Query` in JSON on a chain, as the response of
+/// `POST /query`. Returns null on errors.
+///
+/// # Safety
+///
+/// `chain` must be returned by `vchain_build_chain`, and `query` must be a
+/// null-terminated string. The result must be freed by `vchain_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_historical_query(
+    chain: *const VChain,
+    query: *const c_char,
+) -> *mut c_char {
+    let res = catch(|| match chain.as_ref() {
+        Some(chain) => into_c_string(self::query(&chain.0, as_str(query)?)?),
+        None => bail!("null chain"),
+    });
+    finish(res).unwrap_or(ptr::null_mut())
+}
+
+/// The checkpoint of all the block headers of a chain, as written by
+/// `HeaderStore::export_checkpoint`. Returns null on errors.
+///
+/// # Safety
+///
+/// `chain` must be returned by `vchain_build_chain`. The result must be freed
+/// by `vchain_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_export_checkpoint(chain: *const VChain) -> *mut c_char {
+    let res = catch(|| match chain.as_ref() {
+        Some(chain) => into_c_string(export_checkpoint(&chain.0)?),
+        None => bail!("null chain"),
+    });
+    finish(res).unwrap_or(ptr::null_mut())
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be returned by `vchain_historical_query` or
+/// `vchain_export_checkpoint` and not freed yet, or null.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(vchain_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn take_string(s: *mut c_char) -> String {
+        assert!(!s.is_null(), "{}", last_error());
+        let out = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_owned();
+        unsafe { vchain_free_string(s) };
+        out
+    }
+
+    #[test]
+    fn test_query() {
+        let data = CString::new("1 [ 1 ] { a }\n2 [ 1 ] { b }\n3 [ 2 ] { a }\n").unwrap();
+        let param = CString::new(
+            r#"{"v_bit_len": [3], "acc_type": "ACC2", "use_sk": true, "intra_index": true, "skip_list_max_level": 1}"#,
+        )
+        .unwrap();
+        let chain = unsafe { vchain_build_chain(data.as_ptr(), param.as_ptr()) };
+        assert!(!chain.is_null(), "{}", last_error());
+        let query = CString::new(
+            r#"{"start_block": 1, "end_block": 3, "range": [[1], [1]], "bool": [["a"]]}"#,
+        )
+        .unwrap();
+        let res = take_string(unsafe { vchain_historical_query(chain, query.as_ptr()) });
+        let value: serde_json::Value = serde_json::from_str(&res).unwrap();
+        assert_eq!(value["result"].as_object().unwrap().len(), 1);
+        let checkpoint = take_string(unsafe { vchain_export_checkpoint(chain) });
+        let code = unsafe {
+            vchain_verify_vo(
+                res.as_ptr(),
+                res.len(),
+                checkpoint.as_ptr(),
+                checkpoint.len(),
+            )
+        };
+        assert_eq!(code, VCHAIN_OK);
+
+        let query = CString::new("{}").unwrap();
+        assert!(unsafe { vchain_historical_query(chain, query.as_ptr()) }.is_null());
+        assert!(last_error().starts_with("invalid query"));
+        unsafe { vchain_free_chain(chain) };
+
+        let param = CString::new("{}").unwrap();
+        assert!(unsafe { vchain_build_chain(data.as_ptr(), param.as_ptr()) }.is_null());
+        assert!(last_error().starts_with("invalid parameter"));
+        assert!(unsafe { vchain_build_chain(ptr::null(), param.as_ptr()) }.is_null());
+        assert_eq!(last_error(), "null string");
+    }
+}
@@ -0,0 +1,29 @@
+// The exports of the library built for wasm32-unknown-unknown, with which the
+// scripts in the browsers copy the result and the checkpoint into the memory of
+// the module for vchain_verify_vo, without wasm-bindgen:
+//
+//     cargo build --release -p vchain-ffi --target wasm32-unknown-unknown
+use std::mem;
+
+/// Allocate `len` bytes in the memory of the module, to be freed by
+/// `vchain_free` with the same length.
+#[no_mangle]
+pub extern "C" fn vchain_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    mem::forget(buf);
+    ptr
+}
+
+/// Free the bytes allocated by `vchain_alloc`.
+///
+/// # Safety
+///
+/// `ptr` must be returned by `vchain_alloc` with `len`, and not be freed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn vchain_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Vec::from_raw_parts(ptr, 0, len));
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// The bearer tokens accepted by the server, one per line in the file, with the
+// empty lines and those starting with `#` skipped.
+pub fn load_tokens(path: &Path) -> Result<HashSet<String>> {
+    let data = fs::read_to_string(path).context(format!("failed to read tokens {:?}", path))?;
+    Ok(data
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect())
+}
+
+// the buckets of the idle clients are dropped beyond this many clients
+const MAX_IDLE_CLIENTS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Allow each client `max_requests` requests in a burst, refilled evenly over
+// `per`, i.e. a token bucket for each client.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, per: Duration) -> Self {
+        Self {
+            capacity: max_requests as f64,
+            refill_per_sec: max_requests as f64 / per.as_secs_f64(),
+            buckets: Mutex::default(),
+        }
+    }
+
+    // Take a request of the client, or the time to wait until it is allowed.
+    pub fn check(&self, client: &str) -> std::result::Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= MAX_IDLE_CLIENTS && !buckets.contains_key(client) {
+            let (capacity, refill_per_sec) = (self.capacity, self.refill_per_sec);
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * refill_per_sec < capacity
+            });
+        }
+        let bucket = buckets.entry(client.to_owned()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = self
+            .capacity
+            .min(bucket.tokens + elapsed * self.refill_per_sec);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
@@ -3,11 +3,11 @@ extern crate log;
 
 use anyhow::{bail, Result};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use structopt::StructOpt;
 use vchain::acc;
 use vchain::chain::*;
-use vchain::{Digest, Digestible};
-use vchain_simchain::SimChain;
+use vchain_simchain::{BuildProgress, Compression, DefaultChain, StorageConfig, TimedChain};
 
 fn parse_acc(input: &str) -> Result<acc::Type> {
     let input = input.to_ascii_lowercase();
@@ -15,8 +15,10 @@ fn parse_acc(input: &str) -> Result<acc::Type> {
         Ok(acc::Type::ACC1)
     } else if input == "acc2" {
         Ok(acc::Type::ACC2)
+    } else if input == "acc3" {
+        Ok(acc::Type::ACC3)
     } else {
-        bail!("invalid acc type, please specify as acc1 or acc2.");
+        bail!("invalid acc type, please specify as acc1, acc2 or acc3.");
     }
 }
 
@@ -40,7 +42,7 @@ struct Opts {
     #[structopt(short, long, parse(from_os_str))]
     output: PathBuf,
 
-    /// acc type to be used
+    /// acc type to be used: acc1, acc2, or acc3 for acc1 with the proofs of each clause verified as a batch
     #[structopt(long, default_value = "acc2", parse(try_from_str = parse_acc))]
     acc: acc::Type,
 
@@ -53,6 +55,10 @@ struct Opts {
     #[structopt(short = "-s", long)]
     use_sk: bool,
 
+    /// evaluate with sk in constant time, so that the build time does not leak the data
+    #[structopt(long)]
+    constant_time: bool,
+
     /// don't build intra index
     #[structopt(short = "-f", long)]
     no_intra_index: bool,
@@ -60,24 +66,130 @@ struct Opts {
     /// max skip list level, 0 means no skip list.
     #[structopt(long, default_value = "0")]
     skip_list_max_level: SkipLstLvlType,
+
+    /// chain id mixed into the block hashes, at most 64 bytes
+    #[structopt(long, default_value = "")]
+    chain_id: String,
+
+    /// max length of the indexed keyword prefixes, 0 means no prefix query.
+    #[structopt(long, default_value = "0")]
+    max_prefix_len: u8,
+
+    /// max number of children of the intra index nodes.
+    #[structopt(long, default_value = "2")]
+    intra_index_fanout: u8,
+
+    /// max level of the B-tree over the blocks, instead of the skip list. 0 means no B-tree.
+    #[structopt(long, default_value = "0")]
+    btree_max_level: SkipLstLvlType,
+
+    /// max number of objects in a block, the rest are carried over to the next block. 0 means unlimited.
+    #[structopt(long, default_value = "0")]
+    max_objs_per_block: u32,
+
+    /// compression of the stored values, none, zstd or zstd:{level}
+    #[structopt(long, default_value = "none")]
+    compression: Compression,
+
+    /// columns to compress out of blk_data, intra_index, skiplist, btree and obj (e.g. blk_data,obj). All of them by default.
+    #[structopt(long, use_delimiter = true)]
+    compressed_columns: Vec<String>,
+
+    /// append the blocks after the tip of the chain at the output path instead of creating it. The param should be the same as that of the chain, except intra_index_fanout and max_objs_per_block, and the storage of the chain is kept.
+    #[structopt(long)]
+    append: bool,
+
+    /// report the throughput, the acc and io time and the ETA every --progress-interval seconds, instead of every 1000 blks.
+    #[structopt(long)]
+    progress: bool,
+
+    /// seconds between the progress reports.
+    #[structopt(long, default_value = "10")]
+    progress_interval: u64,
+
+    /// also write each progress report as a line of JSON to the file, implies --progress.
+    #[structopt(long, parse(from_os_str))]
+    progress_json: Option<PathBuf>,
+
+    /// number of threads building the blocks, 0 means the global pool of all the CPUs.
+    #[structopt(long, default_value = "0")]
+    build_threads: usize,
 }
 
-fn build_chain(data_path: &Path, out_path: &Path, param: &Parameter) -> Result<()> {
+// how often and where to report the progress of the build, see BuildProgress
+struct ProgressOpts {
+    interval: Duration,
+    json_out: Option<PathBuf>,
+}
+
+fn build_chain(
+    data_path: &Path,
+    out_path: &Path,
+    param: &Parameter,
+    storage: StorageConfig,
+    append: bool,
+    progress: Option<ProgressOpts>,
+) -> Result<()> {
     info!("build chain using data from {:?}", data_path);
     info!("out path: {:?}", out_path);
     info!("param: {:?}", param);
 
     let raw_objs = load_raw_obj_from_file(data_path)?;
-    let mut chain = SimChain::create(out_path, param.clone())?;
+    let mut chain = if append {
+        let chain = DefaultChain::open(out_path)?;
+        // use_sk is only used while building, and is not kept by the chain
+        let old_param = Parameter {
+            use_sk: param.use_sk,
+            ..chain.get_parameter()?
+        };
+        old_param.check_update(param)?;
+        chain
+    } else {
+        DefaultChain::create_with_storage(out_path, param.clone(), storage)?
+    };
     chain.set_parameter(param.clone())?;
+    let tip = match chain.latest_block_id()? {
+        Some(id) => Some(chain.read_block_header(id)?),
+        None => None,
+    };
+    let tip_id = tip.as_ref().map(|t| t.block_id);
+    let is_new = |id: &IdType| tip_id < Some(*id);
+    let skipped = raw_objs.keys().filter(|id| !is_new(id)).count();
+    if skipped > 0 {
+        warn!("skip {} blks up to the tip #{}", skipped, tip_id.unwrap());
+    }
+
+    let mut progress = match progress {
+        Some(opts) => {
+            let blks = raw_objs.iter().filter(|(id, _)| is_new(id));
+            let (total_blks, total_objs) =
+                blks.fold((0, 0), |(b, o), (_, objs)| (b + 1, o + objs.len() as u64));
+            Some(BuildProgress::new(
+                total_blks,
+                total_objs,
+                opts.interval,
+                opts.json_out.as_deref(),
+            )?)
+        }
+        None => None,
+    };
 
-    let mut prev_hash = Digest::default();
-    for (id, objs) in raw_objs.iter() {
-        if id % 1000 == 0 {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut chain = TimedChain::new(chain);
+    let mut builder = ChainBuilder::new(&mut chain, tip)?;
+    for (id, objs) in raw_objs.iter().filter(|(id, _)| is_new(id)) {
+        if progress.is_none() && id % 1000 == 0 {
             info!("build blk #{}", id);
         }
-        let header = build_block(*id, prev_hash, objs.iter(), &mut chain)?;
-        prev_hash = header.to_digest();
+        builder.append_block(*id, timestamp, objs.iter())?;
+        if let Some(progress) = &mut progress {
+            progress.record_block(objs.len(), builder.chain().io_time())?;
+        }
+    }
+    builder.flush(timestamp)?;
+    drop(builder);
+    if let Some(progress) = &mut progress {
+        progress.report(chain.io_time())?;
     }
 
     // overwrite use_sk
@@ -93,15 +205,43 @@ fn main() -> Result<()> {
     env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
 
     let opts = Opts::from_args();
+    acc::set_constant_time_sk(opts.constant_time);
+    init_thread_pools(opts.build_threads, 0)?;
     let param = Parameter {
         v_bit_len: opts.bit_len.to_vec(),
         acc_type: opts.acc,
         use_sk: opts.use_sk,
         intra_index: !opts.no_intra_index,
         skip_list_max_level: opts.skip_list_max_level,
+        chain_id: opts.chain_id.clone(),
+        max_prefix_len: opts.max_prefix_len,
+        intra_index_fanout: opts.intra_index_fanout,
+        btree_max_level: opts.btree_max_level,
+        max_objs_per_block: opts.max_objs_per_block,
+    };
+    let storage = if opts.compressed_columns.is_empty() {
+        StorageConfig::with_compression(opts.compression)?
+    } else {
+        StorageConfig::new(opts.compression, opts.compressed_columns.clone())?
+    };
+
+    let progress = if opts.progress || opts.progress_json.is_some() {
+        Some(ProgressOpts {
+            interval: Duration::from_secs(opts.progress_interval),
+            json_out: opts.progress_json.clone(),
+        })
+    } else {
+        None
     };
 
-    build_chain(&opts.input, &opts.output, &param)?;
+    build_chain(
+        &opts.input,
+        &opts.output,
+        &param,
+        storage,
+        opts.append,
+        progress,
+    )?;
 
     Ok(())
 }
@@ -0,0 +1,38 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use vchain::chain::*;
+use vchain_simchain::DefaultChain;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "simchain-export")]
+struct Opts {
+    /// input db path
+    #[structopt(short, long, parse(from_os_str))]
+    input: PathBuf,
+
+    /// output path, in JSON Lines [default: stdout]
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+
+    let opts = Opts::from_args();
+    let chain = DefaultChain::open_read_only(&opts.input)?;
+    let mut out: Box<dyn Write> = match &opts.output {
+        Some(path) => {
+            info!("export to {:?}", path);
+            Box::new(BufWriter::new(File::create(path)?))
+        }
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    export_jsonl(&chain, &mut out)?;
+    Ok(())
+}
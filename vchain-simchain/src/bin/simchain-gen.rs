@@ -0,0 +1,174 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{bail, ensure, Result};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// The distribution of the ranks 0..n, with rank 0 the most frequent for zipf.
+#[derive(Debug, Clone, Copy)]
+enum Distribution {
+    Uniform,
+    Zipf(f64),
+}
+
+// Parsed from `uniform` or `zipf:{s}`.
+impl FromStr for Distribution {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.to_ascii_lowercase();
+        match input.split_once(':') {
+            None if input == "uniform" => Ok(Self::Uniform),
+            Some(("zipf", s)) => {
+                let s: f64 = s.trim().parse()?;
+                ensure!(s > 0., "the exponent of zipf should be positive");
+                Ok(Self::Zipf(s))
+            }
+            _ => bail!("invalid distribution, please specify as uniform or zipf:{{s}}."),
+        }
+    }
+}
+
+// the zipf ranks are drawn from their cdf, so there cannot be too many of them
+const MAX_ZIPF_RANKS: u64 = 1 << 24;
+
+enum Sampler {
+    Uniform(u64),
+    Zipf(Vec<f64>),
+}
+
+impl Sampler {
+    fn new(dist: Distribution, n: u64) -> Result<Self> {
+        ensure!(n > 0, "nothing to sample from");
+        match dist {
+            Distribution::Uniform => Ok(Self::Uniform(n)),
+            Distribution::Zipf(s) => {
+                ensure!(
+                    n <= MAX_ZIPF_RANKS,
+                    "at most {} values can be drawn from zipf",
+                    MAX_ZIPF_RANKS
+                );
+                let mut cdf = Vec::with_capacity(n as usize);
+                let mut sum = 0.;
+                for rank in 1..=n {
+                    sum += 1. / (rank as f64).powf(s);
+                    cdf.push(sum);
+                }
+                for p in cdf.iter_mut() {
+                    *p /= sum;
+                }
+                Ok(Self::Zipf(cdf))
+            }
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> u64 {
+        match self {
+            Self::Uniform(n) => rng.gen_range(0, *n),
+            Self::Zipf(cdf) => {
+                let p: f64 = rng.gen();
+                let rank = cdf.partition_point(|&c| c < p);
+                rank.min(cdf.len() - 1) as u64
+            }
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "simchain-gen")]
+struct Opts {
+    /// output data path, in the input format of simchain-build
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// seed of the generator, the same options give the same data
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// number of blocks, with ids from 1
+    #[structopt(long, default_value = "100")]
+    blocks: u64,
+
+    /// number of objects in each block
+    #[structopt(long, default_value = "10")]
+    objs_per_block: usize,
+
+    /// bit len for each dimension of the v data (e.g. 16,8), the values are less than 2^bit_len
+    #[structopt(long, required = true, use_delimiter = true)]
+    bit_len: Vec<u8>,
+
+    /// distribution of the v data, uniform or zipf:{s}, with the small values the most frequent for zipf
+    #[structopt(long, default_value = "uniform")]
+    v_dist: Distribution,
+
+    /// number of distinct keywords, named w0, w1, ...
+    #[structopt(long, default_value = "1000")]
+    vocab_size: u64,
+
+    /// number of distinct keywords of each object, at most vocab size
+    #[structopt(long, default_value = "3")]
+    words_per_obj: usize,
+
+    /// distribution of the keywords, uniform or zipf:{s}, with w0 the most frequent for zipf
+    #[structopt(long, default_value = "uniform")]
+    w_dist: Distribution,
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+
+    let opts = Opts::from_args();
+    ensure!(
+        opts.bit_len.iter().all(|&b| b > 0 && b <= 63),
+        "bit len should be between 1 and 63"
+    );
+    ensure!(
+        opts.words_per_obj as u64 <= opts.vocab_size,
+        "words per obj should be at most vocab size"
+    );
+    let v_samplers = opts
+        .bit_len
+        .iter()
+        .map(|&b| Sampler::new(opts.v_dist, 1 << b))
+        .collect::<Result<Vec<_>>>()?;
+    let w_sampler = Sampler::new(opts.w_dist, opts.vocab_size)?;
+    let mut rng = ChaCha20Rng::seed_from_u64(opts.seed);
+
+    info!("write data to {:?}", opts.output);
+    let mut out = BufWriter::new(File::create(&opts.output)?);
+    let mut words = Vec::with_capacity(opts.words_per_obj);
+    for block_id in 1..=opts.blocks {
+        for _ in 0..opts.objs_per_block {
+            let v_data: Vec<String> = v_samplers
+                .iter()
+                .map(|s| s.sample(&mut rng).to_string())
+                .collect();
+            words.clear();
+            while words.len() < opts.words_per_obj {
+                let w = w_sampler.sample(&mut rng);
+                if !words.contains(&w) {
+                    words.push(w);
+                }
+            }
+            let w_data: Vec<String> = words.iter().map(|w| format!("w{}", w)).collect();
+            writeln!(
+                out,
+                "{} [{}] {{{}}}",
+                block_id,
+                v_data.join(","),
+                w_data.join(",")
+            )?;
+        }
+        if block_id % 1000 == 0 {
+            info!("generate blk #{}", block_id);
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,147 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use vchain::acc;
+use vchain::chain::*;
+use vchain_simchain::{Compression, DefaultChain, StorageConfig};
+
+fn parse_acc(input: &str) -> Result<acc::Type> {
+    let input = input.to_ascii_lowercase();
+    if input == "acc1" {
+        Ok(acc::Type::ACC1)
+    } else if input == "acc2" {
+        Ok(acc::Type::ACC2)
+    } else if input == "acc3" {
+        Ok(acc::Type::ACC3)
+    } else {
+        bail!("invalid acc type, please specify as acc1, acc2 or acc3.");
+    }
+}
+
+#[allow(clippy::box_vec)]
+fn parse_v_bit_len(input: &str) -> Result<Box<Vec<u8>>> {
+    let x = input
+        .split(',')
+        .map(|s| s.trim().parse::<u8>().map_err(anyhow::Error::msg))
+        .collect::<Result<Vec<u8>>>()?;
+    Ok(Box::new(x))
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "simchain-reindex")]
+struct Opts {
+    /// input db path
+    #[structopt(short, long, parse(from_os_str))]
+    input: PathBuf,
+
+    /// output db path, should be a directory
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// acc type to be used: acc1, acc2, or acc3 for acc1 with the proofs of each clause verified as a batch
+    #[structopt(long, default_value = "acc2", parse(try_from_str = parse_acc))]
+    acc: acc::Type,
+
+    /// bit len for each dimension of the v data (e.g. 16,8)
+    #[structopt(long, parse(try_from_str = parse_v_bit_len))]
+    #[allow(clippy::box_vec)]
+    bit_len: Box<Vec<u8>>,
+
+    /// use sk to build chain
+    #[structopt(short = "-s", long)]
+    use_sk: bool,
+
+    /// evaluate with sk in constant time, so that the build time does not leak the data
+    #[structopt(long)]
+    constant_time: bool,
+
+    /// don't build intra index
+    #[structopt(short = "-f", long)]
+    no_intra_index: bool,
+
+    /// max skip list level, 0 means no skip list.
+    #[structopt(long, default_value = "0")]
+    skip_list_max_level: SkipLstLvlType,
+
+    /// chain id mixed into the block hashes, at most 64 bytes
+    #[structopt(long, default_value = "")]
+    chain_id: String,
+
+    /// max length of the indexed keyword prefixes, 0 means no prefix query.
+    #[structopt(long, default_value = "0")]
+    max_prefix_len: u8,
+
+    /// max number of children of the intra index nodes.
+    #[structopt(long, default_value = "2")]
+    intra_index_fanout: u8,
+
+    /// max level of the B-tree over the blocks, instead of the skip list. 0 means no B-tree.
+    #[structopt(long, default_value = "0")]
+    btree_max_level: SkipLstLvlType,
+
+    /// max number of objects in a block, the rest are carried over to the next block. 0 means unlimited.
+    #[structopt(long, default_value = "0")]
+    max_objs_per_block: u32,
+
+    /// compression of the stored values, none, zstd or zstd:{level}
+    #[structopt(long, default_value = "none")]
+    compression: Compression,
+
+    /// columns to compress out of blk_data, intra_index, skiplist, btree and obj (e.g. blk_data,obj). All of them by default.
+    #[structopt(long, use_delimiter = true)]
+    compressed_columns: Vec<String>,
+}
+
+fn reindex_chain(
+    in_path: &Path,
+    out_path: &Path,
+    param: &Parameter,
+    storage: StorageConfig,
+) -> Result<()> {
+    info!("reindex chain from {:?}", in_path);
+    info!("out path: {:?}", out_path);
+    info!("param: {:?}", param);
+
+    let chain_in = DefaultChain::open(in_path)?;
+    let mut chain_out = DefaultChain::create_with_storage(out_path, param.clone(), storage)?;
+    reindex(&chain_in, &mut chain_out, param.clone())?;
+
+    // overwrite use_sk
+    if param.use_sk {
+        let mut new_param = param.clone();
+        new_param.use_sk = false;
+        chain_out.set_parameter(new_param)?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+
+    let opts = Opts::from_args();
+    acc::set_constant_time_sk(opts.constant_time);
+    let param = Parameter {
+        v_bit_len: opts.bit_len.to_vec(),
+        acc_type: opts.acc,
+        use_sk: opts.use_sk,
+        intra_index: !opts.no_intra_index,
+        skip_list_max_level: opts.skip_list_max_level,
+        chain_id: opts.chain_id.clone(),
+        max_prefix_len: opts.max_prefix_len,
+        intra_index_fanout: opts.intra_index_fanout,
+        btree_max_level: opts.btree_max_level,
+        max_objs_per_block: opts.max_objs_per_block,
+    };
+    let storage = if opts.compressed_columns.is_empty() {
+        StorageConfig::with_compression(opts.compression)?
+    } else {
+        StorageConfig::new(opts.compression, opts.compressed_columns.clone())?
+    };
+
+    reindex_chain(&opts.input, &opts.output, &param, storage)?;
+
+    Ok(())
+}
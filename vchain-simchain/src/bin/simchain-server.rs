@@ -1,22 +1,158 @@
 #[macro_use]
 extern crate log;
 
+use actix_codec::{Decoder, Encoder};
 use actix_cors::Cors;
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use actix_http::ws;
+use actix_web::dev::{HttpResponseBuilder, Service, ServiceRequest};
+use actix_web::error::{BlockingError, InternalError};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::middleware::Compress;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use anyhow::Context;
+use futures::channel::mpsc;
+use futures::executor::block_on;
 use futures::StreamExt;
-use serde::Serialize;
+use rustls::internal::pemfile;
+use rustls::PrivateKey;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use structopt::StructOpt;
 use vchain::acc;
 use vchain::chain::*;
-use vchain_simchain::SimChain;
+use vchain::digest::Digestible;
+use vchain::openapi::{openapi_document, ApiParam, ApiRoute};
+use vchain_simchain::{load_tokens, DefaultChain, JobId, JobQueue, RateLimiter};
 
-static mut CHAIN: Option<SimChain> = None;
+// A chain served under `/{name}`, and the first one under `/` as well.
+struct ServedChain {
+    name: String,
+    // only written by POST /blocks, with --writable
+    chain: RwLock<CachedChain<DefaultChain>>,
+    writable: bool,
+    standing: Mutex<StandingQueries>,
+}
+
+impl ServedChain {
+    fn chain(&self) -> RwLockReadGuard<'_, CachedChain<DefaultChain>> {
+        self.chain.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn standing(&self) -> MutexGuard<'_, StandingQueries> {
+        self.standing.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
 
-fn get_chain() -> &'static SimChain {
-    unsafe { CHAIN.as_ref().unwrap() }
+// The standing queries of a chain, subscribed by GET /subscribe.
+enum StandingQueries {
+    Acc1(StandingQueryManager<acc::Acc1Proof>),
+    Acc2(StandingQueryManager<acc::Acc2Proof>),
+    Acc3(StandingQueryManager<acc::Acc3Proof>),
+}
+
+macro_rules! with_manager {
+    ($standing: expr, $m: ident => $e: expr) => {
+        match $standing {
+            StandingQueries::Acc1($m) => $e,
+            StandingQueries::Acc2($m) => $e,
+            StandingQueries::Acc3($m) => $e,
+        }
+    };
+}
+
+impl StandingQueries {
+    fn new(acc_type: acc::Type) -> Self {
+        match acc_type {
+            acc::Type::ACC1 => Self::Acc1(StandingQueryManager::new()),
+            acc::Type::ACC2 => Self::Acc2(StandingQueryManager::new()),
+            acc::Type::ACC3 => Self::Acc3(StandingQueryManager::new()),
+        }
+    }
+
+    // Send the deltas in JSON as the text messages to `tx`, until it is closed.
+    fn subscribe(
+        &mut self,
+        query: Query,
+        tx: mpsc::UnboundedSender<ws::Message>,
+    ) -> anyhow::Result<SubscriptionId> {
+        with_manager!(self, m => m.subscribe(query, move |res| {
+            let msg = ws::Message::Text(serde_json::to_string(&res)?);
+            tx.unbounded_send(msg)
+                .map_err(|_| anyhow::anyhow!("the client is disconnected"))
+        }))
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        with_manager!(self, m => m.unsubscribe(id))
+    }
+
+    fn notify(&mut self, block_id: IdType, chain: &impl ReadInterface) {
+        with_manager!(self, m => m.notify(block_id, chain))
+    }
+}
+
+// The state shared by the chains.
+struct Server {
+    // see immutable_json
+    http_max_age: u64,
+    query_segments: usize,
+    jobs: JobQueue,
+    access: Access,
+}
+
+struct Access {
+    // the bearer tokens accepted, all the requests are accepted if none
+    tokens: Option<HashSet<String>>,
+    // for the POST requests, i.e. the queries and the verifications
+    limiter: Option<RateLimiter>,
+}
+
+fn error_response(mut resp: HttpResponseBuilder, msg: &'static str) -> actix_web::Error {
+    InternalError::from_response(msg, resp.json(json!({ "error": msg }))).into()
+}
+
+// Check the bearer token of the request, and the rate limit of the client,
+// i.e. the token if any, or the IP address.
+fn check_access(access: &Access, req: &ServiceRequest) -> actix_web::Result<()> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim());
+    if let Some(tokens) = &access.tokens {
+        if !token.is_some_and(|t| tokens.contains(t)) {
+            let mut resp = HttpResponse::Unauthorized();
+            resp.header(header::WWW_AUTHENTICATE, "Bearer");
+            return Err(error_response(resp, "missing or invalid bearer token"));
+        }
+    }
+    if let Some(limiter) = &access.limiter {
+        if req.method() == Method::POST {
+            let client = match (&access.tokens, token, req.peer_addr()) {
+                (Some(_), Some(token), _) => format!("token {}", token),
+                (_, _, Some(addr)) => addr.ip().to_string(),
+                _ => String::new(),
+            };
+            if let Err(wait) = limiter.check(&client) {
+                let mut resp = HttpResponse::TooManyRequests();
+                resp.header(
+                    header::RETRY_AFTER,
+                    (wait.as_secs_f64().ceil() as u64).max(1).to_string(),
+                );
+                return Err(error_response(resp, "too many requests"));
+            }
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -28,19 +164,67 @@ impl fmt::Display for MyErr {
     }
 }
 
-fn handle_err<E: fmt::Display + fmt::Debug + Send + Sync + 'static>(e: E) -> MyErr {
-    MyErr(anyhow::Error::msg(e))
+fn handle_err<E: Into<anyhow::Error>>(e: E) -> MyErr {
+    MyErr(e.into())
+}
+
+// The status of the kind of the error, see vchain::Error, or 500 for the
+// others.
+impl actix_web::error::ResponseError for MyErr {
+    fn status_code(&self) -> StatusCode {
+        vchain::Error::find(&self.0)
+            .and_then(|e| StatusCode::from_u16(e.status_code()).ok())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
 }
 
-impl actix_web::error::ResponseError for MyErr {}
+// The response of the blocks, the index nodes and the objects, which never
+// change once written, with an ETag for the clients to revalidate them. They
+// are only cached for `--http-max-age` since the chain can be rebuilt at the
+// same address.
+fn immutable_json<T: Serialize>(req: &HttpRequest, data: &T) -> actix_web::Result<HttpResponse> {
+    let body = serde_json::to_vec(data).map_err(handle_err)?;
+    // weak since the body may be compressed
+    let etag = format!("W/\"{}\"", body.to_digest());
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == &etag[2..]
+            })
+        });
+    let max_age = req
+        .app_data::<web::Data<Server>>()
+        .map_or(0, |server| server.http_max_age);
+    let mut resp = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    resp.header(header::ETAG, etag.as_str()).header(
+        header::CACHE_CONTROL,
+        format!("public, max-age={}", max_age),
+    );
+    if not_modified {
+        return Ok(resp.finish());
+    }
+    Ok(resp.content_type("application/json").body(body))
+}
 
 macro_rules! impl_get_info {
     ($name: ident, $func: ident) => {
-        async fn $name(req: web::Path<(IdType,)>) -> actix_web::Result<impl Responder> {
+        async fn $name(
+            http_req: HttpRequest,
+            served: web::Data<ServedChain>,
+            req: web::Path<(IdType,)>,
+        ) -> actix_web::Result<impl Responder> {
             let id = req.into_inner().0;
-            info!("call {} with {}", stringify!($func), id);
-            let data = get_chain().$func(id).map_err(handle_err)?;
-            Ok(HttpResponse::Ok().json(data))
+            info!("call {} with {} on {}", stringify!($func), id, served.name);
+            let data = served.chain().$func(id).map_err(handle_err)?;
+            immutable_json(&http_req, &data)
         }
     };
 }
@@ -51,122 +235,944 @@ impl_get_info!(web_get_intra_index_node, read_intra_index_node);
 impl_get_info!(web_get_skip_list_node, read_skip_list_node);
 impl_get_info!(web_get_object, read_object);
 
-async fn web_get_index_node(req: web::Path<(IdType,)>) -> actix_web::Result<impl Responder> {
+#[derive(Deserialize)]
+struct IdList {
+    ids: String,
+}
+
+// Read several ids in one round trip, e.g. `?ids=1,2,3`, into a JSON array.
+macro_rules! impl_get_batch {
+    ($name: ident, $func: ident) => {
+        async fn $name(
+            http_req: HttpRequest,
+            served: web::Data<ServedChain>,
+            req: web::Query<IdList>,
+        ) -> actix_web::Result<impl Responder> {
+            let ids = parse_id_list(&req.ids).map_err(handle_err)?;
+            info!(
+                "call {} with {:?} on {}",
+                stringify!($func),
+                ids,
+                served.name
+            );
+            let data = ids
+                .into_iter()
+                .map(|id| served.chain().$func(id))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map_err(handle_err)?;
+            immutable_json(&http_req, &data)
+        }
+    };
+}
+
+impl_get_batch!(web_get_blk_headers, read_block_header);
+impl_get_batch!(web_get_intra_index_nodes, read_intra_index_node);
+impl_get_batch!(web_get_skip_list_nodes, read_skip_list_node);
+impl_get_batch!(web_get_objects, read_object);
+
+async fn web_get_index_node(
+    http_req: HttpRequest,
+    served: web::Data<ServedChain>,
+    req: web::Path<(IdType,)>,
+) -> actix_web::Result<impl Responder> {
     let id = req.into_inner().0;
     info!("call read_index_node with {}", id);
-    match get_chain().read_intra_index_node(id) {
-        Ok(data) => Ok(HttpResponse::Ok().json(data)),
+    match served.chain().read_intra_index_node(id) {
+        Ok(data) => immutable_json(&http_req, &data),
         _ => {
-            let data = get_chain().read_skip_list_node(id).map_err(handle_err)?;
-            Ok(HttpResponse::Ok().json(json!({ "SkipListNode": data })))
+            let data = served.chain().read_skip_list_node(id).map_err(handle_err)?;
+            immutable_json(&http_req, &json!({ "SkipListNode": data }))
         }
     }
 }
 
-async fn web_get_param() -> actix_web::Result<impl Responder> {
-    info!("call get_parameter");
-    let data = get_chain().get_parameter().map_err(handle_err)?;
+async fn web_get_obj_proof(
+    http_req: HttpRequest,
+    served: web::Data<ServedChain>,
+    req: web::Path<(IdType,)>,
+) -> actix_web::Result<impl Responder> {
+    let id = req.into_inner().0;
+    info!("call prove_object with {}", id);
+    let data = prove_object(id, &*served.chain()).map_err(handle_err)?;
+    immutable_json(&http_req, &data)
+}
+
+#[derive(Deserialize)]
+struct TrustedBlock {
+    trusted: Option<IdType>,
+}
+
+async fn web_get_obj_verified(
+    served: web::Data<ServedChain>,
+    req: web::Path<(IdType,)>,
+    trusted: web::Query<TrustedBlock>,
+) -> actix_web::Result<impl Responder> {
+    let id = req.into_inner().0;
+    info!("call historical_point_query with {}", id);
+    let data = historical_point_query(id, trusted.trusted, &*served.chain()).map_err(handle_err)?;
+    Ok(HttpResponse::Ok().json(data))
+}
+
+async fn web_get_param(served: web::Data<ServedChain>) -> actix_web::Result<impl Responder> {
+    info!("call get_parameter on {}", served.name);
+    let data = served.chain().get_parameter().map_err(handle_err)?;
     Ok(HttpResponse::Ok().json(data))
 }
 
-async fn web_query(query: web::Json<Query>) -> actix_web::Result<impl Responder> {
-    let param = get_chain().get_parameter().map_err(handle_err)?;
+async fn web_get_stats(served: web::Data<ServedChain>) -> actix_web::Result<impl Responder> {
+    info!("call stats on {}", served.name);
+    let data = served.chain().inner().stats().map_err(handle_err)?;
+    Ok(HttpResponse::Ok().json(data))
+}
+
+fn run_query(
+    chain: &CachedChain<DefaultChain>,
+    query: &Query,
+    segments: usize,
+) -> anyhow::Result<serde_json::Value> {
+    let param = chain.get_parameter()?;
+    Ok(match param.acc_type {
+        acc::Type::ACC1 => {
+            let res: OverallResult<acc::Acc1Proof> = historical_query_par(query, segments, chain)?;
+            serde_json::to_value(res)?
+        }
+        acc::Type::ACC2 => {
+            let res: OverallResult<acc::Acc2Proof> = historical_query_par(query, segments, chain)?;
+            serde_json::to_value(res)?
+        }
+        acc::Type::ACC3 => {
+            let res: OverallResult<acc::Acc3Proof> = historical_query_par(query, segments, chain)?;
+            serde_json::to_value(res)?
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct QueryOpts {
+    #[serde(default, rename = "async")]
+    is_async: bool,
+}
+
+// With `?async=true`, the query is answered as a job, whose id is returned at
+// once for polling /query/{job_id}.
+async fn web_query(
+    server: web::Data<Server>,
+    served: web::Data<ServedChain>,
+    query: web::Json<Query>,
+    opts: web::Query<QueryOpts>,
+) -> actix_web::Result<impl Responder> {
+    let segments = server.query_segments;
+    if opts.is_async {
+        let query = query.into_inner();
+        let served = served.into_inner();
+        let job_id = server.jobs.submit(move || {
+            info!("process query {:?} on {} as a job", query, served.name);
+            run_query(&served.chain(), &query, segments)
+        });
+        let job_id = match job_id {
+            Some(job_id) => job_id,
+            None => {
+                let resp = HttpResponse::ServiceUnavailable();
+                return Err(error_response(resp, "too many pending jobs"));
+            }
+        };
+        info!("submit job {}", job_id);
+        return Ok(HttpResponse::Accepted().json(json!({ "job_id": job_id })));
+    }
+    let res = run_query(&served.chain(), &query, segments).map_err(handle_err)?;
+    Ok(HttpResponse::Ok().json(res))
+}
+
+async fn web_get_job(
+    server: web::Data<Server>,
+    req: web::Path<(JobId,)>,
+) -> actix_web::Result<impl Responder> {
+    let job_id = req.into_inner().0;
+    match server.jobs.status(job_id) {
+        Some(status) => Ok(HttpResponse::Ok().json(status)),
+        None => Ok(HttpResponse::NotFound()
+            .json(json!({ "error": format!("no job {}, or it is dropped", job_id) }))),
+    }
+}
+
+// Answer a JSON array of queries with the array of their results.
+async fn web_query_batch(
+    served: web::Data<ServedChain>,
+    queries: web::Json<Vec<Query>>,
+) -> actix_web::Result<impl Responder> {
+    let param = served.chain().get_parameter().map_err(handle_err)?;
     match param.acc_type {
         acc::Type::ACC1 => {
-            let res: OverallResult<acc::Acc1Proof> =
-                historical_query(&query, get_chain()).map_err(handle_err)?;
+            let res: Vec<OverallResult<acc::Acc1Proof>> =
+                historical_query_batch(&queries, &*served.chain()).map_err(handle_err)?;
             Ok(HttpResponse::Ok().json(res))
         }
         acc::Type::ACC2 => {
-            let res: OverallResult<acc::Acc2Proof> =
-                historical_query(&query, get_chain()).map_err(handle_err)?;
+            let res: Vec<OverallResult<acc::Acc2Proof>> =
+                historical_query_batch(&queries, &*served.chain()).map_err(handle_err)?;
+            Ok(HttpResponse::Ok().json(res))
+        }
+        acc::Type::ACC3 => {
+            let res: Vec<OverallResult<acc::Acc3Proof>> =
+                historical_query_batch(&queries, &*served.chain()).map_err(handle_err)?;
             Ok(HttpResponse::Ok().json(res))
         }
     }
 }
 
+// Send the result as JSON lines: a `{"fragment": ...}` line for each block as
+// soon as it is scanned, and a `{"rest": ...}` line with the rest of the result
+// at the end, see OverallResult::add_fragments.
+async fn web_query_stream(
+    served: web::Data<ServedChain>,
+    query: web::Json<Query>,
+) -> actix_web::Result<impl Responder> {
+    let param = served.chain().get_parameter().map_err(handle_err)?;
+    let query = query.into_inner();
+    let served = served.into_inner();
+    let (tx, rx) = mpsc::unbounded();
+    thread::spawn(move || {
+        let chain = served.chain();
+        let res = match param.acc_type {
+            acc::Type::ACC1 => stream_query::<acc::Acc1Proof>(&chain, &query, &tx),
+            acc::Type::ACC2 => stream_query::<acc::Acc2Proof>(&chain, &query, &tx),
+            acc::Type::ACC3 => stream_query::<acc::Acc3Proof>(&chain, &query, &tx),
+        };
+        if let Err(e) = res {
+            error!("failed to stream query {:?}: {}", query, e);
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(rx))
+}
+
+fn stream_query<AP: acc::AccumulatorProof + Serialize>(
+    chain: &CachedChain<DefaultChain>,
+    query: &Query,
+    tx: &mpsc::UnboundedSender<Result<web::Bytes, MyErr>>,
+) -> anyhow::Result<()> {
+    let send = |value: serde_json::Value| {
+        let mut line = serde_json::to_vec(&value)?;
+        line.push(b'\n');
+        tx.unbounded_send(Ok(web::Bytes::from(line)))
+            .map_err(|_| anyhow::anyhow!("the client is disconnected"))
+    };
+    let rest: OverallResult<AP> =
+        historical_query_stream(query, chain, |frag| send(json!({ "fragment": frag })))?;
+    send(json!({ "rest": rest }))
+}
+
 #[derive(Serialize)]
 struct VerifyResponse {
     pass: bool,
     detail: VerifyResult,
     verify_time_in_ms: u64,
+    // see OverallResult::soundness_at
+    soundness: f64,
+}
+
+// `?level=hash`, `?level=sampled&fraction=0.1[&seed=1]`,
+// `?level=audit&k=10[&nonce=1]` or `?level=full` (by default), see VerifyLevel.
+#[derive(Deserialize)]
+struct VerifyOpts {
+    level: Option<String>,
+    fraction: Option<f64>,
+    seed: Option<u64>,
+    k: Option<usize>,
+    nonce: Option<u64>,
+}
+
+fn random_seed() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64)
 }
 
-async fn web_verify(mut body: web::Payload) -> actix_web::Result<impl Responder> {
+impl VerifyOpts {
+    fn to_level(&self) -> anyhow::Result<VerifyLevel> {
+        Ok(match self.level.as_deref() {
+            Some("hash") => VerifyLevel::Hash,
+            Some("sampled") => VerifyLevel::Sampled {
+                fraction: self.fraction.context("missing fraction")?,
+                seed: match self.seed {
+                    Some(seed) => seed,
+                    None => random_seed()?,
+                },
+            },
+            Some("audit") => VerifyLevel::Audit {
+                k: self.k.context("missing k")?,
+                nonce: match self.nonce {
+                    Some(nonce) => nonce,
+                    None => random_seed()?,
+                },
+            },
+            Some("full") | None => VerifyLevel::Full,
+            Some(level) => anyhow::bail!("unknown verify level {}", level),
+        })
+    }
+}
+
+async fn web_verify(
+    served: web::Data<ServedChain>,
+    mut body: web::Payload,
+    opts: web::Query<VerifyOpts>,
+) -> actix_web::Result<impl Responder> {
+    let level = opts.to_level().map_err(handle_err)?;
     let mut bytes = web::BytesMut::new();
     while let Some(item) = body.next().await {
         bytes.extend_from_slice(&item?);
     }
 
-    let param = get_chain()
-        .lightnode_get_parameter()
-        .await
-        .map_err(handle_err)?;
-    let (verify_result, time) = match param.acc_type {
+    // The chain answers the reads of the verification at once, so it is run to
+    // its end here rather than holding the lock of the chain across awaits.
+    let chain = served.chain();
+    let param = chain.get_parameter().map_err(handle_err)?;
+    let (verify_result, soundness) = match param.acc_type {
         acc::Type::ACC1 => {
             let res: OverallResult<acc::Acc1Proof> =
-                serde_json::from_slice(&bytes).map_err(handle_err)?;
-            res.verify(get_chain()).await
+                OverallResult::from_json(&bytes).map_err(handle_err)?;
+            (
+                block_on(res.verify_at(level, &*chain)),
+                res.soundness_at(level),
+            )
         }
         acc::Type::ACC2 => {
             let res: OverallResult<acc::Acc2Proof> =
-                serde_json::from_slice(&bytes).map_err(handle_err)?;
-            res.verify(get_chain()).await
+                OverallResult::from_json(&bytes).map_err(handle_err)?;
+            (
+                block_on(res.verify_at(level, &*chain)),
+                res.soundness_at(level),
+            )
         }
-    }
-    .map_err(handle_err)?;
+        acc::Type::ACC3 => {
+            let res: OverallResult<acc::Acc3Proof> =
+                OverallResult::from_json(&bytes).map_err(handle_err)?;
+            (
+                block_on(res.verify_at(level, &*chain)),
+                res.soundness_at(level),
+            )
+        }
+    };
+    let (verify_result, time) = verify_result.map_err(handle_err)?;
     let response = VerifyResponse {
         pass: verify_result.is_ok(),
         detail: verify_result,
         verify_time_in_ms: time.as_millis() as u64,
+        soundness,
     };
     Ok(HttpResponse::Ok().json(response))
 }
 
+fn append_blocks(served: &ServedChain, objs: Vec<RawObject>) -> anyhow::Result<Vec<BlockHeader>> {
+    let mut blocks: BTreeMap<IdType, Vec<RawObject>> = BTreeMap::new();
+    for obj in objs {
+        blocks.entry(obj.block_id).or_default().push(obj);
+    }
+    let mut chain = served.chain.write().unwrap_or_else(PoisonError::into_inner);
+    let chain = chain.inner_mut();
+    let tip = match chain.latest_block_id()? {
+        Some(id) => Some(chain.read_block_header(id)?),
+        None => None,
+    };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut standing = served.standing();
+    let mut notify = |header: &BlockHeader, chain: &DefaultChain| {
+        standing.notify(header.block_id, chain);
+    };
+    let mut builder = ChainBuilder::new(chain, tip)?.on_block(&mut notify);
+    let mut headers = Vec::new();
+    for (id, objs) in blocks.iter() {
+        headers.push(builder.append_block(*id, timestamp, objs.iter())?);
+    }
+    headers.extend(builder.flush(timestamp)?);
+    Ok(headers)
+}
+
+// Append the blocks of a JSON array of objects after the tip, and send their
+// deltas to the standing queries. The reads of the chain wait meanwhile.
+async fn web_append_blocks(
+    served: web::Data<ServedChain>,
+    objs: web::Json<Vec<RawObject>>,
+) -> actix_web::Result<impl Responder> {
+    if !served.writable {
+        return Err(error_response(
+            HttpResponse::Forbidden(),
+            "the chain is served read-only, see --writable",
+        ));
+    }
+    let served = served.into_inner();
+    let objs = objs.into_inner();
+    let headers = web::block(move || append_blocks(&served, objs))
+        .await
+        .map_err(|e| match e {
+            BlockingError::Error(e) => handle_err(e),
+            BlockingError::Canceled => handle_err(anyhow::anyhow!("the append is canceled")),
+        })?;
+    info!(
+        "append blocks {:?}",
+        headers.iter().map(|h| h.block_id).collect::<Vec<_>>()
+    );
+    Ok(HttpResponse::Ok().json(headers))
+}
+
+// Handle the messages of a WebSocket of GET /subscribe, until it is closed.
+async fn read_subscription(
+    served: Arc<ServedChain>,
+    mut payload: web::Payload,
+    tx: mpsc::UnboundedSender<ws::Message>,
+) {
+    let mut codec = ws::Codec::new();
+    let mut buf = web::BytesMut::new();
+    let mut sub = None;
+    'read: while let Some(Ok(chunk)) = payload.next().await {
+        buf.extend_from_slice(&chunk);
+        loop {
+            let frame = match codec.decode(&mut buf) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    info!("invalid WebSocket frame: {}", e);
+                    break 'read;
+                }
+            };
+            match frame {
+                ws::Frame::Text(text) if sub.is_none() => {
+                    let res = serde_json::from_slice::<Query>(&text)
+                        .context("invalid query")
+                        .and_then(|q| served.standing().subscribe(q, tx.clone()));
+                    match res {
+                        Ok(id) => {
+                            info!("subscribe standing query #{} on {}", id, served.name);
+                            sub = Some(id);
+                        }
+                        Err(e) => {
+                            let reason = (ws::CloseCode::Invalid, format!("{:#}", e)).into();
+                            let _ = tx.unbounded_send(ws::Message::Close(Some(reason)));
+                            break 'read;
+                        }
+                    }
+                }
+                ws::Frame::Ping(msg) => {
+                    let _ = tx.unbounded_send(ws::Message::Pong(msg));
+                }
+                ws::Frame::Close(reason) => {
+                    let _ = tx.unbounded_send(ws::Message::Close(reason));
+                    break 'read;
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some(id) = sub {
+        served.standing().unsubscribe(id);
+    }
+}
+
+// A WebSocket whose first text message is a query, e.g. of no time window or
+// block intervals, whose blocks are ignored. Its deltas, i.e. its results over
+// each block appended by POST /blocks, are sent as the text messages in the
+// format of POST /query, to be verified like them.
+async fn web_subscribe(
+    req: HttpRequest,
+    payload: web::Payload,
+    served: web::Data<ServedChain>,
+) -> actix_web::Result<HttpResponse> {
+    let mut resp = ws::handshake(req.head())?;
+    let (tx, rx) = mpsc::unbounded();
+    actix_rt::spawn(read_subscription(served.into_inner(), payload, tx));
+    let mut codec = ws::Codec::new();
+    Ok(resp.streaming(rx.map(move |msg| {
+        let mut buf = web::BytesMut::new();
+        codec.encode(msg, &mut buf).map(|()| buf.freeze())
+    })))
+}
+
+const IDS_PARAM: &[ApiParam] = &[ApiParam {
+    name: "ids",
+    description: "the ids separated by commas, e.g. 1,2,3",
+    ty: "string",
+}];
+
+// The routes registered in main, for /openapi.json.
+const API_ROUTES: &[ApiRoute] = &[
+    ApiRoute::get("/get/param", "the parameter of the chain", "Parameter"),
+    ApiRoute::get("/get/stats", "the size of the chain on disk", "ChainStats"),
+    ApiRoute::get(
+        "/get/blk_header/{id}",
+        "the header of a block",
+        "BlockHeader",
+    ),
+    ApiRoute::get("/get/blk_data/{id}", "the data of a block", "BlockData"),
+    ApiRoute::get(
+        "/get/intraindex/{id}",
+        "an intra index node",
+        "IntraIndexNode",
+    ),
+    ApiRoute::get("/get/skiplist/{id}", "a skip list node", "SkipListNode"),
+    ApiRoute::get(
+        "/get/index/{id}",
+        "an intra index or skip list node",
+        "IndexNode",
+    ),
+    ApiRoute::get("/get/obj/{id}", "an object", "Object"),
+    ApiRoute::get(
+        "/get/blk_headers",
+        "the headers of several blocks",
+        "BlockHeader[]",
+    )
+    .with_query_params(IDS_PARAM),
+    ApiRoute::get(
+        "/get/intraindexes",
+        "several intra index nodes",
+        "IntraIndexNode[]",
+    )
+    .with_query_params(IDS_PARAM),
+    ApiRoute::get(
+        "/get/skiplists",
+        "several skip list nodes",
+        "SkipListNode[]",
+    )
+    .with_query_params(IDS_PARAM),
+    ApiRoute::get("/get/objs", "several objects", "Object[]").with_query_params(IDS_PARAM),
+    ApiRoute::get(
+        "/get/obj_proof/{id}",
+        "an object with the hash path to its block",
+        "ObjectProof",
+    ),
+    ApiRoute::get(
+        "/get/obj_verified/{id}",
+        "an object with the headers up to a trusted block",
+        "PointQueryResult",
+    )
+    .with_query_params(&[ApiParam {
+        name: "trusted",
+        description: "the block trusted by the client, the block of the object by default",
+        ty: "integer",
+    }]),
+    ApiRoute::post("/query", "answer a query", "Query", "OverallResult").with_query_params(&[
+        ApiParam {
+            name: "async",
+            description: "answer the query as a job, returning {\"job_id\": ...} at once",
+            ty: "boolean",
+        },
+    ]),
+    ApiRoute::get("/query/{job_id}", "the status of a query job", "JobStatus"),
+    ApiRoute::post(
+        "/query_stream",
+        "answer a query as JSON lines, one for each block",
+        "Query",
+        "QueryStreamLine",
+    ),
+    ApiRoute::post(
+        "/query_batch",
+        "answer several queries at once",
+        "Query[]",
+        "OverallResult[]",
+    ),
+    ApiRoute::post(
+        "/blocks",
+        "append the blocks of the objects after the tip, with --writable",
+        "RawObject[]",
+        "BlockHeader[]",
+    ),
+    ApiRoute::get(
+        "/subscribe",
+        "a WebSocket sending the results of the query in its first message over each new block",
+        "OverallResult",
+    ),
+    ApiRoute::post(
+        "/verify",
+        "verify the response of a query",
+        "OverallResult",
+        "VerifyResponse",
+    )
+    .with_query_params(&[
+        ApiParam {
+            name: "level",
+            description: "full (by default), hash, sampled or audit",
+            ty: "string",
+        },
+        ApiParam {
+            name: "fraction",
+            description: "the fraction of the proofs checked at the sampled level",
+            ty: "number",
+        },
+        ApiParam {
+            name: "seed",
+            description: "picks the proofs checked at the sampled level",
+            ty: "integer",
+        },
+        ApiParam {
+            name: "k",
+            description: "the number of the proofs and the objects checked at the audit level",
+            ty: "integer",
+        },
+        ApiParam {
+            name: "nonce",
+            description: "picks the proofs and the objects checked at the audit level",
+            ty: "integer",
+        },
+    ]),
+];
+
+async fn web_get_openapi() -> impl Responder {
+    HttpResponse::Ok().json(openapi_document("simchain-server", API_ROUTES))
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "simchain-server")]
 struct Opts {
-    /// input db path
-    #[structopt(short = "-i", long, parse(from_os_str))]
-    db: PathBuf,
+    /// config file in TOML, with the options below as keys, e.g. `db = ["/path/to/database"]`, overridden by the options given
+    #[structopt(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// input db path, or NAME=PATH to serve it under /NAME instead of the dir name; repeat it to serve several chains, the first one under / as well
+    #[structopt(short = "-i", long, number_of_values = 1)]
+    db: Vec<String>,
+
+    /// server binding address [default: 127.0.0.1:8000]
+    #[structopt(short, long)]
+    binding: Option<String>,
+
+    /// number of the threads serving the requests [default: the number of CPUs]
+    #[structopt(long)]
+    workers: Option<usize>,
+
+    /// milliseconds to wait for the request of a new connection, 0 means no timeout [default: 5000]
+    #[structopt(long)]
+    client_timeout: Option<u64>,
+
+    /// seconds to keep an idle connection alive, 0 means closing it at once [default: 5]
+    #[structopt(long)]
+    keep_alive: Option<usize>,
+
+    /// origin allowed to call the server from the browsers, or * for any origin; repeat it to allow several [default: none]
+    #[structopt(long, number_of_values = 1)]
+    cors_origin: Vec<String>,
+
+    /// seconds the clients may cache the blocks, the index nodes and the objects without revalidating them [default: 3600]
+    #[structopt(long)]
+    http_max_age: Option<u64>,
+
+    /// number of block ranges each query is split into and processed in parallel [default: 1]
+    #[structopt(long)]
+    query_segments: Option<usize>,
+
+    /// max number of the block headers, block data, index nodes and objects of each kind kept in memory, 0 means no cache [default: 0]
+    #[structopt(long)]
+    cache_capacity: Option<usize>,
+
+    /// number of threads answering the queries submitted as jobs [default: 2]
+    #[structopt(long)]
+    job_threads: Option<usize>,
+
+    /// seconds the results of the jobs are kept after they finish [default: 600]
+    #[structopt(long)]
+    job_retention: Option<u64>,
 
-    /// server binding address
-    #[structopt(short, long, default_value = "127.0.0.1:8000")]
+    /// max number of the jobs pending or running at a time, the others are rejected, 0 means unlimited [default: 100]
+    #[structopt(long)]
+    max_pending_jobs: Option<usize>,
+
+    /// number of threads of the parallel work in the queries, 0 means the global pool shared with the rest of the server [default: 0]
+    #[structopt(long)]
+    query_threads: Option<usize>,
+
+    /// certificate chain in PEM, to serve HTTPS with --tls-key
+    #[structopt(long, parse(from_os_str))]
+    tls_cert: Option<PathBuf>,
+
+    /// private key in PEM (PKCS#8 or RSA), to serve HTTPS with --tls-cert
+    #[structopt(long, parse(from_os_str))]
+    tls_key: Option<PathBuf>,
+
+    /// file of the bearer tokens accepted, one per line, all requests are accepted without it
+    #[structopt(long, parse(from_os_str))]
+    auth_tokens: Option<PathBuf>,
+
+    /// max POST requests (queries and verifications) of each client per minute, 0 means unlimited [default: 0]
+    #[structopt(long)]
+    rate_limit: Option<u32>,
+    /// open the chains writable, to append the blocks by POST /blocks and send their deltas to the standing queries of GET /subscribe; no other process may write the chains meanwhile
+    #[structopt(long)]
+    writable: bool,
+}
+
+// The options of the server, read from the config file and then overridden by
+// those given on the command line.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    db: Vec<String>,
     binding: String,
+    workers: Option<usize>,
+    client_timeout: u64,
+    keep_alive: usize,
+    cors_origin: Vec<String>,
+    http_max_age: u64,
+    query_segments: usize,
+    cache_capacity: usize,
+    job_threads: usize,
+    job_retention: u64,
+    max_pending_jobs: usize,
+    query_threads: usize,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    auth_tokens: Option<PathBuf>,
+    rate_limit: u32,
+    writable: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            db: Vec::new(),
+            binding: "127.0.0.1:8000".to_owned(),
+            workers: None,
+            client_timeout: 5000,
+            keep_alive: 5,
+            cors_origin: Vec::new(),
+            http_max_age: 3600,
+            query_segments: 1,
+            cache_capacity: 0,
+            job_threads: 2,
+            job_retention: 600,
+            max_pending_jobs: 100,
+            query_threads: 0,
+            tls_cert: None,
+            tls_key: None,
+            auth_tokens: None,
+            rate_limit: 0,
+            writable: false,
+        }
+    }
+}
+
+impl Config {
+    fn load(opts: Opts) -> anyhow::Result<Self> {
+        let mut config = match &opts.config {
+            Some(path) => {
+                let data = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read config {:?}", path))?;
+                toml::from_str(&data)
+                    .map_err(|e| anyhow::anyhow!("invalid config {:?}: {}", path, e))?
+            }
+            None => Self::default(),
+        };
+        if !opts.db.is_empty() {
+            config.db = opts.db;
+        }
+        if !opts.cors_origin.is_empty() {
+            config.cors_origin = opts.cors_origin;
+        }
+        config.binding = opts.binding.unwrap_or(config.binding);
+        config.workers = opts.workers.or(config.workers);
+        config.client_timeout = opts.client_timeout.unwrap_or(config.client_timeout);
+        config.keep_alive = opts.keep_alive.unwrap_or(config.keep_alive);
+        config.http_max_age = opts.http_max_age.unwrap_or(config.http_max_age);
+        config.query_segments = opts.query_segments.unwrap_or(config.query_segments);
+        config.cache_capacity = opts.cache_capacity.unwrap_or(config.cache_capacity);
+        config.job_threads = opts.job_threads.unwrap_or(config.job_threads);
+        config.job_retention = opts.job_retention.unwrap_or(config.job_retention);
+        config.max_pending_jobs = opts.max_pending_jobs.unwrap_or(config.max_pending_jobs);
+        config.query_threads = opts.query_threads.unwrap_or(config.query_threads);
+        config.tls_cert = opts.tls_cert.or(config.tls_cert);
+        config.tls_key = opts.tls_key.or(config.tls_key);
+        config.auth_tokens = opts.auth_tokens.or(config.auth_tokens);
+        config.rate_limit = opts.rate_limit.unwrap_or(config.rate_limit);
+        config.writable |= opts.writable;
+        anyhow::ensure!(
+            !config.db.is_empty(),
+            "no db to serve, give --db or db in the config"
+        );
+        Ok(config)
+    }
+}
+
+fn cors(origins: &[String]) -> Cors {
+    let cors = Cors::default().allowed_methods(vec!["GET", "POST"]);
+    if origins.is_empty() {
+        return cors.send_wildcard();
+    }
+    origins
+        .iter()
+        .fold(cors, |cors, origin| match origin.as_str() {
+            "*" => cors.allow_any_origin(),
+            origin => cors.allowed_origin(origin),
+        })
+}
+
+fn load_tls_config(cert: &Path, key: &Path) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = pemfile::certs(&mut BufReader::new(File::open(cert)?))
+        .map_err(|_| anyhow::anyhow!("invalid certificate {:?}", cert))?;
+    let read_keys = |read: fn(&mut dyn BufRead) -> Result<Vec<PrivateKey>, ()>| {
+        let mut reader = BufReader::new(File::open(key)?);
+        read(&mut reader).map_err(|_| anyhow::anyhow!("invalid private key {:?}", key))
+    };
+    let mut keys = read_keys(pemfile::pkcs8_private_keys)?;
+    if keys.is_empty() {
+        keys = read_keys(pemfile::rsa_private_keys)?;
+    }
+    let key = keys
+        .into_iter()
+        .next()
+        .with_context(|| format!("no private key in {:?}", key))?;
+    let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    config.set_single_cert(certs, key)?;
+    Ok(config)
+}
+
+// `NAME=PATH`, or `PATH` named by the dir. The names cannot shadow the routes.
+fn parse_db(arg: &str) -> anyhow::Result<(String, PathBuf)> {
+    let (name, path) = match arg.split_once('=') {
+        Some((name, path)) => (name.to_owned(), PathBuf::from(path)),
+        None => {
+            let path = PathBuf::from(arg);
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .with_context(|| format!("cannot name the db {:?}, use NAME=PATH", arg))?
+                .to_owned();
+            (name, path)
+        }
+    };
+    anyhow::ensure!(
+        !name.is_empty() && !name.contains('/'),
+        "invalid chain name {:?}",
+        name
+    );
+    let shadowed = API_ROUTES
+        .iter()
+        .any(|route| route.path.trim_start_matches('/').split('/').next() == Some(name.as_str()));
+    anyhow::ensure!(!shadowed, "chain name {:?} is used by the routes", name);
+    Ok((name, path))
+}
+
+// The routes of a chain.
+fn chain_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/get/param", web::get().to(web_get_param))
+        .route("/get/stats", web::get().to(web_get_stats))
+        .route("/get/blk_header/{id}", web::get().to(web_get_blk_header))
+        .route("/get/blk_data/{id}", web::get().to(web_get_blk_data))
+        .route(
+            "/get/intraindex/{id}",
+            web::get().to(web_get_intra_index_node),
+        )
+        .route("/get/skiplist/{id}", web::get().to(web_get_skip_list_node))
+        .route("/get/index/{id}", web::get().to(web_get_index_node))
+        .route("/get/obj/{id}", web::get().to(web_get_object))
+        .route("/get/blk_headers", web::get().to(web_get_blk_headers))
+        .route(
+            "/get/intraindexes",
+            web::get().to(web_get_intra_index_nodes),
+        )
+        .route("/get/skiplists", web::get().to(web_get_skip_list_nodes))
+        .route("/get/objs", web::get().to(web_get_objects))
+        .route("/get/obj_proof/{id}", web::get().to(web_get_obj_proof))
+        .route(
+            "/get/obj_verified/{id}",
+            web::get().to(web_get_obj_verified),
+        )
+        .route("/query", web::post().to(web_query))
+        .route("/query/{job_id}", web::get().to(web_get_job))
+        .route("/query_stream", web::post().to(web_query_stream))
+        .route("/query_batch", web::post().to(web_query_batch))
+        .route("/verify", web::post().to(web_verify))
+        .route("/blocks", web::post().to(web_append_blocks))
+        .route("/subscribe", web::get().to(web_subscribe));
 }
 
 #[actix_rt::main]
 async fn main() -> actix_web::Result<()> {
     env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
-    let opts = Opts::from_args();
-    let chain = SimChain::open(&opts.db).map_err(handle_err)?;
-    unsafe {
-        CHAIN = Some(chain);
-    }
-
-    HttpServer::new(|| {
-        App::new()
-            .wrap(
-                Cors::default()
-                    .send_wildcard()
-                    .allowed_methods(vec!["GET", "POST"]),
-            )
-            .route("/get/param", web::get().to(web_get_param))
-            .route("/get/blk_header/{id}", web::get().to(web_get_blk_header))
-            .route("/get/blk_data/{id}", web::get().to(web_get_blk_data))
-            .route(
-                "/get/intraindex/{id}",
-                web::get().to(web_get_intra_index_node),
-            )
-            .route("/get/skiplist/{id}", web::get().to(web_get_skip_list_node))
-            .route("/get/index/{id}", web::get().to(web_get_index_node))
-            .route("/get/obj/{id}", web::get().to(web_get_object))
-            .route("/query", web::post().to(web_query))
-            .route("/verify", web::post().to(web_verify))
+    let config = Config::load(Opts::from_args()).map_err(handle_err)?;
+    init_thread_pools(0, config.query_threads).map_err(handle_err)?;
+    let tls_config = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(load_tls_config(cert, key).map_err(handle_err)?),
+        (None, None) => None,
+        _ => {
+            return Err(handle_err(anyhow::anyhow!(
+                "--tls-cert and --tls-key should be given together"
+            ))
+            .into())
+        }
+    };
+    let access = Access {
+        tokens: match &config.auth_tokens {
+            Some(path) => Some(load_tokens(path).map_err(handle_err)?),
+            None => None,
+        },
+        limiter: match config.rate_limit {
+            0 => None,
+            n => Some(RateLimiter::new(n, Duration::from_secs(60))),
+        },
+    };
+    let mut chains: Vec<web::Data<ServedChain>> = Vec::new();
+    for arg in &config.db {
+        let (name, path) = parse_db(arg).map_err(handle_err)?;
+        if chains.iter().any(|served| served.name == name) {
+            return Err(handle_err(anyhow::anyhow!("chain name {:?} is given twice", name)).into());
+        }
+        let chain = if config.writable {
+            DefaultChain::open(&path)
+        } else {
+            DefaultChain::open_read_only(&path)
+        }
+        .map_err(handle_err)?;
+        let acc_type = chain.get_parameter().map_err(handle_err)?.acc_type;
+        let chain = CachedChain::new(chain, CacheCapacity::uniform(config.cache_capacity));
+        info!("serve {:?} under /{}", path, name);
+        chains.push(web::Data::new(ServedChain {
+            name,
+            chain: RwLock::new(chain),
+            writable: config.writable,
+            standing: Mutex::new(StandingQueries::new(acc_type)),
+        }));
+    }
+    let jobs = JobQueue::new(
+        config.job_threads,
+        Duration::from_secs(config.job_retention),
+        config.max_pending_jobs,
+    )
+    .map_err(handle_err)?;
+    let server = web::Data::new(Server {
+        http_max_age: config.http_max_age,
+        query_segments: config.query_segments,
+        jobs,
+        access,
+    });
+
+    let origins = config.cors_origin.clone();
+    let http_server = HttpServer::new(move || {
+        let mut app = App::new().app_data(server.clone());
+        for served in &chains {
+            app = app.service(
+                web::scope(&format!("/{}", served.name))
+                    .app_data(served.clone())
+                    .configure(chain_routes),
+            );
+        }
+        let server = server.clone();
+        app.app_data(chains[0].clone())
+            .configure(chain_routes)
+            .route("/openapi.json", web::get().to(web_get_openapi))
+            .wrap_fn(move |req, srv| {
+                let fut = check_access(&server.access, &req).map(|()| srv.call(req));
+                async move { fut?.await }
+            })
+            .wrap(Compress::default())
+            .wrap(cors(&origins))
     })
-    .bind(opts.binding)?
-    .run()
-    .await?;
+    .client_timeout(config.client_timeout)
+    .keep_alive(config.keep_alive);
+    let http_server = match config.workers {
+        Some(workers) => http_server.workers(workers),
+        None => http_server,
+    };
+    let http_server = match tls_config {
+        Some(tls) => http_server.bind_rustls(&config.binding, tls)?,
+        None => http_server.bind(&config.binding)?,
+    };
+    http_server.run().await?;
 
     Ok(())
 }
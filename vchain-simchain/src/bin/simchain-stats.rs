@@ -0,0 +1,121 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+use vchain::chain::*;
+use vchain_simchain::DefaultChain;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => bail!("invalid format, please specify as json or csv."),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "simchain-stats")]
+struct Opts {
+    /// input db path
+    #[structopt(short, long, parse(from_os_str))]
+    input: PathBuf,
+
+    /// output path [default: stdout]
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// output format, json for the stats of the chain, its columns and its blocks, or csv for those of the blocks only
+    #[structopt(long, default_value = "json")]
+    format: Format,
+
+    /// first block to inspect [default: the first block not pruned]
+    #[structopt(long)]
+    start: Option<IdType>,
+
+    /// last block to inspect [default: the latest block]
+    #[structopt(long)]
+    end: Option<IdType>,
+}
+
+fn write_csv(out: &mut impl Write, blocks: &[BlockStats]) -> Result<()> {
+    let mut header_written = false;
+    for stats in blocks {
+        let row = match serde_json::to_value(stats)? {
+            serde_json::Value::Object(row) => row,
+            _ => unreachable!("the stats are a struct"),
+        };
+        if !header_written {
+            let keys: Vec<_> = row.keys().map(|k| k.as_str()).collect();
+            writeln!(out, "{}", keys.join(","))?;
+            header_written = true;
+        }
+        let values: Vec<_> = row.values().map(|v| v.to_string()).collect();
+        writeln!(out, "{}", values.join(","))?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+
+    let opts = Opts::from_args();
+    let chain = DefaultChain::open_read_only(&opts.input)?;
+    let chain_stats = chain.stats()?;
+    let column_sizes = chain.column_sizes()?;
+    info!("chain: {:?}", chain_stats);
+    info!("column sizes: {:?}", column_sizes);
+
+    let mut blocks = Vec::new();
+    if let Some(latest) = chain_stats.latest_block {
+        let first = if chain.read_block_header(0).is_ok() {
+            0
+        } else {
+            1
+        };
+        let start = opts.start.unwrap_or(first).max(chain_stats.pruned_before);
+        let end = opts.end.unwrap_or(latest).min(latest);
+        for id in start..=end {
+            if id % 1000 == 0 {
+                info!("inspect blk #{}", id);
+            }
+            let stats = inspect_block(&chain, id)
+                .with_context(|| format!("failed to inspect blk #{}", id))?;
+            blocks.push(stats);
+        }
+    }
+
+    let mut out: Box<dyn Write> = match &opts.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    match opts.format {
+        Format::Json => {
+            let res = json!({
+                "chain": chain_stats,
+                "column_sizes": column_sizes,
+                "blocks": blocks,
+            });
+            serde_json::to_writer_pretty(&mut out, &res)?;
+            writeln!(out)?;
+        }
+        Format::Csv => write_csv(&mut out, &blocks)?,
+    }
+    out.flush()?;
+    Ok(())
+}
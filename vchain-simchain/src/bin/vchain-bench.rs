@@ -0,0 +1,268 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{ensure, Context, Result};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use vchain::acc::{self, AccumulatorProof};
+use vchain::chain::*;
+use vchain_simchain::DefaultChain;
+
+// A kind of queries, with a range over all the dimensions of the v data
+// and/or the clauses of keywords of the bool query, e.g.
+// `{"name": "both", "range": true, "bool": [["w1", "w2"], ["w3"]]}`.
+#[derive(Debug, Clone, Deserialize)]
+struct Template {
+    name: String,
+    #[serde(default)]
+    range: bool,
+    #[serde(default)]
+    bool: Option<Vec<HashSet<String>>>,
+}
+
+// The queries of each template are run over the windows of each of
+// `range_lens` blocks, ending at random blocks, and for the templates with a
+// range, with each of `selectivities`, the fraction of the v space covered by
+// the range, placed at random.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Workload {
+    templates: Vec<Template>,
+    range_lens: Vec<IdType>,
+    #[serde(default)]
+    selectivities: Vec<f64>,
+    // the number of random queries for each template, range len and
+    // selectivity
+    #[serde(default = "default_queries")]
+    queries: usize,
+    // the number of times each query is run
+    #[serde(default = "default_repeat")]
+    repeat: usize,
+    #[serde(default)]
+    seed: u64,
+}
+
+fn default_queries() -> usize {
+    10
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "vchain-bench")]
+struct Opts {
+    /// input db path
+    #[structopt(short, long, parse(from_os_str))]
+    input: PathBuf,
+
+    /// workload spec path, in JSON
+    #[structopt(short, long, parse(from_os_str))]
+    workload: PathBuf,
+
+    /// output csv path [default: stdout]
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// number of segments of the blocks of each query answered in parallel, see historical_query_par
+    #[structopt(long, default_value = "1")]
+    segments: usize,
+
+    /// don't verify the results
+    #[structopt(long)]
+    no_verify: bool,
+}
+
+const CSV_HEADER: &str = "template,range_len,selectivity,query,run,start_block,end_block,\
+num_objs,query_time_in_ms,verify_time_in_ms,vo_size,vo_size_compact,num_of_acc_proofs,\
+num_of_mismatch_objs,num_of_mismatch_intra_nodes,num_of_mismatch_inter_nodes,pass";
+
+// A random query of the template over `range_len` blocks in [first, last].
+fn gen_query(
+    rng: &mut impl Rng,
+    template: &Template,
+    range_len: IdType,
+    selectivity: Option<f64>,
+    (first, last): (IdType, IdType),
+    bit_len: &[u8],
+) -> Result<Query> {
+    let end_block = rng.gen_range(first + range_len - 1, last + 1);
+    let mut query = json!({
+        "start_block": end_block + 1 - range_len,
+        "end_block": end_block,
+    });
+    if let Some(selectivity) = selectivity {
+        // the same fraction of each dimension
+        let fraction = selectivity.powf(1. / bit_len.len() as f64);
+        let (mut lower, mut upper) = (Vec::new(), Vec::new());
+        for &b in bit_len {
+            let domain = 1u64 << b;
+            let width = ((domain as f64 * fraction).round() as u64).clamp(1, domain);
+            let l = rng.gen_range(0, domain - width + 1);
+            lower.push(l);
+            upper.push(l + width - 1);
+        }
+        query["range"] = json!([lower, upper]);
+    }
+    if let Some(clauses) = &template.bool {
+        query["bool"] = json!(clauses);
+    }
+    Ok(serde_json::from_value(query)?)
+}
+
+fn run<AP>(
+    opts: &Opts,
+    workload: &Workload,
+    chain: &DefaultChain,
+    blocks: (IdType, IdType),
+    out: &mut impl Write,
+) -> Result<()>
+where
+    AP: AccumulatorProof + serde::Serialize + Send,
+{
+    let param = chain.get_parameter()?;
+    let mut rng = ChaCha20Rng::seed_from_u64(workload.seed);
+    let num_blocks = blocks.1 - blocks.0 + 1;
+    writeln!(out, "{}", CSV_HEADER)?;
+    for template in &workload.templates {
+        let selectivities: Vec<Option<f64>> = if template.range {
+            workload.selectivities.iter().copied().map(Some).collect()
+        } else {
+            vec![None]
+        };
+        for &range_len in &workload.range_lens {
+            if range_len > num_blocks {
+                warn!(
+                    "range len {} is over the {} blks of the chain, which are all queried",
+                    range_len, num_blocks
+                );
+            }
+            let range_len = range_len.min(num_blocks);
+            for &selectivity in &selectivities {
+                info!(
+                    "run {} over {} blks, selectivity {:?}",
+                    template.name, range_len, selectivity
+                );
+                for query_no in 0..workload.queries {
+                    let query = gen_query(
+                        &mut rng,
+                        template,
+                        range_len,
+                        selectivity,
+                        blocks,
+                        &param.v_bit_len,
+                    )?;
+                    for run_no in 0..workload.repeat {
+                        let res: OverallResult<AP> =
+                            historical_query_par(&query, opts.segments, chain)?;
+                        let (verify_time_in_ms, pass) = if opts.no_verify {
+                            (String::new(), String::new())
+                        } else {
+                            let (verify_result, time) =
+                                futures::executor::block_on(res.verify(chain))?;
+                            if !verify_result.is_ok() {
+                                warn!("the result of {:?} is invalid: {:?}", query, verify_result);
+                            }
+                            (
+                                time.as_millis().to_string(),
+                                verify_result.is_ok().to_string(),
+                            )
+                        };
+                        writeln!(
+                            out,
+                            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                            template.name,
+                            range_len,
+                            selectivity.map_or_else(String::new, |s| s.to_string()),
+                            query_no,
+                            run_no,
+                            query.start_block,
+                            query.end_block,
+                            res.res_objs.0.len(),
+                            res.query_time_in_ms,
+                            verify_time_in_ms,
+                            res.vo_size,
+                            res.vo_size_compact,
+                            res.vo_stats.num_of_acc_proofs,
+                            res.vo_stats.num_of_mismatch_objs,
+                            res.vo_stats.num_of_mismatch_intra_nodes,
+                            res.vo_stats.num_of_mismatch_inter_nodes,
+                            pass,
+                        )?;
+                    }
+                }
+                out.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+
+    let opts = Opts::from_args();
+    let workload = fs::read_to_string(&opts.workload)
+        .with_context(|| format!("failed to read {:?}", opts.workload))?;
+    let workload: Workload = serde_json::from_str(&workload).context("invalid workload")?;
+    ensure!(
+        !workload.templates.is_empty() && !workload.range_lens.is_empty(),
+        "no templates or range lens in the workload"
+    );
+    ensure!(
+        workload.range_lens.iter().all(|&l| l > 0),
+        "range lens should be positive"
+    );
+    ensure!(
+        workload.selectivities.iter().all(|&s| s > 0. && s <= 1.),
+        "selectivities should be in (0, 1]"
+    );
+    for template in &workload.templates {
+        ensure!(
+            template.range || template.bool.is_some(),
+            "template {} has neither range nor bool",
+            template.name
+        );
+        ensure!(
+            !template.range || !workload.selectivities.is_empty(),
+            "template {} has a range but there are no selectivities",
+            template.name
+        );
+        ensure!(
+            !template.name.contains(','),
+            "template name {} should not contain any comma",
+            template.name
+        );
+    }
+
+    let chain = DefaultChain::open_read_only(&opts.input)?;
+    let last = chain.latest_block_id()?.context("no blks in the chain")?;
+    let first = if chain.read_block_header(0).is_ok() {
+        0
+    } else {
+        1
+    };
+    let first = first.max(chain.read_pruned_before()?);
+    ensure!(first <= last, "all the blks of the chain are pruned");
+
+    let mut out: Box<dyn Write> = match &opts.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let blocks = (first, last);
+    match chain.get_parameter()?.acc_type {
+        acc::Type::ACC1 => run::<acc::Acc1Proof>(&opts, &workload, &chain, blocks, &mut out)?,
+        acc::Type::ACC2 => run::<acc::Acc2Proof>(&opts, &workload, &chain, blocks, &mut out)?,
+        acc::Type::ACC3 => run::<acc::Acc3Proof>(&opts, &workload, &chain, blocks, &mut out)?,
+    }
+    out.flush()?;
+    Ok(())
+}
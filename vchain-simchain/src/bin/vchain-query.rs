@@ -0,0 +1,177 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use structopt::StructOpt;
+use vchain::acc::{self, AccumulatorProof};
+use vchain::chain::*;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "vchain-query")]
+struct Opts {
+    /// server address, of simchain-server or vchain-server, including the chain name if any (e.g. http://127.0.0.1:8000/chain1)
+    #[structopt(short, long, default_value = "http://127.0.0.1:8000")]
+    server: String,
+
+    /// query in JSON, as the body of POST /query
+    #[structopt(short, long, required_unless = "query-file")]
+    query: Option<String>,
+
+    /// file of the query in JSON, instead of --query
+    #[structopt(short = "f", long, parse(from_os_str), conflicts_with = "query")]
+    query_file: Option<PathBuf>,
+
+    /// don't verify the result
+    #[structopt(long)]
+    no_verify: bool,
+
+    /// print the objects and the stats as JSON
+    #[structopt(long)]
+    json: bool,
+}
+
+// A light node reading the headers from the server, which are trusted as they
+// are, like those of a full node.
+struct HttpLightNode {
+    server: String,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpLightNode {
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!("{}{}", self.server, path);
+        debug!("get {}", url);
+        let res = self.client.get(&url).send()?;
+        if !res.status().is_success() {
+            bail!("failed to get {}: {}", url, res.text()?);
+        }
+        Ok(res.json()?)
+    }
+}
+
+#[async_trait::async_trait]
+impl LightNodeInterface for HttpLightNode {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        self.get("/get/param")
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.get(&format!("/get/blk_header/{}", id))
+    }
+    async fn lightnode_read_block_headers(&self, ids: &[IdType]) -> Result<Vec<BlockHeader>> {
+        let mut headers = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(MAX_BATCH_IDS) {
+            let ids: Vec<_> = chunk.iter().map(|id| id.to_string()).collect();
+            let res: Vec<BlockHeader> =
+                self.get(&format!("/get/blk_headers?ids={}", ids.join(",")))?;
+            headers.extend(res);
+        }
+        Ok(headers)
+    }
+}
+
+fn query_and_verify<AP>(opts: &Opts, node: &HttpLightNode, query: &Query) -> Result<()>
+where
+    AP: AccumulatorProof + serde::Serialize + serde::de::DeserializeOwned,
+{
+    let url = format!("{}/query", node.server);
+    info!("post query to {}", url);
+    let timer = Instant::now();
+    let res = node.client.post(&url).json(query).send()?;
+    if !res.status().is_success() {
+        bail!("failed to query: {}", res.text()?);
+    }
+    let bytes = res.bytes()?;
+    let round_trip_in_ms = timer.elapsed().as_millis() as u64;
+    let res = OverallResult::<AP>::from_json(&bytes)?;
+
+    let verify = if opts.no_verify {
+        None
+    } else {
+        let (verify_result, time) = futures::executor::block_on(res.verify(node))?;
+        Some((verify_result, time.as_millis() as u64))
+    };
+
+    let mut objs: Vec<&Object> = res.res_objs.0.values().collect();
+    objs.sort_unstable_by_key(|o| o.id);
+    if opts.json {
+        let objs: Vec<_> = objs
+            .iter()
+            .map(|o| {
+                json!({
+                    "id": o.id,
+                    "block_id": o.block_id,
+                    "v_data": o.v_data,
+                    "w_data": o.w_data,
+                })
+            })
+            .collect();
+        let mut out = json!({
+            "objs": objs,
+            "vo_size": res.vo_size,
+            "query_time_in_ms": res.query_time_in_ms,
+            "round_trip_in_ms": round_trip_in_ms,
+        });
+        if let Some((verify_result, verify_time_in_ms)) = &verify {
+            out["pass"] = json!(verify_result.is_ok());
+            out["detail"] = json!(verify_result);
+            out["verify_time_in_ms"] = json!(verify_time_in_ms);
+        }
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        for o in &objs {
+            let mut w_data: Vec<_> = o.w_data.iter().map(|w| w.as_str()).collect();
+            w_data.sort_unstable();
+            println!(
+                "#{} blk #{} {:?} {{{}}}",
+                o.id,
+                o.block_id,
+                o.v_data,
+                w_data.join(",")
+            );
+        }
+        println!("matched objects: {}", objs.len());
+        println!("vo size: {} bytes", res.vo_size);
+        println!("query time: {} ms", res.query_time_in_ms);
+        println!("round trip time: {} ms", round_trip_in_ms);
+        if let Some((verify_result, verify_time_in_ms)) = &verify {
+            println!("verify time: {} ms", verify_time_in_ms);
+            if verify_result.is_ok() {
+                println!("verify: pass");
+            } else {
+                println!("verify: fail, {}", serde_json::to_string(verify_result)?);
+            }
+        }
+    }
+    match verify {
+        Some((verify_result, _)) if !verify_result.is_ok() => bail!("the result is invalid"),
+        _ => Ok(()),
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+
+    let opts = Opts::from_args();
+    let query = match (&opts.query, &opts.query_file) {
+        (Some(query), _) => query.clone(),
+        (None, Some(path)) => {
+            fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?
+        }
+        (None, None) => bail!("either --query or --query-file should be given"),
+    };
+    let query: Query = serde_json::from_str(&query).context("invalid query")?;
+    let node = HttpLightNode {
+        server: opts.server.trim_end_matches('/').to_owned(),
+        client: reqwest::blocking::Client::new(),
+    };
+    let param: Parameter = node.get("/get/param")?;
+    match param.acc_type {
+        acc::Type::ACC1 => query_and_verify::<acc::Acc1Proof>(&opts, &node, &query),
+        acc::Type::ACC2 => query_and_verify::<acc::Acc2Proof>(&opts, &node, &query),
+        acc::Type::ACC3 => query_and_verify::<acc::Acc3Proof>(&opts, &node, &query),
+    }
+}
@@ -0,0 +1,116 @@
+#[macro_use]
+extern crate log;
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+use vchain::acc::{self, AccumulatorProof};
+use vchain::chain::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Auto,
+    Json,
+    Compact,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "json" => Ok(Self::Json),
+            "compact" => Ok(Self::Compact),
+            _ => bail!("invalid format, please specify as auto, json or compact."),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "vchain-verify")]
+struct Opts {
+    /// result path, of the response of a query in JSON or of OverallResult::to_compact_bytes
+    #[structopt(short, long, parse(from_os_str))]
+    input: PathBuf,
+
+    /// checkpoint path, of the trusted block headers written by HeaderStore::export_checkpoint
+    #[structopt(short, long, parse(from_os_str))]
+    checkpoint: PathBuf,
+
+    /// format of the result, auto to tell json from compact by the first byte
+    #[structopt(long, default_value = "auto")]
+    format: Format,
+
+    /// print the result of the verification as JSON
+    #[structopt(long)]
+    json: bool,
+}
+
+fn verify<AP>(opts: &Opts, data: &[u8], headers: &HeaderStore) -> Result<()>
+where
+    AP: AccumulatorProof + serde::Serialize + serde::de::DeserializeOwned,
+{
+    let format = match opts.format {
+        Format::Auto => match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Format::Json,
+            _ => Format::Compact,
+        },
+        format => format,
+    };
+    let res = match format {
+        Format::Compact => OverallResult::<AP>::from_compact_bytes(data),
+        _ => OverallResult::<AP>::from_json(data),
+    }
+    .context("invalid result")?;
+    info!(
+        "verify the result of {} objs, with the vo of {} bytes",
+        res.res_objs.0.len(),
+        res.vo_size
+    );
+    let (verify_result, time) = res.verify_with_headers(headers)?;
+    let verify_time_in_ms = time.as_millis() as u64;
+
+    if opts.json {
+        let out = json!({
+            "pass": verify_result.is_ok(),
+            "detail": verify_result,
+            "verify_time_in_ms": verify_time_in_ms,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!("matched objects: {}", res.res_objs.0.len());
+        println!("vo size: {} bytes", res.vo_size);
+        println!("verify time: {} ms", verify_time_in_ms);
+        if verify_result.is_ok() {
+            println!("verify: pass");
+        } else {
+            println!("verify: fail, {}", serde_json::to_string(&verify_result)?);
+        }
+    }
+    if !verify_result.is_ok() {
+        bail!("the result is invalid");
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+
+    let opts = Opts::from_args();
+    let headers = File::open(&opts.checkpoint)
+        .with_context(|| format!("failed to open {:?}", opts.checkpoint))?;
+    let headers = HeaderStore::import_checkpoint(BufReader::new(headers))?;
+    info!("trust {} blk headers", headers.len());
+    let data = fs::read(&opts.input).with_context(|| format!("failed to read {:?}", opts.input))?;
+    // the results carry no acc type, so the one of the checkpoint is taken
+    match headers.param().acc_type {
+        acc::Type::ACC1 => verify::<acc::Acc1Proof>(&opts, &data, &headers),
+        acc::Type::ACC2 => verify::<acc::Acc2Proof>(&opts, &data, &headers),
+        acc::Type::ACC3 => verify::<acc::Acc3Proof>(&opts, &data, &headers),
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done { result: Value },
+    Failed { error: String },
+}
+
+struct Job {
+    status: JobStatus,
+    finished_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: JobId,
+    jobs: HashMap<JobId, Job>,
+}
+
+impl Jobs {
+    // Drop the jobs finished more than `retention` ago.
+    fn purge(&mut self, retention: Duration) {
+        let now = Instant::now();
+        self.jobs.retain(|_, job| match job.finished_at {
+            Some(t) => now.duration_since(t) <= retention,
+            None => true,
+        });
+    }
+
+    fn num_unfinished(&self) -> usize {
+        self.jobs
+            .values()
+            .filter(|job| job.finished_at.is_none())
+            .count()
+    }
+
+    fn set_status(&mut self, id: JobId, status: JobStatus) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            if let JobStatus::Done { .. } | JobStatus::Failed { .. } = status {
+                job.finished_at = Some(Instant::now());
+            }
+            job.status = status;
+        }
+    }
+}
+
+// The jobs of the server, e.g. the long queries, run on a pool of their own so
+// that they do not block the workers of the server. The results are kept for
+// `retention` after the jobs finish, and are dropped afterwards. At most
+// `max_pending` jobs are pending or running at a time, 0 means unlimited.
+pub struct JobQueue {
+    pool: rayon::ThreadPool,
+    retention: Duration,
+    max_pending: usize,
+    jobs: Arc<Mutex<Jobs>>,
+}
+
+impl JobQueue {
+    pub fn new(num_threads: usize, retention: Duration, max_pending: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("job-{}", i))
+            .build()?;
+        Ok(Self {
+            pool,
+            retention,
+            max_pending,
+            jobs: Arc::default(),
+        })
+    }
+
+    // None if max_pending jobs are pending or running already.
+    pub fn submit(&self, f: impl FnOnce() -> Result<Value> + Send + 'static) -> Option<JobId> {
+        let id = {
+            let mut jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+            jobs.purge(self.retention);
+            if self.max_pending != 0 && jobs.num_unfinished() >= self.max_pending {
+                return None;
+            }
+            let id = jobs.next_id;
+            jobs.next_id += 1;
+            jobs.jobs.insert(
+                id,
+                Job {
+                    status: JobStatus::Pending,
+                    finished_at: None,
+                },
+            );
+            id
+        };
+        let jobs = self.jobs.clone();
+        self.pool.spawn(move || {
+            let set_status = |status| {
+                jobs.lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .set_status(id, status)
+            };
+            set_status(JobStatus::Running);
+            let status = match f() {
+                Ok(result) => JobStatus::Done { result },
+                Err(e) => JobStatus::Failed {
+                    error: e.to_string(),
+                },
+            };
+            set_status(status);
+        });
+        Some(id)
+    }
+
+    // None for the jobs never submitted or dropped already.
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        jobs.purge(self.retention);
+        jobs.jobs.get(&id).map(|job| job.status.clone())
+    }
+}
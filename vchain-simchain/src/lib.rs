@@ -1,141 +1,255 @@
 #[macro_use]
 extern crate log;
 
-use anyhow::{Context, Result};
-use rocksdb::{self, DB};
-use std::fs;
-use std::path::{Path, PathBuf};
-use vchain::*;
-
-pub struct SimChain {
-    root_path: PathBuf,
-    param: Parameter,
-    block_header_db: DB,
-    block_data_db: DB,
-    intra_index_db: DB,
-    skip_list_db: DB,
-    obj_db: DB,
+#[cfg(not(any(feature = "rocksdb", feature = "sled")))]
+compile_error!("either the rocksdb or the sled feature should be enabled");
+
+use anyhow::{bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+use vchain::{ArchiveManifest, Error, Parameter, ReadInterface, WriteInterface};
+
+mod access;
+pub use access::{load_tokens, RateLimiter};
+
+mod jobs;
+pub use jobs::{JobId, JobQueue, JobStatus};
+
+mod progress;
+pub use progress::{BuildProgress, BuildStats, TimedChain};
+
+#[cfg(feature = "rocksdb")]
+mod rocksdb_chain;
+#[cfg(feature = "rocksdb")]
+pub use rocksdb_chain::SimChain;
+
+#[cfg(feature = "sled")]
+mod sled_chain;
+#[cfg(feature = "sled")]
+pub use sled_chain::SledChain;
+
+// The chain used by the binaries, SimChain unless built without RocksDB.
+#[cfg(feature = "rocksdb")]
+pub type DefaultChain = SimChain;
+#[cfg(all(feature = "sled", not(feature = "rocksdb")))]
+pub type DefaultChain = SledChain;
+
+const ID_COUNTER_KEY: &[u8] = b"id_counter";
+const PRUNED_BEFORE_KEY: &[u8] = b"pruned_before";
+const LATEST_BLOCK_KEY: &[u8] = b"latest_block";
+
+// The version of the layout of the stored data, kept in `format_version` next
+// to the db. The chains without it are of version 1, before the counts of the
+// set data became u64, and cannot be read since the layout changed. A chain of
+// another version has to be built again from its input.
+const FORMAT_VERSION: u32 = 2;
+
+fn write_format_version(path: &Path) -> Result<()> {
+    fs::write(path.join("format_version"), FORMAT_VERSION.to_string())?;
+    Ok(())
 }
 
-impl SimChain {
-    pub fn create(path: &Path, param: Parameter) -> Result<Self> {
-        info!("create db at {:?}", path);
-        fs::create_dir_all(path).context(format!("failed to create dir {:?}", path))?;
-        fs::write(
-            path.join("param.json"),
-            serde_json::to_string_pretty(&param)?,
-        )?;
-        let mut opts = rocksdb::Options::default();
-        opts.create_if_missing(true);
-        Ok(Self {
-            root_path: path.to_owned(),
-            param,
-            block_header_db: DB::open(&opts, path.join("blk_header.db"))?,
-            block_data_db: DB::open(&opts, path.join("blk_data.db"))?,
-            intra_index_db: DB::open(&opts, path.join("intra_index.db"))?,
-            skip_list_db: DB::open(&opts, path.join("skiplist.db"))?,
-            obj_db: DB::open(&opts, path.join("obj.db"))?,
-        })
-    }
+fn check_format_version(path: &Path) -> Result<()> {
+    let version_path = path.join("format_version");
+    let version = if version_path.exists() {
+        fs::read_to_string(version_path)?
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| Error::Storage(format!("invalid format version: {}", e)))?
+    } else {
+        1
+    };
+    ensure!(
+        version == FORMAT_VERSION,
+        Error::Storage(format!(
+            "the chain at {:?} is stored in format version {} rather than {}, build it again from its input",
+            path, version, FORMAT_VERSION
+        ))
+    );
+    Ok(())
+}
 
-    pub fn open(path: &Path) -> Result<Self> {
-        info!("open db at {:?}", path);
-        Ok(Self {
-            root_path: path.to_owned(),
-            param: serde_json::from_str::<Parameter>(&fs::read_to_string(
-                path.join("param.json"),
-            )?)?,
-            block_header_db: DB::open_default(path.join("blk_header.db"))?,
-            block_data_db: DB::open_default(path.join("blk_data.db"))?,
-            intra_index_db: DB::open_default(path.join("intra_index.db"))?,
-            skip_list_db: DB::open_default(path.join("skiplist.db"))?,
-            obj_db: DB::open_default(path.join("obj.db"))?,
-        })
-    }
+// The param is kept in `param.json` next to the db.
+fn write_param(path: &Path, param: &Parameter) -> Result<()> {
+    fs::write(
+        path.join("param.json"),
+        serde_json::to_string_pretty(param)?,
+    )?;
+    Ok(())
 }
 
-#[async_trait::async_trait]
-impl LightNodeInterface for SimChain {
-    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
-        self.get_parameter()
-    }
-    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
-        self.read_block_header(id)
-    }
+fn read_param(path: &Path) -> Result<Parameter> {
+    let data = fs::read_to_string(path.join("param.json"))?;
+    Ok(serde_json::from_str::<Parameter>(&data)?)
 }
 
-impl ReadInterface for SimChain {
-    fn get_parameter(&self) -> Result<Parameter> {
-        Ok(self.param.clone())
-    }
-    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
-        let data = self
-            .block_header_db
-            .get(id.to_le_bytes())?
-            .context("failed to read block header")?;
-        Ok(bincode::deserialize::<BlockHeader>(&data[..])?)
-    }
-    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
-        let data = self
-            .block_data_db
-            .get(id.to_le_bytes())?
-            .context("failed to read block data")?;
-        Ok(bincode::deserialize::<BlockData>(&data[..])?)
-    }
-    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
-        let data = self
-            .intra_index_db
-            .get(id.to_le_bytes())?
-            .context("failed to read index node")?;
-        Ok(bincode::deserialize::<IntraIndexNode>(&data[..])?)
-    }
-    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
-        let data = self
-            .skip_list_db
-            .get(id.to_le_bytes())?
-            .context("failed to read skip list")?;
-        Ok(bincode::deserialize::<SkipListNode>(&data[..])?)
-    }
-    fn read_object(&self, id: IdType) -> Result<Object> {
-        let data = self
-            .obj_db
-            .get(id.to_le_bytes())?
-            .context("failed to read object")?;
-        Ok(bincode::deserialize::<Object>(&data[..])?)
+// The kinds of the data with large values, which can be compressed. They are
+// named after the column families of SimChain.
+pub const COMPRESSIBLE_COLUMNS: &[&str] = &["blk_data", "intra_index", "skiplist", "btree", "obj"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd {
+        level: i32,
+    },
+}
+
+// Parsed from `none`, `zstd` or `zstd:{level}`.
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.to_ascii_lowercase();
+        match input.split_once(':') {
+            None if input == "none" => Ok(Self::None),
+            None if input == "zstd" => Ok(Self::Zstd {
+                level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            }),
+            Some(("zstd", level)) => Ok(Self::Zstd {
+                level: level.trim().parse()?,
+            }),
+            _ => bail!("invalid compression, please specify as none, zstd or zstd:{{level}}."),
+        }
     }
 }
 
-impl WriteInterface for SimChain {
-    fn set_parameter(&mut self, param: Parameter) -> Result<()> {
-        self.param = param;
-        let data = serde_json::to_string_pretty(&self.param)?;
-        fs::write(self.root_path.join("param.json"), data)?;
-        Ok(())
+// How the values are stored, kept in `storage.json` next to the db. It is
+// fixed when the chain is created, and the chains created before it was kept
+// are not compressed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageConfig {
+    pub compression: Compression,
+    // the columns compressed, out of COMPRESSIBLE_COLUMNS
+    pub columns: Vec<String>,
+}
+
+impl StorageConfig {
+    pub fn new(compression: Compression, columns: Vec<String>) -> Result<Self> {
+        for column in &columns {
+            ensure!(
+                COMPRESSIBLE_COLUMNS.contains(&column.as_str()),
+                "column {} cannot be compressed, should be one of {}",
+                column,
+                COMPRESSIBLE_COLUMNS.join(", ")
+            );
+        }
+        if let Compression::Zstd { level } = compression {
+            ensure!(
+                zstd::compression_level_range().contains(&level),
+                "invalid zstd level {}",
+                level
+            );
+        }
+        Ok(Self {
+            compression,
+            columns,
+        })
     }
-    fn write_block_header(&mut self, header: BlockHeader) -> Result<()> {
-        let bytes = bincode::serialize(&header)?;
-        self.block_header_db
-            .put(header.block_id.to_le_bytes(), bytes)?;
-        Ok(())
+
+    // Compress all the compressible columns.
+    pub fn with_compression(compression: Compression) -> Result<Self> {
+        let columns = COMPRESSIBLE_COLUMNS.iter().map(|c| c.to_string()).collect();
+        Self::new(compression, columns)
     }
-    fn write_block_data(&mut self, data: BlockData) -> Result<()> {
-        let bytes = bincode::serialize(&data)?;
-        self.block_data_db.put(data.block_id.to_le_bytes(), bytes)?;
-        Ok(())
+
+    fn compression_of(&self, column: &str) -> Compression {
+        if self.columns.iter().any(|c| c == column) {
+            self.compression
+        } else {
+            Compression::None
+        }
     }
-    fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()> {
-        let bytes = bincode::serialize(&node)?;
-        self.intra_index_db.put(node.id().to_le_bytes(), bytes)?;
-        Ok(())
+
+    fn encode<'a>(&self, column: &str, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match self.compression_of(column) {
+            Compression::None => Ok(Cow::Borrowed(value)),
+            Compression::Zstd { level } => Ok(Cow::Owned(zstd::encode_all(value, level)?)),
+        }
     }
-    fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()> {
-        let bytes = bincode::serialize(&node)?;
-        self.skip_list_db.put(node.id.to_le_bytes(), bytes)?;
-        Ok(())
+
+    fn decode(&self, column: &str, value: Vec<u8>) -> Result<Vec<u8>> {
+        match self.compression_of(column) {
+            Compression::None => Ok(value),
+            Compression::Zstd { .. } => zstd::decode_all(&value[..]).map_err(|e| {
+                Error::Storage(format!("failed to decompress {}: {}", column, e)).into()
+            }),
+        }
     }
-    fn write_object(&mut self, obj: Object) -> Result<()> {
-        let bytes = bincode::serialize(&obj)?;
-        self.obj_db.put(obj.id.to_le_bytes(), bytes)?;
-        Ok(())
+}
+
+fn write_storage(path: &Path, storage: &StorageConfig) -> Result<()> {
+    fs::write(
+        path.join("storage.json"),
+        serde_json::to_string_pretty(storage)?,
+    )?;
+    Ok(())
+}
+
+fn read_storage(path: &Path) -> Result<StorageConfig> {
+    let storage_path = path.join("storage.json");
+    if !storage_path.exists() {
+        return Ok(StorageConfig::default());
     }
+    let data = fs::read_to_string(storage_path)?;
+    Ok(serde_json::from_str::<StorageConfig>(&data)?)
+}
+
+// The sizes of a chain on disk, see SimChain::stats.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainStats {
+    pub latest_block: Option<vchain::IdType>,
+    pub pruned_before: vchain::IdType,
+    pub storage: StorageConfig,
+    // the total size of the files of the chain, in bytes
+    pub disk_size: u64,
+}
+
+fn chain_stats(
+    chain: &impl ReadInterface,
+    path: &Path,
+    storage: &StorageConfig,
+) -> Result<ChainStats> {
+    Ok(ChainStats {
+        latest_block: chain.latest_block_id()?,
+        pruned_before: chain.read_pruned_before()?,
+        storage: storage.clone(),
+        disk_size: dir_size(path)?,
+    })
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += meta.len();
+        }
+    }
+    Ok(size)
+}
+
+fn export_to(chain: &impl ReadInterface, archive: &Path) -> Result<ArchiveManifest> {
+    let mut w = BufWriter::new(File::create(archive)?);
+    vchain::export_archive(chain, &mut w)
+}
+
+// The archive is checked before the chain is created.
+fn import_from<C: WriteInterface>(
+    archive: &Path,
+    create: impl FnOnce(Parameter) -> Result<C>,
+) -> Result<C> {
+    let manifest = vchain::verify_archive(&mut BufReader::new(File::open(archive)?))?;
+    let mut chain = create(manifest.param)?;
+    vchain::import_archive(&mut BufReader::new(File::open(archive)?), &mut chain)?;
+    Ok(chain)
 }
@@ -0,0 +1,266 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use vchain::chain::*;
+
+// A chain with the time spent in its reads and writes added up, to tell the
+// io time of a build from the time of the accumulators.
+pub struct TimedChain<C> {
+    chain: C,
+    io_nanos: AtomicU64,
+}
+
+impl<C> TimedChain<C> {
+    pub fn new(chain: C) -> Self {
+        Self {
+            chain,
+            io_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.chain
+    }
+
+    pub fn into_inner(self) -> C {
+        self.chain
+    }
+
+    pub fn io_time(&self) -> Duration {
+        Duration::from_nanos(self.io_nanos.load(Ordering::Relaxed))
+    }
+}
+
+fn timed<T>(io_nanos: &AtomicU64, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let v = f();
+    io_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    v
+}
+
+impl<C: ReadInterface> ReadInterface for TimedChain<C> {
+    fn get_parameter(&self) -> Result<Parameter> {
+        self.chain.get_parameter()
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        timed(&self.io_nanos, || self.chain.read_block_header(id))
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        timed(&self.io_nanos, || self.chain.read_block_data(id))
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        timed(&self.io_nanos, || self.chain.read_intra_index_node(id))
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        timed(&self.io_nanos, || self.chain.read_skip_list_node(id))
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        timed(&self.io_nanos, || self.chain.read_btree_node(id))
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        timed(&self.io_nanos, || self.chain.read_object(id))
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        timed(&self.io_nanos, || self.chain.read_keyword(sym))
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        timed(&self.io_nanos, || self.chain.read_keywords())
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        timed(&self.io_nanos, || self.chain.read_id_counter())
+    }
+    fn read_pruned_before(&self) -> Result<IdType> {
+        timed(&self.io_nanos, || self.chain.read_pruned_before())
+    }
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        timed(&self.io_nanos, || self.chain.latest_block_id())
+    }
+}
+
+impl<C: WriteInterface> WriteInterface for TimedChain<C> {
+    fn set_parameter(&mut self, param: Parameter) -> Result<()> {
+        self.chain.set_parameter(param)
+    }
+    fn write_block_header(&mut self, header: BlockHeader) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_block_header(header))
+    }
+    fn write_block_data(&mut self, data: BlockData) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_block_data(data))
+    }
+    fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_intra_index_node(node))
+    }
+    fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_skip_list_node(node))
+    }
+    fn write_btree_node(&mut self, node: BTreeNode) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_btree_node(node))
+    }
+    fn write_object(&mut self, obj: Object) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_object(obj))
+    }
+    fn write_keyword(&mut self, sym: SymbolType, word: String) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_keyword(sym, word))
+    }
+    fn write_id_counter(&mut self, counter: IdCounter) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_id_counter(counter))
+    }
+    fn begin_block(&mut self) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.begin_block())
+    }
+    fn commit_block(&mut self) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.commit_block())
+    }
+    fn write_pruned_before(&mut self, block_id: IdType) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.write_pruned_before(block_id))
+    }
+    fn delete_block_data(&mut self, id: IdType) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.delete_block_data(id))
+    }
+    fn delete_intra_index_node(&mut self, id: IdType) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.delete_intra_index_node(id))
+    }
+    fn delete_skip_list_node(&mut self, id: IdType) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.delete_skip_list_node(id))
+    }
+    fn delete_btree_node(&mut self, id: IdType) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.delete_btree_node(id))
+    }
+    fn delete_object(&mut self, id: IdType) -> Result<()> {
+        let chain = &mut self.chain;
+        timed(&self.io_nanos, || chain.delete_object(id))
+    }
+}
+
+// The throughput of a build, reported every `interval` to the log, and as a
+// line of JSON to `json_out` if any, for the scripts of the experiments.
+pub struct BuildProgress {
+    start: Instant,
+    last_report: Instant,
+    interval: Duration,
+    total_blocks: u64,
+    total_objs: u64,
+    blocks: u64,
+    objs: u64,
+    json_out: Option<BufWriter<File>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildStats {
+    pub elapsed_secs: f64,
+    pub blocks: u64,
+    pub total_blocks: u64,
+    pub objs: u64,
+    pub total_objs: u64,
+    pub blocks_per_sec: f64,
+    pub objs_per_sec: f64,
+    // the time of the reads and writes of the chain, and the rest, which is
+    // mostly spent on the accumulators
+    pub io_secs: f64,
+    pub acc_secs: f64,
+    // from the objects left at the current rate, None until any is built
+    pub eta_secs: Option<f64>,
+}
+
+impl BuildProgress {
+    pub fn new(
+        total_blocks: u64,
+        total_objs: u64,
+        interval: Duration,
+        json_out: Option<&Path>,
+    ) -> Result<Self> {
+        let json_out = match json_out {
+            Some(path) => Some(BufWriter::new(File::create(path)?)),
+            None => None,
+        };
+        let now = Instant::now();
+        Ok(Self {
+            start: now,
+            last_report: now,
+            interval,
+            total_blocks,
+            total_objs,
+            blocks: 0,
+            objs: 0,
+            json_out,
+        })
+    }
+
+    // Record a block appended, and report if the interval has passed.
+    pub fn record_block(&mut self, num_objs: usize, io_time: Duration) -> Result<()> {
+        self.blocks += 1;
+        self.objs += num_objs as u64;
+        if self.last_report.elapsed() >= self.interval {
+            self.report(io_time)?;
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self, io_time: Duration) -> BuildStats {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let per_sec = |n: u64| if elapsed > 0. { n as f64 / elapsed } else { 0. };
+        let objs_per_sec = per_sec(self.objs);
+        let eta_secs = if objs_per_sec > 0. {
+            Some(self.total_objs.saturating_sub(self.objs) as f64 / objs_per_sec)
+        } else {
+            None
+        };
+        let io_secs = io_time.as_secs_f64().min(elapsed);
+        BuildStats {
+            elapsed_secs: elapsed,
+            blocks: self.blocks,
+            total_blocks: self.total_blocks,
+            objs: self.objs,
+            total_objs: self.total_objs,
+            blocks_per_sec: per_sec(self.blocks),
+            objs_per_sec,
+            io_secs,
+            acc_secs: elapsed - io_secs,
+            eta_secs,
+        }
+    }
+
+    pub fn report(&mut self, io_time: Duration) -> Result<BuildStats> {
+        self.last_report = Instant::now();
+        let stats = self.stats(io_time);
+        info!(
+            "built {}/{} blks, {}/{} objs, {:.1} blks/s, {:.1} objs/s, acc {:.1}s, io {:.1}s, eta {}",
+            stats.blocks,
+            stats.total_blocks,
+            stats.objs,
+            stats.total_objs,
+            stats.blocks_per_sec,
+            stats.objs_per_sec,
+            stats.acc_secs,
+            stats.io_secs,
+            stats
+                .eta_secs
+                .map_or_else(|| "unknown".to_owned(), |secs| format!("{:.0}s", secs)),
+        );
+        if let Some(out) = &mut self.json_out {
+            serde_json::to_writer(&mut *out, &stats)?;
+            writeln!(out)?;
+            out.flush()?;
+        }
+        Ok(stats)
+    }
+}
@@ -0,0 +1,408 @@
+use crate::{
+    chain_stats, check_format_version, export_to, import_from, read_param, read_storage,
+    write_format_version, write_param, write_storage, ChainStats, StorageConfig, ID_COUNTER_KEY,
+    LATEST_BLOCK_KEY, PRUNED_BEFORE_KEY,
+};
+use anyhow::{ensure, Context, Result};
+use rocksdb::{self, ColumnFamily, IteratorMode, WriteBatch, DB};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use vchain::*;
+
+const BLOCK_HEADER_CF: &str = "blk_header";
+const BLOCK_DATA_CF: &str = "blk_data";
+const INTRA_INDEX_CF: &str = "intra_index";
+const SKIP_LIST_CF: &str = "skiplist";
+const BTREE_CF: &str = "btree";
+const OBJ_CF: &str = "obj";
+const KEYWORD_CF: &str = "keyword";
+const META_CF: &str = "meta";
+
+// Each of them was a separate db named `{cf}.db` before.
+const COLUMN_FAMILIES: &[&str] = &[
+    BLOCK_HEADER_CF,
+    BLOCK_DATA_CF,
+    INTRA_INDEX_CF,
+    SKIP_LIST_CF,
+    BTREE_CF,
+    OBJ_CF,
+    KEYWORD_CF,
+    META_CF,
+];
+
+pub struct SimChain {
+    root_path: PathBuf,
+    param: Parameter,
+    storage: StorageConfig,
+    // the keywords of the chain, whose symbols are stored in place of the words
+    keywords: KeywordTable,
+    db: DB,
+    // the writes of the block being built, see begin_block
+    batch: Mutex<Option<WriteBatch>>,
+}
+
+impl SimChain {
+    pub fn create(path: &Path, param: Parameter) -> Result<Self> {
+        Self::create_with_storage(path, param, StorageConfig::default())
+    }
+
+    // Create the chain with the values of some columns compressed, which are
+    // decompressed transparently as they are read.
+    pub fn create_with_storage(
+        path: &Path,
+        param: Parameter,
+        storage: StorageConfig,
+    ) -> Result<Self> {
+        info!("create db at {:?}", path);
+        fs::create_dir_all(path).context(format!("failed to create dir {:?}", path))?;
+        write_param(path, &param)?;
+        write_storage(path, &storage)?;
+        write_format_version(path)?;
+        Ok(Self {
+            root_path: path.to_owned(),
+            param,
+            storage,
+            keywords: KeywordTable::new(),
+            db: open_db(path)?,
+            batch: Mutex::new(None),
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        info!("open db at {:?}", path);
+        check_format_version(path)?;
+        let param = read_param(path)?;
+        let storage = read_storage(path)?;
+        let db = open_db(path)?;
+        migrate_separate_dbs(path, &db)?;
+        let mut chain = Self {
+            root_path: path.to_owned(),
+            param,
+            storage,
+            keywords: KeywordTable::new(),
+            db,
+            batch: Mutex::new(None),
+        };
+        chain.keywords = KeywordTable::load(&chain)?;
+        Ok(chain)
+    }
+
+    // Open the chain without taking the lock of the db, so that it can be
+    // opened by other processes at the same time. Only the data written before
+    // it is opened can be read, and all the writes fail.
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        info!("open db at {:?} read-only", path);
+        check_format_version(path)?;
+        let param = read_param(path)?;
+        let storage = read_storage(path)?;
+        let db_path = path.join("chain.db");
+        ensure!(
+            db_path.exists(),
+            "no db at {:?}, open the chain for writing first to create or migrate it",
+            db_path
+        );
+        let opts = rocksdb::Options::default();
+        let db = DB::open_cf_for_read_only(&opts, db_path, COLUMN_FAMILIES, false)?;
+        let mut chain = Self {
+            root_path: path.to_owned(),
+            param,
+            storage,
+            keywords: KeywordTable::new(),
+            db,
+            batch: Mutex::new(None),
+        };
+        chain.keywords = KeywordTable::load(&chain)?;
+        Ok(chain)
+    }
+
+    // Write all the data of the chain to a single file, e.g. to copy it to
+    // another machine, see export_archive.
+    pub fn export(&self, archive: &Path) -> Result<ArchiveManifest> {
+        export_to(self, archive)
+    }
+
+    // Create the chain at `path` from an archive written by export.
+    pub fn import(archive: &Path, path: &Path) -> Result<Self> {
+        import_from(archive, |param| Self::create(path, param))
+    }
+
+    pub fn stats(&self) -> Result<ChainStats> {
+        chain_stats(self, &self.root_path, &self.storage)
+    }
+
+    // The bytes of the keys and the stored values of each column family,
+    // after the compression and without the overhead of RocksDB. All the data
+    // is read, unlike stats.
+    pub fn column_sizes(&self) -> Result<BTreeMap<String, u64>> {
+        let mut sizes = BTreeMap::new();
+        for &cf in COLUMN_FAMILIES {
+            let size = self
+                .db
+                .iterator_cf(cf_handle(&self.db, cf)?, IteratorMode::Start)
+                .map(|(k, v)| (k.len() + v.len()) as u64)
+                .sum();
+            sizes.insert(cf.to_owned(), size);
+        }
+        Ok(sizes)
+    }
+
+    fn get(&self, cf: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        let value = self
+            .db
+            .get_cf(cf_handle(&self.db, cf)?, key)
+            .map_err(|e| Error::Storage(e.to_string()))?;
+        match value {
+            Some(v) => Ok(Some(self.storage.decode(cf, v)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_data(&self, cf: &str, id: IdType, what: &str) -> Result<Vec<u8>> {
+        self.get(cf, id_to_bytes(id))?
+            .ok_or_else(|| Error::NotFound(format!("failed to read {} #{}", what, id)).into())
+    }
+
+    // The value of `id`, failing with Error::NotFound if there is none, or
+    // Error::Storage if it cannot be decoded.
+    fn read_value<T: DeserializeOwned>(&self, cf: &str, id: IdType, what: &str) -> Result<T> {
+        let data = self.read_data(cf, id, what)?;
+        bincode::deserialize(&data[..])
+            .map_err(|e| Error::Storage(format!("invalid {} #{}: {}", what, id, e)).into())
+    }
+
+    // Like read_value, for the values written by write_keyword_value.
+    fn read_keyword_value<T: KeywordData>(&self, cf: &str, id: IdType, what: &str) -> Result<T> {
+        let data = self.read_data(cf, id, what)?;
+        self.keywords
+            .decode(&data[..])
+            .map_err(|e| Error::Storage(format!("invalid {} #{}: {:#}", what, id, e)).into())
+    }
+
+    // Write the value with its keywords as their symbols. The keywords new to
+    // the chain are written at once rather than with the block, so that the
+    // table never refers to the keywords not written.
+    fn write_keyword_value<T: KeywordData>(
+        &mut self,
+        cf: &str,
+        id: IdType,
+        value: T,
+    ) -> Result<()> {
+        let (bytes, keywords) = self.keywords.encode(value)?;
+        for (sym, word) in keywords {
+            let word = self.storage.encode(KEYWORD_CF, word.as_bytes())?;
+            self.db
+                .put_cf(cf_handle(&self.db, KEYWORD_CF)?, sym.to_le_bytes(), word)?;
+        }
+        self.put(cf, id_to_bytes(id), bytes)
+    }
+
+    fn put(&mut self, cf: &str, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+        let value = self.storage.encode(cf, value.as_ref())?;
+        let cf = cf_handle(&self.db, cf)?;
+        match self.batch.get_mut().unwrap_or_else(PoisonError::into_inner) {
+            Some(batch) => batch.put_cf(cf, key, value),
+            None => self.db.put_cf(cf, key, value)?,
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, cf: &str, key: impl AsRef<[u8]>) -> Result<()> {
+        let cf = cf_handle(&self.db, cf)?;
+        match self.batch.get_mut().unwrap_or_else(PoisonError::into_inner) {
+            Some(batch) => batch.delete_cf(cf, key),
+            None => self.db.delete_cf(cf, key)?,
+        }
+        Ok(())
+    }
+}
+
+fn open_db(path: &Path) -> Result<DB> {
+    let mut opts = rocksdb::Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    Ok(DB::open_cf(&opts, path.join("chain.db"), COLUMN_FAMILIES)?)
+}
+
+fn cf_handle<'a>(db: &'a DB, cf: &str) -> Result<&'a ColumnFamily> {
+    db.cf_handle(cf)
+        .with_context(|| format!("missing column family {}", cf))
+}
+
+// Move the data of the separate dbs of a chain created by the older versions
+// into the column families, removing the dbs afterwards. A migration cut
+// short is picked up again at the next open.
+fn migrate_separate_dbs(path: &Path, db: &DB) -> Result<()> {
+    for &cf_name in COLUMN_FAMILIES {
+        let old_path = path.join(format!("{}.db", cf_name));
+        if !old_path.exists() {
+            continue;
+        }
+        info!("migrate {:?} into column family {}", old_path, cf_name);
+        let cf = cf_handle(db, cf_name)?;
+        {
+            let old_db = DB::open_default(&old_path)?;
+            for (k, v) in old_db.iterator(IteratorMode::Start) {
+                db.put_cf(cf, k, v)?;
+            }
+        }
+        db.flush_cf(cf)?;
+        fs::remove_dir_all(&old_path)?;
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl LightNodeInterface for SimChain {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        self.get_parameter()
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.read_block_header(id)
+    }
+}
+
+impl ReadInterface for SimChain {
+    fn get_parameter(&self) -> Result<Parameter> {
+        Ok(self.param.clone())
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.read_value(BLOCK_HEADER_CF, id, "block header")
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        self.read_keyword_value(BLOCK_DATA_CF, id, "block data")
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        self.read_keyword_value(INTRA_INDEX_CF, id, "index node")
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        self.read_keyword_value(SKIP_LIST_CF, id, "skip list")
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        self.read_keyword_value(BTREE_CF, id, "b-tree node")
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        self.read_keyword_value(OBJ_CF, id, "object")
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        match self.get(KEYWORD_CF, sym.to_le_bytes())? {
+            Some(data) => Ok(Some(String::from_utf8(data)?)),
+            None => Ok(None),
+        }
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        self.db
+            .iterator_cf(cf_handle(&self.db, KEYWORD_CF)?, IteratorMode::Start)
+            .map(|(k, v)| {
+                let mut sym = [0u8; 4];
+                sym.copy_from_slice(&k[..]);
+                let word = self.storage.decode(KEYWORD_CF, v.into_vec())?;
+                Ok((SymbolType::from_le_bytes(sym), String::from_utf8(word)?))
+            })
+            .collect()
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        match self.get(META_CF, ID_COUNTER_KEY)? {
+            Some(data) => Ok(bincode::deserialize::<IdCounter>(&data[..])?),
+            None => Ok(IdCounter::default()),
+        }
+    }
+    fn read_pruned_before(&self) -> Result<IdType> {
+        match self.get(META_CF, PRUNED_BEFORE_KEY)? {
+            Some(data) => id_from_bytes(&data),
+            None => Ok(0),
+        }
+    }
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        if let Some(data) = self.get(META_CF, LATEST_BLOCK_KEY)? {
+            return Ok(Some(id_from_bytes(&data)?));
+        }
+        // the chains created before the latest block was kept
+        let cf = cf_handle(&self.db, BLOCK_HEADER_CF)?;
+        let mut latest = None;
+        for (k, _) in self.db.iterator_cf(cf, IteratorMode::Start) {
+            latest = latest.max(Some(id_from_bytes(&k)?));
+        }
+        Ok(latest)
+    }
+}
+
+impl WriteInterface for SimChain {
+    fn set_parameter(&mut self, param: Parameter) -> Result<()> {
+        write_param(&self.root_path, &param)?;
+        self.param = param;
+        Ok(())
+    }
+    fn write_block_header(&mut self, header: BlockHeader) -> Result<()> {
+        let bytes = bincode::serialize(&header)?;
+        self.put(BLOCK_HEADER_CF, id_to_bytes(header.block_id), bytes)?;
+        if self.latest_block_id()? < Some(header.block_id) {
+            let bytes = id_to_bytes(header.block_id);
+            self.put(META_CF, LATEST_BLOCK_KEY, bytes)?;
+        }
+        Ok(())
+    }
+    fn write_block_data(&mut self, data: BlockData) -> Result<()> {
+        let id = data.block_id;
+        self.write_keyword_value(BLOCK_DATA_CF, id, data)
+    }
+    fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()> {
+        let id = node.id();
+        self.write_keyword_value(INTRA_INDEX_CF, id, node)
+    }
+    fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()> {
+        let id = node.id;
+        self.write_keyword_value(SKIP_LIST_CF, id, node)
+    }
+    fn write_btree_node(&mut self, node: BTreeNode) -> Result<()> {
+        let id = node.id;
+        self.write_keyword_value(BTREE_CF, id, node)
+    }
+    fn write_object(&mut self, obj: Object) -> Result<()> {
+        let id = obj.id;
+        self.write_keyword_value(OBJ_CF, id, obj)
+    }
+    fn write_keyword(&mut self, sym: SymbolType, word: String) -> Result<()> {
+        self.keywords.insert(sym, &word)?;
+        self.put(KEYWORD_CF, sym.to_le_bytes(), word)?;
+        Ok(())
+    }
+    fn write_id_counter(&mut self, counter: IdCounter) -> Result<()> {
+        let bytes = bincode::serialize(&counter)?;
+        self.put(META_CF, ID_COUNTER_KEY, bytes)?;
+        Ok(())
+    }
+    fn write_pruned_before(&mut self, block_id: IdType) -> Result<()> {
+        let bytes = id_to_bytes(block_id);
+        self.put(META_CF, PRUNED_BEFORE_KEY, bytes)?;
+        Ok(())
+    }
+    fn delete_block_data(&mut self, id: IdType) -> Result<()> {
+        self.delete(BLOCK_DATA_CF, id_to_bytes(id))
+    }
+    fn delete_intra_index_node(&mut self, id: IdType) -> Result<()> {
+        self.delete(INTRA_INDEX_CF, id_to_bytes(id))
+    }
+    fn delete_skip_list_node(&mut self, id: IdType) -> Result<()> {
+        self.delete(SKIP_LIST_CF, id_to_bytes(id))
+    }
+    fn delete_btree_node(&mut self, id: IdType) -> Result<()> {
+        self.delete(BTREE_CF, id_to_bytes(id))
+    }
+    fn delete_object(&mut self, id: IdType) -> Result<()> {
+        self.delete(OBJ_CF, id_to_bytes(id))
+    }
+    fn begin_block(&mut self) -> Result<()> {
+        *self.batch.get_mut().unwrap_or_else(PoisonError::into_inner) = Some(WriteBatch::default());
+        Ok(())
+    }
+    fn commit_block(&mut self) -> Result<()> {
+        let batch = self.batch.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if let Some(batch) = batch.take() {
+            self.db.write(batch)?;
+        }
+        Ok(())
+    }
+}
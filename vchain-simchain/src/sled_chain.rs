@@ -0,0 +1,378 @@
+use crate::{
+    chain_stats, check_format_version, export_to, import_from, read_param, read_storage,
+    write_format_version, write_param, write_storage, ChainStats, StorageConfig, ID_COUNTER_KEY,
+    LATEST_BLOCK_KEY, PRUNED_BEFORE_KEY,
+};
+use anyhow::{ensure, Context, Result};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use vchain::*;
+
+// The data is kept in one tree, with the keys prefixed by the kind of the data
+// so that the writes of a block can be applied as one batch.
+const BLOCK_HEADER: u8 = 0;
+const BLOCK_DATA: u8 = 1;
+const INTRA_INDEX: u8 = 2;
+const SKIP_LIST: u8 = 3;
+const BTREE: u8 = 4;
+const OBJ: u8 = 5;
+const KEYWORD: u8 = 6;
+const META: u8 = 7;
+
+// The names of the kinds, the same as the column families of SimChain.
+const KIND_NAMES: &[&str] = &[
+    "blk_header",
+    "blk_data",
+    "intra_index",
+    "skiplist",
+    "btree",
+    "obj",
+    "keyword",
+    "meta",
+];
+
+fn prefixed(kind: u8, key: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(key.len() + 1);
+    data.push(kind);
+    data.extend_from_slice(key);
+    data
+}
+
+// The same as SimChain, on sled instead of RocksDB for the targets where the
+// latter cannot be built. The data is kept at `chain.sled`.
+pub struct SledChain {
+    root_path: PathBuf,
+    param: Parameter,
+    storage: StorageConfig,
+    // the keywords of the chain, whose symbols are stored in place of the words
+    keywords: KeywordTable,
+    db: sled::Db,
+    // the writes of the block being built, see begin_block
+    batch: Option<sled::Batch>,
+    read_only: bool,
+}
+
+impl SledChain {
+    pub fn create(path: &Path, param: Parameter) -> Result<Self> {
+        Self::create_with_storage(path, param, StorageConfig::default())
+    }
+
+    pub fn create_with_storage(
+        path: &Path,
+        param: Parameter,
+        storage: StorageConfig,
+    ) -> Result<Self> {
+        info!("create db at {:?}", path);
+        fs::create_dir_all(path).context(format!("failed to create dir {:?}", path))?;
+        write_param(path, &param)?;
+        write_storage(path, &storage)?;
+        write_format_version(path)?;
+        Ok(Self {
+            root_path: path.to_owned(),
+            param,
+            storage,
+            keywords: KeywordTable::new(),
+            db: sled::open(path.join("chain.sled"))?,
+            batch: None,
+            read_only: false,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        info!("open db at {:?}", path);
+        Self::open_db(path)
+    }
+
+    // sled has no read-only mode, so the db cannot be opened by other
+    // processes meanwhile. All the writes fail.
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        info!("open db at {:?} read-only", path);
+        let mut chain = Self::open_db(path)?;
+        chain.read_only = true;
+        Ok(chain)
+    }
+
+    fn open_db(path: &Path) -> Result<Self> {
+        check_format_version(path)?;
+        let mut chain = Self {
+            root_path: path.to_owned(),
+            param: read_param(path)?,
+            storage: read_storage(path)?,
+            keywords: KeywordTable::new(),
+            db: sled::open(path.join("chain.sled"))?,
+            batch: None,
+            read_only: false,
+        };
+        chain.keywords = KeywordTable::load(&chain)?;
+        Ok(chain)
+    }
+
+    // Write all the data of the chain to a single file, e.g. to copy it to
+    // another machine, see export_archive.
+    pub fn export(&self, archive: &Path) -> Result<ArchiveManifest> {
+        export_to(self, archive)
+    }
+
+    // Create the chain at `path` from an archive written by export.
+    pub fn import(archive: &Path, path: &Path) -> Result<Self> {
+        import_from(archive, |param| Self::create(path, param))
+    }
+
+    pub fn stats(&self) -> Result<ChainStats> {
+        chain_stats(self, &self.root_path, &self.storage)
+    }
+
+    // The bytes of the keys and the stored values of each kind, named as the
+    // column families of SimChain, after the compression and without the
+    // overhead of sled. All the data is read, unlike stats.
+    pub fn column_sizes(&self) -> Result<BTreeMap<String, u64>> {
+        let mut sizes = BTreeMap::new();
+        for (kind, &name) in KIND_NAMES.iter().enumerate() {
+            let mut size = 0;
+            for kv in self.db.scan_prefix([kind as u8]) {
+                let (k, v) = kv?;
+                size += (k.len() + v.len()) as u64;
+            }
+            sizes.insert(name.to_owned(), size);
+        }
+        Ok(sizes)
+    }
+
+    fn get(&self, kind: u8, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        let value = self
+            .db
+            .get(prefixed(kind, key.as_ref()))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+        match value {
+            Some(v) => Ok(Some(
+                self.storage.decode(KIND_NAMES[kind as usize], v.to_vec())?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn read_data(&self, kind: u8, id: IdType, what: &str) -> Result<Vec<u8>> {
+        self.get(kind, id_to_bytes(id))?
+            .ok_or_else(|| Error::NotFound(format!("failed to read {} #{}", what, id)).into())
+    }
+
+    // The value of `id`, failing with Error::NotFound if there is none, or
+    // Error::Storage if it cannot be decoded.
+    fn read_value<T: DeserializeOwned>(&self, kind: u8, id: IdType, what: &str) -> Result<T> {
+        let data = self.read_data(kind, id, what)?;
+        bincode::deserialize(&data[..])
+            .map_err(|e| Error::Storage(format!("invalid {} #{}: {}", what, id, e)).into())
+    }
+
+    // Like read_value, for the values written by write_keyword_value.
+    fn read_keyword_value<T: KeywordData>(&self, kind: u8, id: IdType, what: &str) -> Result<T> {
+        let data = self.read_data(kind, id, what)?;
+        self.keywords
+            .decode(&data[..])
+            .map_err(|e| Error::Storage(format!("invalid {} #{}: {:#}", what, id, e)).into())
+    }
+
+    // Write the value with its keywords as their symbols, as SimChain does.
+    fn write_keyword_value<T: KeywordData>(
+        &mut self,
+        kind: u8,
+        id: IdType,
+        value: T,
+    ) -> Result<()> {
+        ensure!(!self.read_only, "the chain is opened read-only");
+        let (bytes, keywords) = self.keywords.encode(value)?;
+        for (sym, word) in keywords {
+            let key = prefixed(KEYWORD, &sym.to_le_bytes());
+            let word = self
+                .storage
+                .encode(KIND_NAMES[KEYWORD as usize], word.as_bytes())?;
+            self.db.insert(key, word.as_ref())?;
+        }
+        self.put(kind, id_to_bytes(id), bytes)
+    }
+
+    fn put(&mut self, kind: u8, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+        ensure!(!self.read_only, "the chain is opened read-only");
+        let key = prefixed(kind, key.as_ref());
+        let value = self
+            .storage
+            .encode(KIND_NAMES[kind as usize], value.as_ref())?;
+        match &mut self.batch {
+            Some(batch) => batch.insert(key, value.as_ref()),
+            None => {
+                self.db.insert(key, value.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, kind: u8, key: impl AsRef<[u8]>) -> Result<()> {
+        ensure!(!self.read_only, "the chain is opened read-only");
+        let key = prefixed(kind, key.as_ref());
+        match &mut self.batch {
+            Some(batch) => batch.remove(key),
+            None => {
+                self.db.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl LightNodeInterface for SledChain {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        self.get_parameter()
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.read_block_header(id)
+    }
+}
+
+impl ReadInterface for SledChain {
+    fn get_parameter(&self) -> Result<Parameter> {
+        Ok(self.param.clone())
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.read_value(BLOCK_HEADER, id, "block header")
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        self.read_keyword_value(BLOCK_DATA, id, "block data")
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        self.read_keyword_value(INTRA_INDEX, id, "index node")
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        self.read_keyword_value(SKIP_LIST, id, "skip list")
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        self.read_keyword_value(BTREE, id, "b-tree node")
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        self.read_keyword_value(OBJ, id, "object")
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        match self.get(KEYWORD, sym.to_le_bytes())? {
+            Some(data) => Ok(Some(String::from_utf8(data)?)),
+            None => Ok(None),
+        }
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        self.db
+            .scan_prefix([KEYWORD])
+            .map(|kv| {
+                let (k, v) = kv?;
+                let mut sym = [0u8; 4];
+                sym.copy_from_slice(&k[1..]);
+                let word = self
+                    .storage
+                    .decode(KIND_NAMES[KEYWORD as usize], v.to_vec())?;
+                Ok((SymbolType::from_le_bytes(sym), String::from_utf8(word)?))
+            })
+            .collect()
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        match self.get(META, ID_COUNTER_KEY)? {
+            Some(data) => Ok(bincode::deserialize::<IdCounter>(&data[..])?),
+            None => Ok(IdCounter::default()),
+        }
+    }
+    fn read_pruned_before(&self) -> Result<IdType> {
+        match self.get(META, PRUNED_BEFORE_KEY)? {
+            Some(data) => id_from_bytes(&data),
+            None => Ok(0),
+        }
+    }
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        if let Some(data) = self.get(META, LATEST_BLOCK_KEY)? {
+            return Ok(Some(id_from_bytes(&data)?));
+        }
+        // the chains created before the latest block was kept
+        let mut latest = None;
+        for kv in self.db.scan_prefix([BLOCK_HEADER]) {
+            let (k, _) = kv?;
+            latest = latest.max(Some(id_from_bytes(&k[1..])?));
+        }
+        Ok(latest)
+    }
+}
+
+impl WriteInterface for SledChain {
+    fn set_parameter(&mut self, param: Parameter) -> Result<()> {
+        write_param(&self.root_path, &param)?;
+        self.param = param;
+        Ok(())
+    }
+    fn write_block_header(&mut self, header: BlockHeader) -> Result<()> {
+        let bytes = bincode::serialize(&header)?;
+        self.put(BLOCK_HEADER, id_to_bytes(header.block_id), bytes)?;
+        if self.latest_block_id()? < Some(header.block_id) {
+            let bytes = id_to_bytes(header.block_id);
+            self.put(META, LATEST_BLOCK_KEY, bytes)?;
+        }
+        Ok(())
+    }
+    fn write_block_data(&mut self, data: BlockData) -> Result<()> {
+        let id = data.block_id;
+        self.write_keyword_value(BLOCK_DATA, id, data)
+    }
+    fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()> {
+        let id = node.id();
+        self.write_keyword_value(INTRA_INDEX, id, node)
+    }
+    fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()> {
+        let id = node.id;
+        self.write_keyword_value(SKIP_LIST, id, node)
+    }
+    fn write_btree_node(&mut self, node: BTreeNode) -> Result<()> {
+        let id = node.id;
+        self.write_keyword_value(BTREE, id, node)
+    }
+    fn write_object(&mut self, obj: Object) -> Result<()> {
+        let id = obj.id;
+        self.write_keyword_value(OBJ, id, obj)
+    }
+    fn write_keyword(&mut self, sym: SymbolType, word: String) -> Result<()> {
+        ensure!(!self.read_only, "the chain is opened read-only");
+        self.keywords.insert(sym, &word)?;
+        self.put(KEYWORD, sym.to_le_bytes(), word)?;
+        Ok(())
+    }
+    fn write_id_counter(&mut self, counter: IdCounter) -> Result<()> {
+        let bytes = bincode::serialize(&counter)?;
+        self.put(META, ID_COUNTER_KEY, bytes)?;
+        Ok(())
+    }
+    fn write_pruned_before(&mut self, block_id: IdType) -> Result<()> {
+        let bytes = id_to_bytes(block_id);
+        self.put(META, PRUNED_BEFORE_KEY, bytes)?;
+        Ok(())
+    }
+    fn delete_block_data(&mut self, id: IdType) -> Result<()> {
+        self.delete(BLOCK_DATA, id_to_bytes(id))
+    }
+    fn delete_intra_index_node(&mut self, id: IdType) -> Result<()> {
+        self.delete(INTRA_INDEX, id_to_bytes(id))
+    }
+    fn delete_skip_list_node(&mut self, id: IdType) -> Result<()> {
+        self.delete(SKIP_LIST, id_to_bytes(id))
+    }
+    fn delete_btree_node(&mut self, id: IdType) -> Result<()> {
+        self.delete(BTREE, id_to_bytes(id))
+    }
+    fn delete_object(&mut self, id: IdType) -> Result<()> {
+        self.delete(OBJ, id_to_bytes(id))
+    }
+    fn begin_block(&mut self) -> Result<()> {
+        self.batch = Some(sled::Batch::default());
+        Ok(())
+    }
+    fn commit_block(&mut self) -> Result<()> {
+        if let Some(batch) = self.batch.take() {
+            self.db.apply_batch(batch)?;
+        }
+        Ok(())
+    }
+}
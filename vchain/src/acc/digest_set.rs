@@ -1,63 +1,114 @@
 use crate::acc::utils::digest_to_prime_field;
+use crate::digest::Digest;
+use crate::par::{self, *};
 use crate::set::{MultiSet, SetElement};
 use ark_ff::PrimeField;
 use ark_poly::{univariate::DensePolynomial, UVPolynomial};
 use core::ops::Deref;
-use rayon::{self, prelude::*};
-use std::borrow::Cow;
+#[cfg(feature = "storage")]
+use lru::LruCache;
 
 #[derive(Debug, Clone, Default)]
 pub struct DigestSet<F: PrimeField> {
-    pub(crate) inner: Vec<(F, u32)>,
+    pub(crate) inner: Vec<(F, u64)>,
 }
 
 impl<F: PrimeField> DigestSet<F> {
+    // The elements are sorted by their digests, so the order is deterministic.
     pub fn new<T: SetElement>(input: &MultiSet<T>) -> Self {
-        let mut inner: Vec<(F, u32)> = Vec::with_capacity(input.len());
-        (0..input.len())
-            .into_par_iter()
-            .map(|i| {
-                let (k, v) = input.iter().nth(i).unwrap();
-                let d = k.to_digest();
-                (digest_to_prime_field(&d), *v)
-            })
+        let items: Vec<_> = input.iter().collect();
+        let mut digests: Vec<(Digest, u64)> = Vec::with_capacity(items.len());
+        items
+            .par_iter()
+            .map(|(k, v)| (k.to_digest(), **v))
+            .collect_into_vec(&mut digests);
+        digests.par_sort_unstable_by(|a, b| a.0 .0.cmp(&b.0 .0));
+        let mut inner: Vec<(F, u64)> = Vec::with_capacity(digests.len());
+        digests
+            .par_iter()
+            .map(|(d, v)| (digest_to_prime_field(d), *v))
             .collect_into_vec(&mut inner);
         Self { inner }
     }
 
+    // Compute prod (X + k)^v with a subproduct tree. The leaves are expanded directly and
+    // the upper levels are multiplied using FFT.
     pub fn expand_to_poly(&self) -> DensePolynomial<F> {
-        let mut inputs = Vec::new();
+        let mut roots: Vec<F> = Vec::new();
         for (k, v) in &self.inner {
             for _ in 0..*v {
-                inputs.push(DensePolynomial::from_coefficients_vec(vec![*k, F::one()]));
+                roots.push(*k);
             }
         }
 
-        fn expand<'a, F: PrimeField>(
-            polys: &'a [DensePolynomial<F>],
-        ) -> Cow<'a, DensePolynomial<F>> {
-            if polys.is_empty() {
-                return Cow::Owned(DensePolynomial::from_coefficients_vec(vec![F::one()]));
-            } else if polys.len() == 1 {
-                return Cow::Borrowed(&polys[0]);
+        const LEAF_SIZE: usize = 32;
+
+        fn expand_leaf<F: PrimeField>(roots: &[F]) -> DensePolynomial<F> {
+            let mut coeffs = Vec::with_capacity(roots.len() + 1);
+            coeffs.push(F::one());
+            // multiply by (X + r) in place
+            for r in roots {
+                coeffs.push(F::zero());
+                for i in (1..coeffs.len()).rev() {
+                    coeffs[i] = coeffs[i - 1] + coeffs[i] * r;
+                }
+                coeffs[0] *= r;
+            }
+            DensePolynomial::from_coefficients_vec(coeffs)
+        }
+
+        fn expand<F: PrimeField>(roots: &[F]) -> DensePolynomial<F> {
+            if roots.len() <= LEAF_SIZE {
+                return expand_leaf(roots);
             }
-            let mid = polys.len() / 2;
-            let (left, right) = rayon::join(|| expand(&polys[..mid]), || expand(&polys[mid..]));
-            Cow::Owned(left.as_ref() * right.as_ref())
+            let mid = roots.len() / 2;
+            let (left, right) = par::join(|| expand(&roots[..mid]), || expand(&roots[mid..]));
+            &left * &right
         }
 
-        expand(&inputs).into_owned()
+        expand(&roots)
     }
 }
 
 impl<F: PrimeField> Deref for DigestSet<F> {
-    type Target = Vec<(F, u32)>;
+    type Target = Vec<(F, u64)>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
     }
 }
 
+// Memoize DigestSet::new keyed by a digest of the multiset, e.g. the digest of its acc value.
+#[cfg(feature = "storage")]
+pub struct DigestSetCache<F: PrimeField> {
+    inner: LruCache<Digest, DigestSet<F>>,
+}
+
+#[cfg(feature = "storage")]
+impl<F: PrimeField> DigestSetCache<F> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: LruCache::new(capacity),
+        }
+    }
+
+    pub fn get<T: SetElement>(&mut self, key: Digest, set: &MultiSet<T>) -> &DigestSet<F> {
+        self.get_or_insert_with(key, || DigestSet::new(set))
+    }
+
+    // Like get, with the digest set computed by `f` if not cached.
+    pub fn get_or_insert_with(
+        &mut self,
+        key: Digest,
+        f: impl FnOnce() -> DigestSet<F>,
+    ) -> &DigestSet<F> {
+        if !self.inner.contains(&key) {
+            self.inner.put(key, f());
+        }
+        self.inner.get(&key).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,4 +132,43 @@ mod tests {
         ]);
         assert_eq!(set.expand_to_poly(), expect);
     }
+
+    #[test]
+    fn test_digest_set_order() {
+        let set1 = DigestSet::<Fr>::new(&MultiSet::from_vec((0..50).collect()));
+        let set2 = DigestSet::<Fr>::new(&MultiSet::from_vec((0..50).rev().collect()));
+        assert_eq!(set1.inner, set2.inner);
+    }
+
+    #[cfg(feature = "storage")]
+    #[test]
+    fn test_digest_set_cache() {
+        use crate::digest::Digestible;
+        let set1 = MultiSet::from_vec(vec![1, 2, 3]);
+        let set2 = MultiSet::from_vec(vec![4]);
+        let mut cache = DigestSetCache::<Fr>::new(1);
+        assert_eq!(cache.get(1.to_digest(), &set1).len(), 3);
+        // cached by key
+        assert_eq!(cache.get(1.to_digest(), &set2).len(), 3);
+        // evicted
+        assert_eq!(cache.get(2.to_digest(), &set2).len(), 1);
+        assert_eq!(cache.get(1.to_digest(), &set2).len(), 1);
+    }
+
+    #[test]
+    fn test_digest_to_poly_large() {
+        let set = DigestSet {
+            inner: (1..100u64).map(|i| (Fr::from(i), i % 3 + 1)).collect(),
+        };
+        let mut expect = DensePolynomial::from_coefficients_vec(vec![Fr::from(1u32)]);
+        for (k, v) in set.iter() {
+            for _ in 0..*v {
+                expect = expect.naive_mul(&DensePolynomial::from_coefficients_vec(vec![
+                    *k,
+                    Fr::from(1u32),
+                ]));
+            }
+        }
+        assert_eq!(set.expand_to_poly(), expect);
+    }
 }
@@ -1,4 +1,6 @@
 pub mod digest_set;
+pub mod msm;
+pub mod pub_key;
 pub mod serde_impl;
 pub mod utils;
 
@@ -6,18 +8,40 @@ pub use ark_bls12_381::{
     Bls12_381 as Curve, Fq12, Fr, G1Affine, G1Projective, G2Affine, G2Projective,
 };
 pub type DigestSet = digest_set::DigestSet<Fr>;
+#[cfg(feature = "storage")]
+pub type DigestSetCache = digest_set::DigestSetCache<Fr>;
 
-use crate::digest::{Digest, Digestible};
+use crate::digest::{concat_digest, Digest, Digestible};
+use crate::par::*;
 use crate::set::{MultiSet, SetElement};
+use crate::Error;
 use anyhow::{self, bail, ensure, Context};
-use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
-use ark_ff::{Field, One, PrimeField, ToBytes, Zero};
-use ark_poly::{univariate::DensePolynomial, Polynomial};
+use ark_ec::{
+    models::SWModelParameters, short_weierstrass_jacobian::GroupAffine, AffineCurve, PairingEngine,
+    ProjectiveCurve,
+};
+use ark_ff::{BigInteger256, Field, One, PrimeField, ToBytes, Zero};
+use ark_poly::{
+    univariate::{DenseOrSparsePolynomial, DensePolynomial},
+    Polynomial, UVPolynomial,
+};
 use core::any::Any;
 use core::str::FromStr;
-use rayon::prelude::*;
+use core::sync::atomic::{AtomicBool, Ordering};
+use msm::{msm_g1, msm_g2};
+use pub_key::{PubKeyConfig, PubKeyVec, WindowSize};
 use serde::{Deserialize, Serialize};
-use utils::{xgcd, FixedBaseCurvePow, FixedBaseScalarPow};
+use std::path::Path;
+use std::sync::Mutex;
+use utils::{
+    digest_to_prime_field, xgcd, CtFixedBaseCurvePow, FixedBaseCurvePow, FixedBaseScalarPow,
+};
+
+// max number of elements of the cross product in Acc2::gen_proof to be materialized at once
+#[cfg(test)]
+const ACC2_PROOF_CHUNK_SIZE: usize = 4;
+#[cfg(not(test))]
+const ACC2_PROOF_CHUNK_SIZE: usize = 1 << 16;
 
 #[cfg(test)]
 const GS_VEC_LEN: usize = 0;
@@ -29,39 +53,176 @@ lazy_static! {
     static ref PUB_Q: Fr = Fr::from_str("480721077433357505777975950918924200361380912084288598463024400624539293706").unwrap();
     // 128 bits
     static ref PRI_S: Fr = Fr::from_str("259535143263514268207918833918737523409").unwrap();
-    static ref G1_POWER: FixedBaseCurvePow<G1Projective> =
-        FixedBaseCurvePow::build(&G1Projective::prime_subgroup_generator());
-    static ref G2_POWER: FixedBaseCurvePow<G2Projective> =
-        FixedBaseCurvePow::build(&G2Projective::prime_subgroup_generator());
-    static ref PRI_S_POWER: FixedBaseScalarPow<Fr> = FixedBaseScalarPow::build(&PRI_S);
-    static ref G1_S_VEC: Vec<G1Affine> = {
-        info!("Initialize G1_S_VEC...");
-        let timer = howlong::ProcessCPUTimer::new();
-        let mut res: Vec<G1Affine> = Vec::with_capacity(GS_VEC_LEN);
-        (0..GS_VEC_LEN)
-            .into_par_iter()
-            .map(|i| get_g1s(Fr::from(i as u64)))
-            .collect_into_vec(&mut res);
-        info!("Done in {}.", timer.elapsed());
-        res
-    };
-    static ref G2_S_VEC: Vec<G2Affine> = {
-        info!("Initialize G2_S_VEC...");
-        let timer = howlong::ProcessCPUTimer::new();
-        let mut res: Vec<G2Affine> = Vec::with_capacity(GS_VEC_LEN);
-        (0..GS_VEC_LEN)
-            .into_par_iter()
-            .map(|i| get_g2s(Fr::from(i as u64)))
-            .collect_into_vec(&mut res);
-        info!("Done in {}.", timer.elapsed());
-        res
-    };
+    static ref G1_POWER: FixedBaseCurvePow<G1Projective> = load_or_build_table(
+        "g1_power.bin",
+        |c| c.curve_pow_window,
+        FixedBaseCurvePow::load,
+        FixedBaseCurvePow::save,
+        FixedBaseCurvePow::window,
+        |k| {
+            let base = G1Projective::prime_subgroup_generator();
+            let k = k.unwrap_or_else(|| FixedBaseCurvePow::tune_window(&base, &CURVE_POW_WINDOWS));
+            FixedBaseCurvePow::build_with_window(&base, k)
+        },
+    );
+    static ref G2_POWER: FixedBaseCurvePow<G2Projective> = load_or_build_table(
+        "g2_power.bin",
+        |c| c.curve_pow_window,
+        FixedBaseCurvePow::load,
+        FixedBaseCurvePow::save,
+        FixedBaseCurvePow::window,
+        |k| {
+            let base = G2Projective::prime_subgroup_generator();
+            let k = k.unwrap_or_else(|| FixedBaseCurvePow::tune_window(&base, &CURVE_POW_WINDOWS));
+            FixedBaseCurvePow::build_with_window(&base, k)
+        },
+    );
+    // the powers of the trapdoor s are only kept in memory, never in the cache dir
+    // next to the public tables
+    static ref PRI_S_POWER: FixedBaseScalarPow<Fr> = build_pri_s_power();
+    static ref PUB_KEY_CONFIG: Mutex<Option<PubKeyConfig>> = Mutex::new(None);
+    static ref G1_S_VEC: PubKeyVec<G1Affine> = init_pub_keys(get_g1s, "g1_s_vec.bin");
+    static ref G2_S_VEC: PubKeyVec<G2Affine> = init_pub_keys(get_g2s, "g2_s_vec.bin");
+    static ref G1_POWER_CT: CtFixedBaseCurvePow<G1Projective> =
+        CtFixedBaseCurvePow::from_table(&G1_POWER);
+    static ref G2_POWER_CT: CtFixedBaseCurvePow<G2Projective> =
+        CtFixedBaseCurvePow::from_table(&G2_POWER);
     static ref E_G_G: Fq12 = Curve::pairing(
         G1Affine::prime_subgroup_generator(),
         G2Affine::prime_subgroup_generator()
     );
 }
 
+static PUB_KEY_INITIALIZED: AtomicBool = AtomicBool::new(false);
+static CONSTANT_TIME_SK: AtomicBool = AtomicBool::new(false);
+
+/// Evaluate the accumulators with sk in constant time, so that the build latency does not
+/// leak the set data (e.g. the multiplicities). The loops, the table lookups and the group
+/// operations do not depend on the set data, but the underlying field arithmetic is not
+/// guaranteed to be constant time, see CtFixedBaseCurvePow.
+pub fn set_constant_time_sk(enable: bool) {
+    CONSTANT_TIME_SK.store(enable, Ordering::SeqCst);
+}
+
+fn sk_g1_power(x: &Fr) -> G1Projective {
+    if CONSTANT_TIME_SK.load(Ordering::SeqCst) {
+        G1_POWER_CT.apply(x)
+    } else {
+        G1_POWER.apply(x)
+    }
+}
+
+fn sk_g2_power(x: &Fr) -> G2Projective {
+    if CONSTANT_TIME_SK.load(Ordering::SeqCst) {
+        G2_POWER_CT.apply(x)
+    } else {
+        G2_POWER.apply(x)
+    }
+}
+
+// base^exp, iterating over all bits of exp in constant time mode.
+fn sk_pow(base: Fr, exp: u64) -> Fr {
+    if !CONSTANT_TIME_SK.load(Ordering::SeqCst) {
+        return base.pow(&[exp]);
+    }
+    let base_minus_one = base - Fr::one();
+    let mut res = Fr::one();
+    for i in (0..64).rev() {
+        res.square_in_place();
+        // multiply by either 1 or base without branching
+        res *= Fr::one() + base_minus_one * Fr::from((exp >> i) & 1);
+    }
+    res
+}
+
+/// Configure the pub key vectors. Must be called before any accumulator is computed.
+pub fn set_pub_key_config(config: PubKeyConfig) -> anyhow::Result<()> {
+    let mut cur = PUB_KEY_CONFIG.lock().unwrap();
+    ensure!(
+        !PUB_KEY_INITIALIZED.load(Ordering::SeqCst),
+        "pub keys are already initialized"
+    );
+    *cur = Some(config);
+    Ok(())
+}
+
+fn pub_key_config() -> PubKeyConfig {
+    let cur = PUB_KEY_CONFIG.lock().unwrap();
+    PUB_KEY_INITIALIZED.store(true, Ordering::SeqCst);
+    cur.clone()
+        .unwrap_or_else(|| PubKeyConfig::from_env(GS_VEC_LEN))
+}
+
+fn init_pub_keys<G: AffineCurve>(gen: fn(Fr) -> G, file_name: &str) -> PubKeyVec<G> {
+    let config = pub_key_config();
+    let path = config.cache_dir.map(|dir| dir.join(file_name));
+    PubKeyVec::new(gen, config.precompute_len, path).expect("failed to initialize pub keys")
+}
+
+const CURVE_POW_WINDOWS: [usize; 6] = [3, 4, 5, 6, 7, 8];
+const SCALAR_POW_WINDOWS: [usize; 6] = [6, 7, 8, 9, 10, 11];
+
+// Load the fixed base table from the cache dir if possible, otherwise build and persist it.
+// `build` takes the window size to use, none means to tune it.
+fn load_or_build_table<T>(
+    file_name: &str,
+    window: fn(&PubKeyConfig) -> WindowSize,
+    load: fn(&Path) -> anyhow::Result<T>,
+    save: fn(&T, &Path) -> anyhow::Result<()>,
+    table_window: fn(&T) -> usize,
+    build: impl FnOnce(Option<usize>) -> T,
+) -> T {
+    let config = pub_key_config();
+    let k = match window(&config) {
+        WindowSize::Fixed(k) => Some(k),
+        WindowSize::Auto => None,
+    };
+    let path = match config.cache_dir {
+        Some(dir) => dir.join(file_name),
+        None => return build(k),
+    };
+    if path.exists() {
+        match load(&path) {
+            // a tuned table is reused regardless of its window size
+            Ok(table) if k.is_none() || k == Some(table_window(&table)) => {
+                info!("load fixed base table from {:?}", path);
+                return table;
+            }
+            Ok(_) => info!("window size of {:?} changed, rebuild it", path),
+            Err(e) => warn!("failed to load fixed base table: {:?}", e),
+        }
+    }
+    let table = build(k);
+    if let Err(e) = save(&table, &path) {
+        warn!("failed to persist fixed base table: {:?}", e);
+    }
+    table
+}
+
+// The tables of s of the earlier versions, written to the cache dir, are removed
+// since they give away s.
+fn build_pri_s_power() -> FixedBaseScalarPow<Fr> {
+    let config = pub_key_config();
+    if let Some(dir) = config.cache_dir {
+        let path = dir.join("pri_s_power.bin");
+        if path.exists() {
+            warn!("remove the table of the secret key {:?}", path);
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("failed to remove {:?}: {:?}", path, e);
+            }
+        }
+    }
+    let k = match config.scalar_pow_window {
+        WindowSize::Fixed(k) => k,
+        WindowSize::Auto => FixedBaseScalarPow::tune_window(&*PRI_S, &SCALAR_POW_WINDOWS),
+    };
+    FixedBaseScalarPow::build_with_window(&PRI_S, k)
+}
+
+fn u128_to_scalar(x: u128) -> msm::ScalarRepr {
+    BigInteger256([x as u64, (x >> 64) as u64, 0, 0])
+}
+
 fn get_g1s(coeff: Fr) -> G1Affine {
     let si = PRI_S_POWER.apply(&coeff);
     G1_POWER.apply(&si).into_affine()
@@ -74,8 +235,14 @@ fn get_g2s(coeff: Fr) -> G2Affine {
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Type {
+    // a proof of two points of G2 for each set against the query set
     ACC1,
+    // a single proof of one point of G2 for all the sets against the query set,
+    // built from the n * m pairs of their elements
     ACC2,
+    // ACC1 with the proofs against the same query set verified as a batch, see
+    // Acc3Proof. It is not combined into a proof of constant size like ACC2.
+    ACC3,
 }
 
 pub trait Accumulator {
@@ -101,13 +268,22 @@ pub trait Accumulator {
     fn gen_proof(set1: &DigestSet, set2: &DigestSet) -> anyhow::Result<Self::Proof>;
 }
 
-pub trait AccumulatorProof: Eq + PartialEq {
+pub trait AccumulatorProof: Eq + PartialEq + Send + Sync {
     const TYPE: Type;
 
     fn gen_proof(set1: &DigestSet, set2: &DigestSet) -> anyhow::Result<Self>
     where
         Self: core::marker::Sized;
 
+    // Only ACC1 proves against the expanded polynomial of set1, see
+    // Acc1::gen_proof_with_poly.
+    fn gen_proof_with_poly(_poly1: &DensePolynomial<Fr>, _set2: &DigestSet) -> anyhow::Result<Self>
+    where
+        Self: core::marker::Sized,
+    {
+        bail!(Error::Proof("invalid operation".to_owned()));
+    }
+
     fn combine_proof(&mut self, other: &Self) -> anyhow::Result<()>;
 
     fn as_any(&self) -> &dyn Any;
@@ -125,23 +301,14 @@ impl Acc1 {
             idxes.push(i);
         }
 
-        let mut bases: Vec<G1Affine> = Vec::with_capacity(idxes.len());
-        let mut scalars: Vec<<Fr as PrimeField>::BigInt> = Vec::with_capacity(idxes.len());
-        (0..idxes.len())
-            .into_par_iter()
-            .map(|i| {
-                G1_S_VEC.get(i).copied().unwrap_or_else(|| {
-                    trace!("access g1 pub key at {}", i);
-                    get_g1s(Fr::from(i as u64))
-                })
-            })
-            .collect_into_vec(&mut bases);
+        let bases = G1_S_VEC.get_prefix(idxes.len());
+        let mut scalars: Vec<msm::ScalarRepr> = Vec::with_capacity(idxes.len());
         (0..idxes.len())
             .into_par_iter()
             .map(|i| poly.coeffs[i].into_repr())
             .collect_into_vec(&mut scalars);
 
-        VariableBaseMSM::multi_scalar_mul(&bases[..], &scalars[..]).into_affine()
+        msm_g1(&bases, &scalars).into_affine()
     }
 
     fn poly_to_g2(poly: DensePolynomial<Fr>) -> G2Affine {
@@ -153,23 +320,37 @@ impl Acc1 {
             idxes.push(i);
         }
 
-        let mut bases: Vec<G2Affine> = Vec::with_capacity(idxes.len());
-        let mut scalars: Vec<<Fr as PrimeField>::BigInt> = Vec::with_capacity(idxes.len());
-        (0..idxes.len())
-            .into_par_iter()
-            .map(|i| {
-                G2_S_VEC.get(i).copied().unwrap_or_else(|| {
-                    trace!("access g2 pub key at {}", i);
-                    get_g2s(Fr::from(i as u64))
-                })
-            })
-            .collect_into_vec(&mut bases);
+        let bases = G2_S_VEC.get_prefix(idxes.len());
+        let mut scalars: Vec<msm::ScalarRepr> = Vec::with_capacity(idxes.len());
         (0..idxes.len())
             .into_par_iter()
             .map(|i| poly.coeffs[i].into_repr())
             .collect_into_vec(&mut scalars);
 
-        VariableBaseMSM::multi_scalar_mul(&bases[..], &scalars[..]).into_affine()
+        msm_g2(&bases, &scalars).into_affine()
+    }
+
+    // The acc of a set whose polynomial is already expanded.
+    pub fn cal_acc_g1_poly(poly: &DensePolynomial<Fr>) -> G1Affine {
+        Self::poly_to_g1(poly.clone())
+    }
+
+    // Like gen_proof, with the polynomial of set1 expanded in advance, e.g. for
+    // the sets of the skip list nodes, which are proved against many queries.
+    pub fn gen_proof_with_poly(
+        poly1: &DensePolynomial<Fr>,
+        set2: &DigestSet,
+    ) -> anyhow::Result<Acc1Proof> {
+        let poly2 = set2.expand_to_poly();
+        let (g, x, y) = xgcd(poly1, poly2).context("failed to compute xgcd")?;
+        ensure!(
+            g.degree() == 0,
+            Error::Proof("cannot generate proof".to_owned())
+        );
+        Ok(Acc1Proof {
+            f1: Self::poly_to_g2(&x / &g),
+            f2: Self::poly_to_g2(&y / &g),
+        })
     }
 }
 
@@ -188,8 +369,12 @@ impl AccumulatorProof for Acc1Proof {
         Acc1::gen_proof(set1, set2)
     }
 
+    fn gen_proof_with_poly(poly1: &DensePolynomial<Fr>, set2: &DigestSet) -> anyhow::Result<Self> {
+        Acc1::gen_proof_with_poly(poly1, set2)
+    }
+
     fn combine_proof(&mut self, _other: &Self) -> anyhow::Result<()> {
-        bail!("invalid operation");
+        bail!(Error::Proof("invalid operation".to_owned()));
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -204,6 +389,120 @@ impl Acc1Proof {
             ((*acc2).into(), self.f2.into()),
         ]) == *E_G_G
     }
+
+    /// Verify proofs sharing the same `acc2` via a random linear combination. All `f2`
+    /// terms collapse into one pairing and a single final exponentiation is needed.
+    pub fn batch_verify(items: &[(&Self, &G1Affine)], acc2: &G1Affine) -> bool {
+        match items.len() {
+            0 => return true,
+            1 => return items[0].0.verify(items[0].1, acc2),
+            _ => {}
+        }
+        Self::batch_verify_groups(&[(items, acc2)])
+    }
+
+    /// Like `batch_verify`, but for several groups of proofs, each sharing its own `acc2`.
+    /// All the checks are done with one `product_of_pairings`.
+    pub fn batch_verify_groups(groups: &[(&[(&Self, &G1Affine)], &G1Affine)]) -> bool {
+        let num: usize = groups.iter().map(|(items, _)| items.len()).sum();
+        if num == 0 {
+            return true;
+        }
+
+        let mut coeffs = batch_coefficients(groups.iter().flat_map(|(items, acc2)| {
+            std::iter::once(acc2.to_digest()).chain(items.iter().flat_map(|(proof, acc1)| {
+                vec![acc1.to_digest(), proof.f1.to_digest(), proof.f2.to_digest()]
+            }))
+        }));
+        let mut pairs = Vec::with_capacity(num + groups.len());
+        let mut coeff_sum = Fr::zero();
+        for (items, acc2) in groups {
+            let mut f2 = G2Projective::zero();
+            for ((proof, acc1), coeff) in items.iter().zip(&mut coeffs) {
+                pairs.push((acc1.mul(coeff).into_affine().into(), proof.f1.into()));
+                f2 += &proof.f2.mul(coeff);
+                coeff_sum += &coeff;
+            }
+            pairs.push(((**acc2).into(), f2.into_affine().into()));
+        }
+        Curve::product_of_pairings(&pairs) == E_G_G.pow(coeff_sum.into_repr())
+    }
+}
+
+/// Derive an endless stream of pseudo-random coefficients bound to the given digests.
+fn batch_coefficients(input: impl Iterator<Item = Digest>) -> impl Iterator<Item = Fr> {
+    let seed = concat_digest(input);
+    (0u64..).map(move |i| {
+        let d = concat_digest([seed, i.to_digest()].iter().copied());
+        digest_to_prime_field(&d)
+    })
+}
+
+/// Proves each element of a set is not in an ACC1 accumulated set, one witness per element.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NonMembershipProof {
+    witnesses: Vec<NonMembershipWitness>,
+}
+
+// P_S(x) = q(x) * (x + elem) + r, where r != 0
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct NonMembershipWitness {
+    #[serde(with = "serde_impl")]
+    q: G2Affine,
+    #[serde(with = "serde_impl")]
+    r: Fr,
+}
+
+impl NonMembershipProof {
+    pub fn verify(&self, acc: &G1Affine, elems: &DigestSet) -> bool {
+        if self.witnesses.len() != elems.len() {
+            return false;
+        }
+        let g1s = get_g1s(Fr::one());
+        let g2 = G2Affine::prime_subgroup_generator();
+        elems
+            .par_iter()
+            .zip(self.witnesses.par_iter())
+            .all(|((elem, _), witness)| {
+                if witness.r.is_zero() {
+                    return false;
+                }
+                let mut x = G1_POWER.apply(elem);
+                x.add_assign_mixed(&g1s);
+                Curve::product_of_pairings(&[
+                    ((*acc).into(), g2.into()),
+                    ((-x.into_affine()).into(), witness.q.into()),
+                ]) == E_G_G.pow(witness.r.into_repr())
+            })
+    }
+}
+
+impl Acc1 {
+    pub fn gen_nonmembership_proof(
+        set: &DigestSet,
+        elems: &DigestSet,
+    ) -> anyhow::Result<NonMembershipProof> {
+        let poly: DenseOrSparsePolynomial<Fr> = set.expand_to_poly().into();
+        let witnesses = elems
+            .par_iter()
+            .map(|(elem, _)| {
+                let divisor = DensePolynomial::from_coefficients_vec(vec![*elem, Fr::one()]);
+                let (q, r) = poly
+                    .divide_with_q_and_r(&divisor.into())
+                    .context("failed to divide polynomial")?;
+                let r = r.coeffs.first().copied().unwrap_or_else(Fr::zero);
+                ensure!(
+                    !r.is_zero(),
+                    Error::Proof("cannot generate proof for a member".to_owned())
+                );
+                Ok(NonMembershipWitness {
+                    q: Self::poly_to_g2(q),
+                    r,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(NonMembershipProof { witnesses })
+    }
 }
 
 impl Accumulator for Acc1 {
@@ -213,13 +512,9 @@ impl Accumulator for Acc1 {
     fn cal_acc_g1_sk_d(set: &DigestSet) -> G1Affine {
         let x = set
             .par_iter()
-            .map(|(v, exp)| {
-                let s = *PRI_S + v;
-                let exp = [*exp as u64];
-                s.pow(&exp)
-            })
-            .reduce(Fr::one, |a, b| a * &b);
-        G1_POWER.apply(&x).into_affine()
+            .map(|(v, exp)| sk_pow(*PRI_S + v, *exp))
+            .product::<Fr>();
+        sk_g1_power(&x).into_affine()
     }
     fn cal_acc_g1_d(set: &DigestSet) -> G1Affine {
         let poly = set.expand_to_poly();
@@ -228,27 +523,16 @@ impl Accumulator for Acc1 {
     fn cal_acc_g2_sk_d(set: &DigestSet) -> G2Affine {
         let x = set
             .par_iter()
-            .map(|(v, exp)| {
-                let s = *PRI_S + v;
-                let exp = [*exp as u64];
-                s.pow(&exp)
-            })
-            .reduce(Fr::one, |a, b| a * &b);
-        G2_POWER.apply(&x).into_affine()
+            .map(|(v, exp)| sk_pow(*PRI_S + v, *exp))
+            .product::<Fr>();
+        sk_g2_power(&x).into_affine()
     }
     fn cal_acc_g2_d(set: &DigestSet) -> G2Affine {
         let poly = set.expand_to_poly();
         Self::poly_to_g2(poly)
     }
     fn gen_proof(set1: &DigestSet, set2: &DigestSet) -> anyhow::Result<Self::Proof> {
-        let poly1 = set1.expand_to_poly();
-        let poly2 = set2.expand_to_poly();
-        let (g, x, y) = xgcd(poly1, poly2).context("failed to compute xgcd")?;
-        ensure!(g.degree() == 0, "cannot generate proof");
-        Ok(Acc1Proof {
-            f1: Self::poly_to_g2(&x / &g),
-            f2: Self::poly_to_g2(&y / &g),
-        })
+        Self::gen_proof_with_poly(&set1.expand_to_poly(), set2)
     }
 }
 
@@ -298,21 +582,21 @@ impl Accumulator for Acc2 {
                 let s = PRI_S_POWER.apply(a);
                 s * &Fr::from(*b)
             })
-            .reduce(Fr::zero, |a, b| a + &b);
-        G1_POWER.apply(&x).into_affine()
+            .sum::<Fr>();
+        sk_g1_power(&x).into_affine()
     }
     fn cal_acc_g1_d(set: &DigestSet) -> G1Affine {
         let mut bases: Vec<G1Affine> = Vec::with_capacity(set.len());
-        let mut scalars: Vec<<Fr as PrimeField>::BigInt> = Vec::with_capacity(set.len());
+        let mut scalars: Vec<msm::ScalarRepr> = Vec::with_capacity(set.len());
         (0..set.len())
             .into_par_iter()
             .map(|i| get_g1s(set[i].0))
             .collect_into_vec(&mut bases);
         (0..set.len())
             .into_par_iter()
-            .map(|i| <Fr as PrimeField>::BigInt::from(set[i].1 as u64))
+            .map(|i| <Fr as PrimeField>::BigInt::from(set[i].1))
             .collect_into_vec(&mut scalars);
-        VariableBaseMSM::multi_scalar_mul(&bases[..], &scalars[..]).into_affine()
+        msm_g1(&bases, &scalars).into_affine()
     }
     fn cal_acc_g2_sk_d(set: &DigestSet) -> G2Affine {
         let x = set
@@ -321,61 +605,166 @@ impl Accumulator for Acc2 {
                 let s = PRI_S_POWER.apply(&(*PUB_Q - a));
                 s * &Fr::from(*b)
             })
-            .reduce(Fr::zero, |a, b| a + &b);
-        G2_POWER.apply(&x).into_affine()
+            .sum::<Fr>();
+        sk_g2_power(&x).into_affine()
     }
     fn cal_acc_g2_d(set: &DigestSet) -> G2Affine {
         let mut bases: Vec<G2Affine> = Vec::with_capacity(set.len());
-        let mut scalars: Vec<<Fr as PrimeField>::BigInt> = Vec::with_capacity(set.len());
+        let mut scalars: Vec<msm::ScalarRepr> = Vec::with_capacity(set.len());
         (0..set.len())
             .into_par_iter()
             .map(|i| get_g2s(*PUB_Q - &set[i].0))
             .collect_into_vec(&mut bases);
         (0..set.len())
             .into_par_iter()
-            .map(|i| <Fr as PrimeField>::BigInt::from(set[i].1 as u64))
+            .map(|i| <Fr as PrimeField>::BigInt::from(set[i].1))
             .collect_into_vec(&mut scalars);
-        VariableBaseMSM::multi_scalar_mul(&bases[..], &scalars[..]).into_affine()
+        msm_g2(&bases, &scalars).into_affine()
     }
     fn gen_proof(set1: &DigestSet, set2: &DigestSet) -> anyhow::Result<Self::Proof> {
         let produce_size = set1.len() * set2.len();
-        let mut product: Vec<(Fr, u64)> = Vec::with_capacity(produce_size);
-        (0..produce_size)
-            .into_par_iter()
-            .map(|i| {
-                let set1idx = i / set2.len();
-                let set2idx = i % set2.len();
-                let (s1, q1) = set1[set1idx];
-                let (s2, q2) = set2[set2idx];
-                (*PUB_Q + &s1 - &s2, (q1 * q2) as u64)
-            })
-            .collect_into_vec(&mut product);
-        if product.par_iter().any(|(x, _)| *x == *PUB_Q) {
-            bail!("cannot generate proof");
-        }
+        let mut f = G1Projective::zero();
+        // process the cross product in windows to bound the memory usage
+        for start in (0..produce_size).step_by(ACC2_PROOF_CHUNK_SIZE) {
+            let end = (start + ACC2_PROOF_CHUNK_SIZE).min(produce_size);
+            let product: Vec<(Fr, u128)> = (start..end)
+                .into_par_iter()
+                .map(|i| {
+                    let set1idx = i / set2.len();
+                    let set2idx = i % set2.len();
+                    let (s1, q1) = set1[set1idx];
+                    let (s2, q2) = set2[set2idx];
+                    let q = u128::from(q1)
+                        .checked_mul(u128::from(q2))
+                        .context("multiplicity overflow")?;
+                    Ok((*PUB_Q + &s1 - &s2, q))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            if product.par_iter().any(|(x, _)| *x == *PUB_Q) {
+                bail!(Error::Proof("cannot generate proof".to_owned()));
+            }
 
-        let mut bases: Vec<G1Affine> = Vec::with_capacity(produce_size);
-        let mut scalars: Vec<<Fr as PrimeField>::BigInt> = Vec::with_capacity(produce_size);
-        (0..produce_size)
-            .into_par_iter()
-            .map(|i| get_g1s(product[i].0))
-            .collect_into_vec(&mut bases);
-        (0..produce_size)
-            .into_par_iter()
-            .map(|i| <Fr as PrimeField>::BigInt::from(product[i].1))
-            .collect_into_vec(&mut scalars);
-        let f = VariableBaseMSM::multi_scalar_mul(&bases[..], &scalars[..]).into_affine();
+            let mut bases: Vec<G1Affine> = Vec::with_capacity(product.len());
+            let mut scalars: Vec<msm::ScalarRepr> = Vec::with_capacity(product.len());
+            product
+                .par_iter()
+                .map(|(x, _)| get_g1s(*x))
+                .collect_into_vec(&mut bases);
+            product
+                .par_iter()
+                .map(|(_, q)| u128_to_scalar(*q))
+                .collect_into_vec(&mut scalars);
+            f += &msm_g1(&bases, &scalars);
+        }
+        let f = f.into_affine();
         Ok(Acc2Proof { f })
     }
 }
 
+// Batch-verified ACC1: the same accumulator values and proofs as Acc1, with the proofs of the
+// sets against the same query set verified as one batch.
+pub struct Acc3;
+
+// The Acc1 proofs (f1_i, f2_i) of the sets acc1_i against the same query set acc2, kept side by
+// side by combine_proof, so that the proofs of n sets still take 2n points of G2, like n proofs
+// of ACC1, and nothing is aggregated. They are verified together in a single product of n + 1
+// pairings rather than 2n, each weighted by r_i = H(acc1_i, f1_i, f2_i, acc2, i). The weights
+// are only fixed by the verifier, once the proofs and the accs are all given, so that the errors
+// of several invalid proofs cannot cancel each other out.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Acc3Proof {
+    #[serde(with = "serde_impl::vec")]
+    f1s: Vec<G2Affine>,
+    #[serde(with = "serde_impl::vec")]
+    f2s: Vec<G2Affine>,
+}
+
+fn acc3_coeff(acc1: &G1Affine, f1: &G2Affine, f2: &G2Affine, acc2: &G1Affine, i: usize) -> Fr {
+    let digest = concat_digest(
+        [
+            acc1.to_digest(),
+            f1.to_digest(),
+            f2.to_digest(),
+            acc2.to_digest(),
+            (i as u64).to_digest(),
+        ]
+        .iter()
+        .copied(),
+    );
+    digest_to_prime_field(&digest)
+}
+
+impl AccumulatorProof for Acc3Proof {
+    const TYPE: Type = Type::ACC3;
+
+    fn gen_proof(set1: &DigestSet, set2: &DigestSet) -> anyhow::Result<Self> {
+        Acc3::gen_proof(set1, set2)
+    }
+
+    fn combine_proof(&mut self, other: &Self) -> anyhow::Result<()> {
+        self.f1s.extend_from_slice(&other.f1s);
+        self.f2s.extend_from_slice(&other.f2s);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Acc3Proof {
+    pub fn verify(&self, acc1s: &[G1Affine], acc2: &G1Affine) -> bool {
+        if self.f1s.len() != acc1s.len() || self.f2s.len() != acc1s.len() {
+            return false;
+        }
+        let mut pairs = Vec::with_capacity(acc1s.len() + 1);
+        let mut scalars: Vec<msm::ScalarRepr> = Vec::with_capacity(acc1s.len());
+        let mut coeff_sum = Fr::zero();
+        for (i, ((f1, f2), acc1)) in self.f1s.iter().zip(&self.f2s).zip(acc1s).enumerate() {
+            let coeff = acc3_coeff(acc1, f1, f2, acc2, i);
+            pairs.push((acc1.mul(coeff).into_affine().into(), (*f1).into()));
+            scalars.push(coeff.into_repr());
+            coeff_sum += &coeff;
+        }
+        let f2 = msm_g2(&self.f2s, &scalars).into_affine();
+        pairs.push(((*acc2).into(), f2.into()));
+        Curve::product_of_pairings(&pairs) == E_G_G.pow(coeff_sum.into_repr())
+    }
+}
+
+impl Accumulator for Acc3 {
+    const TYPE: Type = Type::ACC3;
+    type Proof = Acc3Proof;
+
+    fn cal_acc_g1_sk_d(set: &DigestSet) -> G1Affine {
+        Acc1::cal_acc_g1_sk_d(set)
+    }
+    fn cal_acc_g1_d(set: &DigestSet) -> G1Affine {
+        Acc1::cal_acc_g1_d(set)
+    }
+    fn cal_acc_g2_sk_d(set: &DigestSet) -> G2Affine {
+        Acc1::cal_acc_g2_sk_d(set)
+    }
+    fn cal_acc_g2_d(set: &DigestSet) -> G2Affine {
+        Acc1::cal_acc_g2_d(set)
+    }
+    fn gen_proof(set1: &DigestSet, set2: &DigestSet) -> anyhow::Result<Self::Proof> {
+        let Acc1Proof { f1, f2 } = Acc1::gen_proof(set1, set2)?;
+        Ok(Acc3Proof {
+            f1s: vec![f1],
+            f2s: vec![f2],
+        })
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Proof {
     ACC1(Box<Acc1Proof>),
     ACC2(Box<Acc2Proof>),
+    ACC3(Box<Acc3Proof>),
 }
 
-impl Digestible for G1Affine {
+impl<P: SWModelParameters> Digestible for GroupAffine<P> {
     fn to_digest(&self) -> Digest {
         let mut buf = Vec::<u8>::new();
         self.write(&mut buf)
@@ -400,6 +789,7 @@ mod tests {
         assert_eq!(Acc1::cal_acc_g2(&set), Acc1::cal_acc_g2_sk(&set));
         assert_eq!(Acc2::cal_acc_g1(&set), Acc2::cal_acc_g1_sk(&set));
         assert_eq!(Acc2::cal_acc_g2(&set), Acc2::cal_acc_g2_sk(&set));
+        assert_eq!(Acc3::cal_acc_g1(&set), Acc3::cal_acc_g1_sk(&set));
     }
 
     #[test]
@@ -413,6 +803,61 @@ mod tests {
         let acc2 = Acc1::cal_acc_g1_sk_d(&set2);
         assert!(proof.verify(&acc1, &acc2));
         assert!(Acc1::gen_proof(&set1, &set3).is_err());
+        let poly1 = set1.expand_to_poly();
+        assert_eq!(Acc1::gen_proof_with_poly(&poly1, &set2).unwrap(), proof);
+        assert_eq!(Acc1::cal_acc_g1_poly(&poly1), Acc1::cal_acc_g1_d(&set1));
+    }
+
+    #[test]
+    fn test_acc1_proof_batch_verify() {
+        init_logger();
+        let query = DigestSet::new(&MultiSet::from_vec(vec![7, 8]));
+        let query_acc = Acc1::cal_acc_g1_sk_d(&query);
+        let sets: Vec<_> = vec![vec![1, 2], vec![3], vec![4, 5, 6]]
+            .into_iter()
+            .map(|v| DigestSet::new(&MultiSet::from_vec(v)))
+            .collect();
+        let accs: Vec<_> = sets.iter().map(Acc1::cal_acc_g1_sk_d).collect();
+        let proofs: Vec<_> = sets
+            .iter()
+            .map(|s| Acc1::gen_proof(s, &query).unwrap())
+            .collect();
+        let items: Vec<_> = proofs.iter().zip(accs.iter()).collect();
+        assert!(Acc1Proof::batch_verify(&items, &query_acc));
+
+        let mut bad_items = items.clone();
+        bad_items[1].1 = &accs[2];
+        assert!(!Acc1Proof::batch_verify(&bad_items, &query_acc));
+
+        let query2 = DigestSet::new(&MultiSet::from_vec(vec![9]));
+        let query2_acc = Acc1::cal_acc_g1_sk_d(&query2);
+        let proofs2: Vec<_> = sets
+            .iter()
+            .map(|s| Acc1::gen_proof(s, &query2).unwrap())
+            .collect();
+        let items2: Vec<_> = proofs2.iter().zip(accs.iter()).collect();
+        assert!(Acc1Proof::batch_verify_groups(&[
+            (&items, &query_acc),
+            (&items2, &query2_acc)
+        ]));
+        assert!(!Acc1Proof::batch_verify_groups(&[
+            (&items, &query2_acc),
+            (&items2, &query_acc)
+        ]));
+    }
+
+    #[test]
+    fn test_nonmembership_proof() {
+        init_logger();
+        let set = DigestSet::new(&MultiSet::from_vec(vec![1, 2, 2, 3]));
+        let acc = Acc1::cal_acc_g1_sk_d(&set);
+        let elems = DigestSet::new(&MultiSet::from_vec(vec![4, 5]));
+        let proof = Acc1::gen_nonmembership_proof(&set, &elems).unwrap();
+        assert!(proof.verify(&acc, &elems));
+        let other_elems = DigestSet::new(&MultiSet::from_vec(vec![4, 6]));
+        assert!(!proof.verify(&acc, &other_elems));
+        let members = DigestSet::new(&MultiSet::from_vec(vec![2, 4]));
+        assert!(Acc1::gen_nonmembership_proof(&set, &members).is_err());
     }
 
     #[test]
@@ -428,6 +873,18 @@ mod tests {
         assert!(Acc2::gen_proof(&set1, &set3).is_err());
     }
 
+    #[test]
+    fn test_acc2_proof_large_multiplicity() {
+        init_logger();
+        let set1 = DigestSet::new(&MultiSet::from_tuple_vec(vec![(1, u64::MAX), (2, 3)]));
+        let set2 = DigestSet::new(&MultiSet::from_tuple_vec(vec![(4, u64::MAX)]));
+        let proof = Acc2::gen_proof(&set1, &set2).unwrap();
+        let acc1 = Acc2::cal_acc_g1_sk_d(&set1);
+        let acc2 = Acc2::cal_acc_g2_sk_d(&set2);
+        assert!(proof.verify(&acc1, &acc2));
+        assert_eq!(acc1, Acc2::cal_acc_g1_d(&set1));
+    }
+
     #[test]
     fn test_acc2_proof_sum() {
         init_logger();
@@ -447,4 +904,74 @@ mod tests {
         };
         assert!(proof1.verify(&acc1, &acc4));
     }
+
+    #[test]
+    fn test_acc3_proof_combine() {
+        init_logger();
+        let query = DigestSet::new(&MultiSet::from_vec(vec![7, 8]));
+        let query_acc = Acc3::cal_acc_g1_sk_d(&query);
+        let set1 = DigestSet::new(&MultiSet::from_vec(vec![1, 2, 3]));
+        let set2 = DigestSet::new(&MultiSet::from_vec(vec![4, 5]));
+        let set3 = DigestSet::new(&MultiSet::from_vec(vec![7]));
+        let acc1 = Acc3::cal_acc_g1_sk_d(&set1);
+        let acc2 = Acc3::cal_acc_g1_sk_d(&set2);
+        let mut proof = Acc3::gen_proof(&set1, &query).unwrap();
+        assert!(proof.verify(&[acc1], &query_acc));
+        let proof2 = Acc3::gen_proof(&set2, &query).unwrap();
+        proof.combine_proof(&proof2).unwrap();
+        assert!(proof.verify(&[acc1, acc2], &query_acc));
+        assert!(!proof.verify(&[acc2, acc1], &query_acc));
+        assert!(!proof.verify(&[acc1], &query_acc));
+        assert!(Acc3::gen_proof(&set3, &query).is_err());
+    }
+
+    #[test]
+    fn test_acc3_proof_invalid_component() {
+        init_logger();
+        let query = DigestSet::new(&MultiSet::from_vec(vec![7, 8]));
+        let query_acc = Acc3::cal_acc_g1_sk_d(&query);
+        let set1 = DigestSet::new(&MultiSet::from_vec(vec![1, 2, 3]));
+        let set2 = DigestSet::new(&MultiSet::from_vec(vec![4, 5]));
+        let set3 = DigestSet::new(&MultiSet::from_vec(vec![6, 7]));
+        let acc1 = Acc3::cal_acc_g1_sk_d(&set1);
+        let acc2 = Acc3::cal_acc_g1_sk_d(&set2);
+        let acc3 = Acc3::cal_acc_g1_sk_d(&set3);
+        let mut proof = Acc3::gen_proof(&set1, &query).unwrap();
+        proof
+            .combine_proof(&Acc3::gen_proof(&set2, &query).unwrap())
+            .unwrap();
+        assert!(proof.verify(&[acc1, acc2], &query_acc));
+
+        // set3 intersects the query, so its proof is made against another set, and hidden
+        // among the valid ones
+        let mut forged = Acc3::gen_proof(&set1, &query).unwrap();
+        forged
+            .combine_proof(&Acc3::gen_proof(&set2, &query).unwrap())
+            .unwrap();
+        forged.combine_proof(&proof).unwrap();
+        assert!(!forged.verify(&[acc1, acc3, acc1, acc2], &query_acc));
+        assert!(forged.verify(&[acc1, acc2, acc1, acc2], &query_acc));
+
+        // nor can an error in one proof be made up for by another
+        let mut shifted = proof.clone();
+        let delta = G2Affine::prime_subgroup_generator();
+        shifted.f2s[0] = (shifted.f2s[0].into_projective() + delta.into_projective()).into_affine();
+        shifted.f2s[1] = (shifted.f2s[1].into_projective() - delta.into_projective()).into_affine();
+        assert!(!shifted.verify(&[acc1, acc2], &query_acc));
+    }
+
+    #[test]
+    fn test_constant_time_sk() {
+        init_logger();
+        let set = DigestSet::new(&MultiSet::from_vec(vec![1, 1, 2, 3, 3, 3, 4]));
+        let acc1 = Acc1::cal_acc_g1_sk_d(&set);
+        let acc2 = Acc2::cal_acc_g2_sk_d(&set);
+        set_constant_time_sk(true);
+        let ct_acc1 = Acc1::cal_acc_g1_sk_d(&set);
+        let ct_acc2 = Acc2::cal_acc_g2_sk_d(&set);
+        set_constant_time_sk(false);
+        assert_eq!(ct_acc1, acc1);
+        assert_eq!(ct_acc2, acc2);
+        assert_eq!(acc1, Acc1::cal_acc_g1_d(&set));
+    }
 }
@@ -0,0 +1,166 @@
+use super::{Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::msm::VariableBaseMSM;
+use ark_ff::PrimeField;
+use std::sync::RwLock;
+
+pub type ScalarRepr = <Fr as PrimeField>::BigInt;
+
+// Multi-scalar multiplication used to compute the accumulators and proofs.
+// A GPU backend, e.g. GpuMsm, can be plugged in with `set_msm_backend`.
+pub trait MsmBackend: Send + Sync {
+    fn msm_g1(&self, bases: &[G1Affine], scalars: &[ScalarRepr]) -> G1Projective;
+    fn msm_g2(&self, bases: &[G2Affine], scalars: &[ScalarRepr]) -> G2Projective;
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CpuMsm;
+
+impl MsmBackend for CpuMsm {
+    fn msm_g1(&self, bases: &[G1Affine], scalars: &[ScalarRepr]) -> G1Projective {
+        VariableBaseMSM::multi_scalar_mul(bases, scalars)
+    }
+    fn msm_g2(&self, bases: &[G2Affine], scalars: &[ScalarRepr]) -> G2Projective {
+        VariableBaseMSM::multi_scalar_mul(bases, scalars)
+    }
+}
+
+// The kernels of a GPU library, e.g. bound over FFI to CUDA or OpenCL by the application, as
+// this crate links no GPU library itself. The MSMs of fewer than `min_len` bases stay on the
+// CPU, where copying them to the device would cost more than it saves, and so do those the
+// kernels fail on, or have no kernel for.
+#[cfg(feature = "gpu")]
+pub type GpuKernel<G, P> = fn(&[G], &[ScalarRepr]) -> anyhow::Result<P>;
+
+#[cfg(feature = "gpu")]
+#[derive(Debug, Clone, Copy)]
+pub struct GpuMsm {
+    pub min_len: usize,
+    pub kernel_g1: Option<GpuKernel<G1Affine, G1Projective>>,
+    pub kernel_g2: Option<GpuKernel<G2Affine, G2Projective>>,
+}
+
+#[cfg(feature = "gpu")]
+impl GpuMsm {
+    pub const DEFAULT_MIN_LEN: usize = 1 << 14;
+
+    pub fn new(
+        kernel_g1: Option<GpuKernel<G1Affine, G1Projective>>,
+        kernel_g2: Option<GpuKernel<G2Affine, G2Projective>>,
+    ) -> Self {
+        Self {
+            min_len: Self::DEFAULT_MIN_LEN,
+            kernel_g1,
+            kernel_g2,
+        }
+    }
+
+    fn run<G, P>(
+        &self,
+        kernel: Option<GpuKernel<G, P>>,
+        bases: &[G],
+        scalars: &[ScalarRepr],
+        cpu: impl FnOnce() -> P,
+    ) -> P {
+        match kernel {
+            Some(kernel) if bases.len() >= self.min_len => match kernel(bases, scalars) {
+                Ok(res) => res,
+                Err(e) => {
+                    warn!(
+                        "gpu msm of {} bases failed, fall back to cpu: {}",
+                        bases.len(),
+                        e
+                    );
+                    cpu()
+                }
+            },
+            _ => cpu(),
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+impl MsmBackend for GpuMsm {
+    fn msm_g1(&self, bases: &[G1Affine], scalars: &[ScalarRepr]) -> G1Projective {
+        self.run(self.kernel_g1, bases, scalars, || {
+            CpuMsm.msm_g1(bases, scalars)
+        })
+    }
+    fn msm_g2(&self, bases: &[G2Affine], scalars: &[ScalarRepr]) -> G2Projective {
+        self.run(self.kernel_g2, bases, scalars, || {
+            CpuMsm.msm_g2(bases, scalars)
+        })
+    }
+}
+
+lazy_static! {
+    static ref MSM_BACKEND: RwLock<Box<dyn MsmBackend>> = RwLock::new(Box::new(CpuMsm));
+}
+
+pub fn set_msm_backend(backend: Box<dyn MsmBackend>) {
+    *MSM_BACKEND.write().unwrap() = backend;
+}
+
+pub fn msm_g1(bases: &[G1Affine], scalars: &[ScalarRepr]) -> G1Projective {
+    debug_assert_eq!(bases.len(), scalars.len());
+    MSM_BACKEND.read().unwrap().msm_g1(bases, scalars)
+}
+
+pub fn msm_g2(bases: &[G2Affine], scalars: &[ScalarRepr]) -> G2Projective {
+    debug_assert_eq!(bases.len(), scalars.len());
+    MSM_BACKEND.read().unwrap().msm_g2(bases, scalars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ec::{AffineCurve, ProjectiveCurve};
+    use ark_ff::{UniformRand, Zero};
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_cpu_msm() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(123_456_789u64);
+        let bases: Vec<G1Affine> = (0..10)
+            .map(|_| G1Projective::rand(&mut rng).into_affine())
+            .collect();
+        let scalars: Vec<ScalarRepr> = (0..10).map(|_| Fr::rand(&mut rng).into_repr()).collect();
+        let mut expect = G1Projective::zero();
+        for (base, scalar) in bases.iter().zip(scalars.iter()) {
+            expect += &base.mul(*scalar);
+        }
+        assert_eq!(CpuMsm.msm_g1(&bases, &scalars), expect);
+        assert_eq!(msm_g1(&bases, &scalars), expect);
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_gpu_msm() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        // computes on the cpu, but only for even lengths
+        fn kernel(bases: &[G1Affine], scalars: &[ScalarRepr]) -> anyhow::Result<G1Projective> {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            anyhow::ensure!(bases.len().is_multiple_of(2), "odd length");
+            Ok(CpuMsm.msm_g1(bases, scalars))
+        }
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(123_456_789u64);
+        let bases: Vec<G1Affine> = (0..10)
+            .map(|_| G1Projective::rand(&mut rng).into_affine())
+            .collect();
+        let scalars: Vec<ScalarRepr> = (0..10).map(|_| Fr::rand(&mut rng).into_repr()).collect();
+        let gpu = GpuMsm {
+            min_len: 4,
+            ..GpuMsm::new(Some(kernel), None)
+        };
+        for len in &[2, 9, 10] {
+            let len = *len;
+            let expect = CpuMsm.msm_g1(&bases[..len], &scalars[..len]);
+            assert_eq!(gpu.msm_g1(&bases[..len], &scalars[..len]), expect);
+        }
+        // the one below min_len stays on the cpu
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    }
+}
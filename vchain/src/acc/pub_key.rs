@@ -0,0 +1,213 @@
+use super::utils::{FixedBaseCurvePow, FixedBaseScalarPow};
+use super::{Fr, G1Projective};
+use crate::par::*;
+use crate::timer::Timer;
+use anyhow::{bail, ensure, Context, Result};
+use ark_ec::AffineCurve;
+use core::str::FromStr;
+use memmap2::Mmap;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+const MAGIC: &[u8; 8] = b"vchainpk";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WindowSize {
+    Fixed(usize),
+    // pick the fastest one on the current machine
+    Auto,
+}
+
+impl FromStr for WindowSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(WindowSize::Auto);
+        }
+        let k: usize = s.parse().context("invalid window size")?;
+        ensure!((1..=16).contains(&k), "window size should be in 1..=16");
+        Ok(WindowSize::Fixed(k))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PubKeyConfig {
+    // number of g^{s^i} to precompute when the keys are first used
+    pub precompute_len: usize,
+    // directory to persist the computed keys, none means memory only
+    pub cache_dir: Option<PathBuf>,
+    // window size of the fixed base tables for g1/g2 and s
+    pub curve_pow_window: WindowSize,
+    pub scalar_pow_window: WindowSize,
+}
+
+impl PubKeyConfig {
+    // read from VCHAIN_PUB_KEY_LEN, VCHAIN_PUB_KEY_CACHE_DIR, VCHAIN_CURVE_POW_WINDOW
+    // and VCHAIN_SCALAR_POW_WINDOW
+    pub fn from_env(default_len: usize) -> Self {
+        let precompute_len = std::env::var("VCHAIN_PUB_KEY_LEN")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_len);
+        let cache_dir = std::env::var_os("VCHAIN_PUB_KEY_CACHE_DIR").map(PathBuf::from);
+        let window = |key: &str, default: usize| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(WindowSize::Fixed(default))
+        };
+        Self {
+            precompute_len,
+            cache_dir,
+            curve_pow_window: window(
+                "VCHAIN_CURVE_POW_WINDOW",
+                FixedBaseCurvePow::<G1Projective>::DEFAULT_WINDOW,
+            ),
+            scalar_pow_window: window(
+                "VCHAIN_SCALAR_POW_WINDOW",
+                FixedBaseScalarPow::<Fr>::DEFAULT_WINDOW,
+            ),
+        }
+    }
+}
+
+// A prefix of pub keys [g^{s^0}, g^{s^1}, ...], extended on demand.
+pub struct PubKeyVec<G: AffineCurve> {
+    keys: RwLock<Vec<G>>,
+    path: Option<PathBuf>,
+    gen: fn(Fr) -> G,
+}
+
+impl<G: AffineCurve> PubKeyVec<G> {
+    pub fn new(gen: fn(Fr) -> G, precompute_len: usize, path: Option<PathBuf>) -> Result<Self> {
+        let keys = match &path {
+            Some(path) if path.exists() => {
+                info!("load pub keys from {:?}", path);
+                Self::load(path)?
+            }
+            _ => Vec::new(),
+        };
+        let res = Self {
+            keys: RwLock::new(keys),
+            path,
+            gen,
+        };
+        res.ensure_len(precompute_len);
+        Ok(res)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> G {
+        if let Some(key) = self.keys.read().unwrap().get(idx) {
+            return *key;
+        }
+        trace!("access pub key at {}", idx);
+        (self.gen)(Fr::from(idx as u64))
+    }
+
+    // Return the first `len` keys, computing and persisting the missing ones.
+    pub fn get_prefix(&self, len: usize) -> Vec<G> {
+        self.ensure_len(len);
+        self.keys.read().unwrap()[..len].to_vec()
+    }
+
+    pub fn ensure_len(&self, len: usize) {
+        if self.len() >= len {
+            return;
+        }
+        let mut keys = self.keys.write().unwrap();
+        let start = keys.len();
+        if start >= len {
+            return;
+        }
+        info!("compute pub keys from {} to {}...", start, len);
+        let timer = Timer::new();
+        let mut new_keys: Vec<G> = Vec::with_capacity(len - start);
+        (start..len)
+            .into_par_iter()
+            .map(|i| (self.gen)(Fr::from(i as u64)))
+            .collect_into_vec(&mut new_keys);
+        info!("Done in {:?}.", timer.elapsed());
+        if let Some(path) = &self.path {
+            if let Err(e) = Self::append(path, start, &new_keys) {
+                warn!("failed to persist pub keys: {:?}", e);
+            }
+        }
+        keys.append(&mut new_keys);
+    }
+
+    fn key_size() -> usize {
+        G::prime_subgroup_generator().uncompressed_size()
+    }
+
+    fn load(path: &Path) -> Result<Vec<G>> {
+        let file = File::open(path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        ensure!(
+            mmap.len() >= MAGIC.len() && &mmap[..MAGIC.len()] == MAGIC,
+            "invalid pub key file {:?}",
+            path
+        );
+        let data = &mmap[MAGIC.len()..];
+        let size = Self::key_size();
+        ensure!(data.len() % size == 0, "corrupted pub key file {:?}", path);
+        let num = data.len() / size;
+        (0..num)
+            .into_par_iter()
+            .map(|i| G::deserialize_unchecked(&data[i * size..(i + 1) * size]))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("failed to load pub keys from {:?}", path))
+    }
+
+    fn append(path: &Path, start: usize, keys: &[G]) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = Self::key_size();
+        let file_len = file.metadata()?.len() as usize;
+        if file_len == 0 {
+            file.write_all(MAGIC)?;
+        } else if file_len != MAGIC.len() + start * size {
+            bail!("pub key file {:?} out of sync", path);
+        }
+        let mut buf = Vec::with_capacity(keys.len() * size);
+        for key in keys {
+            key.serialize_unchecked(&mut buf)?;
+        }
+        file.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::get_g1s;
+    use super::*;
+
+    #[test]
+    fn test_persist_pub_keys() {
+        let path = std::env::temp_dir().join(format!("vchain-pk-{}.bin", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let keys = PubKeyVec::new(get_g1s, 3, Some(path.clone())).unwrap();
+        assert_eq!(keys.len(), 3);
+        let prefix = keys.get_prefix(5);
+        assert_eq!(prefix[4], get_g1s(Fr::from(4u64)));
+
+        let reopened = PubKeyVec::new(get_g1s, 0, Some(path.clone())).unwrap();
+        assert_eq!(reopened.len(), 5);
+        assert_eq!(reopened.get_prefix(5), prefix);
+        assert_eq!(reopened.get(7), get_g1s(Fr::from(7u64)));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,32 +1,36 @@
-use ark_ec::AffineCurve;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use core::marker::PhantomData;
 use serde::{
     de::{Deserializer, Visitor},
     ser::Serializer,
 };
 
-pub fn serialize<S: Serializer, C: AffineCurve>(c: &C, s: S) -> Result<S::Ok, S::Error> {
+pub fn serialize<S: Serializer, C: CanonicalSerialize>(c: &C, s: S) -> Result<S::Ok, S::Error> {
     let mut buf = Vec::<u8>::new();
     c.serialize(&mut buf)
         .map_err(<S::Error as serde::ser::Error>::custom)?;
     if s.is_human_readable() {
         s.serialize_str(&hex::encode(&buf))
+    } else if let Some(idx) = crate::compact::intern_point(&buf) {
+        s.serialize_u32(idx)
     } else {
         s.serialize_bytes(&buf)
     }
 }
 
-pub fn deserialize<'de, D: Deserializer<'de>, C: AffineCurve>(d: D) -> Result<C, D::Error> {
+pub fn deserialize<'de, D: Deserializer<'de>, C: CanonicalDeserialize>(
+    d: D,
+) -> Result<C, D::Error> {
     use core::fmt;
     use serde::de::Error as DeError;
 
     struct HexVisitor<C>(PhantomData<C>);
 
-    impl<'de, C: AffineCurve> Visitor<'de> for HexVisitor<C> {
+    impl<'de, C: CanonicalDeserialize> Visitor<'de> for HexVisitor<C> {
         type Value = C;
 
         fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            f.write_str("AffineCurve")
+            f.write_str("CanonicalDeserialize")
         }
 
         fn visit_str<E: DeError>(self, value: &str) -> Result<C, E> {
@@ -37,11 +41,11 @@ pub fn deserialize<'de, D: Deserializer<'de>, C: AffineCurve>(d: D) -> Result<C,
 
     struct BytesVisitor<C>(PhantomData<C>);
 
-    impl<'de, C: AffineCurve> Visitor<'de> for BytesVisitor<C> {
+    impl<'de, C: CanonicalDeserialize> Visitor<'de> for BytesVisitor<C> {
         type Value = C;
 
         fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            f.write_str("AffineCurve")
+            f.write_str("CanonicalDeserialize")
         }
 
         fn visit_bytes<E: DeError>(self, v: &[u8]) -> Result<C, E> {
@@ -51,15 +55,81 @@ pub fn deserialize<'de, D: Deserializer<'de>, C: AffineCurve>(d: D) -> Result<C,
 
     if d.is_human_readable() {
         d.deserialize_str(HexVisitor(PhantomData))
+    } else if crate::compact::is_decoding() {
+        let idx = <u32 as serde::Deserialize>::deserialize(d)?;
+        let data =
+            crate::compact::point_at(idx).ok_or_else(|| D::Error::custom("invalid point index"))?;
+        C::deserialize(&data[..]).map_err(D::Error::custom)
     } else {
         d.deserialize_bytes(BytesVisitor(PhantomData))
     }
 }
 
+pub mod vec {
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use serde::{
+        de::{Deserializer, SeqAccess, Visitor},
+        ser::{SerializeSeq, Serializer},
+        Deserialize, Serialize,
+    };
+
+    struct Wrapper<C>(C);
+
+    impl<C: CanonicalSerialize> Serialize for Wrapper<&C> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            super::serialize(self.0, s)
+        }
+    }
+
+    impl<'de, C: CanonicalDeserialize> Deserialize<'de> for Wrapper<C> {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            super::deserialize(d).map(Wrapper)
+        }
+    }
+
+    pub fn serialize<S: Serializer, C: CanonicalSerialize>(
+        v: &[C],
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = s.serialize_seq(Some(v.len()))?;
+        for c in v {
+            seq.serialize_element(&Wrapper(c))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, C: CanonicalDeserialize>(
+        d: D,
+    ) -> Result<Vec<C>, D::Error> {
+        use core::fmt;
+        use core::marker::PhantomData;
+
+        struct VecVisitor<C>(PhantomData<C>);
+
+        impl<'de, C: CanonicalDeserialize> Visitor<'de> for VecVisitor<C> {
+            type Value = Vec<C>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence of CanonicalDeserialize")
+            }
+
+            fn visit_seq<V: SeqAccess<'de>>(self, mut seq: V) -> Result<Vec<C>, V::Error> {
+                let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(Wrapper(c)) = seq.next_element::<Wrapper<C>>()? {
+                    out.push(c);
+                }
+                Ok(out)
+            }
+        }
+
+        d.deserialize_seq(VecVisitor(PhantomData))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use ark_bls12_381::{G1Affine, G2Affine};
+    use ark_bls12_381::{Fr, G1Affine, G2Affine};
+    use ark_ec::AffineCurve;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -68,6 +138,10 @@ mod tests {
         f1: G1Affine,
         #[serde(with = "super")]
         f2: G2Affine,
+        #[serde(with = "super")]
+        f3: Fr,
+        #[serde(with = "super::vec")]
+        f4: Vec<G1Affine>,
     }
 
     #[test]
@@ -76,6 +150,8 @@ mod tests {
         let foo = Foo {
             f1: G1Affine::prime_subgroup_generator(),
             f2: G2Affine::prime_subgroup_generator(),
+            f3: Fr::from(42u32),
+            f4: vec![G1Affine::prime_subgroup_generator(); 2],
         };
 
         let json = serde_json::to_string_pretty(&foo).unwrap();
@@ -1,11 +1,18 @@
-use crate::digest::Digest;
+use crate::digest::{Digest, Digestible};
+use crate::timer::Timer;
+use anyhow::{ensure, Result};
 use ark_ec::ProjectiveCurve;
 use ark_ff::{BigInteger, FpParameters, PrimeField, Zero};
 use ark_poly::{
     univariate::{DenseOrSparsePolynomial, DensePolynomial},
-    UVPolynomial,
+    Polynomial, UVPolynomial,
 };
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use itertools::unfold;
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::path::Path;
+use subtle::{ConditionallySelectable, ConstantTimeEq};
 
 pub fn try_digest_to_prime_field<F: PrimeField>(input: &Digest) -> Option<F> {
     let mut num = F::from_be_bytes_mod_order(&input.0).into_repr();
@@ -21,10 +28,25 @@ pub fn digest_to_prime_field<F: PrimeField>(input: &Digest) -> F {
     try_digest_to_prime_field(input).expect("failed to convert digest to prime field")
 }
 
+// Below this degree, the schoolbook algorithms are faster than the FFT based ones.
+const FAST_POLY_THRESHOLD: usize = 64;
+
 /// Return (g, x, y) s.t. a*x + b*y = g = gcd(a, b)
 pub fn xgcd<'a, F: PrimeField>(
     a: impl Into<DenseOrSparsePolynomial<'a, F>>,
     b: impl Into<DenseOrSparsePolynomial<'a, F>>,
+) -> Option<(DensePolynomial<F>, DensePolynomial<F>, DensePolynomial<F>)> {
+    let a: DensePolynomial<F> = a.into().into();
+    let b: DensePolynomial<F> = b.into().into();
+    if a.coeffs.len().max(b.coeffs.len()) <= FAST_POLY_THRESHOLD {
+        return xgcd_schoolbook(&a, &b);
+    }
+    Some(xgcd_hgcd(a, b))
+}
+
+pub(crate) fn xgcd_schoolbook<'a, F: PrimeField>(
+    a: impl Into<DenseOrSparsePolynomial<'a, F>>,
+    b: impl Into<DenseOrSparsePolynomial<'a, F>>,
 ) -> Option<(DensePolynomial<F>, DensePolynomial<F>, DensePolynomial<F>)> {
     let mut a = a.into();
     let mut b = b.into();
@@ -46,20 +68,273 @@ pub fn xgcd<'a, F: PrimeField>(
     Some((b.into(), x0, y0))
 }
 
+fn xgcd_hgcd<F: PrimeField>(
+    a: DensePolynomial<F>,
+    b: DensePolynomial<F>,
+) -> (DensePolynomial<F>, DensePolynomial<F>, DensePolynomial<F>) {
+    let swap = poly_deg(&a) < poly_deg(&b);
+    let (mut r0, mut r1) = if swap { (b, a) } else { (a, b) };
+    let mut m = PolyMatrix::identity();
+    if !r1.is_zero() && poly_deg(&r0) == poly_deg(&r1) {
+        let (q, r) = poly_div_rem(&r0, &r1);
+        m = PolyMatrix::quotient(q).mul(&m);
+        r0 = std::mem::replace(&mut r1, r);
+    }
+    while !r1.is_zero() {
+        let h = half_gcd(&r0, &r1);
+        let (c, d) = h.apply(&r0, &r1);
+        m = h.mul(&m);
+        if d.is_zero() {
+            r0 = c;
+            break;
+        }
+        let (q, r) = poly_div_rem(&c, &d);
+        m = PolyMatrix::quotient(q).mul(&m);
+        r0 = d;
+        r1 = r;
+    }
+    let [x, y, _, _] = m.0;
+    if swap {
+        (r0, y, x)
+    } else {
+        (r0, x, y)
+    }
+}
+
+// degree of the polynomial, -1 for zero.
+fn poly_deg<F: PrimeField>(p: &DensePolynomial<F>) -> isize {
+    if p.is_zero() {
+        -1
+    } else {
+        p.degree() as isize
+    }
+}
+
+fn poly_shr<F: PrimeField>(p: &DensePolynomial<F>, k: usize) -> DensePolynomial<F> {
+    if p.coeffs.len() <= k {
+        return DensePolynomial::zero();
+    }
+    DensePolynomial::from_coefficients_slice(&p.coeffs[k..])
+}
+
+fn poly_mod_xn<F: PrimeField>(p: &DensePolynomial<F>, n: usize) -> DensePolynomial<F> {
+    DensePolynomial::from_coefficients_slice(&p.coeffs[..n.min(p.coeffs.len())])
+}
+
+// Return g s.t. f * g = 1 mod x^n, using Newton iteration. f(0) must be nonzero.
+fn poly_inv_mod_xn<F: PrimeField>(f: &DensePolynomial<F>, n: usize) -> DensePolynomial<F> {
+    let two = DensePolynomial::from_coefficients_vec(vec![F::one() + F::one()]);
+    let mut g = DensePolynomial::from_coefficients_vec(vec![f.coeffs[0]
+        .inverse()
+        .expect("constant term should be nonzero")]);
+    let mut k = 1;
+    while k < n {
+        k = (2 * k).min(n);
+        let fg = poly_mod_xn(&(&poly_mod_xn(f, k) * &g), k);
+        g = poly_mod_xn(&(&g * &(&two - &fg)), k);
+    }
+    g
+}
+
+// Return (q, r) s.t. a = b * q + r and deg(r) < deg(b). b must be nonzero.
+fn poly_div_rem<F: PrimeField>(
+    a: &DensePolynomial<F>,
+    b: &DensePolynomial<F>,
+) -> (DensePolynomial<F>, DensePolynomial<F>) {
+    let (deg_a, deg_b) = (poly_deg(a), poly_deg(b));
+    if deg_a < deg_b {
+        return (DensePolynomial::zero(), a.clone());
+    }
+    let m = (deg_a - deg_b) as usize;
+    if m < FAST_POLY_THRESHOLD || (deg_b as usize) < FAST_POLY_THRESHOLD {
+        return DenseOrSparsePolynomial::from(a)
+            .divide_with_q_and_r(&b.into())
+            .expect("divisor should be nonzero");
+    }
+    let rev = |p: &DensePolynomial<F>| {
+        let coeffs: Vec<F> = p.coeffs.iter().rev().take(m + 1).copied().collect();
+        DensePolynomial::from_coefficients_vec(coeffs)
+    };
+    let inv = poly_inv_mod_xn(&rev(b), m + 1);
+    let mut q_coeffs = poly_mod_xn(&(&rev(a) * &inv), m + 1).coeffs;
+    q_coeffs.resize(m + 1, F::zero());
+    q_coeffs.reverse();
+    let q = DensePolynomial::from_coefficients_vec(q_coeffs);
+    let r = a - &(b * &q);
+    (q, r)
+}
+
+// A 2x2 polynomial matrix [[m0, m1], [m2, m3]].
+struct PolyMatrix<F: PrimeField>([DensePolynomial<F>; 4]);
+
+impl<F: PrimeField> PolyMatrix<F> {
+    fn identity() -> Self {
+        let one = DensePolynomial::from_coefficients_vec(vec![F::one()]);
+        Self([
+            one.clone(),
+            DensePolynomial::zero(),
+            DensePolynomial::zero(),
+            one,
+        ])
+    }
+
+    // One Euclidean step: (a, b) -> (b, a - q * b).
+    fn quotient(q: DensePolynomial<F>) -> Self {
+        let one = DensePolynomial::from_coefficients_vec(vec![F::one()]);
+        Self([DensePolynomial::zero(), one.clone(), one, -q])
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let [a0, a1, a2, a3] = &self.0;
+        let [b0, b1, b2, b3] = &other.0;
+        Self([
+            &(a0 * b0) + &(a1 * b2),
+            &(a0 * b1) + &(a1 * b3),
+            &(a2 * b0) + &(a3 * b2),
+            &(a2 * b1) + &(a3 * b3),
+        ])
+    }
+
+    fn apply(
+        &self,
+        a: &DensePolynomial<F>,
+        b: &DensePolynomial<F>,
+    ) -> (DensePolynomial<F>, DensePolynomial<F>) {
+        let [m0, m1, m2, m3] = &self.0;
+        (&(m0 * a) + &(m1 * b), &(m2 * a) + &(m3 * b))
+    }
+}
+
+// Return M s.t. M * (a, b) = (c, d) is in the remainder sequence of (a, b) with
+// deg(c) >= ceil(deg(a) / 2) > deg(d). Require deg(a) > deg(b).
+// Ref: K. Thull and C. Yap. A unified approach to HGCD algorithms for polynomials and integers.
+fn half_gcd<F: PrimeField>(a: &DensePolynomial<F>, b: &DensePolynomial<F>) -> PolyMatrix<F> {
+    let n = poly_deg(a);
+    let m = (n + 1) / 2;
+    if poly_deg(b) < m {
+        return PolyMatrix::identity();
+    }
+    if (n as usize) < FAST_POLY_THRESHOLD {
+        let mut res = PolyMatrix::identity();
+        let (mut c, mut d) = (a.clone(), b.clone());
+        while poly_deg(&d) >= m {
+            let (q, r) = poly_div_rem(&c, &d);
+            res = PolyMatrix::quotient(q).mul(&res);
+            c = std::mem::replace(&mut d, r);
+        }
+        return res;
+    }
+    let mu = m as usize;
+    let r = half_gcd(&poly_shr(a, mu), &poly_shr(b, mu));
+    let (c, d) = r.apply(a, b);
+    if poly_deg(&d) < m {
+        return r;
+    }
+    let (q, e) = poly_div_rem(&c, &d);
+    let qr = PolyMatrix::quotient(q).mul(&r);
+    if poly_deg(&e) < m {
+        return qr;
+    }
+    let k = (2 * m - poly_deg(&d)) as usize;
+    let s = half_gcd(&poly_shr(&d, k), &poly_shr(&e, k));
+    s.mul(&qr)
+}
+
+const TABLE_MAGIC: &[u8; 8] = b"vchainfb";
+
+// File format: magic, window size K as u64 (le), then the uncompressed table.
+fn save_table<T: CanonicalSerialize>(k: usize, table: &T, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut buf = Vec::with_capacity(TABLE_MAGIC.len() + 8 + table.uncompressed_size());
+    buf.extend_from_slice(TABLE_MAGIC);
+    buf.extend_from_slice(&(k as u64).to_le_bytes());
+    table.serialize_unchecked(&mut buf)?;
+    // write to a temp file first, so that a concurrent reader never sees a partial table
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, buf)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn load_table<T: CanonicalDeserialize>(path: &Path) -> Result<(usize, T)> {
+    let file = File::open(path)?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    let header_len = TABLE_MAGIC.len() + 8;
+    ensure!(
+        mmap.len() >= header_len && &mmap[..TABLE_MAGIC.len()] == TABLE_MAGIC,
+        "invalid fixed base table file {:?}",
+        path
+    );
+    let mut k = [0u8; 8];
+    k.copy_from_slice(&mmap[TABLE_MAGIC.len()..header_len]);
+    let k = u64::from_le_bytes(k) as usize;
+    ensure!(
+        (1..=MAX_WINDOW).contains(&k),
+        "invalid window size in fixed base table file {:?}",
+        path
+    );
+    let mut reader = &mmap[header_len..];
+    let table = T::deserialize_unchecked(&mut reader)?;
+    ensure!(
+        reader.is_empty(),
+        "corrupted fixed base table file {:?}",
+        path
+    );
+    Ok((k, table))
+}
+
+const MAX_WINDOW: usize = 16;
+const TUNE_SAMPLES: u64 = 64;
+
+// Return the candidate window size with the fastest `apply` on the current machine.
+fn tune_window<T, F: PrimeField>(
+    candidates: &[usize],
+    build: impl Fn(usize) -> T,
+    apply: impl Fn(&T, &F),
+) -> usize {
+    let inputs: Vec<F> = (0..TUNE_SAMPLES)
+        .map(|i| digest_to_prime_field(&i.to_digest()))
+        .collect();
+    let (_, k) = candidates
+        .iter()
+        .map(|&k| {
+            let table = build(k);
+            let timer = Timer::new();
+            for input in &inputs {
+                apply(&table, input);
+            }
+            let time = timer.elapsed();
+            debug!("window size {}: {:?}", k, time);
+            (time, k)
+        })
+        .min()
+        .expect("no window size candidates");
+    info!("use window size {}", k);
+    k
+}
+
 // Ref: https://github.com/blynn/pbc/blob/fbf4589036ce4f662e2d06905862c9e816cf9d08/arith/field.c#L251-L330
 pub struct FixedBaseCurvePow<G: ProjectiveCurve> {
+    k: usize,
     table: Vec<Vec<G>>,
 }
 
 impl<G: ProjectiveCurve> FixedBaseCurvePow<G> {
-    const K: usize = 5;
+    pub const DEFAULT_WINDOW: usize = 5;
 
     pub fn build(base: &G) -> Self {
+        Self::build_with_window(base, Self::DEFAULT_WINDOW)
+    }
+
+    pub fn build_with_window(base: &G, k: usize) -> Self {
+        assert!((1..=MAX_WINDOW).contains(&k), "invalid window size {}", k);
         let bits =
             <<G as ProjectiveCurve>::ScalarField as PrimeField>::Params::MODULUS_BITS as usize;
-        let num_lookups = bits / Self::K + 1;
-        let lookup_size = (1 << Self::K) - 1;
-        let last_lookup_size = (1 << (bits - (num_lookups - 1) * Self::K)) - 1;
+        let num_lookups = bits / k + 1;
+        let lookup_size = (1 << k) - 1;
+        let last_lookup_size = (1 << (bits - (num_lookups - 1) * k)) - 1;
 
         let mut table: Vec<Vec<G>> = Vec::with_capacity(num_lookups);
 
@@ -83,17 +358,40 @@ impl<G: ProjectiveCurve> FixedBaseCurvePow<G> {
                 multiplier.add_assign(&last);
             }
         }
-        Self { table }
+        Self { k, table }
+    }
+
+    pub fn tune_window(base: &G, candidates: &[usize]) -> usize {
+        tune_window(
+            candidates,
+            |k| Self::build_with_window(base, k),
+            |table: &Self, input| {
+                table.apply(input);
+            },
+        )
+    }
+
+    pub fn window(&self) -> usize {
+        self.k
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_table(self.k, &self.table, path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let (k, table) = load_table(path)?;
+        Ok(Self { k, table })
     }
 
     pub fn apply(&self, input: &<G as ProjectiveCurve>::ScalarField) -> G {
         let mut res = G::zero();
         let input_repr = input.into_repr();
-        let num_lookups = input_repr.num_bits() as usize / Self::K + 1;
+        let num_lookups = input_repr.num_bits() as usize / self.k + 1;
         for i in 0..num_lookups {
             let mut word: usize = 0;
-            for j in 0..Self::K {
-                if input_repr.get_bit(i * Self::K + j) {
+            for j in 0..self.k {
+                if input_repr.get_bit(i * self.k + j) {
                     word |= 1 << j;
                 }
             }
@@ -105,18 +403,107 @@ impl<G: ProjectiveCurve> FixedBaseCurvePow<G> {
     }
 }
 
+// Constant-time version of FixedBaseCurvePow::apply for secret inputs. Every sub table is
+// scanned entirely, the entry of the window is selected with masks, and a mixed addition of
+// the entry in affine is done for every lookup regardless of the input.
+//
+// The entries are shifted by an offset and the sum starts at a seed point, both multiples of
+// the base by a public hashed scalar, so that the addition formulas never reach their special
+// cases (adding the identity or doubling) unless a partial sum happens to equal the next entry,
+// which has a negligible probability. The entries are read back without the on-curve or the
+// subgroup checks, which would take a time depending on the point. So the memory accesses and
+// the sequence of the group operations do not depend on the input. The field arithmetic of
+// arkworks, with its conditional final subtractions, is not claimed to be constant time, which
+// this cannot fix.
+pub struct CtFixedBaseCurvePow<G: ProjectiveCurve> {
+    k: usize,
+    elem_size: usize,
+    // table[i] encodes w * 2^{ik} * base + offset for w in 0..2^k, as points in affine
+    table: Vec<Vec<u8>>,
+    seed: G,
+    // -(seed + offset * table.len())
+    correction: G,
+}
+
+impl<G: ProjectiveCurve> CtFixedBaseCurvePow<G> {
+    pub fn from_table(input: &FixedBaseCurvePow<G>) -> Self {
+        let base = input.table[0][0];
+        let c: G::ScalarField = digest_to_prime_field(&"vchain-ct-offset".to_digest());
+        let offset = base.mul(c.into_repr());
+        let c: G::ScalarField = digest_to_prime_field(&"vchain-ct-seed".to_digest());
+        let seed = base.mul(c.into_repr());
+        let mut elem_size = 0;
+        let table: Vec<Vec<u8>> = input
+            .table
+            .iter()
+            .map(|sub_table| {
+                let entries: Vec<G> = std::iter::once(G::zero())
+                    .chain(sub_table.iter().copied())
+                    .map(|entry| entry + offset)
+                    .collect();
+                let mut buf = Vec::new();
+                for entry in G::batch_normalization_into_affine(&entries) {
+                    let start = buf.len();
+                    entry
+                        .serialize_unchecked(&mut buf)
+                        .expect("failed to encode fixed base table");
+                    elem_size = buf.len() - start;
+                }
+                buf
+            })
+            .collect();
+        let offset_sum = offset.mul(G::ScalarField::from(table.len() as u64).into_repr());
+        Self {
+            k: input.k,
+            elem_size,
+            table,
+            seed,
+            correction: -(seed + offset_sum),
+        }
+    }
+
+    pub fn apply(&self, input: &<G as ProjectiveCurve>::ScalarField) -> G {
+        let mut res = self.seed;
+        let input_repr = input.into_repr();
+        let mut buf = vec![0u8; self.elem_size];
+        for (i, sub_table) in self.table.iter().enumerate() {
+            let mut word: u64 = 0;
+            for j in 0..self.k {
+                word |= (input_repr.get_bit(i * self.k + j) as u64) << j;
+            }
+            buf.iter_mut().for_each(|b| *b = 0);
+            for (w, entry) in sub_table.chunks_exact(self.elem_size).enumerate() {
+                let choice = (w as u64).ct_eq(&word);
+                for (b, e) in buf.iter_mut().zip(entry.iter()) {
+                    b.conditional_assign(e, choice);
+                }
+            }
+            let entry = G::Affine::deserialize_unchecked(&buf[..])
+                .expect("failed to decode fixed base table");
+            res.add_assign_mixed(&entry);
+        }
+        res + self.correction
+    }
+}
+
 pub struct FixedBaseScalarPow<F: PrimeField> {
+    k: usize,
     table: Vec<Vec<F>>,
 }
 
 impl<F: PrimeField> FixedBaseScalarPow<F> {
-    const K: usize = 8;
+    pub const DEFAULT_WINDOW: usize = 8;
 
     pub fn build(base: &F) -> Self {
+        Self::build_with_window(base, Self::DEFAULT_WINDOW)
+    }
+
+    pub fn build_with_window(base: &F, k: usize) -> Self {
+        assert!((1..=MAX_WINDOW).contains(&k), "invalid window size {}", k);
         let bits = <F as PrimeField>::Params::MODULUS_BITS as usize;
-        let num_lookups = bits / Self::K + 1;
-        let lookup_size = (1 << Self::K) - 1;
-        let last_lookup_size = (1 << (bits - (num_lookups - 1) * Self::K)) - 1;
+        let num_lookups = bits / k + 1;
+        let lookup_size = (1 << k) - 1;
+        let last_lookup_size = (1 << (bits - (num_lookups - 1) * k)) - 1;
 
         let mut table: Vec<Vec<F>> = Vec::with_capacity(num_lookups);
 
@@ -140,17 +527,31 @@ impl<F: PrimeField> FixedBaseScalarPow<F> {
                 multiplier.mul_assign(&last);
             }
         }
-        Self { table }
+        Self { k, table }
+    }
+
+    pub fn tune_window(base: &F, candidates: &[usize]) -> usize {
+        tune_window(
+            candidates,
+            |k| Self::build_with_window(base, k),
+            |table: &Self, input| {
+                table.apply(input);
+            },
+        )
+    }
+
+    pub fn window(&self) -> usize {
+        self.k
     }
 
     pub fn apply(&self, input: &F) -> F {
         let mut res = F::one();
         let input_repr = input.into_repr();
-        let num_lookups = input_repr.num_bits() as usize / Self::K + 1;
+        let num_lookups = input_repr.num_bits() as usize / self.k + 1;
         for i in 0..num_lookups {
             let mut word: usize = 0;
-            for j in 0..Self::K {
-                if input_repr.get_bit(i * Self::K + j) {
+            for j in 0..self.k {
+                if input_repr.get_bit(i * self.k + j) {
                     word |= 1 << j;
                 }
             }
@@ -166,10 +567,9 @@ impl<F: PrimeField> FixedBaseScalarPow<F> {
 mod tests {
     use super::*;
     use ark_bls12_381::{Fr, G1Projective, G2Projective};
-    use ark_ff::Field;
-    use ark_poly::Polynomial;
+    use ark_ff::{Field, UniformRand};
     use core::ops::MulAssign;
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_xgcd() {
@@ -181,6 +581,33 @@ mod tests {
         assert_eq!(gcd, g);
     }
 
+    #[test]
+    fn test_xgcd_hgcd() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(123_456_789u64);
+        let mut rand_poly = |deg: usize| {
+            DensePolynomial::from_coefficients_vec((0..=deg).map(|_| Fr::rand(&mut rng)).collect())
+        };
+        let common = rand_poly(30);
+        for &(deg1, deg2) in &[(300, 200), (200, 300), (257, 257), (500, 1), (400, 0)] {
+            let poly1 = rand_poly(deg1);
+            let poly2 = rand_poly(deg2);
+            let (g1, x1, y1) = xgcd_schoolbook(&poly1, &poly2).unwrap();
+            let (g2, x2, y2) = xgcd(&poly1, &poly2).unwrap();
+            assert_eq!(g1.degree(), 0);
+            assert_eq!(g2.degree(), 0);
+            assert_eq!(&(&poly1 * &x2) + &(&poly2 * &y2), g2);
+            // the cofactors are unique after normalizing the gcd
+            assert_eq!(&x1 / &g1, &x2 / &g2);
+            assert_eq!(&y1 / &g1, &y2 / &g2);
+
+            let poly1 = &poly1 * &common;
+            let poly2 = &poly2 * &common;
+            let (g, x, y) = xgcd(&poly1, &poly2).unwrap();
+            assert_eq!(g.degree(), common.degree());
+            assert_eq!(&(&poly1 * &x) + &(&poly2 * &y), g);
+        }
+    }
+
     #[test]
     fn test_pow_g1() {
         let g1p = FixedBaseCurvePow::build(&G1Projective::prime_subgroup_generator());
@@ -189,6 +616,37 @@ mod tests {
         let mut expect = G1Projective::prime_subgroup_generator();
         expect.mul_assign(num);
         assert_eq!(g1p.apply(&num), expect);
+        for k in &[1, 3, 8] {
+            let g1p =
+                FixedBaseCurvePow::build_with_window(&G1Projective::prime_subgroup_generator(), *k);
+            assert_eq!(g1p.apply(&num), expect);
+        }
+    }
+
+    #[test]
+    fn test_ct_pow_g1() {
+        let g1p = FixedBaseCurvePow::build(&G1Projective::prime_subgroup_generator());
+        let ct = CtFixedBaseCurvePow::from_table(&g1p);
+        let mut rng = rand::thread_rng();
+        for num in &[rng.gen(), Fr::zero(), Fr::from(1u32), -Fr::from(1u32)] {
+            assert_eq!(ct.apply(num), g1p.apply(num));
+        }
+    }
+
+    #[test]
+    fn test_ct_pow_g2() {
+        let g2p = FixedBaseCurvePow::build(&G2Projective::prime_subgroup_generator());
+        let ct = CtFixedBaseCurvePow::from_table(&g2p);
+        let mut rng = rand::thread_rng();
+        for num in &[rng.gen(), Fr::zero(), -Fr::from(1u32)] {
+            assert_eq!(ct.apply(num), g2p.apply(num));
+        }
+    }
+
+    #[test]
+    fn test_tune_window() {
+        let k = FixedBaseScalarPow::tune_window(&Fr::from(42u32), &[4, 8]);
+        assert!(k == 4 || k == 8);
     }
 
     #[test]
@@ -210,4 +668,26 @@ mod tests {
         let expect = base.pow(num.into_repr());
         assert_eq!(frp.apply(&num), expect);
     }
+
+    #[test]
+    fn test_persist_pow_table() {
+        let dir = std::env::temp_dir().join(format!("vchain-fb-{}", std::process::id()));
+        let mut rng = rand::thread_rng();
+        let num: Fr = rng.gen();
+
+        let g1p = FixedBaseCurvePow::build(&G1Projective::prime_subgroup_generator());
+        g1p.save(&dir.join("g1.bin")).unwrap();
+        let loaded = FixedBaseCurvePow::<G1Projective>::load(&dir.join("g1.bin")).unwrap();
+        assert_eq!(loaded.apply(&num), g1p.apply(&num));
+
+        let g1p =
+            FixedBaseCurvePow::build_with_window(&G1Projective::prime_subgroup_generator(), 3);
+        g1p.save(&dir.join("g1.bin")).unwrap();
+        let loaded = FixedBaseCurvePow::<G1Projective>::load(&dir.join("g1.bin")).unwrap();
+        assert_eq!(loaded.window(), 3);
+        assert_eq!(loaded.apply(&num), g1p.apply(&num));
+
+        assert!(FixedBaseCurvePow::<G2Projective>::load(&dir.join("g1.bin")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,252 @@
+use super::*;
+use crate::digest::{blake2, Digest};
+use anyhow::ensure;
+use std::io::{Read, Write};
+
+// An archive of a chain starts with the magic and the version, followed by a
+// sequence of frames, each of which is its length in u32 followed by the frame
+// in bincode. The manifest is the last frame, with the digest of all the bytes
+// before it.
+const ARCHIVE_MAGIC: &[u8; 8] = b"vchainar";
+pub const ARCHIVE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub param: Parameter,
+    pub id_counter: IdCounter,
+    #[serde(with = "crate::chain::id::serde_impl")]
+    pub pruned_before: IdType,
+    pub num_blocks: u64,
+    pub num_objs: u64,
+    pub num_keywords: u64,
+    pub digest: Digest,
+}
+
+// The data of a block, without the data of a pruned one.
+#[derive(Serialize, Deserialize)]
+struct ArchivedBlock {
+    header: BlockHeader,
+    data: Option<BlockData>,
+    intra_index_nodes: Vec<IntraIndexNode>,
+    skip_list_nodes: Vec<SkipListNode>,
+    btree_nodes: Vec<BTreeNode>,
+    objs: Vec<Object>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Frame {
+    Keyword(SymbolType, String),
+    Block(Box<ArchivedBlock>),
+    Manifest(Box<ArchiveManifest>),
+}
+
+struct ArchiveWriter<'a, W> {
+    w: &'a mut W,
+    state: blake2b_simd::State,
+}
+
+impl<'a, W: Write> ArchiveWriter<'a, W> {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.state.update(data);
+        self.w.write_all(data)?;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let data = bincode::serialize(frame)?;
+        self.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.write_all(&data)
+    }
+}
+
+// Write all the data of the chain to `w`, from the first block up to the
+// latest one.
+pub fn export_archive(chain: &impl ReadInterface, w: &mut impl Write) -> Result<ArchiveManifest> {
+    let mut writer = ArchiveWriter {
+        w,
+        state: blake2().to_state(),
+    };
+    writer.write_all(ARCHIVE_MAGIC)?;
+    writer.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+
+    let mut keywords = chain.read_keywords()?;
+    keywords.sort_unstable();
+    for (sym, word) in &keywords {
+        writer.write_frame(&Frame::Keyword(*sym, word.clone()))?;
+    }
+
+    let pruned_before = chain.read_pruned_before()?;
+    let mut num_blocks = 0;
+    let mut num_objs = 0;
+    let first_block = match chain.read_block_header(0) {
+        Ok(_) => 0,
+        Err(_) => 1,
+    };
+    let latest_block = chain.latest_block_id()?.unwrap_or(0);
+    for header in chain.iter_block_headers(first_block..=latest_block) {
+        let header = header?;
+        let block_id = header.block_id;
+        let mut blk = ArchivedBlock {
+            header,
+            data: None,
+            intra_index_nodes: Vec::new(),
+            skip_list_nodes: Vec::new(),
+            btree_nodes: Vec::new(),
+            objs: Vec::new(),
+        };
+        if block_id >= pruned_before {
+            let data = chain.read_block_data(block_id)?;
+            let obj_ids = match &data.data {
+                IntraData::Flat(ids) => ids.clone(),
+                IntraData::Index(root_id) => {
+                    let mut obj_ids = Vec::new();
+                    let mut node_ids = vec![*root_id];
+                    while let Some(id) = node_ids.pop() {
+                        let node = chain.read_intra_index_node(id)?;
+                        match &node {
+                            IntraIndexNode::NonLeaf(n) => node_ids.extend_from_slice(&n.child_ids),
+                            IntraIndexNode::Leaf(n) => obj_ids.push(n.obj_id),
+                        }
+                        blk.intra_index_nodes.push(node);
+                    }
+                    obj_ids
+                }
+            };
+            for id in obj_ids {
+                blk.objs.push(chain.read_object(id)?);
+            }
+            for &id in &data.skip_list_ids {
+                blk.skip_list_nodes.push(chain.read_skip_list_node(id)?);
+            }
+            for &id in &data.btree_ids {
+                blk.btree_nodes.push(chain.read_btree_node(id)?);
+            }
+            blk.data = Some(data);
+        }
+        num_blocks += 1;
+        num_objs += blk.objs.len() as u64;
+        writer.write_frame(&Frame::Block(Box::new(blk)))?;
+    }
+
+    let manifest = ArchiveManifest {
+        param: chain.get_parameter()?,
+        id_counter: chain.read_id_counter()?,
+        pruned_before,
+        num_blocks,
+        num_objs,
+        num_keywords: keywords.len() as u64,
+        digest: Digest::from(writer.state.finalize()),
+    };
+    writer.write_frame(&Frame::Manifest(Box::new(manifest.clone())))?;
+    writer.w.flush()?;
+    Ok(manifest)
+}
+
+// Read an archive written by export_archive, passing the keywords and the
+// blocks to `f` and checking them against the manifest at the end.
+fn read_archive(
+    r: &mut impl Read,
+    mut f: impl FnMut(Frame) -> Result<()>,
+) -> Result<ArchiveManifest> {
+    let mut state = blake2().to_state();
+    let mut head = [0u8; 12];
+    r.read_exact(&mut head)?;
+    ensure!(
+        &head[..8] == ARCHIVE_MAGIC,
+        Error::Storage("not an archive of a chain".to_owned())
+    );
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&head[8..]);
+    let version = u32::from_le_bytes(version);
+    ensure!(
+        version == ARCHIVE_VERSION,
+        Error::Storage(format!("unsupported archive version {}", version))
+    );
+    state.update(&head);
+
+    let mut num_blocks = 0;
+    let mut num_objs = 0;
+    let mut num_keywords = 0;
+    loop {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)?;
+        // not allocated up front, in case the length is corrupted
+        let len_val = u32::from_le_bytes(len) as usize;
+        let mut data = Vec::new();
+        r.by_ref().take(len_val as u64).read_to_end(&mut data)?;
+        ensure!(
+            data.len() == len_val,
+            Error::Storage("the archive is truncated".to_owned())
+        );
+        let frame = bincode::deserialize::<Frame>(&data)?;
+        match frame {
+            Frame::Keyword(..) => num_keywords += 1,
+            Frame::Block(ref blk) => {
+                num_blocks += 1;
+                num_objs += blk.objs.len() as u64;
+            }
+            Frame::Manifest(manifest) => {
+                ensure!(
+                    manifest.digest == Digest::from(state.finalize())
+                        && manifest.num_blocks == num_blocks
+                        && manifest.num_objs == num_objs
+                        && manifest.num_keywords == num_keywords,
+                    Error::Storage("the archive does not match its manifest".to_owned())
+                );
+                return Ok(*manifest);
+            }
+        }
+        state.update(&len);
+        state.update(&data);
+        f(frame)?;
+    }
+}
+
+// Check the archive against its manifest without writing it anywhere.
+pub fn verify_archive(r: &mut impl Read) -> Result<ArchiveManifest> {
+    read_archive(r, |_| Ok(()))
+}
+
+// Write the data of the archive to the empty `chain`. The archive is only
+// checked against its manifest at the end, so it should be checked with
+// verify_archive first.
+pub fn import_archive(
+    r: &mut impl Read,
+    chain: &mut impl WriteInterface,
+) -> Result<ArchiveManifest> {
+    let manifest = read_archive(r, |frame| {
+        match frame {
+            Frame::Keyword(sym, word) => chain.write_keyword(sym, word)?,
+            Frame::Block(blk) => {
+                let blk = *blk;
+                chain.begin_block()?;
+                for obj in blk.objs {
+                    chain.write_object(obj)?;
+                }
+                for node in blk.intra_index_nodes {
+                    chain.write_intra_index_node(node)?;
+                }
+                for node in blk.skip_list_nodes {
+                    chain.write_skip_list_node(node)?;
+                }
+                for node in blk.btree_nodes {
+                    chain.write_btree_node(node)?;
+                }
+                if let Some(data) = blk.data {
+                    chain.write_block_data(data)?;
+                }
+                chain.write_block_header(blk.header)?;
+                chain.commit_block()?;
+            }
+            // not passed to the callback
+            Frame::Manifest(_) => {}
+        }
+        Ok(())
+    })?;
+    chain.set_parameter(manifest.param.clone())?;
+    chain.write_id_counter(manifest.id_counter)?;
+    if manifest.pruned_before > 0 {
+        chain.write_pruned_before(manifest.pruned_before)?;
+    }
+    Ok(manifest)
+}
@@ -1,21 +1,78 @@
 #![allow(clippy::cognitive_complexity)]
 
 use super::*;
-use crate::digest::{concat_digest, concat_digest_ref, Digest, Digestible};
+use crate::acc::{Accumulator, G1Affine, G1Projective};
+use crate::digest::{concat_digest, Digest, Digestible, MAX_KEY_LEN};
+use crate::par::*;
 use crate::set::MultiSet;
+use anyhow::ensure;
 use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::Zero;
 use smallvec::smallvec;
+use std::collections::HashSet;
 
 pub fn build_block<'a>(
     block_id: IdType,
     prev_hash: Digest,
+    timestamp: u64,
     raw_objs: impl Iterator<Item = &'a RawObject>,
+    tombstones: &[IdType],
     chain: &mut (impl ReadInterface + WriteInterface),
 ) -> Result<BlockHeader> {
     debug!("build block #{}", block_id);
 
     let param = chain.get_parameter()?;
-    let objs: Vec<Object> = raw_objs.map(|o| Object::create(o, &param)).collect();
+    ensure!(
+        param.chain_id.len() <= MAX_KEY_LEN,
+        Error::Parameter(format!("chain id should be at most {} bytes", MAX_KEY_LEN))
+    );
+    ensure!(
+        param.v_bit_len.iter().all(|&b| b <= 64),
+        Error::Parameter("v_bit_len should be at most 64".to_owned())
+    );
+    ensure!(
+        param.intra_index_fanout >= 2,
+        Error::Parameter("intra index fanout should be at least 2".to_owned())
+    );
+    ensure!(
+        param.skip_list_max_level == 0 || param.btree_max_level == 0,
+        Error::Parameter("the skip list and the B-tree cannot be both enabled".to_owned())
+    );
+    if let Some(prev_blk_header) = block_id
+        .checked_sub(1)
+        .and_then(|id| chain.read_block_header(id).ok())
+    {
+        ensure!(
+            timestamp >= prev_blk_header.timestamp,
+            Error::Parameter(format!(
+                "timestamp of block #{} is earlier than its previous block",
+                block_id
+            ))
+        );
+    }
+    let mut ids = chain.read_id_counter()?;
+    let tombstone_set: HashSet<IdType> = tombstones.iter().copied().collect();
+    ensure!(
+        tombstone_set.len() == tombstones.len(),
+        Error::Parameter(format!("duplicated tombstones in block #{}", block_id))
+    );
+    ensure!(
+        tombstones.iter().all(|&id| id < ids.next_obj_id),
+        Error::Parameter(format!(
+            "tombstones of block #{} should refer to the objects in the earlier blocks",
+            block_id
+        ))
+    );
+    let raw_objs: Vec<&RawObject> = raw_objs.collect();
+    ensure!(
+        param.max_objs_per_block == 0 || raw_objs.len() <= param.max_objs_per_block as usize,
+        Error::Parameter(format!(
+            "block #{} has more than {} objects",
+            block_id, param.max_objs_per_block
+        ))
+    );
+    let objs = in_build_pool(|| Object::create_batch(&raw_objs, &param, &mut ids));
+    chain.begin_block()?;
     for obj in &objs {
         chain.write_object(obj.clone())?;
     }
@@ -23,6 +80,8 @@ pub fn build_block<'a>(
     let mut block_header = BlockHeader {
         block_id,
         prev_hash,
+        timestamp,
+        tombstone_root: tombstones_digest(tombstones),
         ..Default::default()
     };
 
@@ -35,105 +94,65 @@ pub fn build_block<'a>(
                 obj.acc_value,
                 obj.id,
                 obj.to_digest(),
+                &mut ids,
             );
             leaves.push(node.clone());
             chain.write_intra_index_node(IntraIndexNode::Leaf(Box::new(node)))?;
         }
 
         let mut non_leaves: Vec<IntraIndexNonLeaf> = Vec::with_capacity(leaves.len());
-        while !leaves.is_empty() {
-            let left_idx = leaves
-                .iter()
-                .enumerate()
-                .max_by_key(|(_i, n)| n.set_data.len())
-                .unwrap()
-                .0;
-            let left = leaves.remove(left_idx);
-
-            if leaves.is_empty() {
-                let node = IntraIndexNonLeaf::create(
-                    block_id,
-                    left.set_data.clone(),
-                    left.acc_value,
-                    smallvec![left.to_digest()],
-                    smallvec![left.id],
-                );
-                non_leaves.push(node.clone());
-                chain.write_intra_index_node(IntraIndexNode::NonLeaf(Box::new(node)))?;
-                break;
-            }
-
-            let mut right_idx = 0;
-            let mut min_set = &left.set_data | &leaves[0].set_data;
-            let mut max_sim =
-                (&left.set_data & &leaves[0].set_data).len() as f64 / min_set.len() as f64;
-            for (i, n) in leaves.iter().enumerate().skip(1) {
-                let s = &left.set_data | &n.set_data;
-                let sim = (&left.set_data & &n.set_data).len() as f64 / s.len() as f64;
-                if sim > max_sim {
-                    max_sim = sim;
-                    min_set = s;
-                    right_idx = i;
-                }
-            }
-            let right = leaves.remove(right_idx);
-            let min_set_acc_value = multiset_to_g1(&min_set, &param);
+        let fanout = param.intra_index_fanout as usize;
+        let (groups, rest) = group_nodes(leaves, fanout, |n| &n.set_data);
+        for node in create_non_leaves(
+            block_id,
+            groups,
+            |n| (n.to_digest(), n.id),
+            &param,
+            &mut ids,
+        ) {
+            non_leaves.push(node.clone());
+            chain.write_intra_index_node(IntraIndexNode::NonLeaf(Box::new(node)))?;
+        }
+        if let Some(left) = rest {
             let node = IntraIndexNonLeaf::create(
                 block_id,
-                min_set,
-                min_set_acc_value,
-                smallvec![left.to_digest(), right.to_digest()],
-                smallvec![left.id, right.id],
+                left.set_data.clone(),
+                left.acc_value,
+                smallvec![left.to_digest()],
+                smallvec![left.id],
+                &mut ids,
             );
             non_leaves.push(node.clone());
             chain.write_intra_index_node(IntraIndexNode::NonLeaf(Box::new(node)))?;
         }
 
         while non_leaves.len() > 1 {
-            let mut new_non_leaves: Vec<IntraIndexNonLeaf> = Vec::with_capacity(non_leaves.len());
-            while non_leaves.len() > 1 {
-                let left_idx = non_leaves
-                    .iter()
-                    .enumerate()
-                    .max_by_key(|(_i, n)| n.set_data.len())
-                    .unwrap()
-                    .0;
-                let left = non_leaves.remove(left_idx);
-
-                let mut right_idx = 0;
-                let mut min_set = &left.set_data | &non_leaves[0].set_data;
-                let mut max_sim =
-                    (&left.set_data & &non_leaves[0].set_data).len() as f64 / min_set.len() as f64;
-                for (i, n) in non_leaves.iter().enumerate().skip(1) {
-                    let s = &left.set_data | &n.set_data;
-                    let sim = (&left.set_data & &n.set_data).len() as f64 / s.len() as f64;
-                    if sim > max_sim {
-                        max_sim = sim;
-                        min_set = s;
-                        right_idx = i;
-                    }
-                }
-                let right = non_leaves.remove(right_idx);
-                let min_set_acc_value = multiset_to_g1(&min_set, &param);
-                let node = IntraIndexNonLeaf::create(
-                    block_id,
-                    min_set,
-                    min_set_acc_value,
-                    smallvec![left.to_digest(), right.to_digest()],
-                    smallvec![left.id, right.id],
-                );
-                new_non_leaves.push(node.clone());
+            let (groups, rest) = group_nodes(non_leaves, fanout, |n| &n.set_data);
+            non_leaves = rest.into_iter().collect();
+            for node in create_non_leaves(
+                block_id,
+                groups,
+                |n| (n.to_digest(), n.id),
+                &param,
+                &mut ids,
+            ) {
+                non_leaves.push(node.clone());
                 chain.write_intra_index_node(IntraIndexNode::NonLeaf(Box::new(node)))?;
             }
-            non_leaves.append(&mut new_non_leaves);
         }
 
         // no objs in this block
         if non_leaves.is_empty() {
             let empty_set: MultiSet<SetElementType> = MultiSet::new();
             let acc_value = multiset_to_g1(&empty_set, &param);
-            let node =
-                IntraIndexNonLeaf::create(block_id, empty_set, acc_value, smallvec![], smallvec![]);
+            let node = IntraIndexNonLeaf::create(
+                block_id,
+                empty_set,
+                acc_value,
+                smallvec![],
+                smallvec![],
+                &mut ids,
+            );
             non_leaves.push(node.clone());
             chain.write_intra_index_node(IntraIndexNode::NonLeaf(Box::new(node)))?;
         }
@@ -146,23 +165,28 @@ pub fn build_block<'a>(
             set_data: root.set_data,
             acc_value: root.acc_value,
             skip_list_ids: Vec::new(),
+            timestamp,
+            tombstones: tombstones.to_vec(),
+            btree_ids: Vec::new(),
         }
     } else {
         let mut hs: Vec<Digest> = Vec::with_capacity(objs.len());
         let mut set_data: MultiSet<SetElementType> = MultiSet::new();
         for obj in &objs {
-            let h = concat_digest_ref([obj.acc_value.to_digest(), obj.to_digest()].iter());
-            hs.push(h);
+            hs.push(obj_leaf_digest(obj));
             set_data = &set_data | &obj.set_data;
         }
-        block_header.data_root = concat_digest(hs.into_iter());
-        let acc_value = multiset_to_g1(&set_data, &param);
+        block_header.data_root = merkle_root(&hs);
+        let acc_value = in_build_pool(|| multiset_to_g1(&set_data, &param));
         BlockData {
             block_id,
             data: IntraData::Flat(objs.iter().map(|o| o.id).collect::<Vec<_>>()),
             set_data,
             acc_value,
             skip_list_ids: Vec::new(),
+            timestamp,
+            tombstones: tombstones.to_vec(),
+            btree_ids: Vec::new(),
         }
     };
 
@@ -175,7 +199,9 @@ pub fn build_block<'a>(
         let mut skip_list_digests: Vec<Digest> =
             Vec::with_capacity(param.skip_list_max_level as usize);
         let mut hash_to_skip = Digest::default();
+        let mut tombstones_to_skip = block_data.tombstones.clone();
 
+        let mut levels = Vec::with_capacity(param.skip_list_max_level as usize);
         'outer: for level in 0..param.skip_list_max_level {
             let blk_num = skipped_blocks_num(level);
             while skipped_blk_num < blk_num {
@@ -189,7 +215,7 @@ pub fn build_block<'a>(
                 hash_to_skip = prev_blk_header.prev_hash;
                 let prev_blk = chain.read_block_data(prev_blk_id)?;
                 match param.acc_type {
-                    acc::Type::ACC1 => {
+                    acc::Type::ACC1 | acc::Type::ACC3 => {
                         set_data_to_skip = &set_data_to_skip | &prev_blk.set_data;
                     }
                     acc::Type::ACC2 => {
@@ -198,22 +224,52 @@ pub fn build_block<'a>(
                     }
                 }
 
+                tombstones_to_skip.extend_from_slice(&prev_blk.tombstones);
+
                 skipped_blk_num += 1;
                 prev_blk_id -= 1;
             }
-
-            let acc_value_to_skip = match param.acc_type {
-                acc::Type::ACC1 => multiset_to_g1(&set_data_to_skip, &param),
-                acc::Type::ACC2 => acc_value_to_skip.into_affine(),
-            };
-
-            let skip_node = SkipListNode::create(
-                block_id,
+            levels.push((
                 level,
                 set_data_to_skip.clone(),
                 acc_value_to_skip,
                 hash_to_skip,
+                tombstones_to_skip.clone(),
+            ));
+        }
+
+        // the accs of ACC1 and ACC3 are computed from scratch for each level,
+        // and the polynomials of ACC1 are kept for the proofs of the jumps
+        let skip_nodes: Vec<SkipListNode> = in_build_pool(|| {
+            levels
+                .into_par_iter()
+                .map(|(level, set_data, acc_value, hash, tombstones)| {
+                    let (acc_value, set_poly) = match param.acc_type {
+                        acc::Type::ACC1 => {
+                            let poly = acc::DigestSet::new(&set_data).expand_to_poly();
+                            let acc_value = if param.use_sk {
+                                acc::Acc1::cal_acc_g1_sk(&set_data)
+                            } else {
+                                acc::Acc1::cal_acc_g1_poly(&poly)
+                            };
+                            (acc_value, poly.coeffs)
+                        }
+                        acc::Type::ACC2 => (acc_value.into_affine(), Vec::new()),
+                        acc::Type::ACC3 => (multiset_to_g1(&set_data, &param), Vec::new()),
+                    };
+                    (level, set_data, acc_value, hash, tombstones, set_poly)
+                })
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .map(|(level, set_data, acc_value, hash, tombstones, set_poly)| {
+            let node = SkipListNode::create(
+                block_id, level, set_data, acc_value, hash, tombstones, &mut ids,
             );
+            SkipListNode { set_poly, ..node }
+        })
+        .collect();
+        for skip_node in skip_nodes {
             skip_list_ids.push(skip_node.id);
             skip_list_digests.push(skip_node.digest);
             chain.write_skip_list_node(skip_node)?;
@@ -225,8 +281,337 @@ pub fn build_block<'a>(
         }
     }
 
+    if param.btree_max_level > 0 {
+        let mut btree_nodes: Vec<BTreeNode> = Vec::with_capacity(param.btree_max_level as usize);
+        'levels: for level in 1..=param.btree_max_level {
+            let covered = btree_covered_blocks(level);
+            if block_id < covered || !block_id.is_multiple_of(covered) {
+                break;
+            }
+            let child_covered = btree_covered_blocks(level - 1);
+            let mut children = Vec::with_capacity(BTREE_FANOUT as usize);
+            for i in (1..BTREE_FANOUT).rev() {
+                match read_btree_child(block_id - i * child_covered, level, chain) {
+                    Ok(Some(child)) => children.push(child),
+                    // the blocks before the start of the chain
+                    _ => break 'levels,
+                }
+            }
+            children.push(match btree_nodes.last() {
+                Some(n) => BTreeChild::from(n),
+                None => BTreeChild {
+                    set_data: block_data.set_data.clone(),
+                    acc_value: block_data.acc_value,
+                    hash: block_header.data_root,
+                    pre_skipped_hash: block_header.prev_hash,
+                    tombstones: block_data.tombstones.clone(),
+                },
+            });
+
+            let mut set_data: MultiSet<SetElementType> = MultiSet::new();
+            let mut acc_value = G1Projective::zero();
+            for child in &children {
+                match param.acc_type {
+                    acc::Type::ACC1 | acc::Type::ACC3 => {
+                        set_data = &set_data | &child.set_data;
+                    }
+                    acc::Type::ACC2 => {
+                        set_data = &set_data + &child.set_data;
+                        acc_value.add_assign_mixed(&child.acc_value);
+                    }
+                }
+            }
+            let acc_value = match param.acc_type {
+                acc::Type::ACC1 | acc::Type::ACC3 => {
+                    in_build_pool(|| multiset_to_g1(&set_data, &param))
+                }
+                acc::Type::ACC2 => acc_value.into_affine(),
+            };
+            let child_hashes: Vec<Digest> = children.iter().map(|c| c.hash).collect();
+            let tombstones = children
+                .iter()
+                .flat_map(|c| c.tombstones.iter().copied())
+                .collect();
+            btree_nodes.push(BTreeNode::create(
+                block_id,
+                level,
+                set_data,
+                acc_value,
+                children[0].pre_skipped_hash,
+                &child_hashes,
+                tombstones,
+                &mut ids,
+            ));
+        }
+
+        if !btree_nodes.is_empty() {
+            block_header.skip_list_root = Some(concat_digest(btree_nodes.iter().map(|n| n.digest)));
+            block_data.btree_ids = btree_nodes.iter().map(|n| n.id).collect();
+            for node in btree_nodes {
+                chain.write_btree_node(node)?;
+            }
+        }
+    }
+
     chain.write_block_header(block_header)?;
     chain.write_block_data(block_data)?;
+    chain.write_id_counter(ids)?;
+    chain.commit_block()?;
 
     Ok(block_header)
 }
+
+// Called by ChainBuilder with each block committed, see ChainBuilder::on_block.
+pub type OnBlock<'a, C> = dyn FnMut(&BlockHeader, &C) + 'a;
+
+// Appends blocks to a chain, keeping track of its tip so that the callers need
+// not thread the hash of the previous block. The objects over
+// `max_objs_per_block` are carried over to the next block, before the objects
+// of that block.
+pub struct ChainBuilder<'a, C> {
+    chain: &'a mut C,
+    chain_id: String,
+    max_objs_per_block: usize,
+    tip: Option<BlockHeader>,
+    pending: Vec<RawObject>,
+    // a reference rather than a box, so that the builder has no drop glue and
+    // the chain can be read once it is no longer used
+    on_block: Option<&'a mut OnBlock<'a, C>>,
+}
+
+impl<'a, C: ReadInterface + WriteInterface> ChainBuilder<'a, C> {
+    // `tip` is the last block of the chain, or None for an empty chain.
+    pub fn new(chain: &'a mut C, tip: Option<BlockHeader>) -> Result<Self> {
+        let param = chain.get_parameter()?;
+        Ok(Self {
+            chain,
+            chain_id: param.chain_id,
+            max_objs_per_block: param.max_objs_per_block as usize,
+            tip,
+            pending: Vec::new(),
+            on_block: None,
+        })
+    }
+
+    // Call `f` with each block once it is committed, e.g. to send the deltas
+    // of the standing queries, see StandingQueryManager::notify.
+    pub fn on_block(mut self, f: &'a mut OnBlock<'a, C>) -> Self {
+        self.on_block = Some(f);
+        self
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.tip.as_ref()
+    }
+
+    pub fn chain(&self) -> &C {
+        self.chain
+    }
+
+    // the objects carried over to the next block
+    pub fn pending(&self) -> &[RawObject] {
+        &self.pending
+    }
+
+    pub fn into_pending(self) -> Vec<RawObject> {
+        self.pending
+    }
+
+    // Append blocks after the tip until no object is carried over.
+    pub fn flush(&mut self, timestamp: u64) -> Result<Vec<BlockHeader>> {
+        let mut headers = Vec::new();
+        while !self.pending.is_empty() {
+            let block_id = self.tip.as_ref().map_or(0, |t| t.block_id) + 1;
+            headers.push(self.append_block(block_id, timestamp, std::iter::empty())?);
+        }
+        Ok(headers)
+    }
+
+    pub fn append_block<'b>(
+        &mut self,
+        block_id: IdType,
+        timestamp: u64,
+        raw_objs: impl Iterator<Item = &'b RawObject>,
+    ) -> Result<BlockHeader> {
+        self.append_block_with_tombstones(block_id, timestamp, raw_objs, &[])
+    }
+
+    // The block supersedes the objects of `tombstones`, which are then left
+    // out of the results of the queries covering it. An object is updated by
+    // adding the new version in the same block.
+    pub fn append_block_with_tombstones<'b>(
+        &mut self,
+        block_id: IdType,
+        timestamp: u64,
+        raw_objs: impl Iterator<Item = &'b RawObject>,
+        tombstones: &[IdType],
+    ) -> Result<BlockHeader> {
+        let prev_hash = match &self.tip {
+            Some(tip) => {
+                ensure!(
+                    block_id > tip.block_id,
+                    Error::Parameter(format!(
+                        "block #{} does not come after the tip #{}",
+                        block_id, tip.block_id
+                    ))
+                );
+                ensure!(
+                    timestamp >= tip.timestamp,
+                    Error::Parameter(format!(
+                        "timestamp of block #{} is earlier than the tip",
+                        block_id
+                    ))
+                );
+                tip.compute_digest(&self.chain_id)
+            }
+            None => Digest::default(),
+        };
+        let raw_objs: Vec<_> = raw_objs.collect();
+        ensure!(
+            raw_objs.iter().all(|o| o.block_id == block_id),
+            Error::Parameter(format!("objects do not belong to block #{}", block_id))
+        );
+        let num_of_objs = self.pending.len() + raw_objs.len();
+        let num_in_blk = match self.max_objs_per_block {
+            0 => num_of_objs,
+            n => num_of_objs.min(n),
+        };
+        let objs: Vec<RawObject> = self
+            .pending
+            .iter()
+            .chain(raw_objs)
+            .map(|o| RawObject {
+                block_id,
+                ..o.clone()
+            })
+            .collect();
+        let header = build_block(
+            block_id,
+            prev_hash,
+            timestamp,
+            objs[..num_in_blk].iter(),
+            tombstones,
+            self.chain,
+        )?;
+        self.pending = objs[num_in_blk..].to_vec();
+        self.tip = Some(header);
+        if let Some(f) = self.on_block.as_mut() {
+            f(&header, self.chain);
+        }
+        Ok(header)
+    }
+}
+
+// a block, or a B-tree node of the level below
+struct BTreeChild {
+    set_data: MultiSet<SetElementType>,
+    acc_value: G1Affine,
+    hash: Digest,
+    pre_skipped_hash: Digest,
+    tombstones: Vec<IdType>,
+}
+
+impl From<&BTreeNode> for BTreeChild {
+    fn from(n: &BTreeNode) -> Self {
+        Self {
+            set_data: n.set_data.clone(),
+            acc_value: n.acc_value,
+            hash: n.digest,
+            pre_skipped_hash: n.pre_skipped_hash,
+            tombstones: n.tombstones.clone(),
+        }
+    }
+}
+
+// The child ending at the block of a B-tree node of the level.
+fn read_btree_child(
+    block_id: IdType,
+    level: SkipLstLvlType,
+    chain: &impl ReadInterface,
+) -> Result<Option<BTreeChild>> {
+    let blk_data = chain.read_block_data(block_id)?;
+    if level == 1 {
+        let header = chain.read_block_header(block_id)?;
+        return Ok(Some(BTreeChild {
+            set_data: blk_data.set_data,
+            acc_value: blk_data.acc_value,
+            hash: header.data_root,
+            pre_skipped_hash: header.prev_hash,
+            tombstones: blk_data.tombstones,
+        }));
+    }
+    match blk_data.btree_ids.get(level as usize - 2) {
+        Some(&id) => Ok(Some(BTreeChild::from(&chain.read_btree_node(id)?))),
+        None => Ok(None),
+    }
+}
+
+// some nodes with the union of their sets
+type NodeGroup<N> = (Vec<N>, MultiSet<SetElementType>);
+
+// Greedily group the nodes by up to `fanout`, each time starting from the one
+// with the largest set and adding the ones most similar to the group by the
+// Jaccard index. Returns the groups with the unions of their sets, and the node
+// left over if any.
+fn group_nodes<N>(
+    mut nodes: Vec<N>,
+    fanout: usize,
+    set_data: impl Fn(&N) -> &MultiSet<SetElementType>,
+) -> (Vec<NodeGroup<N>>, Option<N>) {
+    let mut groups = Vec::with_capacity(nodes.len() / 2);
+    while nodes.len() > 1 {
+        let first_idx = nodes
+            .iter()
+            .enumerate()
+            .max_by_key(|(_i, n)| set_data(n).len())
+            .unwrap()
+            .0;
+        let first = nodes.remove(first_idx);
+        let mut group_set = set_data(&first).clone();
+        let mut group = vec![first];
+
+        while group.len() < fanout && !nodes.is_empty() {
+            let mut next_idx = 0;
+            let mut min_set = &group_set | set_data(&nodes[0]);
+            let mut max_sim =
+                (&group_set & set_data(&nodes[0])).len() as f64 / min_set.len() as f64;
+            for (i, n) in nodes.iter().enumerate().skip(1) {
+                let s = &group_set | set_data(n);
+                let sim = (&group_set & set_data(n)).len() as f64 / s.len() as f64;
+                if sim > max_sim {
+                    max_sim = sim;
+                    min_set = s;
+                    next_idx = i;
+                }
+            }
+            group.push(nodes.remove(next_idx));
+            group_set = min_set;
+        }
+        groups.push((group, group_set));
+    }
+    (groups, nodes.pop())
+}
+
+// Create the parents of the groups, with their accs computed in parallel.
+fn create_non_leaves<N: Sync>(
+    block_id: IdType,
+    groups: Vec<NodeGroup<N>>,
+    child: impl Fn(&N) -> (Digest, IdType),
+    param: &Parameter,
+    ids: &mut IdCounter,
+) -> Vec<IntraIndexNonLeaf> {
+    let accs: Vec<_> = in_build_pool(|| {
+        groups
+            .par_iter()
+            .map(|(_, set)| multiset_to_g1(set, param))
+            .collect()
+    });
+    groups
+        .into_iter()
+        .zip(accs)
+        .map(|((children, set), acc_value)| {
+            let (child_hashes, child_ids) = children.iter().map(&child).unzip();
+            IntraIndexNonLeaf::create(block_id, set, acc_value, child_hashes, child_ids, ids)
+        })
+        .collect()
+}
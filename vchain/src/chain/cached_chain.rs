@@ -0,0 +1,188 @@
+use super::*;
+use lru::LruCache;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+// The max number of the entries of each kind kept by ReadCache, 0 means not
+// cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheCapacity {
+    pub block_headers: usize,
+    pub block_data: usize,
+    pub intra_index_nodes: usize,
+    pub skip_list_nodes: usize,
+    pub btree_nodes: usize,
+    pub objects: usize,
+}
+
+impl CacheCapacity {
+    pub fn uniform(capacity: usize) -> Self {
+        Self {
+            block_headers: capacity,
+            block_data: capacity,
+            intra_index_nodes: capacity,
+            skip_list_nodes: capacity,
+            btree_nodes: capacity,
+            objects: capacity,
+        }
+    }
+}
+
+// The recently read blocks, index nodes and objects, kept by their ids. They
+// are never changed once written, so the cache can be shared by the
+// snapshots of a growing chain, but not by the chains pruned meanwhile, see
+// prune_before.
+pub struct ReadCache {
+    block_headers: Mutex<LruCache<IdType, BlockHeader>>,
+    block_data: Mutex<LruCache<IdType, BlockData>>,
+    intra_index_nodes: Mutex<LruCache<IdType, IntraIndexNode>>,
+    skip_list_nodes: Mutex<LruCache<IdType, SkipListNode>>,
+    btree_nodes: Mutex<LruCache<IdType, BTreeNode>>,
+    objects: Mutex<LruCache<IdType, Object>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ReadCache {
+    pub fn new(capacity: CacheCapacity) -> Self {
+        Self {
+            block_headers: Mutex::new(LruCache::new(capacity.block_headers)),
+            block_data: Mutex::new(LruCache::new(capacity.block_data)),
+            intra_index_nodes: Mutex::new(LruCache::new(capacity.intra_index_nodes)),
+            skip_list_nodes: Mutex::new(LruCache::new(capacity.skip_list_nodes)),
+            btree_nodes: Mutex::new(LruCache::new(capacity.btree_nodes)),
+            objects: Mutex::new(LruCache::new(capacity.objects)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // The lock is not held while reading from the chain, so the same entry
+    // may be read by several threads at once.
+    fn read<K: Copy + Eq + Hash, V: Clone>(
+        &self,
+        cache: &Mutex<LruCache<K, V>>,
+        key: K,
+        read: impl FnOnce(K) -> Result<V>,
+    ) -> Result<V> {
+        let cached = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .cloned();
+        if let Some(v) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(v);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let v = read(key)?;
+        cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(key, v.clone());
+        Ok(v)
+    }
+}
+
+// A chain with the reads of the blocks, the index nodes and the objects going
+// through a ReadCache, e.g. for the servers answering overlapping queries.
+pub struct CachedChain<R: ReadInterface> {
+    chain: R,
+    cache: Arc<ReadCache>,
+}
+
+impl<R: ReadInterface> CachedChain<R> {
+    pub fn new(chain: R, capacity: CacheCapacity) -> Self {
+        Self::with_cache(chain, Arc::new(ReadCache::new(capacity)))
+    }
+
+    pub fn with_cache(chain: R, cache: Arc<ReadCache>) -> Self {
+        Self { chain, cache }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.chain
+    }
+
+    // e.g. to append blocks, which leaves the cache valid
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.chain
+    }
+
+    pub fn into_inner(self) -> R {
+        self.chain
+    }
+
+    pub fn cache(&self) -> &Arc<ReadCache> {
+        &self.cache
+    }
+}
+
+impl<R: ReadInterface> ReadInterface for CachedChain<R> {
+    fn get_parameter(&self) -> Result<Parameter> {
+        self.chain.get_parameter()
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.cache.read(&self.cache.block_headers, id, |id| {
+            self.chain.read_block_header(id)
+        })
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        self.cache.read(&self.cache.block_data, id, |id| {
+            self.chain.read_block_data(id)
+        })
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        self.cache.read(&self.cache.intra_index_nodes, id, |id| {
+            self.chain.read_intra_index_node(id)
+        })
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        self.cache.read(&self.cache.skip_list_nodes, id, |id| {
+            self.chain.read_skip_list_node(id)
+        })
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        self.cache.read(&self.cache.btree_nodes, id, |id| {
+            self.chain.read_btree_node(id)
+        })
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        self.cache
+            .read(&self.cache.objects, id, |id| self.chain.read_object(id))
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        self.chain.read_keyword(sym)
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        self.chain.read_keywords()
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        self.chain.read_id_counter()
+    }
+    fn read_pruned_before(&self) -> Result<IdType> {
+        self.chain.read_pruned_before()
+    }
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        self.chain.latest_block_id()
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ReadInterface + Sync> LightNodeInterface for CachedChain<R> {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        self.get_parameter()
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.read_block_header(id)
+    }
+}
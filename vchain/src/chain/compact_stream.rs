@@ -0,0 +1,225 @@
+use super::*;
+use crate::acc::AccumulatorProof;
+use crate::compact;
+use anyhow::{bail, ensure, Result};
+use futures::join;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+
+// Frames larger than this are rejected by the reader, so that a bad stream
+// cannot make it allocate too much.
+pub const MAX_FRAME_LEN: usize = 16 << 20;
+
+// A result written as a sequence of frames, each of which is its length in u32
+// followed by the frame in the compact encoding. The head is the result without
+// the trees and the objects, followed by the nodes of each tree, one frame
+// each with the objects it refers to.
+#[derive(Serialize, Deserialize)]
+enum Frame<AP: AccumulatorProof> {
+    Head(Box<OverallResult<AP>>),
+    Node(QueryFragment),
+    EndTree,
+}
+
+fn write_frame<AP: AccumulatorProof + Serialize>(
+    w: &mut impl Write,
+    frame: &Frame<AP>,
+) -> Result<()> {
+    let data = compact::to_bytes(frame)?;
+    ensure!(data.len() <= MAX_FRAME_LEN, "frame too large");
+    w.write_all(&(data.len() as u32).to_le_bytes())?;
+    w.write_all(&data)?;
+    Ok(())
+}
+
+// None at the end of the stream.
+fn read_frame<AP: AccumulatorProof + DeserializeOwned>(
+    r: &mut impl Read,
+) -> Result<Option<Frame<AP>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    ensure!(
+        len <= MAX_FRAME_LEN,
+        Error::Verification("frame too large".to_owned())
+    );
+    let mut data = vec![0u8; len];
+    r.read_exact(&mut data)?;
+    Ok(Some(compact::from_bytes(&data)?))
+}
+
+impl<AP: AccumulatorProof + Serialize + Clone> OverallResult<AP> {
+    // Write the result for verify_compact_stream.
+    pub fn write_compact_stream(&self, w: &mut impl Write) -> Result<()> {
+        let head = OverallResult {
+            res_objs: ResultObjs::new(),
+            res_vo: ResultVO {
+                vo_t: ResultVOTree::new(),
+                interval_trees: Vec::new(),
+                vo_acc: self.res_vo.vo_acc.clone(),
+            },
+            query: self.query.clone(),
+            time_window_blocks: self.time_window_blocks,
+            query_exp_set: self.query_exp_set.clone(),
+            query_time_in_ms: self.query_time_in_ms,
+            v_bit_len: self.v_bit_len.clone(),
+            vo_size: self.vo_size,
+            vo_size_json: self.vo_size_json,
+            vo_size_compact: self.vo_size_compact,
+            vo_stats: self.vo_stats.clone(),
+            format_version: self.format_version,
+        };
+        write_frame(w, &Frame::Head(Box::new(head)))?;
+        let trees: Vec<&ResultVOTree> = if self.res_vo.interval_trees.is_empty() {
+            vec![&self.res_vo.vo_t]
+        } else {
+            self.res_vo.interval_trees.iter().collect()
+        };
+        for tree in trees {
+            for n in &tree.0 {
+                let mut ids = Vec::new();
+                n.collect_matched_obj_ids(&mut ids);
+                let objs = ids
+                    .iter()
+                    .filter_map(|id| self.res_objs.get(id))
+                    .cloned()
+                    .collect();
+                let frag = QueryFragment {
+                    vo_node: n.clone(),
+                    objs,
+                };
+                write_frame::<AP>(w, &Frame::Node(frag))?;
+            }
+            write_frame::<AP>(w, &Frame::EndTree)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+// Verify a result read from a stream written by
+// OverallResult::write_compact_stream, one node at a time. Only the acc
+// proofs, the ids of the objects and the tombstones are kept besides the
+// current node. `f` is called with each object of the result as it is read,
+// which is not to be trusted unless the whole result passes.
+pub async fn verify_compact_stream<AP: AccumulatorProof + Serialize + DeserializeOwned>(
+    r: &mut impl Read,
+    level: VerifyLevel,
+    chain: &impl LightNodeInterface,
+    mut f: impl FnMut(&Object),
+) -> Result<VerifyResult> {
+    let head = match read_frame::<AP>(r)? {
+        Some(Frame::Head(head)) => head,
+        _ => bail!(Error::Verification("invalid head of the result".to_owned())),
+    };
+    let query_exp = head.query.to_bool_exp(&head.v_bit_len)?;
+    let mut result = head.verify_acc_at(level, &query_exp)?;
+    let (start_block, end_block) = head
+        .time_window_blocks
+        .unwrap_or((head.query.start_block, head.query.end_block));
+    let (param, blk1, blk2) = join!(
+        chain.lightnode_get_parameter(),
+        chain.lightnode_read_block_header(start_block),
+        chain.lightnode_read_block_header(end_block)
+    );
+    let (blk1, blk2) = (blk1?, blk2?);
+    if !head.verify_time_window(&blk1, &blk2, chain).await? {
+        result.add(InvalidReason::InvalidTimeWindow);
+    }
+    let param = param?;
+    let intervals = match &head.query.intervals {
+        Some(intervals) => intervals.clone(),
+        None => vec![(start_block, end_block)],
+    };
+    let mut obj_ids = Vec::new();
+    let mut revealed_ids = Vec::new();
+    let mut tombstones = HashSet::new();
+    for (i, &(start, end)) in intervals.iter().enumerate() {
+        let (blk1, blk2) = if (start, end) == (start_block, end_block) {
+            (blk1, blk2)
+        } else {
+            let (blk1, blk2) = join!(
+                chain.lightnode_read_block_header(start),
+                chain.lightnode_read_block_header(end)
+            );
+            (blk1?, blk2?)
+        };
+        let mut hash_root = Some(blk1.prev_hash);
+        loop {
+            let QueryFragment { vo_node, objs } = match read_frame::<AP>(r)? {
+                Some(Frame::Node(frag)) => frag,
+                Some(Frame::EndTree) => break,
+                Some(Frame::Head(_)) => bail!(Error::Verification(
+                    "unexpected head of the result".to_owned()
+                )),
+                // fewer trees than the intervals
+                None => {
+                    result.add(InvalidReason::InvalidHash);
+                    return Ok(result);
+                }
+            };
+            let mut res_objs = ResultObjs::new();
+            for obj in objs {
+                let matched = obj
+                    .compute_set_data(&param)
+                    .is_some_and(|set| query_exp.is_match(&set));
+                if !matched {
+                    result.add(InvalidReason::InvalidMatchObj(obj.id));
+                }
+                obj_ids.push(obj.id);
+                f(&obj);
+                res_objs.insert(obj);
+            }
+            let mut revealed_objs = Vec::new();
+            vo_node.collect_revealed_objs(&mut revealed_objs);
+            for obj in revealed_objs {
+                // an object of more dimensions cannot be on the chain
+                let matched = obj
+                    .compute_set_data(&param)
+                    .is_none_or(|set| query_exp.is_match(&set));
+                if matched {
+                    revealed_ids.push(obj.id);
+                }
+            }
+            vo_node.collect_tombstones(&mut tombstones);
+            hash_root = hash_root.and_then(|h| {
+                vo_node.compute_digest(&res_objs, &head.res_vo.vo_acc, &h, &param.chain_id)
+            });
+        }
+        let expected = blk2.compute_digest(&param.chain_id);
+        if hash_root != Some(expected) {
+            let path = match head.query.intervals {
+                Some(_) => format!("interval_trees[{}]", i),
+                None => "tree".to_owned(),
+            };
+            result.add(InvalidReason::HashMismatch(Box::new(HashMismatch {
+                block_id: blk2.block_id,
+                path,
+                expected: Some(expected),
+                computed: hash_root,
+            })));
+        }
+    }
+    // more trees than the intervals
+    if read_frame::<AP>(r)?.is_some() {
+        result.add(InvalidReason::InvalidHash);
+    }
+    // the tombstones only refer to the objects of the earlier blocks, see
+    // OverallResult::verify
+    for id in obj_ids {
+        if tombstones.contains(&id) {
+            result.add(InvalidReason::SupersededObj(id));
+        }
+    }
+    for id in revealed_ids {
+        if !tombstones.contains(&id) {
+            result.add(InvalidReason::InvalidRevealedObj(id));
+        }
+    }
+    Ok(result)
+}
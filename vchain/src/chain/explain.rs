@@ -0,0 +1,201 @@
+use super::vo::*;
+use super::*;
+use crate::acc::AccumulatorProof;
+use crate::set::MultiSet;
+use std::fmt::{self, Write};
+
+// A human-readable dump of the VO of a result, e.g. to find out why a query
+// scanned more nodes than expected. Each line is a node, indented by its depth,
+// with the clause and the acc proof that pruned it, if any.
+impl<AP: AccumulatorProof + Serialize> OverallResult<AP> {
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        self.write_explain(&mut out)
+            .expect("writing to a string never fails");
+        out
+    }
+
+    fn write_explain(&self, f: &mut String) -> fmt::Result {
+        writeln!(
+            f,
+            "query: blks #{}..=#{}, vo {} bytes",
+            self.query.start_block, self.query.end_block, self.vo_size
+        )?;
+        for (i, set) in self.query_exp_set.iter().enumerate() {
+            writeln!(f, "clause {}: {}", i, fmt_clause(set, &self.v_bit_len))?;
+        }
+        Explainer { f }.write_vo(&self.res_vo)
+    }
+}
+
+struct Explainer<'a> {
+    f: &'a mut String,
+}
+
+impl<'a> Explainer<'a> {
+    fn line(&mut self, depth: usize, args: fmt::Arguments) -> fmt::Result {
+        writeln!(self.f, "{:width$}{}", "", args, width = 2 * depth)
+    }
+
+    fn pruned(proof_idx: AccProofIdxType) -> String {
+        format!(
+            "mismatched, pruned by clause {}, proof ({}, {})",
+            proof_idx.0, proof_idx.0, proof_idx.1
+        )
+    }
+
+    fn write_vo<AP: AccumulatorProof + Serialize>(mut self, vo: &ResultVO<AP>) -> fmt::Result {
+        if vo.interval_trees.is_empty() {
+            self.line(0, format_args!("tree: {} nodes", vo.vo_t.0.len()))?;
+            self.write_tree(&vo.vo_t)?;
+        }
+        for (i, tree) in vo.interval_trees.iter().enumerate() {
+            self.line(
+                0,
+                format_args!("interval tree {}: {} nodes", i, tree.0.len()),
+            )?;
+            self.write_tree(tree)?;
+        }
+        self.line(0, format_args!("acc proofs:"))?;
+        let mut proofs: Vec<_> = vo.vo_acc.proofs.iter().collect();
+        proofs.sort_unstable_by_key(|(&i, _)| i);
+        for (i, proofs) in proofs {
+            let size = bincode::serialize(proofs).map_or(0, |b| b.len());
+            self.line(
+                1,
+                format_args!("clause {}: {} proofs, {} bytes", i, proofs.len(), size),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_tree(&mut self, tree: &ResultVOTree) -> fmt::Result {
+        for n in &tree.0 {
+            let size = bincode::serialize(n).map_or(0, |b| b.len());
+            match n {
+                ResultVONode::FlatBlkNode(n) => {
+                    self.line(
+                        1,
+                        format_args!(
+                            "blk #{} flat, {} objs, {} tombstones, {} bytes",
+                            n.block_id,
+                            n.sub_nodes.len(),
+                            n.tombstones.len(),
+                            size
+                        ),
+                    )?;
+                    for sub_node in &n.sub_nodes {
+                        self.write_obj_node(2, sub_node)?;
+                    }
+                }
+                ResultVONode::BlkNode(n) => {
+                    self.line(
+                        1,
+                        format_args!(
+                            "blk #{} intra index, {} tombstones, {} bytes",
+                            n.block_id,
+                            n.tombstones.len(),
+                            size
+                        ),
+                    )?;
+                    self.write_intra_node(2, &n.sub_node)?;
+                }
+                ResultVONode::SkipListRoot(n) => {
+                    self.line(1, format_args!("blk #{} jump, {} bytes", n.block_id, size))?;
+                    for sub_node in &n.sub_nodes {
+                        self.write_jump_node(2, sub_node)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_obj_node(&mut self, depth: usize, n: &ObjNode) -> fmt::Result {
+        match n {
+            ObjNode::Match(n) => self.line(depth, format_args!("obj #{} matched", n.obj_id)),
+            ObjNode::NoMatch(n) => {
+                let pruned = Self::pruned(n.proof_idx);
+                self.line(depth, format_args!("obj #{} {}", n.obj_id, pruned))
+            }
+            ObjNode::Revealed(n) => self.line(depth, format_args!("obj #{} revealed", n.obj.id)),
+        }
+    }
+
+    fn write_intra_node(&mut self, depth: usize, n: &IntraNode) -> fmt::Result {
+        match n {
+            IntraNode::NoMatchIntraLeaf(n) => {
+                let pruned = Self::pruned(n.proof_idx);
+                self.line(depth, format_args!("leaf #{} {}", n.id, pruned))
+            }
+            IntraNode::NoMatchIntraNonLeaf(n) => {
+                let pruned = Self::pruned(n.proof_idx);
+                self.line(depth, format_args!("non-leaf #{} {}", n.id, pruned))
+            }
+            IntraNode::MatchIntraLeaf(n) => self.line(
+                depth,
+                format_args!("leaf #{} matched, obj #{}", n.id, n.obj_id),
+            ),
+            IntraNode::RevealedIntraLeaf(n) => self.line(
+                depth,
+                format_args!("leaf #{} revealed, obj #{}", n.id, n.obj.id),
+            ),
+            IntraNode::IntraNonLeaf(n) => {
+                self.line(depth, format_args!("non-leaf #{}", n.id))?;
+                for child in &n.children {
+                    self.write_intra_node(depth + 1, child)?;
+                }
+                Ok(())
+            }
+            IntraNode::Empty => self.line(depth, format_args!("empty")),
+        }
+    }
+
+    fn write_jump_node(&mut self, depth: usize, n: &JumpOrNoJumpNode) -> fmt::Result {
+        match n {
+            JumpOrNoJumpNode::Jump(n) => {
+                let pruned = Self::pruned(n.proof_idx);
+                self.line(
+                    depth,
+                    format_args!(
+                        "skip list #{} {}, {} tombstones",
+                        n.id,
+                        pruned,
+                        n.tombstones.len()
+                    ),
+                )
+            }
+            JumpOrNoJumpNode::BTreeJump(n) => {
+                let pruned = Self::pruned(n.proof_idx);
+                self.line(
+                    depth,
+                    format_args!(
+                        "b-tree #{} {}, {} tombstones",
+                        n.id,
+                        pruned,
+                        n.tombstones.len()
+                    ),
+                )
+            }
+            JumpOrNoJumpNode::NoJump(n) => {
+                self.line(depth, format_args!("index #{} not jumped", n.id))
+            }
+        }
+    }
+}
+
+fn fmt_clause(set: &MultiSet<SetElementType>, v_bit_len: &[u8]) -> String {
+    let mut elems: Vec<String> = set
+        .keys()
+        .map(|e| match e {
+            SetElementType::V { dim, val, mask } => {
+                let bits = v_bit_len.get(*dim as usize).map_or(0, |&l| bit_mask(l));
+                format!("v{} in [{}, {}]", dim, val, val | (!mask & bits))
+            }
+            SetElementType::W(w) => format!("{:?}", w.word()),
+            SetElementType::WPrefix(w) => format!("{:?}", format!("{}*", w.word())),
+        })
+        .collect();
+    elems.sort();
+    format!("{{{}}}", elems.join(", "))
+}
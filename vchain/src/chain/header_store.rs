@@ -0,0 +1,160 @@
+use super::*;
+use crate::acc::AccumulatorProof;
+use anyhow::ensure;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+// The block headers trusted by a light client, e.g. synced from a full node
+// once, for verifying the results offline. The headers of the consecutive
+// blocks are checked to be linked by their hashes as they are added.
+#[derive(Debug, Clone)]
+pub struct HeaderStore {
+    param: Parameter,
+    headers: BTreeMap<IdType, BlockHeader>,
+}
+
+// The headers in a checkpoint file, in JSON.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    param: Parameter,
+    headers: Vec<BlockHeader>,
+}
+
+impl HeaderStore {
+    pub fn new(param: Parameter) -> Self {
+        Self {
+            param,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_headers(
+        param: Parameter,
+        headers: impl IntoIterator<Item = BlockHeader>,
+    ) -> Result<Self> {
+        let mut store = Self::new(param);
+        for header in headers {
+            store.insert(header)?;
+        }
+        Ok(store)
+    }
+
+    pub fn param(&self) -> &Parameter {
+        &self.param
+    }
+
+    pub fn get(&self, id: IdType) -> Option<&BlockHeader> {
+        self.headers.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn insert(&mut self, header: BlockHeader) -> Result<()> {
+        let chain_id = &self.param.chain_id;
+        let id = header.block_id;
+        if let Some(prev) = id.checked_sub(1).and_then(|id| self.headers.get(&id)) {
+            ensure!(
+                header.prev_hash == prev.compute_digest(chain_id),
+                Error::Verification(format!(
+                    "blk #{} is not linked to blk #{}",
+                    id, prev.block_id
+                ))
+            );
+        }
+        if let Some(next) = self.headers.get(&(id + 1)) {
+            ensure!(
+                next.prev_hash == header.compute_digest(chain_id),
+                Error::Verification(format!(
+                    "blk #{} is not linked to blk #{}",
+                    next.block_id, id
+                ))
+            );
+        }
+        if let Some(old) = self.headers.get(&id) {
+            ensure!(
+                *old == header,
+                Error::Verification(format!("blk #{} is trusted already", id))
+            );
+        }
+        self.headers.insert(id, header);
+        Ok(())
+    }
+
+    // Fetch the headers of the blocks in [start, end] from a node trusted for
+    // now, e.g. to export them as a checkpoint.
+    pub async fn sync(
+        &mut self,
+        start: IdType,
+        end: IdType,
+        chain: &impl LightNodeInterface,
+    ) -> Result<()> {
+        for id in start..=end {
+            if !self.headers.contains_key(&id) {
+                self.insert(chain.lightnode_read_block_header(id).await?)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn export_checkpoint(&self, w: impl Write) -> Result<()> {
+        let checkpoint = Checkpoint {
+            param: self.param.clone(),
+            headers: self.headers.values().copied().collect(),
+        };
+        serde_json::to_writer(w, &checkpoint)?;
+        Ok(())
+    }
+
+    pub fn import_checkpoint(r: impl Read) -> Result<Self> {
+        let checkpoint: Checkpoint = serde_json::from_reader(r)
+            .map_err(|e| Error::Verification(format!("invalid checkpoint: {}", e)))?;
+        Self::with_headers(checkpoint.param, checkpoint.headers)
+    }
+}
+
+#[async_trait::async_trait]
+impl LightNodeInterface for HeaderStore {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        Ok(self.param.clone())
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.headers
+            .get(&id)
+            .copied()
+            .ok_or_else(|| Error::Verification(format!("blk #{} is not trusted", id)).into())
+    }
+}
+
+impl<AP: AccumulatorProof + Serialize> OverallResult<AP> {
+    // Like verify, without any node to talk to.
+    pub fn verify_with_headers(&self, headers: &HeaderStore) -> Result<(VerifyResult, Duration)> {
+        futures::executor::block_on(self.verify(headers))
+    }
+}
+
+// Verify a result in JSON, i.e. the response of POST /query, with the headers
+// of a checkpoint, e.g. for the verifiers in the browsers, which get both as
+// strings. The results carry no acc type, so the one of the checkpoint is taken.
+pub fn verify_json(result: &str, checkpoint: &str) -> Result<VerifyResult> {
+    fn verify<AP>(result: &str, headers: &HeaderStore) -> Result<VerifyResult>
+    where
+        AP: AccumulatorProof + Serialize + serde::de::DeserializeOwned,
+    {
+        let res = OverallResult::<AP>::from_json(result.as_bytes())?;
+        Ok(res.verify_with_headers(headers)?.0)
+    }
+
+    let headers = HeaderStore::import_checkpoint(checkpoint.as_bytes())?;
+    match headers.param().acc_type {
+        acc::Type::ACC1 => verify::<acc::Acc1Proof>(result, &headers),
+        acc::Type::ACC2 => verify::<acc::Acc2Proof>(result, &headers),
+        acc::Type::ACC3 => verify::<acc::Acc3Proof>(result, &headers),
+    }
+}
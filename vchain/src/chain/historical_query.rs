@@ -1,46 +1,568 @@
 #![allow(clippy::cognitive_complexity)]
 
 use super::*;
-use crate::acc::{AccumulatorProof, DigestSet};
-use anyhow::{bail, Result};
-use std::collections::VecDeque;
+use crate::acc::{AccumulatorProof, DigestSet, DigestSetCache};
+use crate::digest::{Digest, Digestible};
+use crate::par::*;
+use crate::set::{MultiSet, SetFilter};
+use crate::timer::Timer;
+use anyhow::{bail, ensure, Result};
+use ark_poly::{univariate::DensePolynomial, UVPolynomial};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+// the acc value of a node is used as the key of its set data
+const DIGEST_SET_CACHE_SIZE: usize = 4096;
+
+// The digest sets of the clauses of the queries and of the set data of the
+// nodes, which can be shared by the queries of a batch.
+struct DigestSets {
+    clauses: Vec<(MultiSet<SetElementType>, DigestSet)>,
+    nodes: DigestSetCache,
+}
+
+impl DigestSets {
+    fn new() -> Self {
+        Self {
+            clauses: Vec::new(),
+            nodes: DigestSetCache::new(DIGEST_SET_CACHE_SIZE),
+        }
+    }
+
+    fn clauses(&mut self, sets: &[MultiSet<SetElementType>]) -> Vec<DigestSet> {
+        sets.iter()
+            .map(|s| match self.clauses.iter().find(|(c, _)| c == s) {
+                Some((_, d)) => d.clone(),
+                None => {
+                    let d = in_query_pool(|| DigestSet::new(s));
+                    self.clauses.push((s.clone(), d.clone()));
+                    d
+                }
+            })
+            .collect()
+    }
+}
+
+// The digest set of a node, computed in the query pool if not cached.
+fn node_digest_set<'a>(
+    cache: &'a mut DigestSetCache,
+    key: Digest,
+    set: &MultiSet<SetElementType>,
+) -> &'a DigestSet {
+    cache.get_or_insert_with(key, || in_query_pool(|| DigestSet::new(set)))
+}
 
 pub fn historical_query<AP: AccumulatorProof + Serialize>(
     q: &Query,
     chain: &impl ReadInterface,
 ) -> Result<OverallResult<AP>> {
     info!("process query {:?}", q);
+    query_with(q, chain, &mut DigestSets::new())
+}
+
+fn query_with<AP: AccumulatorProof + Serialize>(
+    q: &Query,
+    chain: &impl ReadInterface,
+    digest_sets: &mut DigestSets,
+) -> Result<OverallResult<AP>> {
+    q.check_intervals()?;
+    if let Some(intervals) = &q.intervals {
+        return query_intervals(q, intervals, chain, digest_sets);
+    }
+    let (start_block, end_block) = resolve_time_window(q, chain)?;
+    let (mut res, _) = query_blocks(q, start_block, end_block, None, chain, digest_sets)?;
+    if q.start_time.is_some() || q.end_time.is_some() {
+        res.time_window_blocks = Some((start_block, end_block));
+    }
+    Ok(res)
+}
+
+// Answer the queries together, one result for each of them. The identical
+// queries are answered once, and the others share the blocks and the nodes
+// read from the chain, as well as the digest sets of the clauses and the nodes.
+pub fn historical_query_batch<AP: AccumulatorProof + Serialize + Clone>(
+    qs: &[Query],
+    chain: &impl ReadInterface,
+) -> Result<Vec<OverallResult<AP>>> {
+    info!("process {} queries in a batch", qs.len());
+    let reads = SharedReads::new(chain);
+    let mut digest_sets = DigestSets::new();
+    let mut results: Vec<OverallResult<AP>> = Vec::with_capacity(qs.len());
+    for (i, q) in qs.iter().enumerate() {
+        let res = match qs[..i].iter().position(|p| p == q) {
+            Some(j) => results[j].clone(),
+            None => {
+                info!("process query {:?}", q);
+                query_with(q, &reads, &mut digest_sets)?
+            }
+        };
+        results.push(res);
+    }
+    Ok(results)
+}
+
+// Keeps everything read from the chain, see historical_query_batch.
+struct SharedReads<'a, C: ReadInterface> {
+    chain: &'a C,
+    block_headers: RefCell<HashMap<IdType, BlockHeader>>,
+    block_data: RefCell<HashMap<IdType, BlockData>>,
+    intra_index_nodes: RefCell<HashMap<IdType, IntraIndexNode>>,
+    skip_list_nodes: RefCell<HashMap<IdType, SkipListNode>>,
+    btree_nodes: RefCell<HashMap<IdType, BTreeNode>>,
+    objects: RefCell<HashMap<IdType, Object>>,
+}
+
+impl<'a, C: ReadInterface> SharedReads<'a, C> {
+    fn new(chain: &'a C) -> Self {
+        Self {
+            chain,
+            block_headers: RefCell::default(),
+            block_data: RefCell::default(),
+            intra_index_nodes: RefCell::default(),
+            skip_list_nodes: RefCell::default(),
+            btree_nodes: RefCell::default(),
+            objects: RefCell::default(),
+        }
+    }
+}
+
+fn read_shared<K: Copy + Eq + Hash, V: Clone>(
+    cache: &RefCell<HashMap<K, V>>,
+    key: K,
+    read: impl FnOnce(K) -> Result<V>,
+) -> Result<V> {
+    if let Some(v) = cache.borrow().get(&key) {
+        return Ok(v.clone());
+    }
+    let v = read(key)?;
+    cache.borrow_mut().insert(key, v.clone());
+    Ok(v)
+}
+
+impl<C: ReadInterface> ReadInterface for SharedReads<'_, C> {
+    fn get_parameter(&self) -> Result<Parameter> {
+        self.chain.get_parameter()
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        read_shared(&self.block_headers, id, |id| {
+            self.chain.read_block_header(id)
+        })
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        read_shared(&self.block_data, id, |id| self.chain.read_block_data(id))
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        read_shared(&self.intra_index_nodes, id, |id| {
+            self.chain.read_intra_index_node(id)
+        })
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        read_shared(&self.skip_list_nodes, id, |id| {
+            self.chain.read_skip_list_node(id)
+        })
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        read_shared(&self.btree_nodes, id, |id| self.chain.read_btree_node(id))
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        read_shared(&self.objects, id, |id| self.chain.read_object(id))
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        self.chain.read_keyword(sym)
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        self.chain.read_keywords()
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        self.chain.read_id_counter()
+    }
+    fn read_pruned_before(&self) -> Result<IdType> {
+        self.chain.read_pruned_before()
+    }
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        self.chain.latest_block_id()
+    }
+}
+
+// Answer the query over `num_segments` consecutive ranges of its blocks in
+// parallel, and stitch the VOs of the ranges in the order of the blocks. The
+// jumps over the blocks do not cross the ranges, so the VO can be larger than
+// the one of historical_query. The queries over block intervals are answered
+// sequentially.
+pub fn historical_query_par<AP: AccumulatorProof + Serialize + Send>(
+    q: &Query,
+    num_segments: usize,
+    chain: &(impl ReadInterface + Sync),
+) -> Result<OverallResult<AP>> {
+    info!("process query {:?} in {} segments", q, num_segments);
+    ensure!(
+        num_segments > 0,
+        Error::Parameter("number of segments should be positive".to_owned())
+    );
+    if q.intervals.is_some() {
+        return historical_query(q, chain);
+    }
+    let timer = Timer::new();
+    let (start_block, end_block) = resolve_time_window(q, chain)?;
+    let num_blocks = (end_block - start_block) as usize + 1;
+    let seg_len = num_blocks.div_ceil(num_segments);
+    let segments: Vec<(IdType, IdType)> = (start_block..=end_block)
+        .step_by(seg_len)
+        .map(|s| (s, end_block.min(s + (seg_len - 1) as IdType)))
+        .collect();
+    let mut parts = in_query_pool(|| {
+        segments
+            .into_par_iter()
+            .map(|(s, e)| {
+                query_blocks::<AP>(q, s, e, None, chain, &mut DigestSets::new()).map(|(res, _)| res)
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut res = parts.pop().expect("at least one segment");
+    while let Some(part) = parts.pop() {
+        res = merge_results(part, res)?;
+    }
+
+    if q.start_time.is_some() || q.end_time.is_some() {
+        res.time_window_blocks = Some((start_block, end_block));
+    }
+    res.query_time_in_ms = timer.elapsed().as_millis() as u64;
+    res.compute_stats()?;
+    Ok(res)
+}
+
+// Move the objects and the accumulator proofs of the result over some earlier
+// blocks into the one over the later blocks, leaving the VO tree of the former
+// to the caller. The tombstones of the later blocks supersede the matched
+// objects of the earlier ones.
+fn absorb_earlier<AP: AccumulatorProof>(
+    later: &mut OverallResult<AP>,
+    earlier: &mut OverallResult<AP>,
+) -> Result<()> {
+    let mut tombstones: HashSet<IdType> = HashSet::new();
+    later.res_vo.collect_tombstones(&mut tombstones);
+    earlier
+        .res_vo
+        .vo_t
+        .reveal_objs(&tombstones, &mut earlier.res_objs);
+    let vo_acc = std::mem::replace(&mut earlier.res_vo.vo_acc, ResultVOAcc::new());
+    let offsets = later.res_vo.vo_acc.append(vo_acc)?;
+    earlier.res_vo.vo_t.shift_proof_idx(&offsets);
+    later.res_objs.0.extend(earlier.res_objs.0.drain());
+    Ok(())
+}
+
+// Stitch the results over two consecutive ranges of blocks.
+fn merge_results<AP: AccumulatorProof>(
+    mut earlier: OverallResult<AP>,
+    mut later: OverallResult<AP>,
+) -> Result<OverallResult<AP>> {
+    absorb_earlier(&mut later, &mut earlier)?;
+    earlier.res_vo.vo_t.0.append(&mut later.res_vo.vo_t.0);
+    later.res_vo.vo_t = earlier.res_vo.vo_t;
+    Ok(later)
+}
+
+// The superseded objects are filtered only by the tombstones within the
+// intervals.
+fn query_intervals<AP: AccumulatorProof + Serialize>(
+    q: &Query,
+    intervals: &[(IdType, IdType)],
+    chain: &impl ReadInterface,
+    digest_sets: &mut DigestSets,
+) -> Result<OverallResult<AP>> {
+    let timer = Timer::new();
+    let mut parts = intervals
+        .iter()
+        .map(|&(start, end)| {
+            query_blocks::<AP>(q, start, end, None, chain, digest_sets).map(|(res, _)| res)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut res = parts.pop().expect("at least one interval");
+    let tree = std::mem::take(&mut res.res_vo.vo_t);
+    res.res_vo.interval_trees.push(tree);
+    while let Some(mut part) = parts.pop() {
+        absorb_earlier(&mut res, &mut part)?;
+        res.res_vo.interval_trees.insert(0, part.res_vo.vo_t);
+    }
+    res.query_time_in_ms = timer.elapsed().as_millis() as u64;
+    res.compute_stats()?;
+    Ok(res)
+}
+
+// The recent results of the queries issued repeatedly over a growing chain,
+// see historical_query_cached.
+pub struct QueryCache<AP: AccumulatorProof> {
+    capacity: usize,
+    // the most recently used first
+    entries: VecDeque<OverallResult<AP>>,
+}
+
+impl<AP: AccumulatorProof> QueryCache<AP> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // The cached result of the same query up to the latest block not after its
+    // end block.
+    fn take(&mut self, q: &Query) -> Option<OverallResult<AP>> {
+        let idx = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, res)| {
+                res.query.end_block <= q.end_block
+                    && Query {
+                        end_block: q.end_block,
+                        ..res.query.clone()
+                    } == *q
+            })
+            .max_by_key(|(_, res)| res.query.end_block)
+            .map(|(i, _)| i)?;
+        self.entries.remove(idx)
+    }
+
+    fn put(&mut self, res: OverallResult<AP>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.truncate(self.capacity - 1);
+        self.entries.push_front(res);
+    }
+}
+
+// Like historical_query, but reuses the cached result of the same query, which
+// is extended by the blocks after it if only the end block has advanced. The
+// VO of an extended result does not jump across the end of the cached one.
+// The queries with time windows or block intervals are not cached.
+pub fn historical_query_cached<AP: AccumulatorProof + Serialize + Clone>(
+    q: &Query,
+    cache: &mut QueryCache<AP>,
+    chain: &impl ReadInterface,
+) -> Result<OverallResult<AP>> {
+    if q.start_time.is_some() || q.end_time.is_some() || q.intervals.is_some() {
+        return historical_query(q, chain);
+    }
+    let res = match cache.take(q) {
+        Some(cached) if cached.query.end_block == q.end_block => {
+            info!("process query {:?} from cache", q);
+            cached
+        }
+        Some(cached) => {
+            info!(
+                "process query {:?} from cache up to blk #{}",
+                q, cached.query.end_block
+            );
+            let timer = Timer::new();
+            let (later, _) = query_blocks(
+                q,
+                cached.query.end_block + 1,
+                q.end_block,
+                None,
+                chain,
+                &mut DigestSets::new(),
+            )?;
+            let mut res = merge_results(cached, later)?;
+            res.query = q.clone();
+            res.query_time_in_ms = timer.elapsed().as_millis() as u64;
+            res.compute_stats()?;
+            res
+        }
+        None => historical_query(q, chain)?,
+    };
+    cache.put(res.clone());
+    Ok(res)
+}
+
+// Answer the query from its end block backwards, until at least `page_size`
+// objects are found. Each page is verified as a query over just the blocks it
+// covers, and the time window of the query is resolved only for the first page.
+// The objects superseded by the blocks of the earlier pages are not filtered.
+pub fn historical_query_page<AP: AccumulatorProof + Serialize>(
+    q: &Query,
+    page_token: Option<&PageToken>,
+    page_size: usize,
+    chain: &impl ReadInterface,
+) -> Result<QueryPage<AP>> {
+    info!("process query {:?} from {:?}", q, page_token);
+    ensure!(
+        page_size > 0,
+        Error::Parameter("page size should be positive".to_owned())
+    );
+    ensure!(
+        q.intervals.is_none(),
+        Error::Parameter("block intervals are not supported".to_owned())
+    );
+    let (start_block, end_block) = match page_token {
+        Some(token) => (token.start_block, token.end_block),
+        None => resolve_time_window(q, chain)?,
+    };
+    let mut page_q = q.clone();
+    page_q.start_time = None;
+    page_q.end_time = None;
+    let (mut result, first_block) = query_blocks(
+        &page_q,
+        start_block,
+        end_block,
+        Some(page_size),
+        chain,
+        &mut DigestSets::new(),
+    )?;
+    result.query.start_block = first_block;
+    result.query.end_block = end_block;
+    let next_page = if first_block > start_block {
+        Some(PageToken {
+            start_block,
+            end_block: first_block - 1,
+        })
+    } else {
+        None
+    };
+    Ok(QueryPage { result, next_page })
+}
+
+// Like historical_query, but hands the VO of each block, or of each jump over
+// the blocks, to `f` together with the objects matched in it as soon as it is
+// scanned, i.e. from the end block backwards. The returned result holds the
+// rest of the VO, whose accumulator proofs are complete only at the end, see
+// OverallResult::add_fragments.
+pub fn historical_query_stream<AP: AccumulatorProof + Serialize>(
+    q: &Query,
+    chain: &impl ReadInterface,
+    f: impl FnMut(QueryFragment) -> Result<()>,
+) -> Result<OverallResult<AP>> {
+    info!("process query {:?} as a stream", q);
+    ensure!(
+        q.intervals.is_none(),
+        Error::Parameter("block intervals are not supported".to_owned())
+    );
+    let (start_block, end_block) = resolve_time_window(q, chain)?;
+    let (mut res, _) = scan_blocks(
+        q,
+        start_block,
+        end_block,
+        None,
+        chain,
+        &mut DigestSets::new(),
+        f,
+    )?;
+    if q.start_time.is_some() || q.end_time.is_some() {
+        res.time_window_blocks = Some((start_block, end_block));
+    }
+    res.compute_stats()?;
+    Ok(res)
+}
+
+// Returns the result together with the first block it covers.
+fn query_blocks<AP: AccumulatorProof + Serialize>(
+    q: &Query,
+    start_block: IdType,
+    end_block: IdType,
+    page_size: Option<usize>,
+    chain: &impl ReadInterface,
+    digest_sets: &mut DigestSets,
+) -> Result<(OverallResult<AP>, IdType)> {
+    let mut frags = Vec::new();
+    let (mut res, first_block) = scan_blocks(
+        q,
+        start_block,
+        end_block,
+        page_size,
+        chain,
+        digest_sets,
+        |frag| {
+            frags.push(frag);
+            Ok(())
+        },
+    )?;
+    res.add_fragments(frags)?;
+    Ok((res, first_block))
+}
+
+// Scan the blocks from `end_block` backwards and pass the VO nodes to `emit`.
+// Returns the result without the VO nodes and the objects, together with the
+// first block scanned.
+fn scan_blocks<AP: AccumulatorProof + Serialize>(
+    q: &Query,
+    start_block: IdType,
+    end_block: IdType,
+    page_size: Option<usize>,
+    chain: &impl ReadInterface,
+    digest_sets: &mut DigestSets,
+    mut emit: impl FnMut(QueryFragment) -> Result<()>,
+) -> Result<(OverallResult<AP>, IdType)> {
     let param = chain.get_parameter()?;
-    let cpu_timer = howlong::ProcessCPUTimer::new();
-    let timer = howlong::HighResolutionTimer::new();
+    let timer = Timer::new();
 
-    let query_exp = q.to_bool_exp(&param.v_bit_len);
+    let pruned_before = chain.read_pruned_before()?;
+    ensure!(
+        start_block >= pruned_before,
+        Error::Proof(format!(
+            "blks before #{} are pruned, cannot query from blk #{}",
+            pruned_before, start_block
+        ))
+    );
+    let query_exp = q.to_bool_exp(&param.v_bit_len)?;
+    ensure!(
+        max_prefix_len_of(&query_exp) <= param.max_prefix_len as usize,
+        Error::Parameter(format!(
+            "keyword prefixes longer than {} chars are not indexed",
+            param.max_prefix_len
+        ))
+    );
     let mut res = OverallResult {
         res_objs: ResultObjs::new(),
         res_vo: ResultVO::<AP>::new(),
         query: q.clone(),
+        time_window_blocks: None,
         query_exp_set: query_exp.inner.clone(),
         query_time_in_ms: 0,
         v_bit_len: param.v_bit_len.clone(),
         vo_size: 0,
+        vo_size_json: 0,
+        vo_size_compact: 0,
         vo_stats: VOStatistic::default(),
+        format_version: FORMAT_VERSION,
     };
-    let query_exp_digest_set = query_exp
-        .inner
-        .iter()
-        .map(|s| DigestSet::new(s))
-        .collect::<Vec<_>>();
+    let query_exp_digest_set = digest_sets.clauses(&query_exp.inner);
+    let query_exp_filters = query_exp.filters();
+    let digest_set_cache = &mut digest_sets.nodes;
+    // the objects superseded by the blocks scanned so far
+    let mut tombstones: HashSet<IdType> = HashSet::new();
+    let mut num_of_objs = 0;
 
-    let mut block_id = q.end_block;
-    while block_id >= q.start_block {
+    let mut block_id = end_block;
+    while block_id >= start_block {
+        if matches!(page_size, Some(n) if num_of_objs >= n) {
+            break;
+        }
         let blk_data = chain.read_block_data(block_id)?;
         let blk_header = chain.read_block_header(block_id)?;
 
         if !blk_data.skip_list_ids.is_empty() {
             let mut vo_skip = vo::SkipListRoot {
                 block_id,
+                blk_timestamp: blk_header.timestamp,
                 blk_prev_hash: blk_header.prev_hash,
                 blk_data_root: blk_header.data_root,
+                blk_tombstone_root: blk_header.tombstone_root,
                 sub_nodes: Vec::new(),
             };
             let mut jmp_level: Option<SkipLstLvlType> = None;
@@ -48,21 +570,40 @@ pub fn historical_query<AP: AccumulatorProof + Serialize>(
             for (lvl, &skip_list_id) in blk_data.skip_list_ids.iter().enumerate().rev() {
                 let jmp_node = chain.read_skip_list_node(skip_list_id)?;
                 if jmp_level.is_some()
-                    || q.start_block + skipped_blocks_num(lvl as SkipLstLvlType) > block_id
+                    || start_block + skipped_blocks_num(lvl as SkipLstLvlType) > block_id
                 {
                     vo_skip
                         .sub_nodes
                         .push(vo::NoJumpNode::create(&jmp_node).into_jump_or_no_jump_node());
                 } else {
-                    let mismatch_idx = query_exp.mismatch_idx(&jmp_node.set_data);
+                    let mismatch_idx = query_exp.mismatch_idx_in_order(
+                        query_exp.clause_order(&jmp_node.set_data),
+                        &query_exp_filters,
+                        &jmp_node.set_data,
+                        jmp_node.set_filter.as_ref(),
+                    );
                     if let Some(mismatch_idx) = mismatch_idx {
                         jmp_level = Some(lvl as SkipLstLvlType);
-                        let proof_idx = res.res_vo.vo_acc.add_proof(
-                            mismatch_idx,
-                            &query_exp_digest_set[mismatch_idx],
-                            &DigestSet::new(&jmp_node.set_data),
-                            &jmp_node.acc_value,
-                        )?;
+                        tombstones.extend(jmp_node.tombstones.iter().copied());
+                        let proof_idx = if jmp_node.set_poly.is_empty() {
+                            res.res_vo.vo_acc.add_proof(
+                                mismatch_idx,
+                                &query_exp_digest_set[mismatch_idx],
+                                node_digest_set(
+                                    digest_set_cache,
+                                    jmp_node.acc_value.to_digest(),
+                                    &jmp_node.set_data,
+                                ),
+                                &jmp_node.acc_value,
+                            )?
+                        } else {
+                            res.res_vo.vo_acc.add_proof_with_poly(
+                                mismatch_idx,
+                                &query_exp_digest_set[mismatch_idx],
+                                &DensePolynomial::from_coefficients_slice(&jmp_node.set_poly),
+                                &jmp_node.acc_value,
+                            )?
+                        };
                         vo_skip.sub_nodes.push(
                             vo::JumpNode::create(&jmp_node, proof_idx).into_jump_or_no_jump_node(),
                         );
@@ -76,73 +617,201 @@ pub fn historical_query<AP: AccumulatorProof + Serialize>(
 
             if let Some(jmp_level) = jmp_level {
                 vo_skip.sub_nodes.reverse();
-                res.res_vo.vo_t.0.push(vo_skip.into_result_vo_node());
+                emit(QueryFragment {
+                    vo_node: vo_skip.into_result_vo_node(),
+                    objs: Vec::new(),
+                })?;
                 block_id -= skipped_blocks_num(jmp_level);
                 continue;
             }
         } // skip list
 
-        if param.intra_index {
+        if !blk_data.btree_ids.is_empty() {
+            let mut vo_skip = vo::SkipListRoot {
+                block_id,
+                blk_timestamp: blk_header.timestamp,
+                blk_prev_hash: blk_header.prev_hash,
+                blk_data_root: blk_header.data_root,
+                blk_tombstone_root: blk_header.tombstone_root,
+                sub_nodes: Vec::new(),
+            };
+            let mut jmp_blocks: Option<IdType> = None;
+
+            for &btree_id in blk_data.btree_ids.iter().rev() {
+                let node = chain.read_btree_node(btree_id)?;
+                let covered = btree_covered_blocks(node.level);
+                if jmp_blocks.is_some() || start_block + covered > block_id {
+                    vo_skip
+                        .sub_nodes
+                        .push(vo::NoJumpNode::create_btree(&node).into_jump_or_no_jump_node());
+                    continue;
+                }
+                let mismatch_idx = query_exp.mismatch_idx_in_order(
+                    query_exp.clause_order(&node.set_data),
+                    &query_exp_filters,
+                    &node.set_data,
+                    node.set_filter.as_ref(),
+                );
+                if let Some(mismatch_idx) = mismatch_idx {
+                    jmp_blocks = Some(covered);
+                    tombstones.extend(node.tombstones.iter().copied());
+                    let proof_idx = res.res_vo.vo_acc.add_proof(
+                        mismatch_idx,
+                        &query_exp_digest_set[mismatch_idx],
+                        node_digest_set(
+                            digest_set_cache,
+                            node.acc_value.to_digest(),
+                            &node.set_data,
+                        ),
+                        &node.acc_value,
+                    )?;
+                    vo_skip.sub_nodes.push(
+                        vo::BTreeJumpNode::create(&node, proof_idx).into_jump_or_no_jump_node(),
+                    );
+                } else {
+                    vo_skip
+                        .sub_nodes
+                        .push(vo::NoJumpNode::create_btree(&node).into_jump_or_no_jump_node());
+                }
+            }
+
+            if let Some(jmp_blocks) = jmp_blocks {
+                vo_skip.sub_nodes.reverse();
+                emit(QueryFragment {
+                    vo_node: vo_skip.into_result_vo_node(),
+                    objs: Vec::new(),
+                })?;
+                block_id -= jmp_blocks;
+                continue;
+            }
+        } // b-tree
+
+        tombstones.extend(blk_data.tombstones.iter().copied());
+        let mut objs = Vec::new();
+        let vo_node = if param.intra_index {
             query_block_intra_index(
                 &query_exp,
+                &query_exp_filters,
                 &query_exp_digest_set,
+                digest_set_cache,
                 &blk_header,
                 &blk_data,
+                &tombstones,
                 chain,
-                &mut res,
-            )?;
+                &mut res.res_vo.vo_acc,
+                &mut objs,
+            )?
         } else {
             query_block_no_intra_index(
                 &query_exp,
                 &query_exp_digest_set,
+                digest_set_cache,
                 &blk_header,
                 &blk_data,
+                &tombstones,
                 chain,
-                &mut res,
-            )?;
-        }
+                &mut res.res_vo.vo_acc,
+                &mut objs,
+            )?
+        };
+        num_of_objs += objs.len();
+        emit(QueryFragment { vo_node, objs })?;
 
         block_id -= 1;
     }
 
-    res.res_vo.vo_t.0.reverse();
     res.query_time_in_ms = timer.elapsed().as_millis() as u64;
-    res.compute_stats()?;
-    info!("used time: {}", cpu_timer.elapsed());
-    Ok(res)
+    info!("used time: {:?}", timer.elapsed());
+    Ok((res, block_id + 1))
+}
+
+// Narrow [start_block, end_block] of the query down to the blocks within its
+// time window by binary searches over the block headers.
+pub fn resolve_time_window(q: &Query, chain: &impl ReadInterface) -> Result<(IdType, IdType)> {
+    let mut start_block = q.start_block;
+    let mut end_block = q.end_block;
+    if let Some(t) = q.start_time {
+        start_block = partition_blocks(q.start_block, q.end_block + 1, chain, |ts| ts < t)?;
+    }
+    if let Some(t) = q.end_time {
+        let next = partition_blocks(q.start_block, q.end_block + 1, chain, |ts| ts <= t)?;
+        ensure!(
+            next > q.start_block,
+            Error::Parameter("no block within the time window".to_owned())
+        );
+        end_block = next - 1;
+    }
+    ensure!(
+        start_block <= end_block,
+        Error::Parameter("no block within the time window".to_owned())
+    );
+    Ok((start_block, end_block))
+}
+
+// The first block in [l, r) whose timestamp fails `pred`, or r.
+fn partition_blocks(
+    mut l: IdType,
+    mut r: IdType,
+    chain: &impl ReadInterface,
+    pred: impl Fn(u64) -> bool,
+) -> Result<IdType> {
+    while l < r {
+        let m = l + (r - l) / 2;
+        if pred(chain.read_block_header(m)?.timestamp) {
+            l = m + 1;
+        } else {
+            r = m;
+        }
+    }
+    Ok(l)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn query_block_intra_index<AP: AccumulatorProof>(
     query_exp: &BoolExp<SetElementType>,
+    query_exp_filters: &[SetFilter],
     query_exp_digest_set: &[DigestSet],
+    digest_set_cache: &mut DigestSetCache,
     block_header: &BlockHeader,
     block_data: &BlockData,
+    tombstones: &HashSet<IdType>,
     chain: &impl ReadInterface,
-    res: &mut OverallResult<AP>,
-) -> Result<()> {
+    vo_acc: &mut ResultVOAcc<AP>,
+    res_objs: &mut Vec<Object>,
+) -> Result<vo::ResultVONode> {
     let mut vo_blk = vo::BlkNode {
         block_id: block_header.block_id,
+        timestamp: block_header.timestamp,
         skip_list_root: block_header.skip_list_root,
+        tombstones: block_data.tombstones.clone(),
         sub_node: vo::IntraNode::Empty,
     };
 
     let root = match &block_data.data {
         IntraData::Index(id) => match chain.read_intra_index_node(*id)? {
             IntraIndexNode::NonLeaf(n) => n,
-            IntraIndexNode::Leaf(_) => bail!("invalid data"),
+            IntraIndexNode::Leaf(_) => bail!(Error::Storage("invalid data".to_owned())),
         },
-        _ => bail!("invalid data"),
+        _ => bail!(Error::Storage("invalid data".to_owned())),
     };
 
+    // check the clauses most selective in this block first
+    let order = query_exp.clause_order(&block_data.set_data);
+
     let mut intra_index_q: VecDeque<(Box<IntraIndexNonLeaf>, *mut vo::IntraNode)> = VecDeque::new();
     intra_index_q.push_back((root, &mut vo_blk.sub_node as *mut vo::IntraNode));
     while let Some((node, ptr)) = intra_index_q.pop_front() {
-        let mismatch_idx = query_exp.mismatch_idx(&node.set_data);
+        let mismatch_idx = query_exp.mismatch_idx_in_order(
+            order.iter().copied(),
+            query_exp_filters,
+            &node.set_data,
+            node.set_filter.as_ref(),
+        );
         if let Some(mismatch_idx) = mismatch_idx {
-            let proof_idx = res.res_vo.vo_acc.add_proof(
+            let proof_idx = vo_acc.add_proof(
                 mismatch_idx,
                 &query_exp_digest_set[mismatch_idx],
-                &DigestSet::new(&node.set_data),
+                node_digest_set(digest_set_cache, node.acc_value.to_digest(), &node.set_data),
                 &node.acc_value,
             )?;
             unsafe {
@@ -166,12 +835,21 @@ fn query_block_intra_index<AP: AccumulatorProof>(
                         ));
                     }
                     IntraIndexNode::Leaf(n) => {
-                        let mismatch_idx = query_exp.mismatch_idx(&n.set_data);
+                        let mismatch_idx = query_exp.mismatch_idx_in_order(
+                            order.iter().copied(),
+                            query_exp_filters,
+                            &n.set_data,
+                            n.set_filter.as_ref(),
+                        );
                         if let Some(mismatch_idx) = mismatch_idx {
-                            let proof_idx = res.res_vo.vo_acc.add_proof(
+                            let proof_idx = vo_acc.add_proof(
                                 mismatch_idx,
                                 &query_exp_digest_set[mismatch_idx],
-                                &DigestSet::new(&n.set_data),
+                                node_digest_set(
+                                    digest_set_cache,
+                                    n.acc_value.to_digest(),
+                                    &n.set_data,
+                                ),
                                 &n.acc_value,
                             )?;
                             intra_non_leaf.children.push(
@@ -179,10 +857,16 @@ fn query_block_intra_index<AP: AccumulatorProof>(
                             );
                         } else {
                             let obj = chain.read_object(n.obj_id)?;
-                            res.res_objs.insert(obj);
-                            intra_non_leaf
-                                .children
-                                .push(vo::MatchIntraLeaf::create(&n).into_intra_node());
+                            if query_exp.is_match(&obj.set_data) && !tombstones.contains(&obj.id) {
+                                res_objs.push(obj);
+                                intra_non_leaf
+                                    .children
+                                    .push(vo::MatchIntraLeaf::create(&n).into_intra_node());
+                            } else {
+                                intra_non_leaf.children.push(
+                                    vo::RevealedIntraLeaf::create(&n, &obj).into_intra_node(),
+                                );
+                            }
                         }
                     }
                 }
@@ -190,21 +874,26 @@ fn query_block_intra_index<AP: AccumulatorProof>(
         }
     }
 
-    res.res_vo.vo_t.0.push(vo_blk.into_result_vo_node());
-    Ok(())
+    Ok(vo_blk.into_result_vo_node())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn query_block_no_intra_index<AP: AccumulatorProof>(
     query_exp: &BoolExp<SetElementType>,
     query_exp_digest_set: &[DigestSet],
+    digest_set_cache: &mut DigestSetCache,
     block_header: &BlockHeader,
     block_data: &BlockData,
+    tombstones: &HashSet<IdType>,
     chain: &impl ReadInterface,
-    res: &mut OverallResult<AP>,
-) -> Result<()> {
+    vo_acc: &mut ResultVOAcc<AP>,
+    res_objs: &mut Vec<Object>,
+) -> Result<vo::ResultVONode> {
     let mut vo_blk = vo::FlatBlkNode {
         block_id: block_header.block_id,
+        timestamp: block_header.timestamp,
         skip_list_root: block_header.skip_list_root,
+        tombstones: block_data.tombstones.clone(),
         sub_nodes: Vec::new(),
     };
 
@@ -213,29 +902,38 @@ fn query_block_no_intra_index<AP: AccumulatorProof>(
             .iter()
             .map(|&id| chain.read_object(id))
             .collect::<Result<Vec<_>>>()?,
-        _ => bail!("invalid data"),
+        _ => bail!(Error::Storage("invalid data".to_owned())),
     };
 
+    // check the clauses most selective in this block first
+    let order = query_exp.clause_order(&block_data.set_data);
+
     for obj in &objs {
-        let mismatch_idx = query_exp.mismatch_idx(&obj.set_data);
+        let mismatch_idx = order
+            .iter()
+            .copied()
+            .find(|&i| !query_exp[i].is_intersected_with(&obj.set_data));
         if let Some(mismatch_idx) = mismatch_idx {
-            let proof_idx = res.res_vo.vo_acc.add_proof(
+            let proof_idx = vo_acc.add_proof(
                 mismatch_idx,
                 &query_exp_digest_set[mismatch_idx],
-                &DigestSet::new(&obj.set_data),
+                node_digest_set(digest_set_cache, obj.acc_value.to_digest(), &obj.set_data),
                 &obj.acc_value,
             )?;
             vo_blk
                 .sub_nodes
                 .push(vo::NoMatchObjNode::create(obj, proof_idx).into_obj_node());
-        } else {
+        } else if query_exp.is_match(&obj.set_data) && !tombstones.contains(&obj.id) {
             vo_blk
                 .sub_nodes
                 .push(vo::MatchObjNode::create(obj).into_obj_node());
-            res.res_objs.insert(obj.clone());
+            res_objs.push(obj.clone());
+        } else {
+            vo_blk
+                .sub_nodes
+                .push(vo::RevealedObjNode::create(obj).into_obj_node());
         }
     }
 
-    res.res_vo.vo_t.0.push(vo_blk.into_result_vo_node());
-    Ok(())
+    Ok(vo_blk.into_result_vo_node())
 }
@@ -0,0 +1,208 @@
+// The ids were u32 before. The ids below u32::MAX are still encoded in the 4
+// bytes of a u32, in the keys, the digests and the binary data, so that the
+// digests of the chains built before do not change, although their databases
+// cannot be read as they are. The others are u32::MAX followed by the u64.
+use super::IdType;
+use anyhow::{bail, Result};
+use smallvec::SmallVec;
+use std::convert::TryInto;
+
+const LARGE_ID_TAG: u32 = u32::MAX;
+
+pub type IdBytes = SmallVec<[u8; 12]>;
+
+pub fn id_to_bytes(id: IdType) -> IdBytes {
+    let mut out = IdBytes::new();
+    if id < LARGE_ID_TAG as IdType {
+        out.extend_from_slice(&(id as u32).to_le_bytes());
+    } else {
+        out.extend_from_slice(&LARGE_ID_TAG.to_le_bytes());
+        out.extend_from_slice(&id.to_le_bytes());
+    }
+    out
+}
+
+pub fn id_from_bytes(data: &[u8]) -> Result<IdType> {
+    match data.len() {
+        4 => Ok(u32::from_le_bytes(data.try_into()?) as IdType),
+        12 if data[..4] == LARGE_ID_TAG.to_le_bytes() => {
+            Ok(IdType::from_le_bytes(data[4..].try_into()?))
+        }
+        n => bail!("invalid id of {} bytes", n),
+    }
+}
+
+// For the ids in the data stored by the chains. The ids are plain integers in
+// the human readable formats.
+pub mod serde_impl {
+    use super::{IdType, LARGE_ID_TAG};
+    use serde::{
+        de::{Deserializer, Error as DeError, SeqAccess, Visitor},
+        ser::{SerializeTuple, Serializer},
+        Deserialize,
+    };
+
+    pub fn serialize<S: Serializer>(id: &IdType, s: S) -> Result<S::Ok, S::Error> {
+        let id = *id;
+        if s.is_human_readable() {
+            s.serialize_u64(id)
+        } else if id < LARGE_ID_TAG as IdType {
+            s.serialize_u32(id as u32)
+        } else {
+            let mut t = s.serialize_tuple(2)?;
+            t.serialize_element(&LARGE_ID_TAG)?;
+            t.serialize_element(&id)?;
+            t.end()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<IdType, D::Error> {
+        use core::fmt;
+
+        struct IdVisitor;
+
+        impl<'de> Visitor<'de> for IdVisitor {
+            type Value = IdType;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an id")
+            }
+
+            // the formats telling the types of the values, e.g. cbor
+            fn visit_u64<E: DeError>(self, v: u64) -> Result<IdType, E> {
+                Ok(v)
+            }
+
+            // bincode reads only the elements taken, i.e. the u32 alone for
+            // the small ids
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<IdType, A::Error> {
+                let tag: u32 = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                if tag != LARGE_ID_TAG {
+                    return Ok(tag as IdType);
+                }
+                seq.next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))
+            }
+        }
+
+        if d.is_human_readable() {
+            IdType::deserialize(d)
+        } else {
+            d.deserialize_tuple(2, IdVisitor)
+        }
+    }
+
+    pub mod vec {
+        use super::IdType;
+        use serde::{de::Deserializer, ser::Serializer, Deserialize, Serialize};
+        use std::iter::FromIterator;
+
+        struct Wrapper(IdType);
+
+        impl Serialize for Wrapper {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                super::serialize(&self.0, s)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for Wrapper {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                super::deserialize(d).map(Wrapper)
+            }
+        }
+
+        // for Vec<IdType> and SmallVec<[IdType; N]>
+        pub fn serialize<S: Serializer, T: AsRef<[IdType]>>(
+            ids: &T,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            s.collect_seq(ids.as_ref().iter().map(|&id| Wrapper(id)))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, T: FromIterator<IdType>>(
+            d: D,
+        ) -> Result<T, D::Error> {
+            let ids = Vec::<Wrapper>::deserialize(d)?;
+            Ok(ids.into_iter().map(|Wrapper(id)| id).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use smallvec::smallvec;
+
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    struct Foo {
+        #[serde(with = "serde_impl")]
+        f1: IdType,
+        #[serde(with = "serde_impl::vec")]
+        f2: Vec<IdType>,
+        #[serde(with = "serde_impl::vec")]
+        f3: SmallVec<[IdType; 2]>,
+        f4: u64,
+    }
+
+    // Foo before the ids were u64
+    #[derive(Serialize)]
+    struct OldFoo {
+        f1: u32,
+        f2: Vec<u32>,
+        f3: SmallVec<[u32; 2]>,
+        f4: u64,
+    }
+
+    #[test]
+    fn test_id_bytes() {
+        for &id in &[0, 1, 1 << 31, u32::MAX as IdType - 1] {
+            assert_eq!(&id_to_bytes(id)[..], &(id as u32).to_le_bytes()[..]);
+            assert_eq!(id_from_bytes(&id_to_bytes(id)).unwrap(), id);
+        }
+        for &id in &[u32::MAX as IdType, 1 << 40, IdType::MAX] {
+            assert_eq!(id_to_bytes(id).len(), 12);
+            assert_eq!(id_from_bytes(&id_to_bytes(id)).unwrap(), id);
+        }
+        assert!(id_from_bytes(&[0; 8]).is_err());
+        assert!(id_from_bytes(&[0; 12]).is_err());
+    }
+
+    #[test]
+    fn test_id_serde() {
+        let old = OldFoo {
+            f1: 1,
+            f2: vec![2, 3],
+            f3: smallvec![4],
+            f4: 5,
+        };
+        let foo = Foo {
+            f1: 1,
+            f2: vec![2, 3],
+            f3: smallvec![4],
+            f4: 5,
+        };
+        let bin = bincode::serialize(&old).unwrap();
+        assert_eq!(bincode::serialize(&foo).unwrap(), bin);
+        assert_eq!(bincode::deserialize::<Foo>(&bin).unwrap(), foo);
+
+        let foo = Foo {
+            f1: 1 << 40,
+            f2: vec![2, u32::MAX as IdType],
+            f3: smallvec![IdType::MAX, 4],
+            f4: 5,
+        };
+        let bin = bincode::serialize(&foo).unwrap();
+        assert_eq!(bincode::deserialize::<Foo>(&bin).unwrap(), foo);
+        let cbor = serde_cbor::to_vec(&foo).unwrap();
+        assert_eq!(serde_cbor::from_slice::<Foo>(&cbor).unwrap(), foo);
+        let json = serde_json::to_string(&foo).unwrap();
+        assert_eq!(
+            json,
+            r#"{"f1":1099511627776,"f2":[2,4294967295],"f3":[18446744073709551615,4],"f4":5}"#
+        );
+        assert_eq!(serde_json::from_str::<Foo>(&json).unwrap(), foo);
+    }
+}
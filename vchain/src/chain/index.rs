@@ -1,13 +1,10 @@
-use super::{IdType, SetElementType, SkipLstLvlType};
-use crate::acc::G1Affine;
-use crate::digest::{blake2, concat_digest_ref, Digest, Digestible};
-use crate::set::MultiSet;
-use core::sync::atomic::{AtomicU64, Ordering};
+use super::{id_to_bytes, IdCounter, IdType, SetElementType, SkipLstLvlType};
+use crate::acc::{Fr, G1Affine};
+use crate::digest::{concat_digest, concat_digest_ref, keyed_blake2, Digest, Digestible};
+use crate::set::{MultiSet, SetFilter};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
-static INDEX_ID_CNT: AtomicU64 = AtomicU64::new(0);
-
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum IntraIndexNode {
     NonLeaf(Box<IntraIndexNonLeaf>),
@@ -33,6 +30,12 @@ impl IntraIndexNode {
             Self::Leaf(x) => &x.set_data,
         }
     }
+    pub fn set_filter(&self) -> Option<&SetFilter> {
+        match self {
+            Self::NonLeaf(x) => x.set_filter.as_ref(),
+            Self::Leaf(x) => x.set_filter.as_ref(),
+        }
+    }
     pub fn acc_value(&self) -> &G1Affine {
         match self {
             Self::NonLeaf(x) => &x.acc_value,
@@ -43,13 +46,19 @@ impl IntraIndexNode {
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct IntraIndexNonLeaf {
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub id: IdType,
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub block_id: IdType,
     pub set_data: MultiSet<SetElementType>,
+    // summary of set_data to reject non-intersecting queries quickly
+    #[serde(default)]
+    pub set_filter: Option<SetFilter>,
     #[serde(with = "crate::acc::serde_impl")]
     pub acc_value: G1Affine,
     pub child_hash_digest: Digest,
     pub child_hashes: SmallVec<[Digest; 2]>,
+    #[serde(with = "crate::chain::id::serde_impl::vec")]
     pub child_ids: SmallVec<[IdType; 2]>,
 }
 
@@ -60,11 +69,13 @@ impl IntraIndexNonLeaf {
         acc_value: G1Affine,
         child_hashes: SmallVec<[Digest; 2]>,
         child_ids: SmallVec<[IdType; 2]>,
+        ids: &mut IdCounter,
     ) -> Self {
-        let id = INDEX_ID_CNT.fetch_add(1, Ordering::SeqCst) as IdType;
+        let id = ids.alloc_index_id();
         Self {
             id,
             block_id,
+            set_filter: Some(set_data.filter()),
             set_data,
             acc_value,
             child_hash_digest: concat_digest_ref(child_hashes.iter()),
@@ -82,11 +93,16 @@ impl Digestible for IntraIndexNonLeaf {
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct IntraIndexLeaf {
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub id: IdType,
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub block_id: IdType,
     pub set_data: MultiSet<SetElementType>,
+    #[serde(default)]
+    pub set_filter: Option<SetFilter>,
     #[serde(with = "crate::acc::serde_impl")]
     pub acc_value: G1Affine,
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub obj_id: IdType,
     pub obj_hash: Digest,
 }
@@ -98,11 +114,13 @@ impl IntraIndexLeaf {
         acc_value: G1Affine,
         obj_id: IdType,
         obj_hash: Digest,
+        ids: &mut IdCounter,
     ) -> Self {
-        let id = INDEX_ID_CNT.fetch_add(1, Ordering::SeqCst) as IdType;
+        let id = ids.alloc_index_id();
         Self {
             id,
             block_id,
+            set_filter: Some(set_data.filter()),
             set_data,
             acc_value,
             obj_id,
@@ -119,14 +137,25 @@ impl Digestible for IntraIndexLeaf {
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SkipListNode {
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub id: IdType,
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub block_id: IdType,
     pub level: SkipLstLvlType,
     pub set_data: MultiSet<SetElementType>,
+    #[serde(default)]
+    pub set_filter: Option<SetFilter>,
     #[serde(with = "crate::acc::serde_impl")]
     pub acc_value: G1Affine,
     pub pre_skipped_hash: Digest,
+    // the tombstones of the skipped blocks
+    #[serde(default, with = "crate::chain::id::serde_impl::vec")]
+    pub tombstones: Vec<IdType>,
     pub digest: Digest,
+    // the coefficients of the expanded polynomial of `set_data` under ACC1, so
+    // that the proofs of the jumps need not expand it again
+    #[serde(default, with = "crate::acc::serde_impl::vec")]
+    pub set_poly: Vec<Fr>,
 }
 
 impl SkipListNode {
@@ -136,56 +165,196 @@ impl SkipListNode {
         set_data: MultiSet<SetElementType>,
         acc_value: G1Affine,
         pre_skipped_hash: Digest,
+        tombstones: Vec<IdType>,
+        ids: &mut IdCounter,
+    ) -> Self {
+        let id = ids.alloc_index_id();
+        let digest = skip_list_node_digest(&acc_value, &pre_skipped_hash, &tombstones);
+        Self {
+            id,
+            block_id,
+            level,
+            set_filter: Some(set_data.filter()),
+            set_data,
+            acc_value,
+            pre_skipped_hash,
+            tombstones,
+            digest,
+            set_poly: Vec::new(),
+        }
+    }
+}
+
+// A node of the B-tree over the blocks, covering the `btree_covered_blocks(level)`
+// blocks up to `block_id`. The nodes are built with the last block they cover,
+// from the nodes of the level below.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BTreeNode {
+    #[serde(with = "crate::chain::id::serde_impl")]
+    pub id: IdType,
+    #[serde(with = "crate::chain::id::serde_impl")]
+    pub block_id: IdType,
+    pub level: SkipLstLvlType,
+    pub set_data: MultiSet<SetElementType>,
+    #[serde(default)]
+    pub set_filter: Option<SetFilter>,
+    #[serde(with = "crate::acc::serde_impl")]
+    pub acc_value: G1Affine,
+    pub pre_skipped_hash: Digest,
+    // over the data roots of the blocks for level 1, or the digests of the
+    // children otherwise
+    pub child_hash_digest: Digest,
+    #[serde(default, with = "crate::chain::id::serde_impl::vec")]
+    pub tombstones: Vec<IdType>,
+    pub digest: Digest,
+}
+
+impl BTreeNode {
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        block_id: IdType,
+        level: SkipLstLvlType,
+        set_data: MultiSet<SetElementType>,
+        acc_value: G1Affine,
+        pre_skipped_hash: Digest,
+        child_hashes: &[Digest],
+        tombstones: Vec<IdType>,
+        ids: &mut IdCounter,
     ) -> Self {
-        let id = INDEX_ID_CNT.fetch_add(1, Ordering::SeqCst) as IdType;
-        let digest = concat_digest_ref([acc_value.to_digest(), pre_skipped_hash].iter());
+        let id = ids.alloc_index_id();
+        let child_hash_digest = concat_digest_ref(child_hashes.iter());
+        let digest = btree_node_digest(
+            &acc_value,
+            &pre_skipped_hash,
+            &child_hash_digest,
+            &tombstones,
+        );
         Self {
             id,
             block_id,
             level,
+            set_filter: Some(set_data.filter()),
             set_data,
             acc_value,
             pre_skipped_hash,
+            child_hash_digest,
+            tombstones,
             digest,
         }
     }
 }
 
+pub fn btree_node_digest(
+    acc_value: &G1Affine,
+    pre_skipped_hash: &Digest,
+    child_hash_digest: &Digest,
+    tombstones: &[IdType],
+) -> Digest {
+    let mut hs = vec![acc_value.to_digest(), *pre_skipped_hash, *child_hash_digest];
+    hs.extend(tombstones_digest(tombstones));
+    concat_digest(hs.into_iter())
+}
+
+pub fn skip_list_node_digest(
+    acc_value: &G1Affine,
+    pre_skipped_hash: &Digest,
+    tombstones: &[IdType],
+) -> Digest {
+    let mut hs = vec![acc_value.to_digest(), *pre_skipped_hash];
+    hs.extend(tombstones_digest(tombstones));
+    concat_digest(hs.into_iter())
+}
+
+// None for no tombstones, so that the digests of the blocks without them are
+// not changed.
+pub fn tombstones_digest(tombstones: &[IdType]) -> Option<Digest> {
+    if tombstones.is_empty() {
+        None
+    } else {
+        Some(concat_digest(
+            tombstones.iter().map(|&id| id_to_bytes(id)[..].to_digest()),
+        ))
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum IntraData {
     // List of object ids
-    Flat(Vec<IdType>),
+    Flat(#[serde(with = "crate::chain::id::serde_impl::vec")] Vec<IdType>),
     // IntraIndexNode root id
-    Index(IdType),
+    Index(#[serde(with = "crate::chain::id::serde_impl")] IdType),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct BlockData {
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub block_id: IdType,
     pub data: IntraData,
     pub set_data: MultiSet<SetElementType>,
     #[serde(with = "crate::acc::serde_impl")]
     pub acc_value: G1Affine,
+    #[serde(with = "crate::chain::id::serde_impl::vec")]
     pub skip_list_ids: Vec<IdType>,
+    #[serde(default)]
+    pub timestamp: u64,
+    // ids of the objects in the earlier blocks superseded by this block
+    #[serde(default, with = "crate::chain::id::serde_impl::vec")]
+    pub tombstones: Vec<IdType>,
+    // the B-tree nodes ending at this block, from level 1
+    #[serde(default, with = "crate::chain::id::serde_impl::vec")]
+    pub btree_ids: Vec<IdType>,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct BlockHeader {
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub block_id: IdType,
     pub prev_hash: Digest,
     pub data_root: Digest,
+    // root of the skip list or the B-tree nodes of the block
     pub skip_list_root: Option<Digest>,
+    // in seconds since the unix epoch, non-decreasing along the chain
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub tombstone_root: Option<Digest>,
 }
 
-impl Digestible for BlockHeader {
-    fn to_digest(&self) -> Digest {
-        let mut state = blake2().to_state();
-        state.update(&self.block_id.to_le_bytes());
-        state.update(&self.prev_hash.0);
-        state.update(&self.data_root.0);
-        if let Some(d) = self.skip_list_root {
-            state.update(&d.0);
-        }
-        Digest::from(state.finalize())
+impl BlockHeader {
+    pub fn compute_digest(&self, chain_id: &str) -> Digest {
+        block_header_digest(
+            chain_id,
+            self.block_id,
+            self.timestamp,
+            &self.prev_hash,
+            &self.data_root,
+            self.skip_list_root.as_ref(),
+            self.tombstone_root.as_ref(),
+        )
+    }
+}
+
+pub fn block_header_digest(
+    chain_id: &str,
+    block_id: IdType,
+    timestamp: u64,
+    prev_hash: &Digest,
+    data_root: &Digest,
+    skip_list_root: Option<&Digest>,
+    tombstone_root: Option<&Digest>,
+) -> Digest {
+    let mut state = keyed_blake2(chain_id.as_bytes()).to_state();
+    state.update(&id_to_bytes(block_id));
+    state.update(&timestamp.to_le_bytes());
+    state.update(&prev_hash.0);
+    state.update(&data_root.0);
+    if let Some(d) = skip_list_root {
+        state.update(&d.0);
+    }
+    // tagged so that it cannot be taken as the skip list root
+    if let Some(d) = tombstone_root {
+        state.update(&[1u8]);
+        state.update(&d.0);
     }
+    Digest::from(state.finalize())
 }
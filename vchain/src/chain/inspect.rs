@@ -0,0 +1,69 @@
+use super::*;
+
+// The shape of a block and its indexes, e.g. to tell how the parameters of
+// the chain fit the data, see inspect_block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStats {
+    pub block_id: IdType,
+    pub timestamp: u64,
+    pub num_objs: usize,
+    pub num_tombstones: usize,
+    // the distinct elements of the set of the block
+    pub set_data_len: usize,
+    // the levels of the intra index, 0 without it
+    pub intra_index_height: usize,
+    pub intra_index_nodes: usize,
+    // the nodes of the skip list or the B-tree ending at the block, and the
+    // most blocks covered by any of them
+    pub skip_list_nodes: usize,
+    pub skip_list_coverage: IdType,
+    pub btree_nodes: usize,
+    pub btree_coverage: IdType,
+}
+
+// The objects and the index nodes of the block are walked, so it fails for
+// the pruned blocks, see prune_before.
+pub fn inspect_block(chain: &(impl ReadInterface + ?Sized), id: IdType) -> Result<BlockStats> {
+    let data = chain.read_block_data(id)?;
+    let (num_objs, intra_index_height, intra_index_nodes) = match &data.data {
+        IntraData::Flat(ids) => (ids.len(), 0, 0),
+        IntraData::Index(root_id) => {
+            let (mut num_objs, mut height, mut num_nodes) = (0, 0, 0);
+            let mut node_ids = vec![(*root_id, 1)];
+            while let Some((id, depth)) = node_ids.pop() {
+                num_nodes += 1;
+                height = height.max(depth);
+                match chain.read_intra_index_node(id)? {
+                    IntraIndexNode::NonLeaf(n) => {
+                        node_ids.extend(n.child_ids.iter().map(|&id| (id, depth + 1)))
+                    }
+                    IntraIndexNode::Leaf(_) => num_objs += 1,
+                }
+            }
+            (num_objs, height, num_nodes)
+        }
+    };
+    let mut skip_list_coverage = 0;
+    for &id in &data.skip_list_ids {
+        let node = chain.read_skip_list_node(id)?;
+        skip_list_coverage = skip_list_coverage.max(skipped_blocks_num(node.level));
+    }
+    let mut btree_coverage = 0;
+    for &id in &data.btree_ids {
+        let node = chain.read_btree_node(id)?;
+        btree_coverage = btree_coverage.max(btree_covered_blocks(node.level));
+    }
+    Ok(BlockStats {
+        block_id: id,
+        timestamp: data.timestamp,
+        num_objs,
+        num_tombstones: data.tombstones.len(),
+        set_data_len: data.set_data.len(),
+        intra_index_height,
+        intra_index_nodes,
+        skip_list_nodes: data.skip_list_ids.len(),
+        skip_list_coverage,
+        btree_nodes: data.btree_ids.len(),
+        btree_coverage,
+    })
+}
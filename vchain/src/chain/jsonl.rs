@@ -0,0 +1,106 @@
+use super::*;
+use crate::acc::G1Affine;
+use crate::set::MultiSet;
+use std::io::Write;
+
+// A line of the JSON Lines export, tagged by `type`, with the fields in
+// the order of the structs.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonlRecord<'a> {
+    Param(&'a Parameter),
+    Header(&'a BlockHeader),
+    Block(JsonlBlock<'a>),
+    Object(JsonlObject<'a>),
+}
+
+// The block data, with the objects of the intra index listed as well.
+#[derive(Serialize)]
+struct JsonlBlock<'a> {
+    block_id: IdType,
+    timestamp: u64,
+    obj_ids: Vec<IdType>,
+    intra_index_root: Option<IdType>,
+    tombstones: &'a [IdType],
+    skip_list_ids: &'a [IdType],
+    btree_ids: &'a [IdType],
+    set_data: &'a MultiSet<SetElementType>,
+    #[serde(with = "crate::acc::serde_impl")]
+    acc_value: G1Affine,
+}
+
+// The object, with its keywords sorted.
+#[derive(Serialize)]
+struct JsonlObject<'a> {
+    id: IdType,
+    block_id: IdType,
+    v_data: &'a [u64],
+    w_data: Vec<&'a str>,
+    set_data: &'a MultiSet<SetElementType>,
+    #[serde(with = "crate::acc::serde_impl")]
+    acc_value: G1Affine,
+}
+
+fn write_record(w: &mut impl Write, record: &JsonlRecord) -> Result<()> {
+    serde_json::to_writer(&mut *w, record)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+// Write the parameter of the chain, then the header, the data and the objects
+// of each block from the first one up to the latest one, one record per line,
+// e.g. for jq or pandas. Only the headers of the pruned blocks are written.
+// Unlike export_archive, the index nodes are left out, so it cannot be
+// imported back.
+pub fn export_jsonl(chain: &impl ReadInterface, w: &mut impl Write) -> Result<()> {
+    write_record(w, &JsonlRecord::Param(&chain.get_parameter()?))?;
+    let pruned_before = chain.read_pruned_before()?;
+    let first_block = match chain.read_block_header(0) {
+        Ok(_) => 0,
+        Err(_) => 1,
+    };
+    let latest_block = match chain.latest_block_id()? {
+        Some(id) => id,
+        None => return Ok(w.flush()?),
+    };
+    for header in chain.iter_block_headers(first_block..=latest_block) {
+        let header = header?;
+        write_record(w, &JsonlRecord::Header(&header))?;
+        if header.block_id < pruned_before {
+            continue;
+        }
+        let data = chain.read_block_data(header.block_id)?;
+        let obj_ids = block_obj_ids(&data, chain)?;
+        let blk = JsonlBlock {
+            block_id: data.block_id,
+            timestamp: data.timestamp,
+            obj_ids: obj_ids.clone(),
+            intra_index_root: match data.data {
+                IntraData::Flat(_) => None,
+                IntraData::Index(root_id) => Some(root_id),
+            },
+            tombstones: &data.tombstones,
+            skip_list_ids: &data.skip_list_ids,
+            btree_ids: &data.btree_ids,
+            set_data: &data.set_data,
+            acc_value: data.acc_value,
+        };
+        write_record(w, &JsonlRecord::Block(blk))?;
+        for id in obj_ids {
+            let obj = chain.read_object(id)?;
+            let mut w_data: Vec<&str> = obj.w_data.iter().map(|w| w.as_str()).collect();
+            w_data.sort_unstable();
+            let obj = JsonlObject {
+                id: obj.id,
+                block_id: obj.block_id,
+                v_data: &obj.v_data,
+                w_data,
+                set_data: &obj.set_data,
+                acc_value: obj.acc_value,
+            };
+            write_record(w, &JsonlRecord::Object(obj))?;
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use super::{
+    BTreeNode, BlockData, IntraIndexNode, Object, ReadInterface, SetElementType, SkipListNode,
+};
+use crate::digest::{Digest, Digestible};
+use crate::set::MultiSet;
+use crate::Error;
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, de::Deserializer, ser::Serializer, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type SymbolType = u32;
+
+// The keywords added to a KeywordTable by its encode, with their symbols.
+pub type NewKeywords = Vec<(SymbolType, String)>;
+
+// A keyword of SetElementType::W. The keywords read from a chain share the
+// words of its KeywordTable, and the chains store the symbols of the table
+// instead of the words, see KeywordTable::encode. All the formats serialize the
+// word itself, or in the compact encoding its index in the table of the words of
+// the encoding, so the data never depends on the symbols of some chain.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Keyword(Arc<str>);
+
+impl Keyword {
+    pub fn new(word: &str) -> Self {
+        Self(Arc::from(word))
+    }
+
+    pub fn word(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Keyword {
+    fn from(word: &str) -> Self {
+        Self::new(word)
+    }
+}
+
+impl Digestible for Keyword {
+    fn to_digest(&self) -> Digest {
+        self.word().to_digest()
+    }
+}
+
+impl Serialize for Keyword {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match crate::compact::intern_keyword(self) {
+            Some(idx) => serializer.serialize_u32(idx),
+            None => serializer.serialize_str(self.word()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Keyword {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if crate::compact::is_decoding() {
+            let idx = u32::deserialize(deserializer)?;
+            return crate::compact::keyword_at(idx)
+                .ok_or_else(|| serde::de::Error::custom("invalid keyword index"));
+        }
+        let word = String::deserialize(deserializer)?;
+        Ok(Self::new(&word))
+    }
+}
+
+// The values with set data, which the chains store with the keywords of the
+// set data as their symbols, see KeywordTable::encode.
+pub trait KeywordData: Serialize + DeserializeOwned {
+    fn set_data_mut(&mut self) -> &mut MultiSet<SetElementType>;
+}
+
+impl KeywordData for Object {
+    fn set_data_mut(&mut self) -> &mut MultiSet<SetElementType> {
+        &mut self.set_data
+    }
+}
+
+impl KeywordData for BlockData {
+    fn set_data_mut(&mut self) -> &mut MultiSet<SetElementType> {
+        &mut self.set_data
+    }
+}
+
+impl KeywordData for IntraIndexNode {
+    fn set_data_mut(&mut self) -> &mut MultiSet<SetElementType> {
+        match self {
+            Self::NonLeaf(x) => &mut x.set_data,
+            Self::Leaf(x) => &mut x.set_data,
+        }
+    }
+}
+
+impl KeywordData for SkipListNode {
+    fn set_data_mut(&mut self) -> &mut MultiSet<SetElementType> {
+        &mut self.set_data
+    }
+}
+
+impl KeywordData for BTreeNode {
+    fn set_data_mut(&mut self) -> &mut MultiSet<SetElementType> {
+        &mut self.set_data
+    }
+}
+
+// SetElementType with the keywords as their symbols.
+#[derive(Serialize, Deserialize)]
+enum StoredElement {
+    V { dim: u32, val: u64, mask: u64 },
+    W(SymbolType),
+    WPrefix(SymbolType),
+}
+
+// The table of the keywords of a chain, owned by the chain and persisted by it
+// with WriteInterface::write_keyword. The symbols are assigned in the order the
+// keywords are first written, and only mean something to the chain.
+#[derive(Debug, Clone, Default)]
+pub struct KeywordTable {
+    keywords: HashMap<SymbolType, Keyword>,
+    symbols: HashMap<Keyword, SymbolType>,
+    next: SymbolType,
+}
+
+impl KeywordTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The table persisted by the chain.
+    pub fn load(chain: &impl ReadInterface) -> Result<Self> {
+        let mut table = Self::new();
+        for (sym, word) in chain.read_keywords()? {
+            table.insert(sym, &word)?;
+        }
+        Ok(table)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keywords.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keywords.is_empty()
+    }
+
+    pub fn keyword(&self, sym: SymbolType) -> Option<&Keyword> {
+        self.keywords.get(&sym)
+    }
+
+    pub fn symbol(&self, keyword: &Keyword) -> Option<SymbolType> {
+        self.symbols.get(keyword).copied()
+    }
+
+    // Add a keyword with its symbol, e.g. as read from the storage. It is
+    // fine to add a keyword again with the same symbol.
+    pub fn insert(&mut self, sym: SymbolType, word: &str) -> Result<Keyword> {
+        let keyword = Keyword::new(word);
+        match (self.keywords.get(&sym), self.symbols.get(&keyword)) {
+            (None, None) => {
+                self.keywords.insert(sym, keyword.clone());
+                self.symbols.insert(keyword.clone(), sym);
+                self.next = self.next.max(sym + 1);
+                Ok(keyword)
+            }
+            (Some(k), Some(&s)) if s == sym && *k == keyword => Ok(k.clone()),
+            _ => bail!(Error::Storage(format!(
+                "keyword {:?} conflicts with symbol {}",
+                word, sym
+            ))),
+        }
+    }
+
+    fn symbol_or_insert(&mut self, keyword: &Keyword, new: &mut NewKeywords) -> SymbolType {
+        if let Some(sym) = self.symbol(keyword) {
+            return sym;
+        }
+        let sym = self.next;
+        self.next += 1;
+        self.keywords.insert(sym, keyword.clone());
+        self.symbols.insert(keyword.clone(), sym);
+        new.push((sym, keyword.word().to_owned()));
+        sym
+    }
+
+    // Encode the value in bincode, followed by its set data with the keywords
+    // as their symbols. The keywords new to the table are added to it, and
+    // returned to be persisted by the chain together with the value.
+    pub fn encode<T: KeywordData>(&mut self, mut value: T) -> Result<(Vec<u8>, NewKeywords)> {
+        let set_data = std::mem::replace(value.set_data_mut(), MultiSet::new());
+        let mut new = Vec::new();
+        let elems: Vec<(StoredElement, u64)> = set_data
+            .sorted_iter()
+            .map(|(e, &cnt)| {
+                let e = match e {
+                    SetElementType::V { dim, val, mask } => StoredElement::V {
+                        dim: *dim,
+                        val: *val,
+                        mask: *mask,
+                    },
+                    SetElementType::W(k) => StoredElement::W(self.symbol_or_insert(k, &mut new)),
+                    SetElementType::WPrefix(k) => {
+                        StoredElement::WPrefix(self.symbol_or_insert(k, &mut new))
+                    }
+                };
+                (e, cnt)
+            })
+            .collect();
+        Ok((bincode::serialize(&(value, elems))?, new))
+    }
+
+    // Decode a value encoded by encode, with the keywords of the table.
+    pub fn decode<T: KeywordData>(&self, data: &[u8]) -> Result<T> {
+        let (mut value, elems): (T, Vec<(StoredElement, u64)>) = bincode::deserialize(data)?;
+        let keyword = |sym| {
+            self.keyword(sym)
+                .cloned()
+                .ok_or_else(|| Error::Storage(format!("unknown keyword symbol {}", sym)))
+        };
+        let mut set_data = MultiSet::new();
+        for (e, cnt) in elems {
+            let e = match e {
+                StoredElement::V { dim, val, mask } => SetElementType::V { dim, val, mask },
+                StoredElement::W(sym) => SetElementType::W(keyword(sym)?),
+                StoredElement::WPrefix(sym) => SetElementType::WPrefix(keyword(sym)?),
+            };
+            set_data.inner.insert(e, cnt);
+        }
+        *value.set_data_mut() = set_data;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acc::G1Affine;
+    use crate::chain::{v_data_to_set, w_data_to_set};
+
+    #[test]
+    fn test_keyword() {
+        let k1 = Keyword::new("test_keyword_1");
+        let k2 = Keyword::from("test_keyword_2");
+        assert_ne!(k1, k2);
+        assert_eq!(Keyword::new("test_keyword_1"), k1);
+        assert_eq!(k1.word(), "test_keyword_1");
+        assert_eq!(k1.to_digest(), "test_keyword_1".to_digest());
+    }
+
+    #[test]
+    fn test_keyword_serde() {
+        let k = Keyword::new("test_keyword_serde");
+        let json = serde_json::to_string(&k).unwrap();
+        assert_eq!(json, "\"test_keyword_serde\"");
+        assert_eq!(serde_json::from_str::<Keyword>(&json).unwrap(), k);
+        let bin = bincode::serialize(&k).unwrap();
+        assert_eq!(bincode::deserialize::<Keyword>(&bin[..]).unwrap(), k);
+    }
+
+    #[test]
+    fn test_keyword_table() {
+        let obj = |words: &[&str]| {
+            let w_data = words.iter().map(|w| w.to_string()).collect();
+            let set_data = &v_data_to_set(&[1], &[3]) + &w_data_to_set(&w_data, 2);
+            Object {
+                id: 1,
+                block_id: 1,
+                v_data: vec![1],
+                w_data,
+                set_data,
+                acc_value: G1Affine::default(),
+            }
+        };
+        let o1 = obj(&["ab", "c"]);
+        let o2 = obj(&["c", "d"]);
+
+        let mut t1 = KeywordTable::new();
+        let (b1, new) = t1.encode(o1.clone()).unwrap();
+        assert_eq!(new.len(), 3);
+        let (b2, new) = t1.encode(o2.clone()).unwrap();
+        assert_eq!(new, vec![(3, "d".to_owned())]);
+        assert_eq!(t1.decode::<Object>(&b1).unwrap(), o1);
+        assert_eq!(t1.decode::<Object>(&b2).unwrap(), o2);
+        assert!(KeywordTable::new().decode::<Object>(&b1).is_err());
+
+        // the symbols of a table built separately do not matter
+        let mut t2 = KeywordTable::new();
+        let (b3, _) = t2.encode(o2.clone()).unwrap();
+        assert_ne!(t1.symbol(&Keyword::new("d")), t2.symbol(&Keyword::new("d")));
+        assert_eq!(t2.decode::<Object>(&b3).unwrap(), o2);
+
+        let mut t3 = KeywordTable::new();
+        t3.insert(0, "ab").unwrap();
+        assert!(t3.insert(0, "ab").is_ok());
+        assert!(t3.insert(0, "c").is_err());
+        assert!(t3.insert(1, "ab").is_err());
+        assert_eq!(t3.len(), 1);
+    }
+}
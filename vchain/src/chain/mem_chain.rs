@@ -0,0 +1,174 @@
+use super::*;
+use std::collections::HashMap;
+
+// A chain kept in memory, e.g. to prototype and test the queries without a
+// database. Cloning it takes a snapshot of the chain, which can be put back to
+// roll back the blocks appended since.
+#[derive(Debug, Default, Clone)]
+pub struct MemChain {
+    pub(crate) param: Option<Parameter>,
+    pub(crate) block_headers: HashMap<IdType, BlockHeader>,
+    pub(crate) block_data: HashMap<IdType, BlockData>,
+    pub(crate) intra_index_nodes: HashMap<IdType, IntraIndexNode>,
+    pub(crate) skip_list_nodes: HashMap<IdType, SkipListNode>,
+    pub(crate) btree_nodes: HashMap<IdType, BTreeNode>,
+    pub(crate) objects: HashMap<IdType, Object>,
+    pub(crate) keywords: HashMap<SymbolType, String>,
+    pub(crate) id_counter: IdCounter,
+    pub(crate) pruned_before: IdType,
+}
+
+#[async_trait::async_trait]
+impl LightNodeInterface for MemChain {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        self.get_parameter()
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.read_block_header(id)
+    }
+}
+
+impl ReadInterface for MemChain {
+    fn get_parameter(&self) -> Result<Parameter> {
+        self.param
+            .clone()
+            .ok_or_else(|| Error::NotFound("failed to get param".to_owned()).into())
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.block_headers
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("failed to read block header #{}", id)).into())
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        self.block_data
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("failed to read block data #{}", id)).into())
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        self.intra_index_nodes
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("failed to read intra index #{}", id)).into())
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        self.skip_list_nodes
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("failed to read skip list #{}", id)).into())
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        self.btree_nodes
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("failed to read b-tree node #{}", id)).into())
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        self.objects
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("failed to read object #{}", id)).into())
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        Ok(self.keywords.get(&sym).cloned())
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        Ok(self
+            .keywords
+            .iter()
+            .map(|(&sym, word)| (sym, word.clone()))
+            .collect())
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        Ok(self.id_counter)
+    }
+    fn read_pruned_before(&self) -> Result<IdType> {
+        Ok(self.pruned_before)
+    }
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        Ok(self.block_headers.keys().max().copied())
+    }
+}
+
+impl WriteInterface for MemChain {
+    fn set_parameter(&mut self, param: Parameter) -> Result<()> {
+        self.param = Some(param);
+        Ok(())
+    }
+    fn write_block_header(&mut self, header: BlockHeader) -> Result<()> {
+        let id = header.block_id;
+        self.block_headers.insert(id, header);
+        Ok(())
+    }
+    fn write_block_data(&mut self, data: BlockData) -> Result<()> {
+        let id = data.block_id;
+        self.block_data.insert(id, data);
+        Ok(())
+    }
+    fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()> {
+        let id = node.id();
+        self.intra_index_nodes.insert(id, node);
+        Ok(())
+    }
+    fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()> {
+        let id = node.id;
+        self.skip_list_nodes.insert(id, node);
+        Ok(())
+    }
+    fn write_btree_node(&mut self, node: BTreeNode) -> Result<()> {
+        let id = node.id;
+        self.btree_nodes.insert(id, node);
+        Ok(())
+    }
+    fn write_object(&mut self, obj: Object) -> Result<()> {
+        let id = obj.id;
+        self.objects.insert(id, obj);
+        Ok(())
+    }
+    fn write_keyword(&mut self, sym: SymbolType, word: String) -> Result<()> {
+        self.keywords.insert(sym, word);
+        Ok(())
+    }
+    fn write_id_counter(&mut self, counter: IdCounter) -> Result<()> {
+        self.id_counter = counter;
+        Ok(())
+    }
+    fn write_pruned_before(&mut self, block_id: IdType) -> Result<()> {
+        self.pruned_before = block_id;
+        Ok(())
+    }
+    fn delete_block_data(&mut self, id: IdType) -> Result<()> {
+        self.block_data.remove(&id);
+        Ok(())
+    }
+    fn delete_intra_index_node(&mut self, id: IdType) -> Result<()> {
+        self.intra_index_nodes.remove(&id);
+        Ok(())
+    }
+    fn delete_skip_list_node(&mut self, id: IdType) -> Result<()> {
+        self.skip_list_nodes.remove(&id);
+        Ok(())
+    }
+    fn delete_btree_node(&mut self, id: IdType) -> Result<()> {
+        self.btree_nodes.remove(&id);
+        Ok(())
+    }
+    fn delete_object(&mut self, id: IdType) -> Result<()> {
+        self.objects.remove(&id);
+        Ok(())
+    }
+}
+
+impl MemChain {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_param(param: Parameter) -> Self {
+        Self {
+            param: Some(param),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use super::{
+    vo, BlockHeader, IdType, IntraData, IntraIndexNode, LightNodeInterface, Object, Parameter,
+    ReadInterface,
+};
+use crate::digest::{blake2, concat_digest_ref, Digest, Digestible};
+use crate::Error;
+use anyhow::{bail, ensure, Result};
+use futures::join;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+// Binary Merkle tree over the leaves. An odd node at the end of a level is
+// moved up unchanged, and the inner nodes are hashed with a prefix so that
+// they cannot be confused with leaves.
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MerkleStep {
+    // the sibling is on the left
+    Left(Digest),
+    // the sibling is on the right
+    Right(Digest),
+}
+
+fn merkle_parent(left: &Digest, right: &Digest) -> Digest {
+    let mut state = blake2().to_state();
+    state.update(&[1u8]);
+    state.update(&left.0);
+    state.update(&right.0);
+    Digest::from(state.finalize())
+}
+
+fn merkle_next_level(level: &[Digest]) -> Vec<Digest> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [l, r] => merkle_parent(l, r),
+            [x] => *x,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn merkle_root(leaves: &[Digest]) -> Digest {
+    if leaves.is_empty() {
+        return Digest::default();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = merkle_next_level(&level);
+    }
+    level[0]
+}
+
+pub fn merkle_path(leaves: &[Digest], mut idx: usize) -> Vec<MerkleStep> {
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = idx ^ 1;
+        if sibling < level.len() {
+            if sibling < idx {
+                path.push(MerkleStep::Left(level[sibling]));
+            } else {
+                path.push(MerkleStep::Right(level[sibling]));
+            }
+        }
+        level = merkle_next_level(&level);
+        idx /= 2;
+    }
+    path
+}
+
+pub fn merkle_root_from_path(leaf: &Digest, path: &[MerkleStep]) -> Digest {
+    path.iter().fold(*leaf, |h, step| match step {
+        MerkleStep::Left(s) => merkle_parent(s, &h),
+        MerkleStep::Right(s) => merkle_parent(&h, s),
+    })
+}
+
+pub fn obj_leaf_digest(obj: &Object) -> Digest {
+    concat_digest_ref([obj.acc_value.to_digest(), obj.to_digest()].iter())
+}
+
+// Prove that an object is in a block built without the intra index.
+pub fn prove_object_inclusion(
+    block_id: IdType,
+    obj_id: IdType,
+    chain: &impl ReadInterface,
+) -> Result<vo::ObjInclusionProof> {
+    let blk_data = chain.read_block_data(block_id)?;
+    let ids = match &blk_data.data {
+        IntraData::Flat(ids) => ids,
+        IntraData::Index(_) => bail!(Error::Proof(format!(
+            "block #{} is not built in the flat mode",
+            block_id
+        ))),
+    };
+    let idx = ids.iter().position(|&id| id == obj_id).ok_or_else(|| {
+        Error::NotFound(format!("object #{} is not in block #{}", obj_id, block_id))
+    })?;
+    let leaves = ids
+        .iter()
+        .map(|&id| chain.read_object(id).map(|o| obj_leaf_digest(&o)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(vo::ObjInclusionProof {
+        block_id,
+        obj_id,
+        path: merkle_path(&leaves, idx),
+    })
+}
+
+// A non-leaf of the intra index above an object, see ObjectProof.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IntraIndexStep {
+    pub acc_digest: Digest,
+    pub child_hashes: SmallVec<[Digest; 2]>,
+    // the child on the path
+    pub idx: usize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ObjectPath {
+    Flat(Vec<MerkleStep>),
+    // from the parent of the leaf up to the root
+    Index(Vec<IntraIndexStep>),
+}
+
+// Proof of an object up to the data root of its block, for the clients that
+// know the id of an object and only need to check that it is on the chain.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ObjectProof {
+    pub obj: Object,
+    pub path: ObjectPath,
+}
+
+impl ObjectProof {
+    pub fn compute_data_root(&self) -> Option<Digest> {
+        let leaf = obj_leaf_digest(&self.obj);
+        match &self.path {
+            ObjectPath::Flat(path) => Some(merkle_root_from_path(&leaf, path)),
+            ObjectPath::Index(steps) => steps.iter().try_fold(leaf, |h, step| {
+                let mut child_hashes = step.child_hashes.clone();
+                *child_hashes.get_mut(step.idx)? = h;
+                let child_hash_digest = concat_digest_ref(child_hashes.iter());
+                Some(concat_digest_ref(
+                    [step.acc_digest, child_hash_digest].iter(),
+                ))
+            }),
+        }
+    }
+
+    // set_data is not covered by the hash path, so it is checked against the one
+    // from v_data and w_data.
+    pub fn verify(&self, header: &BlockHeader, param: &Parameter) -> bool {
+        header.block_id == self.obj.block_id
+            && self.obj.compute_set_data(param).as_ref() == Some(&self.obj.set_data)
+            && self.compute_data_root() == Some(header.data_root)
+    }
+}
+
+pub fn prove_object(obj_id: IdType, chain: &impl ReadInterface) -> Result<ObjectProof> {
+    let obj = chain.read_object(obj_id)?;
+    let path = match chain.read_block_data(obj.block_id)?.data {
+        IntraData::Flat(_) => {
+            ObjectPath::Flat(prove_object_inclusion(obj.block_id, obj_id, chain)?.path)
+        }
+        IntraData::Index(root_id) => {
+            ObjectPath::Index(intra_index_path(root_id, obj_id, chain)?.ok_or_else(|| {
+                Error::NotFound(format!("object #{} is not in its block", obj_id))
+            })?)
+        }
+    };
+    Ok(ObjectProof { obj, path })
+}
+
+// The result of historical_point_query.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PointQueryResult {
+    pub proof: ObjectProof,
+    // from the block of the object up to the trusted block
+    pub headers: Vec<BlockHeader>,
+}
+
+impl PointQueryResult {
+    pub fn verify_with(&self, trusted: &BlockHeader, param: &Parameter) -> bool {
+        let chain_id = &param.chain_id;
+        let (first, last) = match (self.headers.first(), self.headers.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return false,
+        };
+        self.proof.verify(first, param)
+            && self
+                .headers
+                .windows(2)
+                .all(|w| w[1].prev_hash == w[0].compute_digest(chain_id))
+            && last.compute_digest(chain_id) == trusted.compute_digest(chain_id)
+    }
+
+    pub async fn verify(&self, chain: &impl LightNodeInterface) -> Result<bool> {
+        let last = self
+            .headers
+            .last()
+            .ok_or_else(|| Error::Verification("no block header".to_owned()))?;
+        let (param, trusted) = join!(
+            chain.lightnode_get_parameter(),
+            chain.lightnode_read_block_header(last.block_id)
+        );
+        Ok(self.verify_with(&trusted?, &param?))
+    }
+}
+
+// Look up an object by its id, with the proof of it up to its block and the
+// headers from there up to `trusted_block`, a block already trusted by the
+// client. By default, it is the block of the object.
+pub fn historical_point_query(
+    obj_id: IdType,
+    trusted_block: Option<IdType>,
+    chain: &impl ReadInterface,
+) -> Result<PointQueryResult> {
+    info!("process point query of obj #{}", obj_id);
+    let proof = prove_object(obj_id, chain)?;
+    let block_id = proof.obj.block_id;
+    let trusted_block = trusted_block.unwrap_or(block_id);
+    ensure!(
+        trusted_block >= block_id,
+        Error::Parameter(format!(
+            "trusted block #{} is before the block of the object",
+            trusted_block
+        ))
+    );
+    let headers = (block_id..=trusted_block)
+        .map(|id| chain.read_block_header(id))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PointQueryResult { proof, headers })
+}
+
+// The steps from the leaf of the object up to the node, if it is under the node.
+fn intra_index_path(
+    node_id: IdType,
+    obj_id: IdType,
+    chain: &impl ReadInterface,
+) -> Result<Option<Vec<IntraIndexStep>>> {
+    let node = match chain.read_intra_index_node(node_id)? {
+        IntraIndexNode::Leaf(n) => {
+            return Ok(if n.obj_id == obj_id {
+                Some(Vec::new())
+            } else {
+                None
+            })
+        }
+        IntraIndexNode::NonLeaf(n) => n,
+    };
+    for (idx, &child_id) in node.child_ids.iter().enumerate() {
+        if let Some(mut path) = intra_index_path(child_id, obj_id, chain)? {
+            path.push(IntraIndexStep {
+                acc_digest: node.acc_value.to_digest(),
+                child_hashes: node.child_hashes.clone(),
+                idx,
+            });
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_path() {
+        for n in 1..10u32 {
+            let leaves: Vec<Digest> = (0..n).map(|i| i.to_digest()).collect();
+            let root = merkle_root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let path = merkle_path(&leaves, i);
+                assert_eq!(merkle_root_from_path(leaf, &path), root);
+                assert_ne!(merkle_root_from_path(&n.to_digest(), &path), root);
+            }
+        }
+        assert_eq!(merkle_root(&[1u32.to_digest()]), 1u32.to_digest());
+    }
+}
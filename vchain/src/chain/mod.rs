@@ -1,44 +1,208 @@
 use crate::acc;
-use anyhow::Result;
+use crate::Error;
+use anyhow::{bail, ensure, Result};
 use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 
 pub mod utils;
 pub use utils::*;
 
+pub mod keyword;
+pub use keyword::*;
+
 pub mod object;
 pub use object::*;
 
 pub mod index;
 pub use index::*;
 
+#[cfg(feature = "storage")]
 pub mod build;
+#[cfg(feature = "storage")]
 pub use build::*;
 
+pub mod merkle;
+pub use merkle::*;
+
 pub mod query;
 pub use query::*;
 
 pub mod query_result;
 pub use query_result::*;
 
+#[cfg(feature = "storage")]
+pub mod archive;
+#[cfg(feature = "storage")]
+pub use archive::*;
+
+#[cfg(feature = "storage")]
+pub mod cached_chain;
+#[cfg(feature = "storage")]
+pub use cached_chain::*;
+
+pub mod compact_stream;
+pub use compact_stream::*;
+
+pub mod explain;
+
+pub mod header_store;
+pub use header_store::*;
+
+#[cfg(feature = "storage")]
 pub mod historical_query;
+#[cfg(feature = "storage")]
 pub use historical_query::*;
 
-pub type IdType = u32;
+pub mod id;
+pub use id::{id_from_bytes, id_to_bytes, IdBytes};
+
+#[cfg(feature = "storage")]
+pub mod inspect;
+#[cfg(feature = "storage")]
+pub use inspect::*;
+
+#[cfg(feature = "storage")]
+pub mod jsonl;
+#[cfg(feature = "storage")]
+pub use jsonl::*;
+
+#[cfg(feature = "storage")]
+pub mod mem_chain;
+#[cfg(feature = "storage")]
+pub use mem_chain::*;
+
+#[cfg(feature = "parallel")]
+pub mod pool;
+#[cfg(feature = "parallel")]
+pub use pool::*;
+
+// without the `parallel` feature, the work of the pools is done in the
+// calling thread
+#[cfg(not(feature = "parallel"))]
+#[cfg_attr(not(feature = "storage"), allow(dead_code))]
+pub(crate) fn in_build_pool<R>(op: impl FnOnce() -> R) -> R {
+    op()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn in_query_pool<R>(op: impl FnOnce() -> R) -> R {
+    op()
+}
+
+#[cfg(feature = "storage")]
+pub mod prune;
+#[cfg(feature = "storage")]
+pub use prune::*;
+
+#[cfg(feature = "storage")]
+pub mod reindex;
+#[cfg(feature = "storage")]
+pub use reindex::*;
+
+#[cfg(feature = "storage")]
+pub mod standing_query;
+#[cfg(feature = "storage")]
+pub use standing_query::*;
+
+pub type IdType = u64;
 pub type SkipLstLvlType = u8;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Parameter {
     pub v_bit_len: Vec<u8>,
+    // ACC1, ACC2, or ACC3 for ACC1 with the proofs of each clause verified as a
+    // batch, see acc::Type
     pub acc_type: acc::Type,
     pub use_sk: bool, // only for debug purpose
     pub intra_index: bool,
     pub skip_list_max_level: SkipLstLvlType,
+    // mixed into the block header hashes, so that the VOs of one chain cannot
+    // be replayed on another chain built from the same data
+    #[serde(default)]
+    pub chain_id: String,
+    // the prefixes of keywords up to this many chars are indexed for the
+    // `prefix*` queries, 0 means disabled
+    #[serde(default)]
+    pub max_prefix_len: u8,
+    // max number of children of the non-leaf nodes in the intra index
+    #[serde(default = "default_intra_index_fanout")]
+    pub intra_index_fanout: u8,
+    // max level of the B-tree over the blocks, used instead of the skip list,
+    // 0 means disabled
+    #[serde(default)]
+    pub btree_max_level: SkipLstLvlType,
+    // the objects over this many are carried over to the next block by
+    // `ChainBuilder`, 0 means unlimited
+    #[serde(default)]
+    pub max_objs_per_block: u32,
+}
+
+fn default_intra_index_fanout() -> u8 {
+    2
+}
+
+impl Parameter {
+    // Check that the chain can switch to `new` between two blocks, i.e. only
+    // the parameters of building the new blocks are changed, and not those the
+    // queries and the proofs of the blocks built so far depend on.
+    pub fn check_update(&self, new: &Parameter) -> Result<()> {
+        let unchanged = Parameter {
+            intra_index_fanout: self.intra_index_fanout,
+            max_objs_per_block: self.max_objs_per_block,
+            ..new.clone()
+        };
+        ensure!(
+            unchanged == *self,
+            Error::Parameter(
+                "only intra_index_fanout and max_objs_per_block can be changed".to_owned()
+            )
+        );
+        ensure!(
+            new.intra_index_fanout >= 2,
+            Error::Parameter("intra index fanout should be at least 2".to_owned())
+        );
+        Ok(())
+    }
+}
+
+// The next free ids of the objects and the index nodes, persisted by the chain
+// so that the ids keep increasing across processes.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IdCounter {
+    #[serde(with = "crate::chain::id::serde_impl")]
+    pub next_obj_id: IdType,
+    #[serde(with = "crate::chain::id::serde_impl")]
+    pub next_index_id: IdType,
+}
+
+impl IdCounter {
+    // Reserve `n` consecutive object ids, returning the first one.
+    pub fn alloc_obj_ids(&mut self, n: usize) -> IdType {
+        let id = self.next_obj_id;
+        self.next_obj_id += n as IdType;
+        id
+    }
+
+    pub fn alloc_index_id(&mut self) -> IdType {
+        let id = self.next_index_id;
+        self.next_index_id += 1;
+        id
+    }
 }
 
 #[async_trait::async_trait]
-pub trait LightNodeInterface {
+pub trait LightNodeInterface: Sync {
     async fn lightnode_get_parameter(&self) -> Result<Parameter>;
     async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader>;
+    // In the order of `ids`, failing if any of them is missing. The nodes
+    // across the network should fetch them in one round trip.
+    async fn lightnode_read_block_headers(&self, ids: &[IdType]) -> Result<Vec<BlockHeader>> {
+        let mut headers = Vec::with_capacity(ids.len());
+        for &id in ids {
+            headers.push(self.lightnode_read_block_header(id).await?);
+        }
+        Ok(headers)
+    }
 }
 
 pub trait ReadInterface {
@@ -47,7 +211,80 @@ pub trait ReadInterface {
     fn read_block_data(&self, id: IdType) -> Result<BlockData>;
     fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode>;
     fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode>;
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode>;
     fn read_object(&self, id: IdType) -> Result<Object>;
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>>;
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>>;
+    // the default counter for a new chain
+    fn read_id_counter(&self) -> Result<IdCounter>;
+    // the first block not pruned, see prune_before
+    fn read_pruned_before(&self) -> Result<IdType> {
+        Ok(0)
+    }
+    // The last block, None for an empty chain. By default the headers are
+    // probed, assuming the blocks have consecutive ids from 0 or 1.
+    fn latest_block_id(&self) -> Result<Option<IdType>> {
+        let mut lo = match (self.read_block_header(0), self.read_block_header(1)) {
+            (Ok(_), _) => 0,
+            (_, Ok(_)) => 1,
+            _ => return Ok(None),
+        };
+        // blk #lo exists and blk #hi does not
+        let mut step = 1;
+        let mut hi = lo + step;
+        while self.read_block_header(hi).is_ok() {
+            lo = hi;
+            step *= 2;
+            hi = lo + step;
+        }
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.read_block_header(mid).is_ok() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(Some(lo))
+    }
+    fn iter_block_headers<'a>(
+        &'a self,
+        range: RangeInclusive<IdType>,
+    ) -> Box<dyn Iterator<Item = Result<BlockHeader>> + 'a> {
+        Box::new(range.map(move |id| self.read_block_header(id)))
+    }
+    // the objects of a pruned block cannot be read
+    fn iter_objects_in_block<'a>(
+        &'a self,
+        block_id: IdType,
+    ) -> Result<Box<dyn Iterator<Item = Result<Object>> + 'a>> {
+        let obj_ids = block_obj_ids(&self.read_block_data(block_id)?, self)?;
+        Ok(Box::new(
+            obj_ids.into_iter().map(move |id| self.read_object(id)),
+        ))
+    }
+}
+
+// in the order the objects were added to the block, which is that of their ids
+// rather than of the leaves of the intra index, as they are grouped by their sets
+pub(crate) fn block_obj_ids(
+    data: &BlockData,
+    chain: &(impl ReadInterface + ?Sized),
+) -> Result<Vec<IdType>> {
+    let root_id = match &data.data {
+        IntraData::Flat(ids) => return Ok(ids.clone()),
+        IntraData::Index(root_id) => *root_id,
+    };
+    let mut obj_ids = Vec::new();
+    let mut node_ids = vec![root_id];
+    while let Some(id) = node_ids.pop() {
+        match chain.read_intra_index_node(id)? {
+            IntraIndexNode::NonLeaf(n) => node_ids.extend(n.child_ids.iter().copied()),
+            IntraIndexNode::Leaf(n) => obj_ids.push(n.obj_id),
+        }
+    }
+    obj_ids.sort_unstable();
+    Ok(obj_ids)
 }
 
 pub trait WriteInterface {
@@ -56,8 +293,38 @@ pub trait WriteInterface {
     fn write_block_data(&mut self, data: BlockData) -> Result<()>;
     fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()>;
     fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()>;
+    fn write_btree_node(&mut self, node: BTreeNode) -> Result<()>;
     fn write_object(&mut self, obj: Object) -> Result<()>;
+    fn write_keyword(&mut self, sym: SymbolType, word: String) -> Result<()>;
+    fn write_id_counter(&mut self, counter: IdCounter) -> Result<()>;
+    // The writes between them make up a block, which a chain may apply
+    // atomically. A block begun again drops the writes not yet committed.
+    fn begin_block(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn commit_block(&mut self) -> Result<()> {
+        Ok(())
+    }
+    // Only needed by prune_before, which fails on the chains without them.
+    fn write_pruned_before(&mut self, _block_id: IdType) -> Result<()> {
+        bail!("pruning is not supported by the chain")
+    }
+    fn delete_block_data(&mut self, _id: IdType) -> Result<()> {
+        bail!("pruning is not supported by the chain")
+    }
+    fn delete_intra_index_node(&mut self, _id: IdType) -> Result<()> {
+        bail!("pruning is not supported by the chain")
+    }
+    fn delete_skip_list_node(&mut self, _id: IdType) -> Result<()> {
+        bail!("pruning is not supported by the chain")
+    }
+    fn delete_btree_node(&mut self, _id: IdType) -> Result<()> {
+        bail!("pruning is not supported by the chain")
+    }
+    fn delete_object(&mut self, _id: IdType) -> Result<()> {
+        bail!("pruning is not supported by the chain")
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "storage"))]
 mod tests;
@@ -1,26 +1,25 @@
-use super::{multiset_to_g1, IdType, Parameter};
+use super::{id_to_bytes, multiset_to_g1, IdCounter, IdType, Keyword, Parameter};
 use crate::acc::G1Affine;
 use crate::digest::{blake2, Digest, Digestible};
+use crate::par::*;
 use crate::set::MultiSet;
-use core::sync::atomic::{AtomicU64, Ordering};
-use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-static OBJECT_ID_CNT: AtomicU64 = AtomicU64::new(0);
-
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RawObject {
     pub block_id: IdType,
-    pub v_data: Vec<u32>,
+    pub v_data: Vec<u64>,
     pub w_data: HashSet<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Object {
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub id: IdType,
+    #[serde(with = "crate::chain::id::serde_impl")]
     pub block_id: IdType,
-    pub v_data: Vec<u32>,
+    pub v_data: Vec<u64>,
     pub w_data: HashSet<String>,
     pub set_data: MultiSet<SetElementType>,
     #[serde(with = "crate::acc::serde_impl")]
@@ -28,15 +27,22 @@ pub struct Object {
 }
 
 impl Object {
-    pub fn create(obj: &RawObject, param: &Parameter) -> Self {
-        let id = OBJECT_ID_CNT.fetch_add(1, Ordering::SeqCst) as IdType;
-        let set_v = v_data_to_set(&obj.v_data, &param.v_bit_len);
-        let set_w = obj
-            .w_data
-            .iter()
-            .map(|w| SetElementType::W(w.clone()))
-            .collect::<MultiSet<_>>();
-        let set_data = &set_v + &set_w;
+    pub fn create(obj: &RawObject, param: &Parameter, ids: &mut IdCounter) -> Self {
+        let id = ids.alloc_obj_ids(1);
+        Self::create_with_id(id, obj, param)
+    }
+
+    // Create the objects in parallel, with consecutive ids in the input order.
+    pub fn create_batch(objs: &[&RawObject], param: &Parameter, ids: &mut IdCounter) -> Vec<Self> {
+        let first_id = ids.alloc_obj_ids(objs.len());
+        objs.par_iter()
+            .enumerate()
+            .map(|(i, obj)| Self::create_with_id(first_id + i as IdType, obj, param))
+            .collect()
+    }
+
+    fn create_with_id(id: IdType, obj: &RawObject, param: &Parameter) -> Self {
+        let set_data = data_to_set(&obj.v_data, &obj.w_data, param);
         let acc_value = multiset_to_g1(&set_data, param);
         Self {
             id,
@@ -47,13 +53,33 @@ impl Object {
             acc_value,
         }
     }
+
+    // The set data from v_data and w_data, which are covered by the digest of the
+    // object unlike set_data, so that the clients need not trust the latter. None
+    // if v_data has more dimensions than the chain.
+    pub fn compute_set_data(&self, param: &Parameter) -> Option<MultiSet<SetElementType>> {
+        if self.v_data.len() > param.v_bit_len.len() {
+            return None;
+        }
+        Some(data_to_set(&self.v_data, &self.w_data, param))
+    }
+}
+
+fn data_to_set(
+    v_data: &[u64],
+    w_data: &HashSet<String>,
+    param: &Parameter,
+) -> MultiSet<SetElementType> {
+    let set_v = v_data_to_set(v_data, &param.v_bit_len);
+    let set_w = w_data_to_set(w_data, param.max_prefix_len);
+    &set_v + &set_w
 }
 
 impl Digestible for Object {
     fn to_digest(&self) -> Digest {
         let mut state = blake2().to_state();
-        state.update(&self.id.to_le_bytes());
-        state.update(&self.block_id.to_le_bytes());
+        state.update(&id_to_bytes(self.id));
+        state.update(&id_to_bytes(self.block_id));
         for v in &self.v_data {
             state.update(&v.to_le_bytes());
         }
@@ -69,8 +95,10 @@ impl Digestible for Object {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum SetElementType {
     // To transform V to range: [val, val + ~mask & (mask - 1)]
-    V { dim: u32, val: u32, mask: u32 },
-    W(String),
+    V { dim: u32, val: u64, mask: u64 },
+    W(Keyword),
+    // a prefix of some keyword, matched by the `prefix*` queries
+    WPrefix(Keyword),
 }
 
 impl Digestible for SetElementType {
@@ -83,19 +111,34 @@ impl Digestible for SetElementType {
                 state.update(&mask.to_le_bytes());
                 Digest::from(state.finalize())
             }
-            SetElementType::W(s) => s.to_digest(),
+            SetElementType::W(w) => w.to_digest(),
+            SetElementType::WPrefix(w) => {
+                let mut state = blake2().to_state();
+                state.update(b"*");
+                state.update(&w.to_digest().0);
+                Digest::from(state.finalize())
+            }
         }
     }
 }
 
-pub fn v_data_to_set(input: &[u32], bit_len: &[u8]) -> MultiSet<SetElementType> {
+// The lowest `bit_len` bits, for `bit_len` up to 64.
+pub fn bit_mask(bit_len: u8) -> u64 {
+    if bit_len < 64 {
+        !(u64::MAX << bit_len)
+    } else {
+        u64::MAX
+    }
+}
+
+pub fn v_data_to_set(input: &[u64], bit_len: &[u8]) -> MultiSet<SetElementType> {
     input
         .iter()
         .enumerate()
         .flat_map(|(i, &v)| {
-            let m: u32 = !(0xffff_ffff << bit_len[i]);
+            let m = bit_mask(bit_len[i]);
             (0..bit_len[i]).map(move |j| {
-                let mask = (0xffff_ffff << j) & m;
+                let mask = (u64::MAX << j) & m;
                 let val = v & mask;
                 SetElementType::V {
                     dim: i as u32,
@@ -107,6 +150,22 @@ pub fn v_data_to_set(input: &[u32], bit_len: &[u8]) -> MultiSet<SetElementType>
         .collect()
 }
 
+pub fn w_data_to_set(input: &HashSet<String>, max_prefix_len: u8) -> MultiSet<SetElementType> {
+    input
+        .iter()
+        .flat_map(|w| {
+            let prefixes = w
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| i)
+                .chain(core::iter::once(w.len()))
+                .take(max_prefix_len as usize)
+                .map(move |i| SetElementType::WPrefix(Keyword::new(&w[..i])));
+            core::iter::once(SetElementType::W(Keyword::new(w))).chain(prefixes)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,4 +185,42 @@ mod tests {
         ]);
         assert_eq!(res, expect)
     }
+
+    #[test]
+    fn test_v_data_to_set_64() {
+        use SetElementType::V;
+        let res = v_data_to_set(&[u64::MAX], &[64]);
+        assert_eq!(res.len(), 64);
+        assert!(res.contains_key(&V {
+            dim: 0,
+            val: 1 << 63,
+            mask: 1 << 63,
+        }));
+        assert!(res.contains_key(&V {
+            dim: 0,
+            val: u64::MAX,
+            mask: u64::MAX,
+        }));
+    }
+
+    #[test]
+    fn test_w_data_to_set() {
+        use SetElementType::{WPrefix, W};
+        let k = |s: &str| Keyword::new(s);
+        let input: HashSet<String> = ["ab".to_owned(), "bcd".to_owned()]
+            .iter()
+            .cloned()
+            .collect();
+        let expect = MultiSet::from_vec(vec![
+            W(k("ab")),
+            WPrefix(k("a")),
+            WPrefix(k("ab")),
+            W(k("bcd")),
+            WPrefix(k("b")),
+            WPrefix(k("bc")),
+        ]);
+        assert_eq!(w_data_to_set(&input, 2), expect);
+        let expect = MultiSet::from_vec(vec![W(k("ab")), W(k("bcd"))]);
+        assert_eq!(w_data_to_set(&input, 0), expect);
+    }
 }
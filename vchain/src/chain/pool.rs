@@ -0,0 +1,63 @@
+use anyhow::{ensure, Result};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::OnceLock;
+
+// The thread pools of the parallel work in build_block and in the queries, so
+// that a heavy build does not starve the queries answered at the same time,
+// e.g. by vchain-node. Both are the global pool of rayon until set by
+// init_thread_pools.
+static BUILD_POOL: OnceLock<ThreadPool> = OnceLock::new();
+static QUERY_POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+fn new_pool(name: &'static str, num_threads: usize) -> Result<ThreadPool> {
+    Ok(ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(move |i| format!("{}-{}", name, i))
+        .build()?)
+}
+
+// Create the pools of `build_threads` and `query_threads` threads, 0 to keep
+// the global pool for either. They can only be set once in a process.
+pub fn init_thread_pools(build_threads: usize, query_threads: usize) -> Result<()> {
+    if build_threads > 0 {
+        let pool = new_pool("build", build_threads)?;
+        ensure!(
+            BUILD_POOL.set(pool).is_ok(),
+            "the build pool is set already"
+        );
+    }
+    if query_threads > 0 {
+        let pool = new_pool("query", query_threads)?;
+        ensure!(
+            QUERY_POOL.set(pool).is_ok(),
+            "the query pool is set already"
+        );
+    }
+    Ok(())
+}
+
+pub fn build_pool() -> Option<&'static ThreadPool> {
+    BUILD_POOL.get()
+}
+
+pub fn query_pool() -> Option<&'static ThreadPool> {
+    QUERY_POOL.get()
+}
+
+fn install<R: Send>(pool: Option<&ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+// The chains need not be Sync, so only the parallel parts of the builds and
+// the queries are run in the pools, rather than the whole of them.
+#[cfg_attr(not(feature = "storage"), allow(dead_code))]
+pub(crate) fn in_build_pool<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    install(build_pool(), op)
+}
+
+pub(crate) fn in_query_pool<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    install(query_pool(), op)
+}
@@ -0,0 +1,73 @@
+use super::*;
+use anyhow::ensure;
+
+// The number of the blocks before a new block whose data is read to build it.
+fn blocks_read_by_build(param: &Parameter) -> IdType {
+    let mut n = 1;
+    if param.skip_list_max_level > 0 {
+        n = n.max(skipped_blocks_num(param.skip_list_max_level - 1));
+    }
+    if param.btree_max_level > 0 {
+        n = n.max(btree_covered_blocks(param.btree_max_level));
+    }
+    n
+}
+
+// Delete the objects, the block data and the index nodes of the blocks before
+// `block_id`, keeping their headers to verify the results of the later blocks.
+// The queries starting before it fail afterwards. The blocks after it needed
+// to build the next blocks should be built already.
+pub fn prune_before(
+    block_id: IdType,
+    chain: &mut (impl ReadInterface + WriteInterface),
+) -> Result<()> {
+    let param = chain.get_parameter()?;
+    let pruned_before = chain.read_pruned_before()?;
+    if block_id <= pruned_before {
+        return Ok(());
+    }
+    let last_needed = block_id + blocks_read_by_build(&param) - 1;
+    ensure!(
+        chain.read_block_header(last_needed).is_ok(),
+        Error::Parameter(format!(
+            "blk #{} should be built before pruning the blks before #{}",
+            last_needed, block_id
+        ))
+    );
+    // the queries fail clearly from now on, even if the pruning is cut short
+    chain.write_pruned_before(block_id)?;
+    for id in pruned_before..block_id {
+        if chain.read_block_header(id).is_err() {
+            continue;
+        }
+        info!("prune blk #{}", id);
+        let data = chain.read_block_data(id)?;
+        chain.begin_block()?;
+        match data.data {
+            IntraData::Flat(obj_ids) => {
+                for obj_id in obj_ids {
+                    chain.delete_object(obj_id)?;
+                }
+            }
+            IntraData::Index(root_id) => {
+                let mut node_ids = vec![root_id];
+                while let Some(node_id) = node_ids.pop() {
+                    match chain.read_intra_index_node(node_id)? {
+                        IntraIndexNode::NonLeaf(n) => node_ids.extend_from_slice(&n.child_ids),
+                        IntraIndexNode::Leaf(n) => chain.delete_object(n.obj_id)?,
+                    }
+                    chain.delete_intra_index_node(node_id)?;
+                }
+            }
+        }
+        for skip_list_id in data.skip_list_ids {
+            chain.delete_skip_list_node(skip_list_id)?;
+        }
+        for btree_id in data.btree_ids {
+            chain.delete_btree_node(btree_id)?;
+        }
+        chain.delete_block_data(id)?;
+        chain.commit_block()?;
+    }
+    Ok(())
+}
@@ -1,31 +1,118 @@
-use super::{IdType, SetElementType};
-use crate::set::{MultiSet, SetElement};
+use super::{bit_mask, IdType, Keyword, SetElementType};
+use crate::set::{MultiSet, SetElement, SetFilter};
+use crate::Error;
+use anyhow::{ensure, Result};
 use core::iter::FromIterator;
 use core::ops::Deref;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashSet, VecDeque};
 
+// A clause with negated literals, which is satisfied if the set intersects
+// with `pos` or misses any element of `neg`.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MixedClause<T: SetElement> {
+    pub pos: MultiSet<T>,
+    pub neg: MultiSet<T>,
+}
+
+impl<T: SetElement> MixedClause<T> {
+    pub fn is_satisfied(&self, set: &MultiSet<T>) -> bool {
+        self.pos.is_intersected_with(set) || self.neg.keys().any(|k| !set.contains_key(k))
+    }
+}
+
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct BoolExp<T: SetElement> {
     pub(crate) inner: Vec<MultiSet<T>>,
+    // The mismatches of these clauses cannot be proved with the accumulators,
+    // so the objects failing only them are revealed in the VO.
+    #[serde(default)]
+    pub(crate) mixed: Vec<MixedClause<T>>,
 }
 
 impl<T: SetElement> BoolExp<T> {
     pub fn new() -> Self {
-        Self { inner: Vec::new() }
+        Self {
+            inner: Vec::new(),
+            mixed: Vec::new(),
+        }
     }
 
     pub fn from_vec(input: Vec<MultiSet<T>>) -> Self {
         Self::from_iter(input.into_iter())
     }
 
+    pub fn mixed_clauses(&self) -> &[MixedClause<T>] {
+        &self.mixed
+    }
+
+    // Add a clause given as its positive and negated literals.
+    pub fn push_clause(&mut self, pos: HashSet<T>, neg: HashSet<T>) {
+        if !pos.is_disjoint(&neg) {
+            // always true
+            return;
+        }
+        if neg.is_empty() {
+            self.inner.push(pos.into_iter().collect());
+        } else {
+            self.mixed.push(MixedClause {
+                pos: pos.into_iter().collect(),
+                neg: neg.into_iter().collect(),
+            });
+        }
+    }
+
     pub fn is_match(&self, set: &MultiSet<T>) -> bool {
-        self.mismatch_idx(set).is_none()
+        self.mismatch_idx(set).is_none() && self.mixed.iter().all(|c| c.is_satisfied(set))
     }
 
     pub fn mismatch_idx(&self, set: &MultiSet<T>) -> Option<usize> {
         self.iter().position(|s| !s.is_intersected_with(set))
     }
+
+    pub fn filters(&self) -> Vec<SetFilter> {
+        self.iter().map(|s| s.filter()).collect()
+    }
+
+    // Same as mismatch_idx, but rejects the clauses whose filters do not overlap
+    // with `set_filter` without looking up the elements.
+    pub fn mismatch_idx_with_filter(
+        &self,
+        filters: &[SetFilter],
+        set: &MultiSet<T>,
+        set_filter: Option<&SetFilter>,
+    ) -> Option<usize> {
+        self.mismatch_idx_in_order(0..self.len(), filters, set, set_filter)
+    }
+
+    // Same as mismatch_idx_with_filter, but checks the clauses in `order`.
+    pub fn mismatch_idx_in_order(
+        &self,
+        order: impl IntoIterator<Item = usize>,
+        filters: &[SetFilter],
+        set: &MultiSet<T>,
+        set_filter: Option<&SetFilter>,
+    ) -> Option<usize> {
+        debug_assert_eq!(self.len(), filters.len());
+        order.into_iter().find(|&i| {
+            matches!(set_filter, Some(sf) if !filters[i].may_intersect(sf))
+                || !self[i].is_intersected_with(set)
+        })
+    }
+
+    // The clauses ordered by the number of elements of `stats` they cover, so
+    // that the ones most likely to mismatch go first. `stats` is usually the set
+    // data of a block, which counts the elements of its objects.
+    pub fn clause_order(&self, stats: &MultiSet<T>) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.sort_by_cached_key(|&i| {
+            self[i]
+                .keys()
+                .map(|k| stats.inner.get(k).copied().unwrap_or(0))
+                .sum::<u64>()
+        });
+        order
+    }
 }
 
 impl<T: SetElement> Deref for BoolExp<T> {
@@ -40,69 +127,212 @@ impl<T: SetElement> FromIterator<MultiSet<T>> for BoolExp<T> {
     fn from_iter<I: IntoIterator<Item = MultiSet<T>>>(iter: I) -> Self {
         Self {
             inner: iter.into_iter().collect::<Vec<_>>(),
+            mixed: Vec::new(),
         }
     }
 }
 
+// A keyword ending with '*' matches all the keywords with that prefix.
+pub fn keyword_to_set_element(w: &str) -> SetElementType {
+    match w.strip_suffix('*') {
+        Some(prefix) => SetElementType::WPrefix(Keyword::new(prefix)),
+        None => SetElementType::W(Keyword::new(w)),
+    }
+}
+
+// The longest prefix in the query, in chars.
+pub fn max_prefix_len_of(exp: &BoolExp<SetElementType>) -> usize {
+    exp.iter()
+        .flat_map(|s| s.keys())
+        .chain(
+            exp.mixed
+                .iter()
+                .flat_map(|c| c.pos.keys().chain(c.neg.keys())),
+        )
+        .filter_map(|e| match e {
+            SetElementType::WPrefix(w) => Some(w.word().chars().count()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// A bound of a range. A number is inclusive and `{"exclusive": x}` is not.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RangeBound {
+    Inclusive(u64),
+    Exclusive { exclusive: u64 },
+}
+
+// The lower and the upper bounds of each dimension. None means unbounded.
 #[derive(Debug, Default, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
-pub struct Range(pub(crate) [Vec<Option<u32>>; 2]);
+pub struct Range(pub(crate) [Vec<Option<RangeBound>>; 2]);
+
+// The prefixes covering [l, r] in dimension `dim`.
+fn range_to_set(dim: usize, l: u64, r: u64, bit_len: u8) -> MultiSet<SetElementType> {
+    let mut set_data = MultiSet::<SetElementType>::new();
+
+    let mut queue: VecDeque<(u64, u64)> = VecDeque::new();
+    queue.push_back((0, 0));
+
+    while let Some((mut mask, left)) = queue.pop_front() {
+        let mask_inv = !mask;
+        let right = left | mask_inv;
+
+        if l <= left && right <= r {
+            mask &= bit_mask(bit_len);
+            set_data.inner.insert(
+                SetElementType::V {
+                    dim: dim as u32,
+                    val: left,
+                    mask,
+                },
+                1,
+            );
+            continue;
+        }
+
+        if right < l || r < left {
+            continue;
+        }
+
+        let new_mask = !(mask_inv >> 1);
+        queue.push_back((new_mask, left));
+        queue.push_back((new_mask, left | (new_mask & mask_inv)));
+    }
+
+    set_data
+}
 
 impl Range {
     pub fn to_bool_exp(&self, bit_len: &[u8]) -> BoolExp<SetElementType> {
         let mut exp = BoolExp::new();
-        for (i, range) in self[0].iter().zip(self[1].iter()).enumerate() {
-            let (l, r) = match (range.0, range.1) {
-                (Some(x), Some(y)) => (*x, *y),
-                _ => continue,
+        for (i, (l, r)) in self[0].iter().zip(self[1].iter()).enumerate() {
+            let max = bit_mask(bit_len[i]);
+            let l = match l {
+                None => Some(0),
+                Some(RangeBound::Inclusive(x)) => Some(*x),
+                Some(RangeBound::Exclusive { exclusive: x }) => x.checked_add(1),
             };
-
-            let mut set_data = MultiSet::<SetElementType>::new();
-
-            let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
-            queue.push_back((0, 0));
-
-            while let Some((mut mask, left)) = queue.pop_front() {
-                let mask_inv = !mask;
-                let right = left | mask_inv;
-
-                if l <= left && right <= r {
-                    if bit_len[i] < 32 {
-                        mask &= !(0xffff_ffff << bit_len[i]);
-                    }
-                    set_data.inner.insert(
-                        SetElementType::V {
-                            dim: i as u32,
-                            val: left,
-                            mask,
-                        },
-                        1,
-                    );
-                    continue;
-                }
-
-                if right < l || r < left {
-                    continue;
+            let r = match r {
+                None => Some(max),
+                Some(RangeBound::Inclusive(y)) => Some((*y).min(max)),
+                Some(RangeBound::Exclusive { exclusive: y }) => {
+                    y.checked_sub(1).map(|y| y.min(max))
                 }
-
-                let new_mask = !(mask_inv >> 1);
-                queue.push_back((new_mask, left));
-                queue.push_back((new_mask, left | (new_mask & mask_inv)));
+            };
+            match (l, r) {
+                // no constraint on this dimension
+                (Some(0), Some(r)) if r == max => continue,
+                (Some(l), Some(r)) => exp.inner.push(range_to_set(i, l, r, bit_len[i])),
+                // empty range
+                _ => exp.inner.push(MultiSet::new()),
             }
-
-            exp.inner.push(set_data);
         }
         exp
     }
 }
 
 impl Deref for Range {
-    type Target = [Vec<Option<u32>>; 2];
+    type Target = [Vec<Option<RangeBound>>; 2];
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
+// Inclusive range [start, end] of the v data in one dimension.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RangePredicate {
+    pub dim: u32,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoolExpr {
+    And(Vec<BoolExpr>),
+    Or(Vec<BoolExpr>),
+    Not(Box<BoolExpr>),
+    Keyword(String),
+    Range(RangePredicate),
+}
+
+// the CNF of an expression grows exponentially in the worst case
+const MAX_CNF_CLAUSES: usize = 1024;
+
+// clauses as (positive literals, negated literals)
+type Cnf = Vec<(HashSet<SetElementType>, HashSet<SetElementType>)>;
+
+impl BoolExpr {
+    pub fn to_bool_exp(&self, bit_len: &[u8]) -> Result<BoolExp<SetElementType>> {
+        let mut exp = BoolExp::new();
+        for (pos, neg) in self.to_cnf(false, bit_len)? {
+            exp.push_clause(pos, neg);
+        }
+        Ok(exp)
+    }
+
+    fn to_cnf(&self, negated: bool, bit_len: &[u8]) -> Result<Cnf> {
+        let res = match (self, negated) {
+            (Self::Keyword(w), _) => {
+                let lit = [keyword_to_set_element(w)];
+                if negated {
+                    vec![(HashSet::new(), lit.iter().cloned().collect())]
+                } else {
+                    vec![(lit.iter().cloned().collect(), HashSet::new())]
+                }
+            }
+            (Self::Range(r), _) => {
+                let dim = r.dim as usize;
+                ensure!(
+                    dim < bit_len.len(),
+                    Error::Parameter(format!("invalid dimension {}", r.dim))
+                );
+                let set = range_to_set(dim, r.start, r.end, bit_len[dim]);
+                if negated {
+                    set.keys()
+                        .map(|e| (HashSet::new(), [e.clone()].iter().cloned().collect()))
+                        .collect()
+                } else {
+                    vec![(set.keys().cloned().collect(), HashSet::new())]
+                }
+            }
+            (Self::Not(e), _) => e.to_cnf(!negated, bit_len)?,
+            (Self::And(es), false) | (Self::Or(es), true) => {
+                let mut res = Cnf::new();
+                for e in es {
+                    res.extend(e.to_cnf(negated, bit_len)?);
+                    ensure!(
+                        res.len() <= MAX_CNF_CLAUSES,
+                        Error::Parameter("boolean expression too complex".to_owned())
+                    );
+                }
+                res
+            }
+            (Self::And(es), true) | (Self::Or(es), false) => {
+                // distribute the disjunction over the clauses, starting from false
+                let mut res: Cnf = vec![(HashSet::new(), HashSet::new())];
+                for e in es {
+                    let cnf = e.to_cnf(negated, bit_len)?;
+                    ensure!(
+                        res.len() * cnf.len() <= MAX_CNF_CLAUSES,
+                        Error::Parameter("boolean expression too complex".to_owned())
+                    );
+                    res = res
+                        .iter()
+                        .flat_map(|(p1, n1)| cnf.iter().map(move |(p2, n2)| (p1 | p2, n1 | n2)))
+                        .collect();
+                }
+                res
+            }
+        };
+        Ok(res)
+    }
+}
+
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Query {
     pub start_block: IdType,
@@ -111,23 +341,87 @@ pub struct Query {
     pub q_range: Option<Range>,
     #[serde(rename = "bool")]
     pub q_bool: Option<Vec<HashSet<String>>>,
+    // combined with q_range and q_bool by AND
+    #[serde(rename = "expr", default, skip_serializing_if = "Option::is_none")]
+    pub q_expr: Option<BoolExpr>,
+    // only the blocks in [start_block, end_block] with timestamps in
+    // [start_time, end_time] are queried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<u64>,
+    // only the blocks in these disjoint intervals of [start_block, end_block]
+    // are queried, with one VO tree for each interval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intervals: Option<Vec<(IdType, IdType)>>,
 }
 
 impl Query {
-    pub fn to_bool_exp(&self, bit_len: &[u8]) -> BoolExp<SetElementType> {
+    pub fn to_bool_exp(&self, bit_len: &[u8]) -> Result<BoolExp<SetElementType>> {
         let mut exp = BoolExp::new();
         if let Some(q_range) = &self.q_range {
+            let dim = q_range[0].len().max(q_range[1].len());
+            ensure!(
+                dim <= bit_len.len(),
+                Error::Parameter(format!("invalid range of {} dimensions", dim))
+            );
             exp.inner
                 .extend(q_range.to_bool_exp(bit_len).iter().cloned());
         }
         if let Some(q_bool) = &self.q_bool {
+            // a keyword starting with '!' is negated
             for sub_exp in q_bool.iter() {
-                exp.inner.push(MultiSet::from_iter(
-                    sub_exp.iter().map(|w| SetElementType::W(w.clone())),
-                ));
+                let mut pos = HashSet::new();
+                let mut neg = HashSet::new();
+                for w in sub_exp.iter() {
+                    match w.strip_prefix('!') {
+                        Some(w) => neg.insert(keyword_to_set_element(w)),
+                        None => pos.insert(keyword_to_set_element(w)),
+                    };
+                }
+                exp.push_clause(pos, neg);
             }
         }
-        exp
+        if let Some(q_expr) = &self.q_expr {
+            let sub_exp = q_expr.to_bool_exp(bit_len)?;
+            exp.inner.extend(sub_exp.inner);
+            exp.mixed.extend(sub_exp.mixed);
+        }
+        Ok(exp)
+    }
+
+    pub fn check_intervals(&self) -> Result<()> {
+        let intervals = match &self.intervals {
+            Some(intervals) => intervals,
+            None => return Ok(()),
+        };
+        ensure!(
+            !intervals.is_empty(),
+            Error::Parameter("no block interval".to_owned())
+        );
+        ensure!(
+            self.start_time.is_none() && self.end_time.is_none(),
+            Error::Parameter("block intervals cannot be used with a time window".to_owned())
+        );
+        let mut next = self.start_block;
+        for &(start, end) in intervals {
+            ensure!(
+                next <= start && start <= end,
+                Error::Parameter(
+                    "block intervals should be sorted and disjoint within [start_block, end_block]"
+                        .to_owned()
+                )
+            );
+            next = end + 1;
+        }
+        ensure!(
+            next - 1 <= self.end_block,
+            Error::Parameter(
+                "block intervals should be sorted and disjoint within [start_block, end_block]"
+                    .to_owned()
+            )
+        );
+        Ok(())
     }
 }
 
@@ -146,13 +440,52 @@ mod tests {
         let set2 = MultiSet::from_vec(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]);
         assert_eq!(exp.mismatch_idx(&set1), Some(1));
         assert!(exp.is_match(&set2));
+        let filters = exp.filters();
+        assert_eq!(
+            exp.mismatch_idx_with_filter(&filters, &set1, Some(&set1.filter())),
+            Some(1)
+        );
+        assert_eq!(
+            exp.mismatch_idx_with_filter(&filters, &set2, Some(&set2.filter())),
+            None
+        );
+        assert_eq!(exp.mismatch_idx_with_filter(&filters, &set1, None), Some(1));
+    }
+
+    #[test]
+    fn test_clause_order() {
+        let exp = BoolExp::from_vec(vec![
+            MultiSet::from_vec(vec!["a".to_owned(), "b".to_owned()]),
+            MultiSet::from_vec(vec!["c".to_owned()]),
+            MultiSet::from_vec(vec!["d".to_owned()]),
+        ]);
+        let stats = MultiSet::from_vec(vec![
+            "a".to_owned(),
+            "a".to_owned(),
+            "b".to_owned(),
+            "c".to_owned(),
+            "c".to_owned(),
+        ]);
+        let order = exp.clause_order(&stats);
+        assert_eq!(order, vec![2, 1, 0]);
+        let filters = exp.filters();
+        let set = MultiSet::from_vec(vec!["b".to_owned()]);
+        assert_eq!(
+            exp.mismatch_idx_in_order(order.iter().copied(), &filters, &set, None),
+            Some(2)
+        );
+        assert_eq!(exp.mismatch_idx(&set), Some(1));
     }
 
     #[test]
     fn test_range() {
+        use RangeBound::Inclusive;
         use SetElementType::V;
 
-        let range = Range([vec![Some(0), None, Some(3)], vec![Some(6), None, Some(4)]]);
+        let range = Range([
+            vec![Some(Inclusive(0)), None, Some(Inclusive(3))],
+            vec![Some(Inclusive(6)), None, Some(Inclusive(4))],
+        ]);
         #[rustfmt::skip]
         let expect = BoolExp::from_vec(vec![
             MultiSet::from_vec(vec![
@@ -168,14 +501,60 @@ mod tests {
         assert_eq!(range.to_bool_exp(&[3, 3, 3]), expect);
     }
 
+    #[test]
+    fn test_range_bounds() {
+        use RangeBound::{Exclusive, Inclusive};
+        use SetElementType::V;
+
+        let range = Range([
+            vec![Some(Exclusive { exclusive: 1 }), None, Some(Inclusive(0))],
+            vec![None, Some(Exclusive { exclusive: 6 }), Some(Inclusive(100))],
+        ]);
+        #[rustfmt::skip]
+        let expect = BoolExp::from_vec(vec![
+            MultiSet::from_vec(vec![
+                V { dim: 0, val: 0b010, mask: 0b110 },
+                V { dim: 0, val: 0b100, mask: 0b100 },
+            ]),
+            MultiSet::from_vec(vec![
+                V { dim: 1, val: 0b000, mask: 0b100 },
+                V { dim: 1, val: 0b100, mask: 0b110 },
+            ]),
+        ]);
+        assert_eq!(range.to_bool_exp(&[3, 3, 3]), expect);
+
+        let range = Range([
+            vec![Some(Exclusive { exclusive: 7 })],
+            vec![Some(Exclusive { exclusive: 0 })],
+        ]);
+        let exp = range.to_bool_exp(&[3]);
+        assert_eq!(exp.len(), 1);
+        assert!(exp[0].is_empty());
+    }
+
+    #[test]
+    fn test_range_64() {
+        use RangeBound::Inclusive;
+        use SetElementType::V;
+        let range = Range([vec![Some(Inclusive(1 << 63))], vec![None]]);
+        let expect = MultiSet::from_vec(vec![V {
+            dim: 0,
+            val: 1 << 63,
+            mask: 1 << 63,
+        }]);
+        assert_eq!(range.to_bool_exp(&[64])[0], expect);
+    }
+
     #[test]
     fn test_query() {
+        use RangeBound::{Exclusive, Inclusive};
+
         let data = json!({
             "start_block": 1,
             "end_block": 2,
             "range": [
                 [0, null, 3],
-                [6, null, 4],
+                [6, null, {"exclusive": 4}],
             ],
             "bool": [
                 ["a"],
@@ -186,18 +565,66 @@ mod tests {
             start_block: 1,
             end_block: 2,
             q_range: Some(Range([
-                vec![Some(0), None, Some(3)],
-                vec![Some(6), None, Some(4)],
+                vec![Some(Inclusive(0)), None, Some(Inclusive(3))],
+                vec![Some(Inclusive(6)), None, Some(Exclusive { exclusive: 4 })],
             ])),
             q_bool: Some(vec![
                 ["a".to_owned()].iter().cloned().collect::<HashSet<_>>(),
                 ["b".to_owned()].iter().cloned().collect::<HashSet<_>>(),
             ]),
+            q_expr: None,
+            start_time: None,
+            end_time: None,
+            intervals: None,
         };
         assert_eq!(
             serde_json::from_value::<Query>(data.clone()).unwrap(),
             expect
         );
-        assert_eq!(data, serde_json::to_value(expect).unwrap());
+        assert_eq!(data, serde_json::to_value(expect.clone()).unwrap());
+        assert!(expect.to_bool_exp(&[3, 3, 3]).is_ok());
+        let err = expect.to_bool_exp(&[3, 3]).unwrap_err();
+        assert_eq!(
+            Error::find(&err),
+            Some(&Error::Parameter(
+                "invalid range of 3 dimensions".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_bool_expr() {
+        let w = |s: &str| SetElementType::W(Keyword::new(s));
+        let set = |ws: &[&str]| MultiSet::from_iter(ws.iter().map(|s| w(s)));
+
+        let expr = serde_json::from_value::<BoolExpr>(json!({"or": [
+            {"and": [{"keyword": "a"}, {"keyword": "b"}]},
+            {"not": {"keyword": "c"}},
+        ]}))
+        .unwrap();
+        let exp = expr.to_bool_exp(&[]).unwrap();
+        assert!(exp.is_empty());
+        assert_eq!(exp.mixed_clauses().len(), 2);
+        assert!(exp.is_match(&set(&["a", "b", "c"])));
+        assert!(exp.is_match(&set(&["a"])));
+        assert!(!exp.is_match(&set(&["a", "c"])));
+
+        let expr = serde_json::from_value::<BoolExpr>(json!({"not": {"or": [
+            {"not": {"keyword": "a"}},
+            {"not": {"keyword": "b"}},
+        ]}}))
+        .unwrap();
+        let exp = expr.to_bool_exp(&[]).unwrap();
+        assert_eq!(exp.len(), 2);
+        assert!(exp.mixed_clauses().is_empty());
+        assert!(exp.is_match(&set(&["a", "b"])));
+        assert!(!exp.is_match(&set(&["a"])));
+
+        let expr = BoolExpr::Range(RangePredicate {
+            dim: 1,
+            start: 0,
+            end: 1,
+        });
+        assert!(expr.to_bool_exp(&[3]).is_err());
     }
 }
@@ -1,16 +1,19 @@
 use super::*;
 use crate::acc::{self, Accumulator, AccumulatorProof};
 use crate::acc::{G1Affine, G1Projective};
-use crate::digest::{blake2, concat_digest, concat_digest_ref, Digest, Digestible};
+use crate::digest::{concat_digest, concat_digest_ref, Digest, Digestible, DIGEST_LEN};
+use crate::par::*;
 use crate::set::MultiSet;
+use crate::timer::Timer;
+use anyhow::{ensure, Context};
 use ark_ec::ProjectiveCurve;
 use ark_ff::Zero;
+use ark_poly::univariate::DensePolynomial;
 use core::ops::Deref;
-use futures::join;
-use howlong::Duration;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum InvalidReason {
@@ -18,7 +21,25 @@ pub enum InvalidReason {
     InvalidAccIdx(AccProofIdxType),
     InvalidAccProof(AccProofIdxType),
     InvalidMatchObj(IdType),
+    InvalidRevealedObj(IdType),
+    SupersededObj(IdType),
+    InvalidTimeWindow,
+    // the VO trees do not match the intervals of the query
     InvalidHash,
+    HashMismatch(Box<HashMismatch>),
+}
+
+// Where the hashes of a VO tree differ from the block headers.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HashMismatch {
+    pub block_id: IdType,
+    // the node in the VO, e.g. `tree[3].data_root` for the data root of the
+    // 4th node of the tree
+    pub path: String,
+    // None if the block is missing from the chain
+    pub expected: Option<Digest>,
+    // None if the node refers to missing objects or accs
+    pub computed: Option<Digest>,
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -87,77 +108,233 @@ impl<AP: AccumulatorProof> ResultVOAcc<AP> {
     }
 
     pub fn verify(&self, query_exp: &BoolExp<SetElementType>) -> VerifyResult {
+        self.verify_filtered(query_exp, &|_| true)
+    }
+
+    // Verify the proofs picked with probability `fraction` by a hash of `seed`.
+    // ACC2 has a single proof for each clause, and ACC3 verifies the proofs
+    // of each clause as a batch, so their clauses are picked as a whole.
+    pub fn verify_sampled(
+        &self,
+        query_exp: &BoolExp<SetElementType>,
+        fraction: f64,
+        seed: u64,
+    ) -> VerifyResult {
+        self.verify_filtered(query_exp, &|idx| is_sampled(idx, fraction, seed))
+    }
+
+    // The clauses, and the proofs of ACC1 failing the batch verification, are
+    // verified in parallel. The reasons are reported in the same order as the
+    // proofs are iterated.
+    // Verify `k` of the proofs, picked by a hash of `nonce`. Like
+    // verify_sampled, the clauses of ACC2 and ACC3 are picked as a whole.
+    pub fn verify_picked(
+        &self,
+        query_exp: &BoolExp<SetElementType>,
+        k: usize,
+        nonce: u64,
+    ) -> VerifyResult {
+        let picked = pick(self.proof_idxs(), k, |idx| {
+            sample_key(nonce, &[idx.0 as u64, idx.1 as u64])
+        });
+        self.verify_filtered(query_exp, &|idx| picked.contains(&idx))
+    }
+
+    pub fn proof_idxs(&self) -> Vec<AccProofIdxType> {
+        self.proofs
+            .iter()
+            .flat_map(|(&i, proofs)| (0..proofs.len()).map(move |j| (i, j)))
+            .collect()
+    }
+
+    fn verify_filtered(
+        &self,
+        query_exp: &BoolExp<SetElementType>,
+        keep: &(dyn Fn(AccProofIdxType) -> bool + Sync),
+    ) -> VerifyResult {
         let mut result = VerifyResult::default();
         match AP::TYPE {
             acc::Type::ACC1 => {
-                for (&i, proofs) in self.proofs.iter() {
-                    let query_acc = match query_exp.get(i) {
-                        Some(set) => acc::Acc1::cal_acc_g1(set),
-                        None => {
-                            result.add(InvalidReason::InvalidSetIdx(i));
-                            continue;
-                        }
-                    };
-                    for (j, proof) in proofs.iter().enumerate() {
-                        let acc_proof_idx = (i, j);
-                        let proof = match proof.as_any().downcast_ref::<acc::Acc1Proof>() {
-                            Some(proof) => proof,
-                            None => {
-                                result.add(InvalidReason::InvalidAccIdx(acc_proof_idx));
-                                continue;
-                            }
-                        };
-                        let obj_acc = match self.get_object_acc(acc_proof_idx) {
-                            Some(acc) => acc,
-                            None => {
-                                result.add(InvalidReason::InvalidAccIdx(acc_proof_idx));
-                                continue;
-                            }
-                        };
-                        if !proof.verify(obj_acc, &query_acc) {
-                            result.add(InvalidReason::InvalidAccProof(acc_proof_idx));
-                        }
+                let mut query_accs: Vec<(usize, &Vec<AP>, G1Affine)> = Vec::new();
+                let accs: Vec<_> = self
+                    .proofs
+                    .par_iter()
+                    .map(|(&i, proofs)| (i, proofs, query_exp.get(i).map(acc::Acc1::cal_acc_g1)))
+                    .collect();
+                for (i, proofs, query_acc) in accs {
+                    match query_acc {
+                        Some(query_acc) => query_accs.push((i, proofs, query_acc)),
+                        None => result.add(InvalidReason::InvalidSetIdx(i)),
                     }
                 }
+                if self.batch_verify_acc1_all(&query_accs, keep) {
+                    return result;
+                }
+                let reasons: Vec<Vec<InvalidReason>> = query_accs
+                    .par_iter()
+                    .map(|(i, proofs, query_acc)| self.verify_acc1(*i, proofs, query_acc, keep))
+                    .collect();
+                for reason in reasons.into_iter().flatten() {
+                    result.add(reason);
+                }
             }
-            acc::Type::ACC2 => {
-                for (&i, proofs) in self.proofs.iter() {
-                    let query_acc = match query_exp.get(i) {
-                        Some(set) => acc::Acc2::cal_acc_g2(set),
-                        None => {
-                            result.add(InvalidReason::InvalidSetIdx(i));
-                            continue;
-                        }
-                    };
-                    let obj_accs = match self.object_accs.get(&i) {
-                        Some(accs) => accs,
-                        None => {
-                            result.add(InvalidReason::InvalidSetIdx(i));
-                            continue;
-                        }
-                    };
-                    debug_assert_eq!(proofs.len(), 1);
-                    let acc_proof_idx = (i, 0);
-                    let proof = match proofs[0].as_any().downcast_ref::<acc::Acc2Proof>() {
-                        Some(proof) => proof,
-                        None => {
-                            result.add(InvalidReason::InvalidAccIdx(acc_proof_idx));
-                            continue;
-                        }
-                    };
-                    let mut g1 = G1Projective::zero();
-                    for obj_acc in obj_accs.iter() {
-                        g1.add_assign_mixed(&obj_acc.0);
-                    }
-                    if !proof.verify(&g1.into_affine(), &query_acc) {
-                        result.add(InvalidReason::InvalidAccProof(acc_proof_idx));
-                    }
+            acc::Type::ACC2 | acc::Type::ACC3 => {
+                let reasons: Vec<Option<InvalidReason>> = self
+                    .proofs
+                    .par_iter()
+                    .map(|(&i, proofs)| self.verify_clause(i, proofs, query_exp, keep))
+                    .collect();
+                for reason in reasons.into_iter().flatten() {
+                    result.add(reason);
                 }
             }
         }
         result
     }
 
+    // Verify the proofs of an ACC1 clause, one by one to locate the invalid ones.
+    fn verify_acc1(
+        &self,
+        set_idx: usize,
+        proofs: &[AP],
+        query_acc: &G1Affine,
+        keep: &(dyn Fn(AccProofIdxType) -> bool + Sync),
+    ) -> Vec<InvalidReason> {
+        if self.batch_verify_acc1(set_idx, proofs, query_acc, keep) {
+            return Vec::new();
+        }
+        let reasons: Vec<Option<InvalidReason>> = proofs
+            .par_iter()
+            .enumerate()
+            .map(|(j, proof)| {
+                let acc_proof_idx = (set_idx, j);
+                if !keep(acc_proof_idx) {
+                    return None;
+                }
+                let proof = match proof.as_any().downcast_ref::<acc::Acc1Proof>() {
+                    Some(proof) => proof,
+                    None => return Some(InvalidReason::InvalidAccIdx(acc_proof_idx)),
+                };
+                let obj_acc = match self.get_object_acc(acc_proof_idx) {
+                    Some(acc) => acc,
+                    None => return Some(InvalidReason::InvalidAccIdx(acc_proof_idx)),
+                };
+                if !proof.verify(obj_acc, query_acc) {
+                    return Some(InvalidReason::InvalidAccProof(acc_proof_idx));
+                }
+                None
+            })
+            .collect();
+        reasons.into_iter().flatten().collect()
+    }
+
+    // Verify the single proof of an ACC2 clause, or the batch of the proofs of
+    // an ACC3 clause.
+    fn verify_clause(
+        &self,
+        set_idx: usize,
+        proofs: &[AP],
+        query_exp: &BoolExp<SetElementType>,
+        keep: &(dyn Fn(AccProofIdxType) -> bool + Sync),
+    ) -> Option<InvalidReason> {
+        let set = match query_exp.get(set_idx) {
+            Some(set) => set,
+            None => return Some(InvalidReason::InvalidSetIdx(set_idx)),
+        };
+        let obj_accs = match self.object_accs.get(&set_idx) {
+            Some(accs) => accs,
+            None => return Some(InvalidReason::InvalidSetIdx(set_idx)),
+        };
+        debug_assert_eq!(proofs.len(), 1);
+        let acc_proof_idx = (set_idx, 0);
+        if !keep(acc_proof_idx) {
+            return None;
+        }
+        let valid = match AP::TYPE {
+            acc::Type::ACC2 => {
+                let proof = match proofs[0].as_any().downcast_ref::<acc::Acc2Proof>() {
+                    Some(proof) => proof,
+                    None => return Some(InvalidReason::InvalidAccIdx(acc_proof_idx)),
+                };
+                let mut g1 = G1Projective::zero();
+                for obj_acc in obj_accs.iter() {
+                    g1.add_assign_mixed(&obj_acc.0);
+                }
+                proof.verify(&g1.into_affine(), &acc::Acc2::cal_acc_g2(set))
+            }
+            acc::Type::ACC3 => {
+                let proof = match proofs[0].as_any().downcast_ref::<acc::Acc3Proof>() {
+                    Some(proof) => proof,
+                    None => return Some(InvalidReason::InvalidAccIdx(acc_proof_idx)),
+                };
+                let obj_accs: Vec<G1Affine> = obj_accs.iter().map(|acc| acc.0).collect();
+                proof.verify(&obj_accs, &acc::Acc3::cal_acc_g1(set))
+            }
+            acc::Type::ACC1 => unreachable!(),
+        };
+        if valid {
+            None
+        } else {
+            Some(InvalidReason::InvalidAccProof(acc_proof_idx))
+        }
+    }
+
+    fn acc1_batch_items<'a>(
+        &'a self,
+        set_idx: usize,
+        proofs: &'a [AP],
+        keep: &(dyn Fn(AccProofIdxType) -> bool + Sync),
+    ) -> Option<Vec<(&'a acc::Acc1Proof, &'a G1Affine)>> {
+        let obj_accs = self.object_accs.get(&set_idx)?;
+        if obj_accs.len() != proofs.len() {
+            return None;
+        }
+        proofs
+            .iter()
+            .zip(obj_accs.iter())
+            .enumerate()
+            .filter(|(j, _)| keep((set_idx, *j)))
+            .map(|(_, (proof, obj_acc))| {
+                let proof = proof.as_any().downcast_ref::<acc::Acc1Proof>()?;
+                Some((proof, &obj_acc.0))
+            })
+            .collect()
+    }
+
+    fn batch_verify_acc1(
+        &self,
+        set_idx: usize,
+        proofs: &[AP],
+        query_acc: &G1Affine,
+        keep: &(dyn Fn(AccProofIdxType) -> bool + Sync),
+    ) -> bool {
+        match self.acc1_batch_items(set_idx, proofs, keep) {
+            Some(items) => acc::Acc1Proof::batch_verify(&items, query_acc),
+            None => false,
+        }
+    }
+
+    // verify the proofs of all the clauses at once
+    fn batch_verify_acc1_all(
+        &self,
+        query_accs: &[(usize, &Vec<AP>, G1Affine)],
+        keep: &(dyn Fn(AccProofIdxType) -> bool + Sync),
+    ) -> bool {
+        let mut items = Vec::with_capacity(query_accs.len());
+        for (i, proofs, _) in query_accs {
+            match self.acc1_batch_items(*i, proofs, keep) {
+                Some(item) => items.push(item),
+                None => return false,
+            }
+        }
+        let groups: Vec<_> = items
+            .iter()
+            .zip(query_accs.iter())
+            .map(|(item, (_, _, query_acc))| (&item[..], query_acc))
+            .collect();
+        acc::Acc1Proof::batch_verify_groups(&groups)
+    }
+
     pub fn add_proof(
         &mut self,
         mismatch_idx: usize,
@@ -165,9 +342,30 @@ impl<AP: AccumulatorProof> ResultVOAcc<AP> {
         object_set_d: &acc::DigestSet,
         object_acc: &G1Affine,
     ) -> Result<AccProofIdxType> {
-        let object_acc = ObjAcc(*object_acc);
-        let proof = AP::gen_proof(object_set_d, query_exp_set_d)?;
+        let proof = in_query_pool(|| AP::gen_proof(object_set_d, query_exp_set_d))?;
+        self.push_proof(mismatch_idx, proof, object_acc)
+    }
 
+    // Like add_proof, with the polynomial of the object set expanded in advance.
+    // Only for ACC1.
+    pub fn add_proof_with_poly(
+        &mut self,
+        mismatch_idx: usize,
+        query_exp_set_d: &acc::DigestSet,
+        object_poly: &DensePolynomial<acc::Fr>,
+        object_acc: &G1Affine,
+    ) -> Result<AccProofIdxType> {
+        let proof = in_query_pool(|| AP::gen_proof_with_poly(object_poly, query_exp_set_d))?;
+        self.push_proof(mismatch_idx, proof, object_acc)
+    }
+
+    fn push_proof(
+        &mut self,
+        mismatch_idx: usize,
+        proof: AP,
+        object_acc: &G1Affine,
+    ) -> Result<AccProofIdxType> {
+        let object_acc = ObjAcc(*object_acc);
         match AP::TYPE {
             acc::Type::ACC1 => {
                 let proof_ptr = self.proofs.entry(mismatch_idx).or_insert_with(Vec::new);
@@ -180,7 +378,7 @@ impl<AP: AccumulatorProof> ResultVOAcc<AP> {
                 debug_assert_eq!(proof_ptr.len(), acc_ptr.len());
                 Ok((mismatch_idx, proof_ptr.len() - 1))
             }
-            acc::Type::ACC2 => {
+            acc::Type::ACC2 | acc::Type::ACC3 => {
                 let proof_ptr = self.proofs.entry(mismatch_idx).or_insert_with(Vec::new);
                 let acc_ptr = self
                     .object_accs
@@ -198,11 +396,70 @@ impl<AP: AccumulatorProof> ResultVOAcc<AP> {
         }
     }
 
+    // Move the proofs and the object accs of `other` into this one. Returns the
+    // offsets to be added to the indices into the object accs of `other`, see
+    // ResultVOTree::shift_proof_idx.
+    pub fn append(&mut self, other: Self) -> Result<HashMap<usize, usize>> {
+        let mut offsets = HashMap::new();
+        for (i, mut accs) in other.object_accs {
+            let acc_ptr = self.object_accs.entry(i).or_insert_with(Vec::new);
+            offsets.insert(i, acc_ptr.len());
+            acc_ptr.append(&mut accs);
+        }
+        for (i, mut proofs) in other.proofs {
+            let proof_ptr = self.proofs.entry(i).or_insert_with(Vec::new);
+            match AP::TYPE {
+                acc::Type::ACC1 => proof_ptr.append(&mut proofs),
+                acc::Type::ACC2 | acc::Type::ACC3 => {
+                    if proof_ptr.is_empty() {
+                        proof_ptr.append(&mut proofs);
+                    } else {
+                        debug_assert_eq!(proof_ptr.len(), 1);
+                        for proof in &proofs {
+                            proof_ptr[0].combine_proof(proof)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(offsets)
+    }
+
     pub fn compute_stats(&self, stats: &mut VOStatistic) {
         stats.num_of_acc_proofs = self.proofs.values().map(|v| v.len() as u64).sum();
     }
 }
 
+// The leading bytes of the hash of `seed` and the index of a proof or an
+// object, by which they are sampled.
+fn sample_key(seed: u64, idx: &[u64]) -> u64 {
+    let d =
+        concat_digest(std::iter::once(seed.to_digest()).chain(idx.iter().map(|i| i.to_digest())));
+    let mut x = [0u8; 8];
+    x.copy_from_slice(&d.0[..8]);
+    u64::from_le_bytes(x)
+}
+
+// Pick the proof with probability `fraction`.
+fn is_sampled(idx: AccProofIdxType, fraction: f64, seed: u64) -> bool {
+    (sample_key(seed, &[idx.0 as u64, idx.1 as u64]) as f64) < fraction * u64::MAX as f64
+}
+
+// The `k` items with the smallest keys.
+fn pick<T: Copy + Eq + std::hash::Hash>(
+    mut items: Vec<T>,
+    k: usize,
+    key: impl Fn(&T) -> u64,
+) -> HashSet<T> {
+    items.sort_by_cached_key(key);
+    items.truncate(k);
+    items.into_iter().collect()
+}
+
+fn shift_proof_idx(proof_idx: &mut AccProofIdxType, offsets: &HashMap<usize, usize>) {
+    proof_idx.1 += offsets.get(&proof_idx.0).copied().unwrap_or(0);
+}
+
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ResultVOTree(pub Vec<vo::ResultVONode>);
 
@@ -216,10 +473,18 @@ impl ResultVOTree {
         res_objs: &ResultObjs,
         vo_acc: &ResultVOAcc<AP>,
         prev_hash: &Digest,
+        chain_id: &str,
     ) -> Option<Digest> {
+        // The data roots of the blocks are computed in parallel, and then
+        // chained with the hashes of the previous blocks.
+        let data_roots: Vec<Digest> = self
+            .0
+            .par_iter()
+            .map(|n| n.compute_data_root(res_objs, vo_acc))
+            .collect::<Option<_>>()?;
         let mut hash_root = *prev_hash;
-        for n in &self.0 {
-            hash_root = n.compute_digest(res_objs, vo_acc, &hash_root)?;
+        for (n, data_root) in self.0.iter().zip(data_roots.iter()) {
+            hash_root = n.compute_digest_with(data_root, res_objs, vo_acc, &hash_root, chain_id)?;
         }
         Some(hash_root)
     }
@@ -229,12 +494,42 @@ impl ResultVOTree {
             sub_node.compute_stats(stats);
         }
     }
+
+    pub fn collect_revealed_objs<'a>(&'a self, out: &mut Vec<&'a Object>) {
+        for sub_node in &self.0 {
+            sub_node.collect_revealed_objs(out);
+        }
+    }
+
+    // the tombstones of all the blocks covered by the tree
+    pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+        for sub_node in &self.0 {
+            sub_node.collect_tombstones(out);
+        }
+    }
+
+    pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+        for sub_node in &mut self.0 {
+            sub_node.shift_proof_idx(offsets);
+        }
+    }
+
+    // Turn the matched objects in `ids` into revealed ones, taking them out of
+    // `res_objs`. For the objects superseded by the blocks after the tree.
+    pub fn reveal_objs(&mut self, ids: &HashSet<IdType>, res_objs: &mut ResultObjs) {
+        for sub_node in &mut self.0 {
+            sub_node.reveal_objs(ids, res_objs);
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ResultVO<AP: AccumulatorProof> {
     #[serde(rename = "tree")]
     pub vo_t: ResultVOTree,
+    // instead of vo_t for the queries over block intervals, one for each interval
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interval_trees: Vec<ResultVOTree>,
     #[serde(rename = "acc")]
     pub vo_acc: ResultVOAcc<AP>,
 }
@@ -243,13 +538,46 @@ impl<AP: AccumulatorProof> ResultVO<AP> {
     pub fn new() -> Self {
         Self {
             vo_t: ResultVOTree::new(),
+            interval_trees: Vec::new(),
             vo_acc: ResultVOAcc::<AP>::new(),
         }
     }
+    pub fn trees(&self) -> impl Iterator<Item = &ResultVOTree> {
+        std::iter::once(&self.vo_t).chain(self.interval_trees.iter())
+    }
     pub fn compute_stats(&self, stats: &mut VOStatistic) {
-        self.vo_t.compute_stats(stats);
+        for tree in self.trees() {
+            tree.compute_stats(stats);
+        }
         self.vo_acc.compute_stats(stats);
     }
+    pub fn collect_revealed_objs<'a>(&'a self, out: &mut Vec<&'a Object>) {
+        for tree in self.trees() {
+            tree.collect_revealed_objs(out);
+        }
+    }
+    pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+        for tree in self.trees() {
+            tree.collect_tombstones(out);
+        }
+    }
+}
+
+// How much of a result is checked by OverallResult::verify_at. The objects are
+// checked against the query unless audited, and the VO is always hashed up to
+// the block headers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyLevel {
+    // none of the acc proofs
+    Hash,
+    // the acc proofs picked with probability `fraction`, by a seed that should
+    // not be known to the server, see ResultVOAcc::verify_sampled
+    Sampled { fraction: f64, seed: u64 },
+    // `k` of the acc proofs and `k` of the objects, picked by a hash of a
+    // nonce chosen by the client, see OverallResult::soundness_at
+    Audit { k: usize, nonce: u64 },
+    Full,
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -259,6 +587,24 @@ pub struct VOStatistic {
     pub num_of_mismatch_objs: u64,
     pub num_of_mismatch_intra_nodes: u64,
     pub num_of_mismatch_inter_nodes: u64,
+    #[serde(default)]
+    pub num_of_revealed_objs: u64,
+    // the bytes of the VO in bincode taken by the acc proofs with the accs of
+    // the objects, by the revealed objects, by the digests of the objects and
+    // the intra index nodes, and by the skip list or the B-tree nodes; the rest
+    // are the ids and the structure of the VO
+    #[serde(default)]
+    pub bytes_of_acc_proofs: u64,
+    #[serde(default)]
+    pub bytes_of_objs: u64,
+    #[serde(default)]
+    pub bytes_of_digests: u64,
+    #[serde(default)]
+    pub bytes_of_skip_list_nodes: u64,
+}
+
+fn bincode_size<T: Serialize>(v: &T) -> u64 {
+    bincode::serialized_size(v).unwrap_or(0)
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -268,59 +614,478 @@ pub struct OverallResult<AP: AccumulatorProof> {
     #[serde(rename = "vo")]
     pub res_vo: ResultVO<AP>,
     pub query: Query,
+    // the blocks resolved from the time window of the query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_window_blocks: Option<(IdType, IdType)>,
     pub query_exp_set: Vec<MultiSet<SetElementType>>,
     pub query_time_in_ms: u64,
     pub v_bit_len: Vec<u8>,
     pub vo_size: u64,
+    // the sizes of the VO in JSON and in the compact encoding, see
+    // OverallResult::to_compact_bytes
+    #[serde(default)]
+    pub vo_size_json: u64,
+    #[serde(default)]
+    pub vo_size_compact: u64,
     #[serde(rename = "stats")]
     pub vo_stats: VOStatistic,
+    // the last field, so that the results before it can be read from the
+    // packed encodings too
+    #[serde(default = "FormatVersion::unversioned")]
+    pub format_version: FormatVersion,
+}
+
+// Turn a result in JSON of a major format version into the next major.
+fn migrate_json(major: u16, value: &mut serde_json::Value) -> Result<()> {
+    match major {
+        // the unversioned results are the same as 1.0
+        0 => value["format_version"] = serde_json::to_value(FormatVersion { major: 1, minor: 0 })?,
+        _ => anyhow::bail!(Error::Verification(format!(
+            "no migration from format version {}.x",
+            major
+        ))),
+    }
+    Ok(())
+}
+
+// The version of the serialized results, with the minor bumped for the fields
+// added with defaults and the major for the other changes, which the results
+// of the older majors are migrated over in OverallResult::from_json.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct FormatVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+pub const FORMAT_VERSION: FormatVersion = FormatVersion { major: 1, minor: 0 };
+
+impl FormatVersion {
+    // the results before the versions were added, which are read as 1.0
+    pub fn unversioned() -> Self {
+        Self { major: 0, minor: 0 }
+    }
+}
+
+impl Default for FormatVersion {
+    fn default() -> Self {
+        FORMAT_VERSION
+    }
+}
+
+impl core::fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl core::str::FromStr for FormatVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (major, minor) = s
+            .split_once('.')
+            .with_context(|| format!("invalid format version {}", s))?;
+        let v = Self {
+            major: major.parse()?,
+            minor: minor.parse()?,
+        };
+        ensure!(
+            v.major <= FORMAT_VERSION.major,
+            Error::Verification(format!(
+                "unsupported format version {} of the result, at most {}.x is supported",
+                v, FORMAT_VERSION.major
+            ))
+        );
+        Ok(v)
+    }
+}
+
+impl Serialize for FormatVersion {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FormatVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Where the next page of a query starts, see `historical_query_page`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PageToken {
+    pub start_block: IdType,
+    pub end_block: IdType,
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QueryPage<AP: AccumulatorProof> {
+    pub result: OverallResult<AP>,
+    pub next_page: Option<PageToken>,
+}
+
+// The VO of a block or of a jump over blocks, see `historical_query_stream`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QueryFragment {
+    pub vo_node: vo::ResultVONode,
+    pub objs: Vec<Object>,
 }
 
 impl<AP: AccumulatorProof + Serialize> OverallResult<AP> {
+    // Encode the result with the digests and the curve points interned, see
+    // crate::compact.
+    pub fn to_compact_bytes(&self) -> Result<Vec<u8>> {
+        crate::compact::to_bytes(self)
+    }
+
+    pub fn from_compact_bytes(data: &[u8]) -> Result<Self>
+    where
+        AP: serde::de::DeserializeOwned,
+    {
+        crate::compact::from_bytes(data)
+    }
+
+    // Read a result in JSON of this or an older format version.
+    pub fn from_json(data: &[u8]) -> Result<Self>
+    where
+        AP: serde::de::DeserializeOwned,
+    {
+        let mut value: serde_json::Value = serde_json::from_slice(data)?;
+        ensure!(
+            value.is_object(),
+            Error::Verification("invalid result".to_owned())
+        );
+        let version: FormatVersion = match value.get("format_version") {
+            Some(v) => serde_json::from_value(v.clone())?,
+            None => FormatVersion::unversioned(),
+        };
+        for major in version.major..FORMAT_VERSION.major {
+            migrate_json(major, &mut value)?;
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    // Put the fragments streamed by historical_query_stream, in the order they
+    // are received, back into the rest of the result.
+    pub fn add_fragments(&mut self, frags: impl IntoIterator<Item = QueryFragment>) -> Result<()> {
+        let mut vo_nodes = Vec::new();
+        for frag in frags {
+            vo_nodes.push(frag.vo_node);
+            for obj in frag.objs {
+                self.res_objs.insert(obj);
+            }
+        }
+        vo_nodes.reverse();
+        vo_nodes.append(&mut self.res_vo.vo_t.0);
+        self.res_vo.vo_t.0 = vo_nodes;
+        self.compute_stats()
+    }
+
     pub async fn verify(
         &self,
         chain: &impl LightNodeInterface,
     ) -> Result<(VerifyResult, Duration)> {
-        info!("verify result");
-        let cpu_timer = howlong::ProcessCPUTimer::new();
-        let timer = howlong::HighResolutionTimer::new();
-        let res = self.inner_verify(chain).await?;
+        self.verify_at(VerifyLevel::Full, chain).await
+    }
+
+    // Like verify, but checks only as much as `level` asks for, e.g. with the
+    // headers trusted by a light client given as a HeaderStore.
+    pub async fn verify_at(
+        &self,
+        level: VerifyLevel,
+        chain: &impl LightNodeInterface,
+    ) -> Result<(VerifyResult, Duration)> {
+        info!("verify result at {:?}", level);
+        let timer = Timer::new();
+        let res = self.inner_verify(level, chain).await?;
         let time = timer.elapsed();
-        info!("used time: {}", cpu_timer.elapsed());
+        info!("used time: {:?}", time);
         Ok((res, time))
     }
 
-    async fn inner_verify(&self, chain: &impl LightNodeInterface) -> Result<VerifyResult> {
+    async fn inner_verify(
+        &self,
+        level: VerifyLevel,
+        chain: &impl LightNodeInterface,
+    ) -> Result<VerifyResult> {
         let mut result = VerifyResult::default();
-        let query_exp = self.query.to_bool_exp(&self.v_bit_len);
+        let param = chain.lightnode_get_parameter().await?;
+        let query_exp = self.query.to_bool_exp(&self.v_bit_len)?;
+        let mut tombstones = HashSet::new();
+        self.res_vo.collect_tombstones(&mut tombstones);
+        let picked_objs = match level {
+            VerifyLevel::Audit { k, nonce } => {
+                let ids = self.res_objs.keys().copied().collect();
+                Some(pick(ids, k, |&id| sample_key(nonce, &[id])))
+            }
+            _ => None,
+        };
         for (id, obj) in self.res_objs.iter() {
-            if !query_exp.is_match(&obj.set_data) {
+            if let Some(picked_objs) = &picked_objs {
+                if !picked_objs.contains(id) {
+                    continue;
+                }
+            }
+            let matched = obj
+                .compute_set_data(&param)
+                .is_some_and(|set| query_exp.is_match(&set));
+            if !matched {
                 result.add(InvalidReason::InvalidMatchObj(*id));
             }
+            // the tombstones only refer to the objects of the earlier blocks
+            if tombstones.contains(id) {
+                result.add(InvalidReason::SupersededObj(*id));
+            }
+        }
+        // the matched objects superseded by the later blocks are revealed too
+        let mut revealed_objs = Vec::new();
+        self.res_vo.collect_revealed_objs(&mut revealed_objs);
+        for obj in revealed_objs {
+            // an object of more dimensions cannot be on the chain
+            let matched = obj
+                .compute_set_data(&param)
+                .is_none_or(|set| query_exp.is_match(&set));
+            if matched && !tombstones.contains(&obj.id) {
+                result.add(InvalidReason::InvalidRevealedObj(obj.id));
+            }
         }
-        let acc_res = self.res_vo.vo_acc.verify(&query_exp);
-        result.append(acc_res);
-        let (blk1, blk2) = join!(
-            chain.lightnode_read_block_header(self.query.start_block),
-            chain.lightnode_read_block_header(self.query.end_block)
+        result.append(self.verify_acc_at(level, &query_exp)?);
+        let (start_block, end_block) = self
+            .time_window_blocks
+            .unwrap_or((self.query.start_block, self.query.end_block));
+        let intervals = match &self.query.intervals {
+            Some(intervals) => intervals.clone(),
+            None => vec![(start_block, end_block)],
+        };
+        // the headers of the ends of the query and of the intervals
+        let mut ids = vec![start_block, end_block];
+        for &(start, end) in &intervals {
+            ids.push(start);
+            ids.push(end);
+        }
+        let headers = chain.lightnode_read_block_headers(&ids).await?;
+        ensure!(
+            headers.len() == ids.len(),
+            Error::Verification("missing block headers".to_owned())
         );
-        let prev_hash = blk1?.prev_hash;
-        let hash_root = blk2?.to_digest();
-        if self
-            .res_vo
-            .vo_t
-            .compute_digest(&self.res_objs, &self.res_vo.vo_acc, &prev_hash)
-            != Some(hash_root)
-        {
+        let (blk1, blk2) = (headers[0], headers[1]);
+        if !self.verify_time_window(&blk1, &blk2, chain).await? {
+            result.add(InvalidReason::InvalidTimeWindow);
+        }
+        let chain_id = param.chain_id;
+        let trees: Vec<&ResultVOTree> = match self.query.intervals {
+            Some(_) if self.res_vo.vo_t.0.is_empty() => self.res_vo.interval_trees.iter().collect(),
+            None if self.res_vo.interval_trees.is_empty() => vec![&self.res_vo.vo_t],
+            _ => Vec::new(),
+        };
+        if trees.len() != intervals.len() {
             result.add(InvalidReason::InvalidHash);
+            return Ok(result);
+        }
+        for (i, tree) in trees.into_iter().enumerate() {
+            let (blk1, blk2) = (headers[2 * i + 2], headers[2 * i + 3]);
+            let hash_root = blk2.compute_digest(&chain_id);
+            let computed = tree.compute_digest(
+                &self.res_objs,
+                &self.res_vo.vo_acc,
+                &blk1.prev_hash,
+                &chain_id,
+            );
+            if computed == Some(hash_root) {
+                continue;
+            }
+            let path = match self.query.intervals {
+                Some(_) => format!("interval_trees[{}]", i),
+                None => "tree".to_owned(),
+            };
+            let mut mismatches = self
+                .locate_hash_mismatches(&path, tree, &blk1.prev_hash, &chain_id, chain)
+                .await;
+            // all the nodes agree with their blocks, but the tree does not
+            // end at the last block
+            if mismatches.is_empty() {
+                mismatches.push(HashMismatch {
+                    block_id: blk2.block_id,
+                    path,
+                    expected: Some(hash_root),
+                    computed,
+                });
+            }
+            for m in mismatches {
+                result.add(InvalidReason::HashMismatch(Box::new(m)));
+            }
         }
         Ok(result)
     }
 
+    pub(crate) fn verify_acc_at(
+        &self,
+        level: VerifyLevel,
+        query_exp: &BoolExp<SetElementType>,
+    ) -> Result<VerifyResult> {
+        Ok(match level {
+            VerifyLevel::Hash => VerifyResult::default(),
+            VerifyLevel::Sampled { fraction, seed } => {
+                ensure!(
+                    (0.0..=1.0).contains(&fraction),
+                    Error::Parameter("the fraction of proofs should be in [0, 1]".to_owned())
+                );
+                self.res_vo.vo_acc.verify_sampled(query_exp, fraction, seed)
+            }
+            VerifyLevel::Audit { k, nonce } => {
+                self.res_vo.vo_acc.verify_picked(query_exp, k, nonce)
+            }
+            VerifyLevel::Full => self.res_vo.vo_acc.verify(query_exp),
+        })
+    }
+
+    // The least chance of a result with a single invalid acc proof or object
+    // failing the verification at `level`. A result with a fraction `e` of
+    // them invalid fails the audit of `k` of them with a chance of at least
+    // `1 - (1 - e)^k`.
+    pub fn soundness_at(&self, level: VerifyLevel) -> f64 {
+        let picked = |k: usize, n: usize| {
+            if n == 0 {
+                1.0
+            } else {
+                (k as f64 / n as f64).min(1.0)
+            }
+        };
+        let num_of_proofs = self.res_vo.vo_acc.proof_idxs().len();
+        let (proofs, objs) = match level {
+            VerifyLevel::Hash => (picked(0, num_of_proofs), 1.0),
+            VerifyLevel::Sampled { fraction, .. } => {
+                (if num_of_proofs == 0 { 1.0 } else { fraction }, 1.0)
+            }
+            VerifyLevel::Audit { k, .. } => {
+                (picked(k, num_of_proofs), picked(k, self.res_objs.len()))
+            }
+            VerifyLevel::Full => (1.0, 1.0),
+        };
+        proofs.min(objs)
+    }
+
+    // Compare each node of a tree failing the verification with the header of
+    // its block. Each node is hashed on top of the header of the previous node,
+    // so that a tampered node does not fail the nodes after it.
+    async fn locate_hash_mismatches(
+        &self,
+        path: &str,
+        tree: &ResultVOTree,
+        prev_hash: &Digest,
+        chain_id: &str,
+        chain: &impl LightNodeInterface,
+    ) -> Vec<HashMismatch> {
+        let ids: Vec<IdType> = tree.0.iter().map(|n| n.block_id()).collect();
+        let headers = match chain.lightnode_read_block_headers(&ids).await {
+            Ok(headers) if headers.len() == ids.len() => headers.into_iter().map(Some).collect(),
+            // some of the blocks are missing, e.g. made up by the VO
+            _ => {
+                let mut headers = Vec::with_capacity(ids.len());
+                for &id in &ids {
+                    headers.push(chain.lightnode_read_block_header(id).await.ok());
+                }
+                headers
+            }
+        };
+        let mut mismatches = Vec::new();
+        let mut prev_hash = *prev_hash;
+        for (i, (n, header)) in tree.0.iter().zip(headers).enumerate() {
+            let block_id = n.block_id();
+            let expected = header.map(|h| h.compute_digest(chain_id));
+            let data_root = n.compute_data_root(&self.res_objs, &self.res_vo.vo_acc);
+            if let Some(header) = header {
+                if data_root != Some(header.data_root) {
+                    mismatches.push(HashMismatch {
+                        block_id,
+                        path: format!("{}[{}].data_root", path, i),
+                        expected: Some(header.data_root),
+                        computed: data_root,
+                    });
+                    prev_hash = header.compute_digest(chain_id);
+                    continue;
+                }
+            }
+            let computed = data_root.and_then(|data_root| {
+                n.compute_digest_with(
+                    &data_root,
+                    &self.res_objs,
+                    &self.res_vo.vo_acc,
+                    &prev_hash,
+                    chain_id,
+                )
+            });
+            if expected.is_none() || computed != expected {
+                mismatches.push(HashMismatch {
+                    block_id,
+                    path: format!("{}[{}]", path, i),
+                    expected,
+                    computed,
+                });
+            }
+            prev_hash = expected.or(computed).unwrap_or_default();
+        }
+        mismatches
+    }
+
+    // Check that [blk1, blk2] are exactly the blocks of the query within its
+    // time window.
+    pub(crate) async fn verify_time_window(
+        &self,
+        blk1: &BlockHeader,
+        blk2: &BlockHeader,
+        chain: &impl LightNodeInterface,
+    ) -> Result<bool> {
+        let q = &self.query;
+        let no_time = q.start_time.is_none() && q.end_time.is_none();
+        if self.time_window_blocks.is_none() || no_time {
+            return Ok(self.time_window_blocks.is_none() && no_time);
+        }
+        if blk1.block_id < q.start_block
+            || blk2.block_id > q.end_block
+            || blk1.block_id > blk2.block_id
+        {
+            return Ok(false);
+        }
+        if let Some(t) = q.start_time {
+            if blk1.timestamp < t {
+                return Ok(false);
+            }
+            if blk1.block_id > q.start_block {
+                let prev = chain.lightnode_read_block_header(blk1.block_id - 1).await?;
+                if prev.timestamp >= t {
+                    return Ok(false);
+                }
+            }
+        } else if blk1.block_id != q.start_block {
+            // without the start time, the window starts at the start block
+            return Ok(false);
+        }
+        if let Some(t) = q.end_time {
+            if blk2.timestamp > t {
+                return Ok(false);
+            }
+            if blk2.block_id < q.end_block {
+                let next = chain.lightnode_read_block_header(blk2.block_id + 1).await?;
+                if next.timestamp <= t {
+                    return Ok(false);
+                }
+            }
+        } else if blk2.block_id != q.end_block {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
     pub fn compute_stats(&mut self) -> Result<()> {
         self.vo_size = bincode::serialize(&self.res_vo)?.len() as u64;
+        self.vo_size_json = serde_json::to_vec(&self.res_vo)?.len() as u64;
+        self.vo_size_compact = crate::compact::to_bytes(&self.res_vo)?.len() as u64;
         self.vo_stats = Default::default();
         self.res_vo.compute_stats(&mut self.vo_stats);
+        self.vo_stats.bytes_of_acc_proofs = bincode_size(&self.res_vo.vo_acc);
         Ok(())
     }
 }
@@ -343,9 +1108,7 @@ pub mod vo {
             _vo_acc: &ResultVOAcc<AP>,
         ) -> Option<Digest> {
             let obj = res_objs.get(&self.obj_id)?;
-            Some(concat_digest_ref(
-                [obj.acc_value.to_digest(), obj.to_digest()].iter(),
-            ))
+            Some(obj_leaf_digest(obj))
         }
         pub fn into_obj_node(self) -> ObjNode {
             ObjNode::Match(Box::new(self))
@@ -385,6 +1148,30 @@ pub mod vo {
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
             stats.num_of_mismatch_objs += 1;
+            stats.bytes_of_digests += DIGEST_LEN as u64;
+        }
+    }
+
+    // An object failing only the clauses which cannot be proved by the
+    // accumulators, sent as is so that the client can check it.
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct RevealedObjNode {
+        pub obj: Object,
+    }
+
+    impl RevealedObjNode {
+        pub fn create(o: &Object) -> Self {
+            Self { obj: o.clone() }
+        }
+        pub fn into_obj_node(self) -> ObjNode {
+            ObjNode::Revealed(Box::new(self))
+        }
+        pub fn compute_digest(&self) -> Option<Digest> {
+            Some(obj_leaf_digest(&self.obj))
+        }
+        pub fn compute_stats(&self, stats: &mut VOStatistic) {
+            stats.num_of_revealed_objs += 1;
+            stats.bytes_of_objs += bincode_size(&self.obj);
         }
     }
 
@@ -392,6 +1179,7 @@ pub mod vo {
     pub enum ObjNode {
         Match(Box<MatchObjNode>),
         NoMatch(Box<NoMatchObjNode>),
+        Revealed(Box<RevealedObjNode>),
     }
 
     impl ObjNode {
@@ -403,12 +1191,28 @@ pub mod vo {
             match self {
                 Self::Match(n) => n.compute_digest(res_objs, vo_acc),
                 Self::NoMatch(n) => n.compute_digest(res_objs, vo_acc),
+                Self::Revealed(n) => n.compute_digest(),
             }
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
             match self {
                 Self::Match(n) => n.compute_stats(stats),
                 Self::NoMatch(n) => n.compute_stats(stats),
+                Self::Revealed(n) => n.compute_stats(stats),
+            }
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            if let Self::NoMatch(n) = self {
+                super::shift_proof_idx(&mut n.proof_idx, offsets);
+            }
+        }
+        pub fn reveal_objs(&mut self, ids: &HashSet<IdType>, res_objs: &mut ResultObjs) {
+            let obj = match self {
+                Self::Match(n) if ids.contains(&n.obj_id) => res_objs.0.remove(&n.obj_id),
+                _ => None,
+            };
+            if let Some(obj) = obj {
+                *self = RevealedObjNode { obj }.into_obj_node();
             }
         }
     }
@@ -416,7 +1220,11 @@ pub mod vo {
     #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct FlatBlkNode {
         pub block_id: IdType,
+        #[serde(default)]
+        pub timestamp: u64,
         pub skip_list_root: Option<Digest>,
+        #[serde(default)]
+        pub tombstones: Vec<IdType>,
         pub sub_nodes: Vec<ObjNode>,
     }
 
@@ -426,30 +1234,77 @@ pub mod vo {
             res_objs: &ResultObjs,
             vo_acc: &ResultVOAcc<AP>,
             prev_hash: &Digest,
+            chain_id: &str,
         ) -> Option<Digest> {
-            let mut hs: Vec<Digest> = Vec::with_capacity(self.sub_nodes.len());
-            for sub_node in &self.sub_nodes {
-                hs.push(sub_node.compute_digest(res_objs, vo_acc)?);
-            }
-            let data_root = concat_digest(hs.into_iter());
-
-            let mut state = blake2().to_state();
-            state.update(&self.block_id.to_le_bytes());
-            state.update(&prev_hash.0);
-            state.update(&data_root.0);
-            if let Some(d) = self.skip_list_root {
-                state.update(&d.0);
-            }
-            Some(Digest::from(state.finalize()))
+            let data_root = self.compute_data_root(res_objs, vo_acc)?;
+            Some(self.compute_digest_with(&data_root, prev_hash, chain_id))
+        }
+        pub fn compute_data_root<AP: AccumulatorProof>(
+            &self,
+            res_objs: &ResultObjs,
+            vo_acc: &ResultVOAcc<AP>,
+        ) -> Option<Digest> {
+            let hs: Vec<Digest> = self
+                .sub_nodes
+                .par_iter()
+                .map(|sub_node| sub_node.compute_digest(res_objs, vo_acc))
+                .collect::<Option<_>>()?;
+            Some(merkle_root(&hs))
+        }
+        pub fn compute_digest_with(
+            &self,
+            data_root: &Digest,
+            prev_hash: &Digest,
+            chain_id: &str,
+        ) -> Digest {
+            block_header_digest(
+                chain_id,
+                self.block_id,
+                self.timestamp,
+                prev_hash,
+                data_root,
+                self.skip_list_root.as_ref(),
+                tombstones_digest(&self.tombstones).as_ref(),
+            )
         }
         pub fn into_result_vo_node(self) -> ResultVONode {
             ResultVONode::FlatBlkNode(Box::new(self))
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
+            if self.skip_list_root.is_some() {
+                stats.bytes_of_digests += DIGEST_LEN as u64;
+            }
             for sub_node in &self.sub_nodes {
                 sub_node.compute_stats(stats);
             }
         }
+        pub fn collect_revealed_objs<'a>(&'a self, out: &mut Vec<&'a Object>) {
+            for sub_node in &self.sub_nodes {
+                if let ObjNode::Revealed(n) = sub_node {
+                    out.push(&n.obj);
+                }
+            }
+        }
+        pub fn collect_matched_obj_ids(&self, out: &mut Vec<IdType>) {
+            for sub_node in &self.sub_nodes {
+                if let ObjNode::Match(n) = sub_node {
+                    out.push(n.obj_id);
+                }
+            }
+        }
+        pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+            out.extend(self.tombstones.iter().copied());
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            for sub_node in &mut self.sub_nodes {
+                sub_node.shift_proof_idx(offsets);
+            }
+        }
+        pub fn reveal_objs(&mut self, ids: &HashSet<IdType>, res_objs: &mut ResultObjs) {
+            for sub_node in &mut self.sub_nodes {
+                sub_node.reveal_objs(ids, res_objs);
+            }
+        }
     }
 
     #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
@@ -482,6 +1337,7 @@ pub mod vo {
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
             stats.num_of_mismatch_intra_nodes += 1;
+            stats.bytes_of_digests += DIGEST_LEN as u64;
         }
     }
 
@@ -515,6 +1371,7 @@ pub mod vo {
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
             stats.num_of_mismatch_intra_nodes += 1;
+            stats.bytes_of_digests += DIGEST_LEN as u64;
         }
     }
 
@@ -549,11 +1406,40 @@ pub mod vo {
         }
     }
 
+    // See RevealedObjNode.
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct RevealedIntraLeaf {
+        pub id: IdType,
+        pub obj: Object,
+    }
+
+    impl RevealedIntraLeaf {
+        pub fn create(n: &IntraIndexLeaf, obj: &Object) -> Self {
+            Self {
+                id: n.id,
+                obj: obj.clone(),
+            }
+        }
+        pub fn into_intra_node(self) -> IntraNode {
+            IntraNode::RevealedIntraLeaf(Box::new(self))
+        }
+        pub fn compute_digest(&self) -> Option<Digest> {
+            Some(concat_digest_ref(
+                [self.obj.acc_value.to_digest(), self.obj.to_digest()].iter(),
+            ))
+        }
+        pub fn compute_stats(&self, stats: &mut VOStatistic) {
+            stats.num_of_revealed_objs += 1;
+            stats.bytes_of_objs += bincode_size(&self.obj);
+        }
+    }
+
     #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub enum IntraNode {
         NoMatchIntraLeaf(Box<NoMatchIntraLeaf>),
         NoMatchIntraNonLeaf(Box<NoMatchIntraNonLeaf>),
         MatchIntraLeaf(Box<MatchIntraLeaf>),
+        RevealedIntraLeaf(Box<RevealedIntraLeaf>),
         IntraNonLeaf(Box<IntraNonLeaf>),
         Empty,
     }
@@ -568,6 +1454,7 @@ pub mod vo {
                 Self::NoMatchIntraLeaf(n) => n.compute_digest(res_objs, vo_acc),
                 Self::NoMatchIntraNonLeaf(n) => n.compute_digest(res_objs, vo_acc),
                 Self::MatchIntraLeaf(n) => n.compute_digest(res_objs, vo_acc),
+                Self::RevealedIntraLeaf(n) => n.compute_digest(),
                 Self::IntraNonLeaf(n) => n.compute_digest(res_objs, vo_acc),
                 Self::Empty => None,
             }
@@ -577,10 +1464,63 @@ pub mod vo {
                 Self::NoMatchIntraLeaf(n) => n.compute_stats(stats),
                 Self::NoMatchIntraNonLeaf(n) => n.compute_stats(stats),
                 Self::MatchIntraLeaf(n) => n.compute_stats(stats),
+                Self::RevealedIntraLeaf(n) => n.compute_stats(stats),
                 Self::IntraNonLeaf(n) => n.compute_stats(stats),
                 Self::Empty => {}
             }
         }
+        pub fn collect_revealed_objs<'a>(&'a self, out: &mut Vec<&'a Object>) {
+            match self {
+                Self::RevealedIntraLeaf(n) => out.push(&n.obj),
+                Self::IntraNonLeaf(n) => {
+                    for child in &n.children {
+                        child.collect_revealed_objs(out);
+                    }
+                }
+                _ => {}
+            }
+        }
+        pub fn collect_matched_obj_ids(&self, out: &mut Vec<IdType>) {
+            match self {
+                Self::MatchIntraLeaf(n) => out.push(n.obj_id),
+                Self::IntraNonLeaf(n) => {
+                    for child in &n.children {
+                        child.collect_matched_obj_ids(out);
+                    }
+                }
+                _ => {}
+            }
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            match self {
+                Self::NoMatchIntraLeaf(n) => super::shift_proof_idx(&mut n.proof_idx, offsets),
+                Self::NoMatchIntraNonLeaf(n) => super::shift_proof_idx(&mut n.proof_idx, offsets),
+                Self::IntraNonLeaf(n) => {
+                    for child in &mut n.children {
+                        child.shift_proof_idx(offsets);
+                    }
+                }
+                _ => {}
+            }
+        }
+        pub fn reveal_objs(&mut self, ids: &HashSet<IdType>, res_objs: &mut ResultObjs) {
+            let revealed = match self {
+                Self::MatchIntraLeaf(n) if ids.contains(&n.obj_id) => res_objs
+                    .0
+                    .remove(&n.obj_id)
+                    .map(|obj| RevealedIntraLeaf { id: n.id, obj }),
+                Self::IntraNonLeaf(n) => {
+                    for child in &mut n.children {
+                        child.reveal_objs(ids, res_objs);
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(revealed) = revealed {
+                *self = revealed.into_intra_node();
+            }
+        }
     }
 
     #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -623,10 +1563,35 @@ pub mod vo {
         }
     }
 
+    // Proof of an object in a flat block, see prove_object_inclusion.
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct ObjInclusionProof {
+        pub block_id: IdType,
+        pub obj_id: IdType,
+        pub path: Vec<MerkleStep>,
+    }
+
+    impl ObjInclusionProof {
+        pub fn compute_data_root(&self, obj: &Object) -> Option<Digest> {
+            if obj.id != self.obj_id || obj.block_id != self.block_id {
+                return None;
+            }
+            Some(merkle_root_from_path(&obj_leaf_digest(obj), &self.path))
+        }
+        pub async fn verify(&self, obj: &Object, chain: &impl LightNodeInterface) -> Result<bool> {
+            let header = chain.lightnode_read_block_header(self.block_id).await?;
+            Ok(self.compute_data_root(obj) == Some(header.data_root))
+        }
+    }
+
     #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct BlkNode {
         pub block_id: IdType,
+        #[serde(default)]
+        pub timestamp: u64,
         pub skip_list_root: Option<Digest>,
+        #[serde(default)]
+        pub tombstones: Vec<IdType>,
         pub sub_node: IntraNode,
     }
 
@@ -636,29 +1601,66 @@ pub mod vo {
             res_objs: &ResultObjs,
             vo_acc: &ResultVOAcc<AP>,
             prev_hash: &Digest,
+            chain_id: &str,
         ) -> Option<Digest> {
-            let data_root = self.sub_node.compute_digest(res_objs, vo_acc)?;
-            let mut state = blake2().to_state();
-            state.update(&self.block_id.to_le_bytes());
-            state.update(&prev_hash.0);
-            state.update(&data_root.0);
-            if let Some(d) = self.skip_list_root {
-                state.update(&d.0);
-            }
-            Some(Digest::from(state.finalize()))
+            let data_root = self.compute_data_root(res_objs, vo_acc)?;
+            Some(self.compute_digest_with(&data_root, prev_hash, chain_id))
+        }
+        pub fn compute_data_root<AP: AccumulatorProof>(
+            &self,
+            res_objs: &ResultObjs,
+            vo_acc: &ResultVOAcc<AP>,
+        ) -> Option<Digest> {
+            self.sub_node.compute_digest(res_objs, vo_acc)
+        }
+        pub fn compute_digest_with(
+            &self,
+            data_root: &Digest,
+            prev_hash: &Digest,
+            chain_id: &str,
+        ) -> Digest {
+            block_header_digest(
+                chain_id,
+                self.block_id,
+                self.timestamp,
+                prev_hash,
+                data_root,
+                self.skip_list_root.as_ref(),
+                tombstones_digest(&self.tombstones).as_ref(),
+            )
         }
         pub fn into_result_vo_node(self) -> ResultVONode {
             ResultVONode::BlkNode(Box::new(self))
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
+            if self.skip_list_root.is_some() {
+                stats.bytes_of_digests += DIGEST_LEN as u64;
+            }
             self.sub_node.compute_stats(stats);
         }
+        pub fn collect_revealed_objs<'a>(&'a self, out: &mut Vec<&'a Object>) {
+            self.sub_node.collect_revealed_objs(out);
+        }
+        pub fn collect_matched_obj_ids(&self, out: &mut Vec<IdType>) {
+            self.sub_node.collect_matched_obj_ids(out);
+        }
+        pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+            out.extend(self.tombstones.iter().copied());
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            self.sub_node.shift_proof_idx(offsets);
+        }
+        pub fn reveal_objs(&mut self, ids: &HashSet<IdType>, res_objs: &mut ResultObjs) {
+            self.sub_node.reveal_objs(ids, res_objs);
+        }
     }
 
-    #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+    #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct JumpNode {
         pub id: IdType,
         pub proof_idx: AccProofIdxType,
+        #[serde(default)]
+        pub tombstones: Vec<IdType>,
     }
 
     impl JumpNode {
@@ -666,6 +1668,7 @@ pub mod vo {
             Self {
                 id: n.id,
                 proof_idx,
+                tombstones: n.tombstones.clone(),
             }
         }
         pub fn compute_digest<AP: AccumulatorProof>(
@@ -675,8 +1678,10 @@ pub mod vo {
             prev_hash: &Digest,
         ) -> Option<Digest> {
             let acc_value = vo_acc.get_object_acc(self.proof_idx)?;
-            Some(concat_digest_ref(
-                [acc_value.to_digest(), *prev_hash].iter(),
+            Some(skip_list_node_digest(
+                acc_value,
+                prev_hash,
+                &self.tombstones,
             ))
         }
         pub fn into_jump_or_no_jump_node(self) -> JumpOrNoJumpNode {
@@ -687,6 +1692,46 @@ pub mod vo {
         }
     }
 
+    #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct BTreeJumpNode {
+        pub id: IdType,
+        pub proof_idx: AccProofIdxType,
+        pub child_hash_digest: Digest,
+        #[serde(default)]
+        pub tombstones: Vec<IdType>,
+    }
+
+    impl BTreeJumpNode {
+        pub fn create(n: &BTreeNode, proof_idx: AccProofIdxType) -> Self {
+            Self {
+                id: n.id,
+                proof_idx,
+                child_hash_digest: n.child_hash_digest,
+                tombstones: n.tombstones.clone(),
+            }
+        }
+        pub fn compute_digest<AP: AccumulatorProof>(
+            &self,
+            _res_objs: &ResultObjs,
+            vo_acc: &ResultVOAcc<AP>,
+            prev_hash: &Digest,
+        ) -> Option<Digest> {
+            let acc_value = vo_acc.get_object_acc(self.proof_idx)?;
+            Some(btree_node_digest(
+                acc_value,
+                prev_hash,
+                &self.child_hash_digest,
+                &self.tombstones,
+            ))
+        }
+        pub fn into_jump_or_no_jump_node(self) -> JumpOrNoJumpNode {
+            JumpOrNoJumpNode::BTreeJump(Box::new(self))
+        }
+        pub fn compute_stats(&self, stats: &mut VOStatistic) {
+            stats.num_of_mismatch_inter_nodes += 1;
+        }
+    }
+
     #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
     pub struct NoJumpNode {
         pub id: IdType,
@@ -700,6 +1745,12 @@ pub mod vo {
                 digest: n.digest,
             }
         }
+        pub fn create_btree(n: &BTreeNode) -> Self {
+            Self {
+                id: n.id,
+                digest: n.digest,
+            }
+        }
         pub fn compute_digest<AP: AccumulatorProof>(
             &self,
             _res_objs: &ResultObjs,
@@ -718,6 +1769,7 @@ pub mod vo {
     pub enum JumpOrNoJumpNode {
         Jump(Box<JumpNode>),
         NoJump(Box<NoJumpNode>),
+        BTreeJump(Box<BTreeJumpNode>),
     }
 
     impl JumpOrNoJumpNode {
@@ -730,21 +1782,43 @@ pub mod vo {
             match self {
                 Self::Jump(n) => n.compute_digest(res_objs, vo_acc, prev_hash),
                 Self::NoJump(n) => n.compute_digest(res_objs, vo_acc, prev_hash),
+                Self::BTreeJump(n) => n.compute_digest(res_objs, vo_acc, prev_hash),
             }
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
             match self {
                 Self::Jump(n) => n.compute_stats(stats),
                 Self::NoJump(n) => n.compute_stats(stats),
+                Self::BTreeJump(n) => n.compute_stats(stats),
+            }
+        }
+        pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+            match self {
+                Self::Jump(n) => out.extend(n.tombstones.iter().copied()),
+                Self::BTreeJump(n) => out.extend(n.tombstones.iter().copied()),
+                Self::NoJump(_) => {}
+            }
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            match self {
+                Self::Jump(n) => super::shift_proof_idx(&mut n.proof_idx, offsets),
+                Self::BTreeJump(n) => super::shift_proof_idx(&mut n.proof_idx, offsets),
+                Self::NoJump(_) => {}
             }
         }
     }
 
+    // The skip list or the B-tree nodes of a block, one of which jumps over the
+    // blocks up to it.
     #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct SkipListRoot {
         pub block_id: IdType,
+        #[serde(default)]
+        pub blk_timestamp: u64,
         pub blk_prev_hash: Digest,
         pub blk_data_root: Digest,
+        #[serde(default)]
+        pub blk_tombstone_root: Option<Digest>,
         pub sub_nodes: Vec<JumpOrNoJumpNode>,
     }
 
@@ -754,27 +1828,43 @@ pub mod vo {
             res_objs: &ResultObjs,
             vo_acc: &ResultVOAcc<AP>,
             prev_hash: &Digest,
+            chain_id: &str,
         ) -> Option<Digest> {
             let mut hs: Vec<Digest> = Vec::with_capacity(self.sub_nodes.len());
             for sub_node in &self.sub_nodes {
                 hs.push(sub_node.compute_digest(res_objs, vo_acc, prev_hash)?);
             }
             let skip_list_root = concat_digest(hs.into_iter());
-            let mut state = blake2().to_state();
-            state.update(&self.block_id.to_le_bytes());
-            state.update(&self.blk_prev_hash.0);
-            state.update(&self.blk_data_root.0);
-            state.update(&skip_list_root.0);
-            Some(Digest::from(state.finalize()))
+            Some(block_header_digest(
+                chain_id,
+                self.block_id,
+                self.blk_timestamp,
+                &self.blk_prev_hash,
+                &self.blk_data_root,
+                Some(&skip_list_root),
+                self.blk_tombstone_root.as_ref(),
+            ))
         }
         pub fn into_result_vo_node(self) -> ResultVONode {
             ResultVONode::SkipListRoot(Box::new(self))
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
+            stats.bytes_of_skip_list_nodes += bincode_size(self);
             for sub_node in &self.sub_nodes {
                 sub_node.compute_stats(stats);
             }
         }
+        // the tombstones of this block are covered by the jump too
+        pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+            for sub_node in &self.sub_nodes {
+                sub_node.collect_tombstones(out);
+            }
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            for sub_node in &mut self.sub_nodes {
+                sub_node.shift_proof_idx(offsets);
+            }
+        }
     }
 
     #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -790,11 +1880,47 @@ pub mod vo {
             res_objs: &ResultObjs,
             vo_acc: &ResultVOAcc<AP>,
             prev_hash: &Digest,
+            chain_id: &str,
+        ) -> Option<Digest> {
+            match self {
+                Self::FlatBlkNode(n) => n.compute_digest(res_objs, vo_acc, prev_hash, chain_id),
+                Self::BlkNode(n) => n.compute_digest(res_objs, vo_acc, prev_hash, chain_id),
+                Self::SkipListRoot(n) => n.compute_digest(res_objs, vo_acc, prev_hash, chain_id),
+            }
+        }
+        // The data root of the block, which does not depend on the previous
+        // blocks. It is given in the skip list root.
+        pub fn compute_data_root<AP: AccumulatorProof>(
+            &self,
+            res_objs: &ResultObjs,
+            vo_acc: &ResultVOAcc<AP>,
         ) -> Option<Digest> {
             match self {
-                Self::FlatBlkNode(n) => n.compute_digest(res_objs, vo_acc, prev_hash),
-                Self::BlkNode(n) => n.compute_digest(res_objs, vo_acc, prev_hash),
-                Self::SkipListRoot(n) => n.compute_digest(res_objs, vo_acc, prev_hash),
+                Self::FlatBlkNode(n) => n.compute_data_root(res_objs, vo_acc),
+                Self::BlkNode(n) => n.compute_data_root(res_objs, vo_acc),
+                Self::SkipListRoot(n) => Some(n.blk_data_root),
+            }
+        }
+        pub fn block_id(&self) -> IdType {
+            match self {
+                Self::FlatBlkNode(n) => n.block_id,
+                Self::BlkNode(n) => n.block_id,
+                Self::SkipListRoot(n) => n.block_id,
+            }
+        }
+        // Like compute_digest, with the data root from compute_data_root.
+        pub fn compute_digest_with<AP: AccumulatorProof>(
+            &self,
+            data_root: &Digest,
+            res_objs: &ResultObjs,
+            vo_acc: &ResultVOAcc<AP>,
+            prev_hash: &Digest,
+            chain_id: &str,
+        ) -> Option<Digest> {
+            match self {
+                Self::FlatBlkNode(n) => Some(n.compute_digest_with(data_root, prev_hash, chain_id)),
+                Self::BlkNode(n) => Some(n.compute_digest_with(data_root, prev_hash, chain_id)),
+                Self::SkipListRoot(n) => n.compute_digest(res_objs, vo_acc, prev_hash, chain_id),
             }
         }
         pub fn compute_stats(&self, stats: &mut VOStatistic) {
@@ -804,5 +1930,41 @@ pub mod vo {
                 Self::SkipListRoot(n) => n.compute_stats(stats),
             }
         }
+        pub fn collect_revealed_objs<'a>(&'a self, out: &mut Vec<&'a Object>) {
+            match self {
+                Self::FlatBlkNode(n) => n.collect_revealed_objs(out),
+                Self::BlkNode(n) => n.collect_revealed_objs(out),
+                Self::SkipListRoot(_) => {}
+            }
+        }
+        // the objects of the result referred to by the node
+        pub fn collect_matched_obj_ids(&self, out: &mut Vec<IdType>) {
+            match self {
+                Self::FlatBlkNode(n) => n.collect_matched_obj_ids(out),
+                Self::BlkNode(n) => n.collect_matched_obj_ids(out),
+                Self::SkipListRoot(_) => {}
+            }
+        }
+        pub fn collect_tombstones(&self, out: &mut HashSet<IdType>) {
+            match self {
+                Self::FlatBlkNode(n) => n.collect_tombstones(out),
+                Self::BlkNode(n) => n.collect_tombstones(out),
+                Self::SkipListRoot(n) => n.collect_tombstones(out),
+            }
+        }
+        pub fn shift_proof_idx(&mut self, offsets: &HashMap<usize, usize>) {
+            match self {
+                Self::FlatBlkNode(n) => n.shift_proof_idx(offsets),
+                Self::BlkNode(n) => n.shift_proof_idx(offsets),
+                Self::SkipListRoot(n) => n.shift_proof_idx(offsets),
+            }
+        }
+        pub fn reveal_objs(&mut self, ids: &HashSet<IdType>, res_objs: &mut ResultObjs) {
+            match self {
+                Self::FlatBlkNode(n) => n.reveal_objs(ids, res_objs),
+                Self::BlkNode(n) => n.reveal_objs(ids, res_objs),
+                Self::SkipListRoot(_) => {}
+            }
+        }
     }
 }
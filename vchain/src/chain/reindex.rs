@@ -0,0 +1,63 @@
+use super::*;
+use anyhow::Context;
+use std::collections::HashMap;
+
+// Rebuild the blocks of `chain_in` into the empty `chain_out` under
+// `new_param`, from the objects stored in `chain_in`. The blocks are replayed
+// from the first one up to the latest one, keeping their timestamps and
+// tombstones. Returns the last block of `chain_out`.
+pub fn reindex(
+    chain_in: &impl ReadInterface,
+    chain_out: &mut (impl ReadInterface + WriteInterface),
+    new_param: Parameter,
+) -> Result<Option<BlockHeader>> {
+    info!("reindex with param {:?}", new_param);
+    chain_out.set_parameter(new_param)?;
+    let next_obj_id = chain_out.read_id_counter()?.next_obj_id;
+    // the objects are replayed in the order of their ids, which is kept by the
+    // new chain, even if they are carried over to other blocks
+    let mut new_ids: HashMap<IdType, IdType> = HashMap::new();
+
+    let first_block = match chain_in.read_block_header(0) {
+        Ok(_) => 0,
+        Err(_) => 1,
+    };
+    let latest_block = chain_in.latest_block_id()?.unwrap_or(0);
+    let mut builder = ChainBuilder::new(chain_out, None)?;
+    let mut timestamp = 0;
+    for header in chain_in.iter_block_headers(first_block..=latest_block) {
+        let header = header?;
+        let block_id = header.block_id;
+        if block_id % 1000 == 0 {
+            info!("reindex blk #{}", block_id);
+        }
+        let blk_data = chain_in.read_block_data(block_id)?;
+        let mut objs = chain_in
+            .iter_objects_in_block(block_id)?
+            .collect::<Result<Vec<_>>>()?;
+        objs.sort_unstable_by_key(|obj| obj.id);
+        let mut raw_objs = Vec::with_capacity(objs.len());
+        for obj in objs {
+            new_ids.insert(obj.id, next_obj_id + new_ids.len() as IdType);
+            raw_objs.push(RawObject {
+                block_id,
+                v_data: obj.v_data,
+                w_data: obj.w_data,
+            });
+        }
+        let tombstones = blk_data
+            .tombstones
+            .iter()
+            .map(|id| {
+                new_ids
+                    .get(id)
+                    .copied()
+                    .with_context(|| format!("tombstone of unknown object #{}", id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        timestamp = header.timestamp;
+        builder.append_block_with_tombstones(block_id, timestamp, raw_objs.iter(), &tombstones)?;
+    }
+    builder.flush(timestamp)?;
+    Ok(builder.tip().copied())
+}
@@ -0,0 +1,84 @@
+use super::*;
+use crate::acc::AccumulatorProof;
+use anyhow::{ensure, Result};
+use std::collections::BTreeMap;
+
+pub type SubscriptionId = u64;
+
+type Subscriber<AP> = Box<dyn FnMut(OverallResult<AP>) -> Result<()> + Send>;
+
+// Queries registered once and answered again over each new block. The delta of
+// a block is the result of the query over just that block, which is verified
+// like any other result. Its VO carries the tombstones of the block, so the
+// subscribers can drop the objects they have received that are superseded.
+pub struct StandingQueryManager<AP: AccumulatorProof> {
+    next_id: SubscriptionId,
+    subscriptions: BTreeMap<SubscriptionId, (Query, Subscriber<AP>)>,
+}
+
+impl<AP: AccumulatorProof + Serialize> StandingQueryManager<AP> {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            subscriptions: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    // The blocks of the query are ignored. `f` is called with the delta of
+    // each new block, and the subscription is dropped once it returns an error.
+    pub fn subscribe(
+        &mut self,
+        q: Query,
+        f: impl FnMut(OverallResult<AP>) -> Result<()> + Send + 'static,
+    ) -> Result<SubscriptionId> {
+        ensure!(
+            q.start_time.is_none() && q.end_time.is_none() && q.intervals.is_none(),
+            Error::Parameter(
+                "standing queries cannot have time windows or block intervals".to_owned()
+            )
+        );
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscriptions.insert(id, (q, Box::new(f)));
+        Ok(id)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.subscriptions.remove(&id).is_some()
+    }
+
+    // Send the deltas of a block just committed, e.g. by ChainBuilder::on_block.
+    // The subscriptions whose queries fail on the chain are dropped too.
+    pub fn notify(&mut self, block_id: IdType, chain: &impl ReadInterface) {
+        let mut dropped = Vec::new();
+        for (&id, (q, f)) in &mut self.subscriptions {
+            let block_q = Query {
+                start_block: block_id,
+                end_block: block_id,
+                ..q.clone()
+            };
+            let res = historical_query(&block_q, chain).and_then(f);
+            if let Err(e) = res {
+                info!("drop standing query #{}: {:?}", id, e);
+                dropped.push(id);
+            }
+        }
+        for id in dropped {
+            self.subscriptions.remove(&id);
+        }
+    }
+}
+
+impl<AP: AccumulatorProof + Serialize> Default for StandingQueryManager<AP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,110 +1,18 @@
 use super::*;
 use crate::acc;
-use crate::digest::{Digest, Digestible};
-use anyhow::Context;
+use crate::digest::Digest;
+use crate::set::MultiSet;
 use serde_json::json;
-use std::collections::HashMap;
-
-#[derive(Debug, Default)]
-struct FakeInMemChain {
-    param: Option<Parameter>,
-    block_headers: HashMap<IdType, BlockHeader>,
-    block_data: HashMap<IdType, BlockData>,
-    intra_index_nodes: HashMap<IdType, IntraIndexNode>,
-    skip_list_nodes: HashMap<IdType, SkipListNode>,
-    objects: HashMap<IdType, Object>,
-}
-
-#[async_trait::async_trait]
-impl LightNodeInterface for FakeInMemChain {
-    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
-        self.get_parameter()
-    }
-    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
-        self.read_block_header(id)
-    }
-}
-
-impl ReadInterface for FakeInMemChain {
-    fn get_parameter(&self) -> Result<Parameter> {
-        self.param.clone().context("failed to get param")
-    }
-    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
-        self.block_headers
-            .get(&id)
-            .cloned()
-            .context("failed to read block header")
-    }
-    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
-        self.block_data
-            .get(&id)
-            .cloned()
-            .context("failed to read block data")
-    }
-    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
-        self.intra_index_nodes
-            .get(&id)
-            .cloned()
-            .context("failed to read intra index")
-    }
-    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
-        self.skip_list_nodes
-            .get(&id)
-            .cloned()
-            .context("failed to read skip list")
-    }
-    fn read_object(&self, id: IdType) -> Result<Object> {
-        self.objects
-            .get(&id)
-            .cloned()
-            .context("failed to read object")
-    }
-}
-
-impl WriteInterface for FakeInMemChain {
-    fn set_parameter(&mut self, param: Parameter) -> Result<()> {
-        self.param = Some(param);
-        Ok(())
-    }
-    fn write_block_header(&mut self, header: BlockHeader) -> Result<()> {
-        let id = header.block_id;
-        self.block_headers.insert(id, header);
-        Ok(())
-    }
-    fn write_block_data(&mut self, data: BlockData) -> Result<()> {
-        let id = data.block_id;
-        self.block_data.insert(id, data);
-        Ok(())
-    }
-    fn write_intra_index_node(&mut self, node: IntraIndexNode) -> Result<()> {
-        let id = node.id();
-        self.intra_index_nodes.insert(id, node);
-        Ok(())
-    }
-    fn write_skip_list_node(&mut self, node: SkipListNode) -> Result<()> {
-        let id = node.id;
-        self.skip_list_nodes.insert(id, node);
-        Ok(())
-    }
-    fn write_object(&mut self, obj: Object) -> Result<()> {
-        let id = obj.id;
-        self.objects.insert(id, obj);
-        Ok(())
-    }
-}
-
-impl FakeInMemChain {
-    fn new() -> Self {
-        Default::default()
-    }
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
+impl MemChain {
     fn build_chain(&mut self, data: &str, param: &Parameter) -> Result<()> {
         info!("build chain");
         self.set_parameter(param.clone())?;
-        let mut prev_hash = Digest::default();
+        let mut builder = ChainBuilder::new(self, None)?;
         for (id, objs) in load_raw_obj_from_str(data)?.iter() {
-            let header = build_block(*id, prev_hash, objs.iter(), self)?;
-            prev_hash = header.to_digest();
+            builder.append_block(*id, *id * 10, objs.iter())?;
         }
         Ok(())
     }
@@ -151,13 +59,18 @@ fn init_logger() {
 #[actix_rt::test]
 async fn test_data1_acc1_flat() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC1,
         use_sk: true,
         intra_index: false,
         skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_1, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -178,13 +91,18 @@ async fn test_data1_acc1_flat() {
 #[actix_rt::test]
 async fn test_data1_acc1() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC1,
         use_sk: true,
         intra_index: true,
         skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_1, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -205,13 +123,18 @@ async fn test_data1_acc1() {
 #[actix_rt::test]
 async fn test_data1_acc2_flat() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC2,
         use_sk: true,
         intra_index: false,
         skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_1, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -232,13 +155,18 @@ async fn test_data1_acc2_flat() {
 #[actix_rt::test]
 async fn test_data1_acc2() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC2,
         use_sk: true,
         intra_index: true,
         skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_1, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -259,13 +187,18 @@ async fn test_data1_acc2() {
 #[actix_rt::test]
 async fn test_data2_acc2() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC2,
         use_sk: true,
         intra_index: true,
         skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_2, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -286,13 +219,18 @@ async fn test_data2_acc2() {
 #[actix_rt::test]
 async fn test_data2_acc2_skip_list() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC2,
         use_sk: true,
         intra_index: true,
         skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_2, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -313,13 +251,18 @@ async fn test_data2_acc2_skip_list() {
 #[actix_rt::test]
 async fn test_data2_acc1_skip_list() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC1,
         use_sk: true,
         intra_index: true,
         skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_2, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -337,16 +280,53 @@ async fn test_data2_acc1_skip_list() {
     assert!(res.verify(&chain).await.unwrap().0.is_ok());
 }
 
+#[actix_rt::test]
+async fn test_data2_acc3_skip_list() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC3,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc3Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+}
+
 #[actix_rt::test]
 async fn test_data1_incomplete() {
     init_logger();
-    let mut chain = FakeInMemChain::new();
+    let mut chain = MemChain::new();
     let param = Parameter {
         v_bit_len: vec![3],
         acc_type: acc::Type::ACC2,
         use_sk: true,
         intra_index: true,
         skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
     };
     chain.build_chain(TEST_DATA_1, &param).unwrap();
     let query = serde_json::from_value::<Query>(json!({
@@ -360,7 +340,2119 @@ async fn test_data1_incomplete() {
     }))
     .unwrap();
     let mut res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
-    let new_range = Range([vec![Some(1)], vec![Some(2)]]);
+    let new_range = Range([
+        vec![Some(RangeBound::Inclusive(1))],
+        vec![Some(RangeBound::Inclusive(2))],
+    ]);
     res.query.q_range = Some(new_range);
     assert!(!res.verify(&chain).await.unwrap().0.is_ok());
 }
+
+#[actix_rt::test]
+async fn test_data1_chain_id() {
+    init_logger();
+    let mut param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: "chain-a".to_owned(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain_a = MemChain::new();
+    chain_a.build_chain(TEST_DATA_1, &param).unwrap();
+    param.chain_id = "chain-b".to_owned();
+    let mut chain_b = MemChain::new();
+    // the same data under other ids, so that the VO does not verify by chance
+    chain_b.id_counter = chain_a.id_counter;
+    chain_b.build_chain(TEST_DATA_1, &param).unwrap();
+    assert_ne!(
+        chain_a
+            .read_block_header(2)
+            .unwrap()
+            .compute_digest("chain-a"),
+        chain_b
+            .read_block_header(2)
+            .unwrap()
+            .compute_digest("chain-b")
+    );
+
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain_a).unwrap();
+    assert!(res.verify(&chain_a).await.unwrap().0.is_ok());
+    assert!(!res.verify(&chain_b).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data1_obj_inclusion() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: false,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let obj_ids = match chain.read_block_data(2).unwrap().data {
+        IntraData::Flat(ids) => ids,
+        _ => unreachable!(),
+    };
+    for &obj_id in &obj_ids {
+        let proof = prove_object_inclusion(2, obj_id, &chain).unwrap();
+        let mut obj = chain.read_object(obj_id).unwrap();
+        assert!(proof.verify(&obj, &chain).await.unwrap());
+        obj.v_data[0] += 1;
+        assert!(!proof.verify(&obj, &chain).await.unwrap());
+    }
+    assert!(prove_object_inclusion(1, obj_ids[0], &chain).is_err());
+}
+
+#[test]
+fn test_prove_object() {
+    for &intra_index in &[false, true] {
+        let mut chain = MemChain::new();
+        let param = Parameter {
+            v_bit_len: vec![3],
+            acc_type: acc::Type::ACC2,
+            use_sk: true,
+            intra_index,
+            skip_list_max_level: 0,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 3,
+            btree_max_level: 0,
+            max_objs_per_block: 0,
+        };
+        chain.build_chain(TEST_DATA_1, &param).unwrap();
+        let header1 = chain.read_block_header(1).unwrap();
+        let header2 = chain.read_block_header(2).unwrap();
+        let mut obj_ids: Vec<IdType> = chain.objects.keys().copied().collect();
+        obj_ids.sort_unstable();
+        for obj_id in obj_ids {
+            let mut proof = prove_object(obj_id, &chain).unwrap();
+            let (header, other) = match proof.obj.block_id {
+                1 => (&header1, &header2),
+                _ => (&header2, &header1),
+            };
+            assert!(proof.verify(header, &param));
+            assert!(!proof.verify(other, &param));
+            let set_data = proof.obj.set_data.clone();
+            proof.obj.set_data = MultiSet::new();
+            assert!(!proof.verify(header, &param));
+            proof.obj.set_data = set_data;
+            proof.obj.v_data[0] += 1;
+            assert!(!proof.verify(header, &param));
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_point_query() {
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 0,
+        chain_id: "test".to_owned(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let obj_id = chain.objects.values().find(|o| o.block_id == 1).unwrap().id;
+
+    let res = historical_point_query(obj_id, None, &chain).unwrap();
+    assert_eq!(res.headers.len(), 1);
+    assert!(res.verify(&chain).await.unwrap());
+
+    let mut res = historical_point_query(obj_id, Some(2), &chain).unwrap();
+    assert_eq!(res.headers.len(), 2);
+    assert!(res.verify(&chain).await.unwrap());
+    let trusted = chain.read_block_header(2).unwrap();
+    assert!(res.verify_with(&trusted, &param));
+    let other = Parameter {
+        chain_id: "other".to_owned(),
+        ..param.clone()
+    };
+    assert!(!res.verify_with(&trusted, &other));
+    res.headers[0].timestamp += 1;
+    assert!(!res.verify_with(&trusted, &param));
+
+    let obj_id = chain.objects.values().find(|o| o.block_id == 2).unwrap().id;
+    assert!(historical_point_query(obj_id, Some(1), &chain).is_err());
+}
+
+#[actix_rt::test]
+async fn test_data1_bool_expr() {
+    init_logger();
+    for &intra_index in &[false, true] {
+        let mut chain = MemChain::new();
+        let param = Parameter {
+            v_bit_len: vec![3],
+            acc_type: acc::Type::ACC2,
+            use_sk: true,
+            intra_index,
+            skip_list_max_level: 0,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 2,
+            btree_max_level: 0,
+            max_objs_per_block: 0,
+        };
+        chain.build_chain(TEST_DATA_1, &param).unwrap();
+        let query = serde_json::from_value::<Query>(json!({
+            "start_block": 1,
+            "end_block": 2,
+            "expr": {"and": [
+                {"not": {"keyword": "a"}},
+                {"range": {"dim": 0, "start": 1, "end": 2}},
+            ]},
+        }))
+        .unwrap();
+        let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+        assert_eq!(res.vo_stats.num_of_objs, 2);
+        assert_eq!(res.vo_stats.num_of_revealed_objs, 2);
+        assert!(res.verify(&chain).await.unwrap().0.is_ok());
+    }
+}
+
+// The server may not hide a matched object by revealing it with a set_data
+// failing the query, which is not covered by the hashes.
+#[actix_rt::test]
+async fn test_data1_tampered_set_data() {
+    init_logger();
+    for &intra_index in &[false, true] {
+        let mut chain = MemChain::new();
+        let param = Parameter {
+            v_bit_len: vec![3],
+            acc_type: acc::Type::ACC2,
+            use_sk: true,
+            intra_index,
+            skip_list_max_level: 0,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 2,
+            btree_max_level: 0,
+            max_objs_per_block: 0,
+        };
+        chain.build_chain(TEST_DATA_1, &param).unwrap();
+        let query = serde_json::from_value::<Query>(json!({
+            "start_block": 1,
+            "end_block": 2,
+            "expr": {"and": [
+                {"not": {"keyword": "a"}},
+                {"range": {"dim": 0, "start": 1, "end": 2}},
+            ]},
+        }))
+        .unwrap();
+        let mut res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+        let id = *res.res_objs.keys().next().unwrap();
+        let obj = res.res_objs.0.get_mut(&id).unwrap();
+        let a = w_data_to_set(&["a".to_owned()].iter().cloned().collect(), 0);
+        obj.set_data = &obj.set_data + &a;
+        let ids = [id].iter().copied().collect();
+        res.res_vo.vo_t.reveal_objs(&ids, &mut res.res_objs);
+        assert!(!res.res_objs.contains_key(&id));
+        let mut expect = VerifyResult::default();
+        expect.add(InvalidReason::InvalidRevealedObj(id));
+        assert_eq!(res.verify(&chain).await.unwrap().0, expect);
+    }
+}
+
+#[actix_rt::test]
+async fn test_data1_negated_keyword() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC1,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "range": [
+            [1],
+            [3],
+        ],
+        "bool": [["!a"]],
+    }))
+    .unwrap();
+    let mut res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 3);
+    assert_eq!(res.vo_stats.num_of_revealed_objs, 3);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    res.query.q_bool = Some(vec![["a".to_owned()].iter().cloned().collect()]);
+    assert!(!res.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_keyword_prefix() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 3,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let data = r#"
+1 [ 1 ] { btc }
+1 [ 2 ] { btcz }
+1 [ 3 ] { eth }
+2 [ 1 ] { bt }
+"#;
+    chain.build_chain(data, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "bool": [["btc*"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 2);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "bool": [["btcz*"]],
+    }))
+    .unwrap();
+    let res: Result<OverallResult<acc::Acc2Proof>> = historical_query(&query, &chain);
+    assert!(res.is_err());
+}
+
+#[actix_rt::test]
+async fn test_data1_time_window() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "range": [
+            [1],
+            [4],
+        ],
+        "bool": null,
+        "start_time": 15,
+    }))
+    .unwrap();
+    let mut res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.time_window_blocks, Some((2, 2)));
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    res.query.start_time = Some(5);
+    assert!(!res.verify(&chain).await.unwrap().0.is_ok());
+
+    // the window of a query with one time bound ends at the block bound
+    let mut shrunk = query.clone();
+    shrunk.start_time = Some(5);
+    shrunk.end_block = 1;
+    let mut res: OverallResult<acc::Acc2Proof> = historical_query(&shrunk, &chain).unwrap();
+    assert_eq!(res.time_window_blocks, Some((1, 1)));
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+    res.query.end_block = 2;
+    assert!(!res.verify(&chain).await.unwrap().0.is_ok());
+
+    // and a query without the time bounds has no window
+    let mut shrunk = query.clone();
+    shrunk.start_time = None;
+    shrunk.start_block = 2;
+    let mut res: OverallResult<acc::Acc2Proof> = historical_query(&shrunk, &chain).unwrap();
+    assert_eq!(res.time_window_blocks, None);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+    res.query.start_block = 1;
+    res.time_window_blocks = Some((2, 2));
+    assert!(!res.verify(&chain).await.unwrap().0.is_ok());
+
+    let mut query = query;
+    query.end_time = Some(5);
+    let res: Result<OverallResult<acc::Acc2Proof>> = historical_query(&query, &chain);
+    assert!(res.is_err());
+}
+
+#[actix_rt::test]
+async fn test_data1_paging() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "range": [
+            [1],
+            [3],
+        ],
+        "bool": null,
+    }))
+    .unwrap();
+    let mut token = None;
+    let mut num_of_objs = 0;
+    let mut num_of_pages = 0;
+    loop {
+        let page: QueryPage<acc::Acc2Proof> =
+            historical_query_page(&query, token.as_ref(), 2, &chain).unwrap();
+        assert!(page.result.verify(&chain).await.unwrap().0.is_ok());
+        num_of_objs += page.result.vo_stats.num_of_objs;
+        num_of_pages += 1;
+        token = page.next_page;
+        if token.is_none() {
+            break;
+        }
+    }
+    assert_eq!(num_of_objs, 6);
+    assert_eq!(num_of_pages, 2);
+}
+
+#[test]
+fn test_chain_builder() {
+    let mut chain = MemChain::new();
+    chain
+        .build_chain(
+            TEST_DATA_1,
+            &Parameter {
+                v_bit_len: vec![3],
+                acc_type: acc::Type::ACC2,
+                use_sk: true,
+                intra_index: false,
+                skip_list_max_level: 0,
+                chain_id: String::new(),
+                max_prefix_len: 0,
+                intra_index_fanout: 2,
+                btree_max_level: 0,
+                max_objs_per_block: 0,
+            },
+        )
+        .unwrap();
+    let tip = chain.read_block_header(2).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, Some(tip)).unwrap();
+    let obj = |block_id| RawObject {
+        block_id,
+        v_data: vec![1],
+        w_data: ["c".to_owned()].iter().cloned().collect(),
+    };
+    assert!(builder.append_block(2, 30, [obj(2)].iter()).is_err());
+    assert!(builder.append_block(3, 10, [obj(3)].iter()).is_err());
+    assert!(builder.append_block(3, 30, [obj(4)].iter()).is_err());
+    let header = builder.append_block(3, 30, [obj(3)].iter()).unwrap();
+    assert_eq!(builder.tip(), Some(&header));
+    assert_eq!(header.prev_hash, tip.compute_digest(""));
+}
+
+#[actix_rt::test]
+async fn test_data1_tombstones() {
+    init_logger();
+    for &intra_index in &[false, true] {
+        let mut chain = MemChain::new();
+        let param = Parameter {
+            v_bit_len: vec![3],
+            acc_type: acc::Type::ACC2,
+            use_sk: true,
+            intra_index,
+            skip_list_max_level: 1,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 2,
+            btree_max_level: 0,
+            max_objs_per_block: 0,
+        };
+        chain.build_chain(TEST_DATA_1, &param).unwrap();
+        let first_obj = chain.objects.values().map(|o| o.id).min().unwrap();
+        let tip = chain.read_block_header(2).unwrap();
+        let mut builder = ChainBuilder::new(&mut chain, Some(tip)).unwrap();
+        let obj = |block_id| RawObject {
+            block_id,
+            v_data: vec![1],
+            w_data: ["c".to_owned()].iter().cloned().collect(),
+        };
+        assert!(builder
+            .append_block_with_tombstones(3, 30, [obj(3)].iter(), &[1000])
+            .is_err());
+        assert!(builder
+            .append_block_with_tombstones(3, 30, [obj(3)].iter(), &[first_obj, first_obj])
+            .is_err());
+        builder.append_block(3, 30, [obj(3)].iter()).unwrap();
+        // update the first object of block 1 from { a } to { c }
+        builder
+            .append_block_with_tombstones(4, 40, [obj(4)].iter(), &[first_obj])
+            .unwrap();
+        builder.append_block(5, 50, [obj(5)].iter()).unwrap();
+        builder.append_block(6, 60, [obj(6)].iter()).unwrap();
+
+        let query = |end_block| {
+            serde_json::from_value::<Query>(json!({
+                "start_block": 1,
+                "end_block": end_block,
+                "range": [
+                    [1],
+                    [4],
+                ],
+                "bool": [["a"]],
+            }))
+            .unwrap()
+        };
+        let res: OverallResult<acc::Acc2Proof> = historical_query(&query(3), &chain).unwrap();
+        assert_eq!(res.res_objs.len(), 4);
+        assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+        // the tombstone is in the blocks jumped over by the skip list
+        let mut res: OverallResult<acc::Acc2Proof> = historical_query(&query(6), &chain).unwrap();
+        assert_eq!(res.res_objs.len(), 3);
+        assert!(!res.res_objs.contains_key(&first_obj));
+        assert_eq!(res.vo_stats.num_of_mismatch_inter_nodes, 1);
+        assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+        res.res_objs.insert(chain.read_object(first_obj).unwrap());
+        let mut expect = VerifyResult::default();
+        expect.add(InvalidReason::SupersededObj(first_obj));
+        assert_eq!(res.verify(&chain).await.unwrap().0, expect);
+
+        // the tombstone is in a later segment
+        let res: OverallResult<acc::Acc2Proof> =
+            historical_query_par(&query(6), 3, &chain).unwrap();
+        assert_eq!(res.res_objs.len(), 3);
+        assert!(!res.res_objs.contains_key(&first_obj));
+        assert!(res.verify(&chain).await.unwrap().0.is_ok());
+    }
+}
+
+#[actix_rt::test]
+async fn test_data2_par() {
+    init_logger();
+    let mut param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC1,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain).unwrap();
+    for &num_segments in &[1, 3, 20, 100] {
+        let res_par: OverallResult<acc::Acc1Proof> =
+            historical_query_par(&query, num_segments, &chain).unwrap();
+        assert_eq!(res_par.res_objs, res.res_objs);
+        assert!(res_par.verify(&chain).await.unwrap().0.is_ok());
+    }
+    assert!(historical_query_par::<acc::Acc1Proof>(&query, 0, &chain).is_err());
+
+    param.acc_type = acc::Type::ACC2;
+    param.intra_index = false;
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query_par(&query, 3, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data2_btree() {
+    init_logger();
+    let mut param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 2,
+        max_objs_per_block: 0,
+    };
+    assert!(MemChain::new().build_chain(TEST_DATA_2, &param).is_err());
+    param.skip_list_max_level = 0;
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    assert_eq!(chain.read_block_data(16).unwrap().btree_ids.len(), 2);
+    assert_eq!(chain.read_block_data(12).unwrap().btree_ids.len(), 1);
+    assert!(chain.read_block_data(15).unwrap().btree_ids.is_empty());
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    // over blocks 13 to 16
+    assert_eq!(res.vo_stats.num_of_mismatch_inter_nodes, 1);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    param.acc_type = acc::Type::ACC1;
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    assert_eq!(res.vo_stats.num_of_mismatch_inter_nodes, 1);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data1_max_objs_per_block() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 3,
+    };
+    chain.set_parameter(param).unwrap();
+    let raw_objs = load_raw_obj_from_str(TEST_DATA_1).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, None).unwrap();
+    for (id, objs) in raw_objs.iter() {
+        builder.append_block(*id, 10, objs.iter()).unwrap();
+    }
+    assert_eq!(builder.pending().len(), 2);
+    assert_eq!(builder.flush(10).unwrap().len(), 1);
+    assert!(builder.pending().is_empty());
+    assert!(build_block(
+        4,
+        Default::default(),
+        10,
+        raw_objs[&1].iter(),
+        &[],
+        &mut chain
+    )
+    .is_err());
+
+    let mut num_of_objs = vec![0; 3];
+    for obj in chain.objects.values() {
+        num_of_objs[obj.block_id as usize - 1] += 1;
+    }
+    assert_eq!(num_of_objs, vec![3, 3, 2]);
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 3,
+        "range": [
+            [1],
+            [4],
+        ],
+        "bool": null,
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.res_objs.len(), 8);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data2_reindex() {
+    init_logger();
+    let mut param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: false,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain_in = MemChain::new();
+    chain_in.build_chain(TEST_DATA_2, &param).unwrap();
+
+    param.acc_type = acc::Type::ACC1;
+    param.intra_index = true;
+    param.skip_list_max_level = 2;
+    let mut chain_out = MemChain::new();
+    let tip = reindex(&chain_in, &mut chain_out, param.clone()).unwrap();
+    assert_eq!(tip.map(|t| t.block_id), Some(20));
+    assert_eq!(chain_out.get_parameter().unwrap(), param);
+    assert_eq!(chain_out.objects.len(), chain_in.objects.len());
+    assert!(matches!(
+        chain_out.read_block_data(1).unwrap().data,
+        IntraData::Index(_)
+    ));
+    assert_eq!(
+        chain_out.read_block_header(5).unwrap().timestamp,
+        chain_in.read_block_header(5).unwrap().timestamp
+    );
+
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain_out).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    assert!(res.verify(&chain_out).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data2_stream() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let mut frags = Vec::new();
+    let mut rest: OverallResult<acc::Acc2Proof> = historical_query_stream(&query, &chain, |frag| {
+        frags.push(frag);
+        Ok(())
+    })
+    .unwrap();
+    assert!(rest.res_vo.vo_t.0.is_empty());
+    assert!(rest.res_objs.is_empty());
+    let num_of_frags = frags.len();
+    rest.add_fragments(frags).unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(rest.res_objs, res.res_objs);
+    assert_eq!(rest.res_vo, res.res_vo);
+    assert_eq!(res.res_vo.vo_t.0.len(), num_of_frags);
+    assert!(rest.verify(&chain).await.unwrap().0.is_ok());
+
+    let err =
+        historical_query_stream::<acc::Acc2Proof>(&query, &chain, |_| anyhow::bail!("closed"));
+    assert!(err.is_err());
+}
+
+#[actix_rt::test]
+async fn test_data2_query_cache() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = |end_block| {
+        serde_json::from_value::<Query>(json!({
+            "start_block": 1,
+            "end_block": end_block,
+            "range": [
+                [1],
+                [1],
+            ],
+            "bool": [["a"]],
+        }))
+        .unwrap()
+    };
+
+    let mut cache = QueryCache::new(2);
+    let res: OverallResult<acc::Acc2Proof> =
+        historical_query_cached(&query(10), &mut cache, &chain).unwrap();
+    assert_eq!(cache.len(), 1);
+    let cached = historical_query_cached(&query(10), &mut cache, &chain).unwrap();
+    assert_eq!(cached.res_vo, res.res_vo);
+    assert_eq!(cache.len(), 1);
+
+    let res = historical_query_cached(&query(20), &mut cache, &chain).unwrap();
+    assert_eq!(cache.len(), 1);
+    let expect: OverallResult<acc::Acc2Proof> = historical_query(&query(20), &chain).unwrap();
+    assert_eq!(res.res_objs, expect.res_objs);
+    assert_eq!(res.query, query(20));
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    // an earlier end block is not served from the later result
+    historical_query_cached(&query(5), &mut cache, &chain).unwrap();
+    assert_eq!(cache.len(), 2);
+    let mut other = query(20);
+    other.start_block = 2;
+    historical_query_cached(&other, &mut cache, &chain).unwrap();
+    assert_eq!(cache.len(), 2);
+}
+
+#[actix_rt::test]
+async fn test_data2_batch() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC1,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = |end_block, keyword| {
+        serde_json::from_value::<Query>(json!({
+            "start_block": 1,
+            "end_block": end_block,
+            "range": [
+                [1],
+                [1],
+            ],
+            "bool": [[keyword]],
+        }))
+        .unwrap()
+    };
+
+    let qs = vec![query(20, "a"), query(10, "b"), query(20, "a")];
+    let res: Vec<OverallResult<acc::Acc1Proof>> = historical_query_batch(&qs, &chain).unwrap();
+    assert_eq!(res.len(), qs.len());
+    for (q, r) in qs.iter().zip(res.iter()) {
+        let expect: OverallResult<acc::Acc1Proof> = historical_query(q, &chain).unwrap();
+        assert_eq!(r.query, *q);
+        assert_eq!(r.res_objs, expect.res_objs);
+        assert_eq!(r.res_vo, expect.res_vo);
+        assert!(r.verify(&chain).await.unwrap().0.is_ok());
+    }
+    assert!(historical_query_batch::<acc::Acc1Proof>(&[], &chain)
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_rt::test]
+async fn test_data2_compact_vo() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert!(res.vo_size_compact < res.vo_size);
+    assert!(res.vo_size < res.vo_size_json);
+    let data = res.to_compact_bytes().unwrap();
+    let decoded = OverallResult::<acc::Acc2Proof>::from_compact_bytes(&data).unwrap();
+    assert_eq!(decoded, res);
+    assert!(decoded.verify(&chain).await.unwrap().0.is_ok());
+
+    // the format versions
+    assert_eq!(res.format_version, FORMAT_VERSION);
+    let mut value = serde_json::to_value(&res).unwrap();
+    assert_eq!(value["format_version"], json!("1.0"));
+    value.as_object_mut().unwrap().remove("format_version");
+    let data = serde_json::to_vec(&value).unwrap();
+    let decoded: OverallResult<acc::Acc2Proof> = serde_json::from_slice(&data).unwrap();
+    assert_eq!(decoded.format_version, FormatVersion::unversioned());
+    assert_eq!(OverallResult::from_json(&data).unwrap(), res);
+    value["format_version"] = json!("2.0");
+    let data = serde_json::to_vec(&value).unwrap();
+    let err = OverallResult::<acc::Acc2Proof>::from_json(&data).unwrap_err();
+    assert!(err.to_string().contains("unsupported format version 2.0"));
+}
+
+#[actix_rt::test]
+async fn test_data2_verify_level() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC1,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let trusted = HeaderStore::with_headers(
+        chain.get_parameter().unwrap(),
+        (1..=20).map(|id| chain.read_block_header(id).unwrap()),
+    )
+    .unwrap();
+
+    let mut res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain).unwrap();
+    for level in [
+        VerifyLevel::Hash,
+        VerifyLevel::Sampled {
+            fraction: 0.5,
+            seed: 1,
+        },
+        VerifyLevel::Full,
+    ] {
+        assert!(res.verify_at(level, &trusted).await.unwrap().0.is_ok());
+    }
+
+    // the proofs are not checked by the hashes
+    let mut other_query = query.clone();
+    other_query.q_bool = Some(vec![vec!["b".to_owned()].into_iter().collect()]);
+    let other: OverallResult<acc::Acc1Proof> = historical_query(&other_query, &chain).unwrap();
+    let other_proof = other
+        .res_vo
+        .vo_acc
+        .proofs
+        .values()
+        .flatten()
+        .next()
+        .unwrap();
+    let proof = res
+        .res_vo
+        .vo_acc
+        .proofs
+        .values_mut()
+        .flatten()
+        .next()
+        .unwrap();
+    *proof = other_proof.clone();
+    assert!(res
+        .verify_at(VerifyLevel::Hash, &trusted)
+        .await
+        .unwrap()
+        .0
+        .is_ok());
+    let (full, _) = res.verify_at(VerifyLevel::Full, &trusted).await.unwrap();
+    assert!(!full.is_ok());
+    let all = VerifyLevel::Sampled {
+        fraction: 1.0,
+        seed: 1,
+    };
+    assert_eq!(res.verify_at(all, &trusted).await.unwrap().0, full);
+    let none = VerifyLevel::Sampled {
+        fraction: 0.0,
+        seed: 1,
+    };
+    assert!(res.verify_at(none, &trusted).await.unwrap().0.is_ok());
+
+    let untrusted = HeaderStore::new(chain.get_parameter().unwrap());
+    assert!(res.verify_at(VerifyLevel::Hash, &untrusted).await.is_err());
+}
+
+#[actix_rt::test]
+async fn test_data2_intervals() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = |intervals: serde_json::Value| {
+        serde_json::from_value::<Query>(json!({
+            "start_block": 1,
+            "end_block": 20,
+            "range": [
+                [1],
+                [1],
+            ],
+            "bool": [["a"]],
+            "intervals": intervals,
+        }))
+        .unwrap()
+    };
+
+    let q = query(json!([[1, 5], [8, 12], [15, 20]]));
+    let mut res: OverallResult<acc::Acc2Proof> = historical_query(&q, &chain).unwrap();
+    assert!(res.res_vo.vo_t.0.is_empty());
+    assert_eq!(res.res_vo.interval_trees.len(), 3);
+    let mut expect = ResultObjs::new();
+    for &(start_block, end_block) in q.intervals.as_ref().unwrap() {
+        let mut sub_q = q.clone();
+        sub_q.intervals = None;
+        sub_q.start_block = start_block;
+        sub_q.end_block = end_block;
+        let sub_res: OverallResult<acc::Acc2Proof> = historical_query(&sub_q, &chain).unwrap();
+        expect.0.extend(sub_res.res_objs.0);
+    }
+    assert_eq!(res.res_objs, expect);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    res.res_vo.interval_trees.pop();
+    let mut expect = VerifyResult::default();
+    expect.add(InvalidReason::InvalidHash);
+    assert_eq!(res.verify(&chain).await.unwrap().0, expect);
+
+    for intervals in &[
+        json!([]),
+        json!([[0, 5]]),
+        json!([[3, 5], [1, 2]]),
+        json!([[1, 5], [5, 6]]),
+        json!([[6, 5]]),
+        json!([[15, 21]]),
+    ] {
+        assert!(historical_query::<acc::Acc2Proof>(&query(intervals.clone()), &chain).is_err());
+    }
+}
+
+#[actix_rt::test]
+async fn test_data2_standing_query() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let mut manager = StandingQueryManager::<acc::Acc2Proof>::new();
+    let deltas = Arc::new(Mutex::new(Vec::new()));
+    let deltas2 = deltas.clone();
+    manager
+        .subscribe(query.clone(), move |res| {
+            deltas2.lock().unwrap().push(res);
+            Ok(())
+        })
+        .unwrap();
+    let dropped = manager
+        .subscribe(query.clone(), |_| anyhow::bail!("closed"))
+        .unwrap();
+    let mut time_window_q = query.clone();
+    time_window_q.end_time = Some(10);
+    assert!(manager.subscribe(time_window_q, |_| Ok(())).is_err());
+
+    let mut chain = MemChain::new();
+    chain.set_parameter(param).unwrap();
+    let mut notify =
+        |header: &BlockHeader, chain: &MemChain| manager.notify(header.block_id, chain);
+    let mut builder = ChainBuilder::new(&mut chain, None)
+        .unwrap()
+        .on_block(&mut notify);
+    for (id, objs) in load_raw_obj_from_str(TEST_DATA_2).unwrap() {
+        builder.append_block(id, 10, objs.iter()).unwrap();
+    }
+    assert_eq!(manager.len(), 1);
+    assert!(!manager.unsubscribe(dropped));
+
+    let deltas = std::mem::take(&mut *deltas.lock().unwrap());
+    assert_eq!(deltas.len(), 20);
+    let mut res_objs = ResultObjs::new();
+    for delta in deltas.iter() {
+        assert!(delta.verify(&chain).await.unwrap().0.is_ok());
+        res_objs.0.extend(delta.res_objs.0.clone());
+    }
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res_objs, res.res_objs);
+}
+
+#[actix_rt::test]
+async fn test_data1_parameter_update() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let new_param = Parameter {
+        intra_index_fanout: 3,
+        max_objs_per_block: 10,
+        ..param.clone()
+    };
+    param.check_update(&new_param).unwrap();
+    let shorter = Parameter {
+        v_bit_len: vec![2],
+        ..param.clone()
+    };
+    assert!(param.check_update(&shorter).is_err());
+    let fanout_1 = Parameter {
+        intra_index_fanout: 1,
+        ..param.clone()
+    };
+    assert!(param.check_update(&fanout_1).is_err());
+
+    // switch to the new parameter after the first block
+    let blocks = load_raw_obj_from_str(TEST_DATA_1).unwrap();
+    let mut chain = MemChain::new();
+    chain.set_parameter(param).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, None).unwrap();
+    builder.append_block(1, 10, blocks[&1].iter()).unwrap();
+    let tip = builder.tip().cloned();
+    chain.set_parameter(new_param).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, tip).unwrap();
+    builder.append_block(2, 20, blocks[&2].iter()).unwrap();
+
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "range": [[1], [2]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 4);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[test]
+fn test_id_counter() {
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain_a = MemChain::new();
+    chain_a.build_chain(TEST_DATA_1, &param).unwrap();
+    let mut chain_b = MemChain::new();
+    chain_b.build_chain(TEST_DATA_1, &param).unwrap();
+
+    let num_of_objs = chain_a.objects.len() as IdType;
+    assert_eq!(chain_a.id_counter.next_obj_id, num_of_objs);
+    assert!((0..num_of_objs).all(|id| chain_a.objects.contains_key(&id)));
+    let num_of_nodes = (chain_a.intra_index_nodes.len() + chain_a.skip_list_nodes.len()) as IdType;
+    assert_eq!(chain_a.id_counter.next_index_id, num_of_nodes);
+    // the ids do not depend on other chains built by the same process
+    assert_eq!(chain_a.id_counter, chain_b.id_counter);
+    assert_eq!(
+        chain_a.read_block_header(2).unwrap(),
+        chain_b.read_block_header(2).unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_data1_fanout() {
+    init_logger();
+    let mut chain = MemChain::new();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 3,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let root = match chain.read_block_data(1).unwrap().data {
+        IntraData::Index(id) => chain.read_intra_index_node(id).unwrap(),
+        _ => unreachable!(),
+    };
+    match root {
+        IntraIndexNode::NonLeaf(n) => assert_eq!(n.child_ids.len(), 2),
+        _ => unreachable!(),
+    }
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 2,
+        "range": [
+            [2],
+            [3],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.vo_stats.num_of_objs, 2);
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data2_explain() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC1,
+        use_sk: true,
+        intra_index: false,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain).unwrap();
+    let text = res.explain();
+    info!("{}", text);
+    assert!(text.starts_with("query: blks #1..=#20"));
+    assert!(text.contains(r#"{"a"}"#));
+    assert!(text.contains("v0 in [1, 1]"));
+    for id in res.res_objs.0.keys() {
+        assert!(text.contains(&format!("obj #{} matched", id)));
+    }
+    let num_pruned = text.matches("pruned by clause").count() as u64;
+    assert_eq!(
+        num_pruned,
+        res.vo_stats.num_of_mismatch_objs + res.vo_stats.num_of_mismatch_inter_nodes
+    );
+
+    let stats = &res.vo_stats;
+    assert_eq!(
+        stats.bytes_of_acc_proofs,
+        bincode::serialized_size(&res.res_vo.vo_acc).unwrap()
+    );
+    assert!(stats.bytes_of_digests >= 32 * stats.num_of_mismatch_objs);
+    assert_eq!(stats.bytes_of_objs, 0);
+    assert!(stats.bytes_of_skip_list_nodes > 0);
+    assert!(
+        stats.bytes_of_acc_proofs + stats.bytes_of_digests + stats.bytes_of_skip_list_nodes
+            < res.vo_size
+    );
+}
+
+#[actix_rt::test]
+async fn test_data2_hash_mismatch() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: false,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert!(res.verify(&chain).await.unwrap().0.is_ok());
+
+    // tamper an object not matched in block #2
+    let mut res2 = res.clone();
+    match &mut res2.res_vo.vo_t.0[1] {
+        vo::ResultVONode::FlatBlkNode(n) => match &mut n.sub_nodes[0] {
+            vo::ObjNode::NoMatch(n) => n.obj_hash = Digest::default(),
+            n => panic!("unexpected node {:?}", n),
+        },
+        n => panic!("unexpected node {:?}", n),
+    }
+    let (r, _) = res2.verify(&chain).await.unwrap();
+    let header = chain.read_block_header(2).unwrap();
+    let mut expect = VerifyResult::default();
+    expect.add(InvalidReason::HashMismatch(Box::new(HashMismatch {
+        block_id: 2,
+        path: "tree[1].data_root".to_owned(),
+        expected: Some(header.data_root),
+        computed: res2.res_vo.vo_t.0[1].compute_data_root(&res2.res_objs, &res2.res_vo.vo_acc),
+    })));
+    assert_eq!(r, expect);
+
+    // drop the last block
+    let mut res3 = res.clone();
+    res3.res_vo.vo_t.0.pop();
+    let (r, _) = res3.verify(&chain).await.unwrap();
+    let header = chain.read_block_header(20).unwrap();
+    let mut expect = VerifyResult::default();
+    expect.add(InvalidReason::HashMismatch(Box::new(HashMismatch {
+        block_id: 20,
+        path: "tree".to_owned(),
+        expected: Some(header.compute_digest("")),
+        computed: Some(chain.read_block_header(19).unwrap().compute_digest("")),
+    })));
+    assert_eq!(r, expect);
+}
+
+#[actix_rt::test]
+async fn test_data2_compact_stream() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    let mut data = Vec::new();
+    res.write_compact_stream(&mut data).unwrap();
+    let mut objs = ResultObjs::new();
+    let r =
+        verify_compact_stream::<acc::Acc2Proof>(&mut &data[..], VerifyLevel::Full, &chain, |obj| {
+            objs.insert(obj.clone())
+        })
+        .await
+        .unwrap();
+    assert!(r.is_ok());
+    assert_eq!(objs, res.res_objs);
+
+    // a truncated stream
+    let r = verify_compact_stream::<acc::Acc2Proof>(
+        &mut &data[..data.len() - 1],
+        VerifyLevel::Full,
+        &chain,
+        |_| {},
+    )
+    .await;
+    assert!(r.is_err());
+
+    // a tampered object
+    let mut res2 = res.clone();
+    let id = *res2.res_objs.keys().next().unwrap();
+    res2.res_objs.0.get_mut(&id).unwrap().v_data.push(0);
+    let mut data = Vec::new();
+    res2.write_compact_stream(&mut data).unwrap();
+    let r =
+        verify_compact_stream::<acc::Acc2Proof>(&mut &data[..], VerifyLevel::Full, &chain, |_| {})
+            .await
+            .unwrap();
+    assert!(!r.is_ok());
+    assert!(!res2.verify(&chain).await.unwrap().0.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data2_verify_audit() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC1,
+        use_sk: true,
+        intra_index: false,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+
+    let mut res: OverallResult<acc::Acc1Proof> = historical_query(&query, &chain).unwrap();
+    let num_of_proofs = res.vo_stats.num_of_acc_proofs as usize;
+    let num_of_objs = res.res_objs.len();
+    assert!(num_of_objs < num_of_proofs);
+    let audit = |k| VerifyLevel::Audit { k, nonce: 1 };
+    assert!(res.verify_at(audit(2), &chain).await.unwrap().0.is_ok());
+    assert_eq!(res.soundness_at(VerifyLevel::Full), 1.0);
+    assert_eq!(res.soundness_at(VerifyLevel::Hash), 0.0);
+    assert_eq!(res.soundness_at(audit(2)), 2.0 / num_of_proofs as f64);
+    assert_eq!(res.soundness_at(audit(num_of_proofs)), 1.0);
+
+    // tamper all the proofs by those of another query
+    let mut other_query = query.clone();
+    other_query.q_bool = Some(vec![vec!["b".to_owned()].into_iter().collect()]);
+    let other: OverallResult<acc::Acc1Proof> = historical_query(&other_query, &chain).unwrap();
+    let other_proof = other
+        .res_vo
+        .vo_acc
+        .proofs
+        .values()
+        .flatten()
+        .next()
+        .unwrap();
+    for proof in res.res_vo.vo_acc.proofs.values_mut().flatten() {
+        *proof = other_proof.clone();
+    }
+    let (full, _) = res.verify_at(VerifyLevel::Full, &chain).await.unwrap();
+    assert!(!full.is_ok());
+    let (all, _) = res.verify_at(audit(num_of_proofs), &chain).await.unwrap();
+    assert_eq!(all, full);
+    assert!(res.verify_at(audit(0), &chain).await.unwrap().0.is_ok());
+    let (two, _) = res.verify_at(audit(2), &chain).await.unwrap();
+    assert!(!two.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_data2_header_store() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: "chain-a".to_owned(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [
+            [1],
+            [1],
+        ],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+
+    let mut store = HeaderStore::new(chain.get_parameter().unwrap());
+    assert!(res.verify_with_headers(&store).is_err());
+    store.sync(1, 20, &chain).await.unwrap();
+    assert_eq!(store.len(), 20);
+    let mut checkpoint = Vec::new();
+    store.export_checkpoint(&mut checkpoint).unwrap();
+
+    let store = HeaderStore::import_checkpoint(&checkpoint[..]).unwrap();
+    assert_eq!(store.get(20), Some(&chain.read_block_header(20).unwrap()));
+    let (r, _) = res.verify_with_headers(&store).unwrap();
+    assert!(r.is_ok());
+    let checkpoint = String::from_utf8(checkpoint).unwrap();
+    let r = verify_json(&serde_json::to_string(&res).unwrap(), &checkpoint).unwrap();
+    assert!(r.is_ok());
+    let err = verify_json("[]", &checkpoint).unwrap_err();
+    assert_eq!(
+        Error::find(&err),
+        Some(&Error::Verification("invalid result".to_owned()))
+    );
+
+    // the headers not linked by their hashes
+    let mut header = chain.read_block_header(10).unwrap();
+    header.timestamp += 1;
+    let mut store2 = store.clone();
+    assert!(store2.insert(header).is_err());
+    let mut store2 = HeaderStore::new(chain.get_parameter().unwrap());
+    store2.insert(header).unwrap();
+    assert!(store2.insert(chain.read_block_header(11).unwrap()).is_err());
+    assert!(store2.insert(chain.read_block_header(9).unwrap()).is_ok());
+}
+
+#[test]
+fn test_mem_chain_snapshot() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::with_param(param);
+    let raw_objs = load_raw_obj_from_str(TEST_DATA_1).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, None).unwrap();
+    builder.append_block(1, 10, raw_objs[&1].iter()).unwrap();
+    let snapshot = chain.clone();
+
+    let tip = chain.read_block_header(1).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, Some(tip)).unwrap();
+    let header = builder.append_block(2, 20, raw_objs[&2].iter()).unwrap();
+
+    // roll back block 2 and append it again
+    let mut chain = snapshot;
+    assert!(chain.read_block_header(2).is_err());
+    let tip = chain.read_block_header(1).unwrap();
+    let mut builder = ChainBuilder::new(&mut chain, Some(tip)).unwrap();
+    builder.append_block(2, 20, raw_objs[&2].iter()).unwrap();
+    assert_eq!(chain.read_block_header(2).unwrap(), header);
+}
+
+#[actix_rt::test]
+async fn test_data2_prune() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let obj_id = chain.objects.values().find(|o| o.block_id == 5).unwrap().id;
+
+    // the next blocks are built from the data of up to 8 blocks before them
+    assert!(prune_before(15, &mut chain).is_err());
+    prune_before(10, &mut chain).unwrap();
+    assert_eq!(chain.read_pruned_before().unwrap(), 10);
+    assert!(chain.read_block_header(5).is_ok());
+    assert!(chain.read_block_data(5).is_err());
+    assert!(chain.read_object(obj_id).is_err());
+    assert!(chain.read_block_data(10).is_ok());
+    assert!(chain.intra_index_nodes.values().all(|n| n.block_id() >= 10));
+    assert!(chain.skip_list_nodes.values().all(|n| n.block_id >= 10));
+
+    let mut query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [[1], [1]],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let err = historical_query::<acc::Acc2Proof>(&query, &chain).unwrap_err();
+    assert!(err.to_string().contains("pruned"));
+    query.start_block = 10;
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert_eq!(res.res_objs.len(), 2);
+    let (r, _) = res.verify(&chain).await.unwrap();
+    assert!(r.is_ok());
+}
+
+#[test]
+fn test_data2_archive() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    prune_before(5, &mut chain).unwrap();
+    let mut archive = Vec::new();
+    let manifest = export_archive(&chain, &mut archive).unwrap();
+    assert_eq!(manifest.num_blocks, 20);
+    assert_eq!(manifest.num_objs, 16);
+    assert_eq!(verify_archive(&mut &archive[..]).unwrap(), manifest);
+
+    let mut chain2 = MemChain::new();
+    import_archive(&mut &archive[..], &mut chain2).unwrap();
+    assert_eq!(chain2.get_parameter().unwrap(), param);
+    assert_eq!(
+        chain2.read_id_counter().unwrap(),
+        chain.read_id_counter().unwrap()
+    );
+    assert_eq!(chain2.read_pruned_before().unwrap(), 5);
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 5,
+        "end_block": 20,
+        "range": [[1], [1]],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res1: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    let res2: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain2).unwrap();
+    assert_eq!(res1.res_objs, res2.res_objs);
+    assert_eq!(res1.res_vo, res2.res_vo);
+
+    let mut tampered = archive.clone();
+    let n = tampered.len();
+    tampered[n / 2] ^= 1;
+    assert!(verify_archive(&mut &tampered[..]).is_err());
+    assert!(verify_archive(&mut &archive[..n - 1]).is_err());
+}
+
+// a chain with only the required methods of ReadInterface
+struct PointReads<'a>(&'a MemChain);
+
+impl ReadInterface for PointReads<'_> {
+    fn get_parameter(&self) -> Result<Parameter> {
+        self.0.get_parameter()
+    }
+    fn read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.0.read_block_header(id)
+    }
+    fn read_block_data(&self, id: IdType) -> Result<BlockData> {
+        self.0.read_block_data(id)
+    }
+    fn read_intra_index_node(&self, id: IdType) -> Result<IntraIndexNode> {
+        self.0.read_intra_index_node(id)
+    }
+    fn read_skip_list_node(&self, id: IdType) -> Result<SkipListNode> {
+        self.0.read_skip_list_node(id)
+    }
+    fn read_btree_node(&self, id: IdType) -> Result<BTreeNode> {
+        self.0.read_btree_node(id)
+    }
+    fn read_object(&self, id: IdType) -> Result<Object> {
+        self.0.read_object(id)
+    }
+    fn read_keyword(&self, sym: SymbolType) -> Result<Option<String>> {
+        self.0.read_keyword(sym)
+    }
+    fn read_keywords(&self) -> Result<Vec<(SymbolType, String)>> {
+        self.0.read_keywords()
+    }
+    fn read_id_counter(&self) -> Result<IdCounter> {
+        self.0.read_id_counter()
+    }
+}
+
+#[test]
+fn test_data2_iter_blocks() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 0,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    assert_eq!(chain.latest_block_id().unwrap(), None);
+    assert_eq!(PointReads(&chain).latest_block_id().unwrap(), None);
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    assert_eq!(chain.latest_block_id().unwrap(), Some(20));
+    for n in 1..=20 {
+        let mut chain = chain.clone();
+        chain.block_headers.retain(|&id, _| id <= n);
+        assert_eq!(PointReads(&chain).latest_block_id().unwrap(), Some(n));
+    }
+
+    let headers = chain
+        .iter_block_headers(3..=5)
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        headers.iter().map(|h| h.block_id).collect::<Vec<_>>(),
+        vec![3, 4, 5]
+    );
+    assert!(chain
+        .iter_block_headers(19..=21)
+        .collect::<Result<Vec<_>>>()
+        .is_err());
+    let objs = chain
+        .iter_objects_in_block(5)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(objs.len(), 1);
+    assert_eq!(objs[0].block_id, 5);
+    assert!(chain.iter_objects_in_block(21).is_err());
+}
+
+#[test]
+fn test_iter_objects_in_insertion_order() {
+    init_logger();
+    // the leaves of the intra index are grouped by their sets, out of this order
+    let data = r#"
+1 [ 1 ] { a }
+1 [ 2 ] { b }
+1 [ 3 ] { a }
+1 [ 4 ] { c }
+1 [ 5 ] { b }
+1 [ 6 ] { a }
+"#;
+    for &intra_index in &[true, false] {
+        let param = Parameter {
+            v_bit_len: vec![3],
+            acc_type: acc::Type::ACC2,
+            use_sk: true,
+            intra_index,
+            skip_list_max_level: 0,
+            chain_id: String::new(),
+            max_prefix_len: 0,
+            intra_index_fanout: 2,
+            btree_max_level: 0,
+            max_objs_per_block: 0,
+        };
+        let mut chain = MemChain::new();
+        chain.build_chain(data, &param).unwrap();
+        let raw_objs = &load_raw_obj_from_str(data).unwrap()[&1];
+        let objs = chain
+            .iter_objects_in_block(1)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            objs.iter()
+                .map(|o| (o.v_data.clone(), o.w_data.clone()))
+                .collect::<Vec<_>>(),
+            raw_objs
+                .iter()
+                .map(|o| (o.v_data.clone(), o.w_data.clone()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn test_data2_cached_chain() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [[1], [1]],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res1: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+
+    let cached = CachedChain::new(chain.clone(), CacheCapacity::uniform(1000));
+    let res2: OverallResult<acc::Acc2Proof> = historical_query(&query, &cached).unwrap();
+    assert_eq!(res1.res_objs, res2.res_objs);
+    assert_eq!(res1.res_vo, res2.res_vo);
+    let misses = cached.cache().misses();
+    assert!(misses > 0);
+    let res3: OverallResult<acc::Acc2Proof> = historical_query(&query, &cached).unwrap();
+    assert_eq!(res1.res_vo, res3.res_vo);
+    assert_eq!(cached.cache().misses(), misses);
+    assert!(cached.cache().hits() >= misses);
+
+    // nothing is kept without capacity
+    let uncached = CachedChain::new(chain, CacheCapacity::uniform(0));
+    let _: OverallResult<acc::Acc2Proof> = historical_query(&query, &uncached).unwrap();
+    let _: OverallResult<acc::Acc2Proof> = historical_query(&query, &uncached).unwrap();
+    assert_eq!(uncached.cache().hits(), 0);
+}
+
+// counts the reads of the headers by a light node
+struct CountingNode<'a> {
+    chain: &'a MemChain,
+    single_reads: AtomicUsize,
+    batched_reads: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl LightNodeInterface for CountingNode<'_> {
+    async fn lightnode_get_parameter(&self) -> Result<Parameter> {
+        self.chain.get_parameter()
+    }
+    async fn lightnode_read_block_header(&self, id: IdType) -> Result<BlockHeader> {
+        self.single_reads.fetch_add(1, Ordering::SeqCst);
+        self.chain.read_block_header(id)
+    }
+    async fn lightnode_read_block_headers(&self, ids: &[IdType]) -> Result<Vec<BlockHeader>> {
+        self.batched_reads.fetch_add(1, Ordering::SeqCst);
+        ids.iter()
+            .map(|&id| self.chain.read_block_header(id))
+            .collect()
+    }
+}
+
+#[actix_rt::test]
+async fn test_data2_batched_headers() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    assert_eq!(
+        chain
+            .lightnode_read_block_headers(&[3, 1, 3])
+            .await
+            .unwrap()
+            .iter()
+            .map(|h| h.block_id)
+            .collect::<Vec<_>>(),
+        vec![3, 1, 3]
+    );
+    assert!(chain.lightnode_read_block_headers(&[1, 21]).await.is_err());
+
+    let node = CountingNode {
+        chain: &chain,
+        single_reads: AtomicUsize::new(0),
+        batched_reads: AtomicUsize::new(0),
+    };
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 2,
+        "end_block": 20,
+        "range": [[1], [1]],
+        "bool": [["a"]],
+        "intervals": [[2, 5], [8, 20]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query(&query, &chain).unwrap();
+    assert!(res.verify(&node).await.unwrap().0.is_ok());
+    assert_eq!(node.single_reads.load(Ordering::SeqCst), 0);
+    assert_eq!(node.batched_reads.load(Ordering::SeqCst), 1);
+
+    // the headers of the nodes of a tampered tree are read in one batch too
+    let mut tampered = res.clone();
+    tampered.res_vo.interval_trees[1].0.pop();
+    assert!(!tampered.verify(&node).await.unwrap().0.is_ok());
+    assert_eq!(node.single_reads.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_inspect_block() {
+    init_logger();
+    let mut param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let stats = inspect_block(&chain, 1).unwrap();
+    let objs: Vec<Object> = chain
+        .iter_objects_in_block(1)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(stats.num_objs, 4);
+    let elements: std::collections::HashSet<_> =
+        objs.iter().flat_map(|o| o.set_data.keys()).collect();
+    assert_eq!(stats.set_data_len, elements.len());
+    // 4 leaves under 2 non-leaves and the root
+    assert_eq!(stats.intra_index_height, 3);
+    assert_eq!(stats.intra_index_nodes, 7);
+    assert_eq!(stats.skip_list_nodes, 0);
+
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let stats = inspect_block(&chain, 20).unwrap();
+    assert_eq!(stats.skip_list_nodes, 2);
+    assert_eq!(stats.skip_list_coverage, skipped_blocks_num(1));
+
+    param.intra_index = false;
+    param.skip_list_max_level = 0;
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_1, &param).unwrap();
+    let stats = inspect_block(&chain, 2).unwrap();
+    assert_eq!(stats.num_objs, 4);
+    assert_eq!((stats.intra_index_height, stats.intra_index_nodes), (0, 0));
+    assert_eq!((stats.skip_list_nodes, stats.skip_list_coverage), (0, 0));
+}
+
+#[test]
+fn test_data2_export_jsonl() {
+    init_logger();
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    prune_before(5, &mut chain).unwrap();
+    let mut out = Vec::new();
+    export_jsonl(&chain, &mut out).unwrap();
+    let lines: Vec<serde_json::Value> = std::str::from_utf8(&out)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let count = |ty: &str| lines.iter().filter(|l| l["type"] == ty).count();
+    assert_eq!(lines[0]["type"], "param");
+    assert_eq!(lines[0]["v_bit_len"], json!([3]));
+    assert_eq!(count("header"), 20);
+    assert_eq!(count("block"), 16);
+    assert_eq!(count("object"), 16);
+    let obj = lines.iter().find(|l| l["type"] == "object").unwrap();
+    let id = obj["id"].as_u64().unwrap() as IdType;
+    assert_eq!(obj["block_id"], 5);
+    let expect = chain.read_object(id).unwrap();
+    assert_eq!(obj["v_data"], json!(expect.v_data));
+    let mut w_data: Vec<_> = expect.w_data.iter().collect();
+    w_data.sort_unstable();
+    assert_eq!(obj["w_data"], json!(w_data));
+    let blk = lines.iter().find(|l| l["type"] == "block").unwrap();
+    assert_eq!(blk["obj_ids"], json!([id]));
+
+    // the same chain gives the same lines
+    let mut out2 = Vec::new();
+    export_jsonl(&chain, &mut out2).unwrap();
+    assert_eq!(out, out2);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_thread_pools() {
+    init_logger();
+    // the pools are shared by all the tests, so they are as large as the
+    // global pool
+    let n = rayon::current_num_threads();
+    init_thread_pools(n, n).unwrap();
+    assert!(init_thread_pools(n, 0).is_err());
+    assert_eq!(build_pool().unwrap().current_num_threads(), n);
+    assert!(in_build_pool(|| rayon::current_thread_index().is_some()));
+    assert!(in_query_pool(|| rayon::current_thread_index().is_some()));
+    assert!(in_query_pool(|| {
+        std::thread::current()
+            .name()
+            .is_some_and(|name| name.starts_with("query-"))
+    }));
+
+    let param = Parameter {
+        v_bit_len: vec![3],
+        acc_type: acc::Type::ACC2,
+        use_sk: true,
+        intra_index: true,
+        skip_list_max_level: 2,
+        chain_id: String::new(),
+        max_prefix_len: 0,
+        intra_index_fanout: 2,
+        btree_max_level: 0,
+        max_objs_per_block: 0,
+    };
+    let mut chain = MemChain::new();
+    chain.build_chain(TEST_DATA_2, &param).unwrap();
+    let query = serde_json::from_value::<Query>(json!({
+        "start_block": 1,
+        "end_block": 20,
+        "range": [[1], [1]],
+        "bool": [["a"]],
+    }))
+    .unwrap();
+    let res: OverallResult<acc::Acc2Proof> = historical_query_par(&query, 3, &chain).unwrap();
+    let (r, _) = futures::executor::block_on(res.verify(&chain)).unwrap();
+    assert!(r.is_ok());
+}
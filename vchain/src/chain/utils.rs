@@ -1,7 +1,7 @@
 use super::{IdType, Parameter, RawObject, SetElementType, SkipLstLvlType};
 use crate::acc::{self, Accumulator, G1Affine, G2Affine};
 use crate::set::MultiSet;
-use anyhow::{Context, Error, Result};
+use anyhow::{ensure, Context, Error, Result};
 use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
@@ -15,6 +15,8 @@ pub fn multiset_to_g1(set: &MultiSet<SetElementType>, param: &Parameter) -> G1Af
         (acc::Type::ACC1, false) => acc::Acc1::cal_acc_g1(&set),
         (acc::Type::ACC2, true) => acc::Acc2::cal_acc_g1_sk(&set),
         (acc::Type::ACC2, false) => acc::Acc2::cal_acc_g1(&set),
+        (acc::Type::ACC3, true) => acc::Acc3::cal_acc_g1_sk(&set),
+        (acc::Type::ACC3, false) => acc::Acc3::cal_acc_g1(&set),
     }
 }
 
@@ -25,6 +27,8 @@ pub fn multiset_to_g2(set: &MultiSet<SetElementType>, param: &Parameter) -> G2Af
         (acc::Type::ACC1, false) => acc::Acc1::cal_acc_g2(&set),
         (acc::Type::ACC2, true) => acc::Acc2::cal_acc_g2_sk(&set),
         (acc::Type::ACC2, false) => acc::Acc2::cal_acc_g2(&set),
+        (acc::Type::ACC3, true) => acc::Acc3::cal_acc_g2_sk(&set),
+        (acc::Type::ACC3, false) => acc::Acc3::cal_acc_g2(&set),
     }
 }
 
@@ -33,6 +37,14 @@ pub fn skipped_blocks_num(level: SkipLstLvlType) -> IdType {
     1 << (level + 2)
 }
 
+pub const BTREE_FANOUT: IdType = 4;
+
+// number of blocks covered by a B-tree node of the level, 1 for the blocks
+#[inline]
+pub fn btree_covered_blocks(level: SkipLstLvlType) -> IdType {
+    BTREE_FANOUT.pow(level as u32)
+}
+
 // input format: block_id sep [ v_data ] sep { w_data }
 // sep = \t or space
 // v_data = v_1 comma v_2 ...
@@ -56,14 +68,14 @@ pub fn load_raw_obj_from_str(input: &str) -> Result<BTreeMap<IdType, Vec<RawObje
             .context(format!("failed to parse line {}", line))?
             .trim()
             .parse()?;
-        let v_data: Vec<u32> = split_str
+        let v_data: Vec<u64> = split_str
             .next()
             .context(format!("failed to parse line {}", line))?
             .trim()
             .split(',')
             .map(|s| s.trim())
             .filter(|s| !s.is_empty())
-            .map(|s| s.parse::<u32>().map_err(Error::from))
+            .map(|s| s.parse::<u64>().map_err(Error::from))
             .collect::<Result<_>>()?;
         let w_data: HashSet<String> = split_str
             .next()
@@ -86,10 +98,40 @@ pub fn load_raw_obj_from_str(input: &str) -> Result<BTreeMap<IdType, Vec<RawObje
     Ok(res)
 }
 
+// The most ids taken by the batched reads of the servers.
+pub const MAX_BATCH_IDS: usize = 1000;
+
+// Parse the ids of a batched read, e.g. `1,2,3`.
+pub fn parse_id_list(input: &str) -> Result<Vec<IdType>> {
+    let ids = input
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<IdType>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| crate::Error::Parameter(format!("failed to parse ids {}: {}", input, e)))?;
+    ensure!(
+        ids.len() <= MAX_BATCH_IDS,
+        crate::Error::Parameter(format!("at most {} ids can be read at once", MAX_BATCH_IDS))
+    );
+    Ok(ids)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_id_list() {
+        assert_eq!(parse_id_list("1, 2,3,").unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_id_list("").unwrap(), Vec::<IdType>::new());
+        assert!(parse_id_list("1,a").is_err());
+        let many = (0..=MAX_BATCH_IDS)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        assert!(parse_id_list(&many.join(",")).is_err());
+    }
+
     #[test]
     fn test_load_raw_obj() {
         let input = "1\t[1,2]\t{a,b}\n2 [ 3, 4 ] { c, d, }\n2\t[ 5, 6 ]\t { e }\n";
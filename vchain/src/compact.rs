@@ -0,0 +1,293 @@
+// A compact binary encoding of the query results. The values are encoded in
+// packed CBOR, where the structs are arrays and the integers take as few bytes
+// as they need. Each distinct digest, curve point and keyword is stored only
+// once in a table, and referred to by its index in the table elsewhere.
+
+use crate::chain::Keyword;
+use crate::digest::{Digest, DIGEST_LEN};
+use crate::Error;
+use anyhow::{ensure, Result};
+use core::fmt;
+use serde::{
+    de::{DeserializeOwned, Deserializer, Visitor},
+    ser::Serializer,
+    Deserialize, Serialize,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+#[derive(Default)]
+struct Tables {
+    digests: Vec<Digest>,
+    // the canonical encodings of the curve points and the field elements
+    points: Vec<Vec<u8>>,
+    keywords: Vec<Keyword>,
+}
+
+enum State {
+    Encoding {
+        tables: Tables,
+        digest_idx: HashMap<Digest, u32>,
+        point_idx: HashMap<Vec<u8>, u32>,
+        keyword_idx: HashMap<Keyword, u32>,
+    },
+    Decoding(Tables),
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+// Clears the state of the current thread, even if the encoding or the decoding
+// fails.
+struct StateGuard;
+
+impl StateGuard {
+    fn begin(state: State) -> Result<Self> {
+        STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            ensure!(s.is_none(), "nested compact encoding");
+            *s = Some(state);
+            Ok(Self)
+        })
+    }
+
+    fn take(self) -> Option<State> {
+        STATE.with(|s| s.borrow_mut().take())
+    }
+}
+
+impl Drop for StateGuard {
+    fn drop(&mut self) {
+        STATE.with(|s| s.borrow_mut().take());
+    }
+}
+
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Bytes;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("bytes")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Bytes, E> {
+                Ok(Bytes(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Bytes, E> {
+                Ok(Bytes(v))
+            }
+        }
+
+        d.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Encoded {
+    // the digests one after another
+    digests: Bytes,
+    points: Vec<Bytes>,
+    // the words of the keywords
+    keywords: Vec<String>,
+    body: Bytes,
+}
+
+// The enums are encoded as arrays of the variant indices and the values, since
+// the decoder cannot tell the struct variants from the unit ones otherwise.
+fn to_packed_cbor<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut ser = serde_cbor::Serializer::new(serde_cbor::ser::IoWrite::new(&mut buf))
+        .packed_format()
+        .legacy_enums();
+    value.serialize(&mut ser)?;
+    Ok(buf)
+}
+
+pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let guard = StateGuard::begin(State::Encoding {
+        tables: Tables::default(),
+        digest_idx: HashMap::new(),
+        point_idx: HashMap::new(),
+        keyword_idx: HashMap::new(),
+    })?;
+    let body = to_packed_cbor(value)?;
+    let tables = match guard.take() {
+        Some(State::Encoding { tables, .. }) => tables,
+        _ => unreachable!(),
+    };
+    let encoded = Encoded {
+        digests: Bytes(tables.digests.iter().flat_map(|d| d.0).collect()),
+        points: tables.points.into_iter().map(Bytes).collect(),
+        keywords: tables
+            .keywords
+            .iter()
+            .map(|k| k.word().to_owned())
+            .collect(),
+        body: Bytes(body),
+    };
+    to_packed_cbor(&encoded)
+}
+
+pub fn from_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    let encoded: Encoded = serde_cbor::from_slice(data)?;
+    ensure!(
+        encoded.digests.0.len().is_multiple_of(DIGEST_LEN),
+        Error::Verification("invalid digest table".to_owned())
+    );
+    let tables = Tables {
+        digests: encoded
+            .digests
+            .0
+            .chunks(DIGEST_LEN)
+            .map(|c| {
+                let mut d = Digest::default();
+                d.0.copy_from_slice(c);
+                d
+            })
+            .collect(),
+        points: encoded.points.into_iter().map(|b| b.0).collect(),
+        keywords: encoded.keywords.iter().map(|w| Keyword::new(w)).collect(),
+    };
+    let _guard = StateGuard::begin(State::Decoding(tables))?;
+    serde_cbor::from_slice(&encoded.body.0)
+        .map_err(|e| Error::Verification(format!("invalid compact encoding: {}", e)).into())
+}
+
+// The index of the digest in the table of the encoding in progress on this
+// thread, if any.
+pub(crate) fn intern_digest(d: &Digest) -> Option<u32> {
+    STATE.with(|s| match &mut *s.borrow_mut() {
+        Some(State::Encoding {
+            tables, digest_idx, ..
+        }) => Some(*digest_idx.entry(*d).or_insert_with(|| {
+            tables.digests.push(*d);
+            (tables.digests.len() - 1) as u32
+        })),
+        _ => None,
+    })
+}
+
+// Like intern_digest, for the canonical encoding of a curve point.
+pub(crate) fn intern_point(buf: &[u8]) -> Option<u32> {
+    STATE.with(|s| match &mut *s.borrow_mut() {
+        Some(State::Encoding {
+            tables, point_idx, ..
+        }) => {
+            if let Some(&i) = point_idx.get(buf) {
+                return Some(i);
+            }
+            tables.points.push(buf.to_vec());
+            let i = (tables.points.len() - 1) as u32;
+            point_idx.insert(buf.to_vec(), i);
+            Some(i)
+        }
+        _ => None,
+    })
+}
+
+// Like intern_digest, for a keyword.
+pub(crate) fn intern_keyword(k: &Keyword) -> Option<u32> {
+    STATE.with(|s| match &mut *s.borrow_mut() {
+        Some(State::Encoding {
+            tables,
+            keyword_idx,
+            ..
+        }) => {
+            if let Some(&i) = keyword_idx.get(k) {
+                return Some(i);
+            }
+            tables.keywords.push(k.clone());
+            let i = (tables.keywords.len() - 1) as u32;
+            keyword_idx.insert(k.clone(), i);
+            Some(i)
+        }
+        _ => None,
+    })
+}
+
+pub(crate) fn is_decoding() -> bool {
+    STATE.with(|s| matches!(&*s.borrow(), Some(State::Decoding(_))))
+}
+
+pub(crate) fn digest_at(i: u32) -> Option<Digest> {
+    STATE.with(|s| match &*s.borrow() {
+        Some(State::Decoding(tables)) => tables.digests.get(i as usize).copied(),
+        _ => None,
+    })
+}
+
+pub(crate) fn point_at(i: u32) -> Option<Vec<u8>> {
+    STATE.with(|s| match &*s.borrow() {
+        Some(State::Decoding(tables)) => tables.points.get(i as usize).cloned(),
+        _ => None,
+    })
+}
+
+pub(crate) fn keyword_at(i: u32) -> Option<Keyword> {
+    STATE.with(|s| match &*s.borrow() {
+        Some(State::Decoding(tables)) => tables.keywords.get(i as usize).cloned(),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Digestible;
+
+    #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+    struct Foo {
+        id: u64,
+        digests: Vec<Digest>,
+    }
+
+    #[test]
+    fn test_compact_interning() {
+        let d1 = 1u32.to_digest();
+        let d2 = 2u32.to_digest();
+        let foo = Foo {
+            id: 1,
+            digests: vec![d1, d2, d1, d1, d2],
+        };
+        let data = to_bytes(&foo).unwrap();
+        assert!(data.len() < 3 * DIGEST_LEN);
+        assert_eq!(from_bytes::<Foo>(&data).unwrap(), foo);
+        assert!(from_bytes::<Foo>(&data[..data.len() - 1]).is_err());
+        // the state is cleared after a failure
+        assert!(!is_decoding());
+        assert_eq!(from_bytes::<Foo>(&data).unwrap(), foo);
+    }
+
+    #[test]
+    fn test_compact_keywords() {
+        let k1 = Keyword::new("test_compact_keyword_1");
+        let k2 = Keyword::new("test_compact_keyword_2");
+        let keywords = vec![k1.clone(), k2.clone(), k1.clone(), k1];
+        let data = to_bytes(&keywords).unwrap();
+        let count = |word: &str| {
+            data.windows(word.len())
+                .filter(|w| *w == word.as_bytes())
+                .count()
+        };
+        assert_eq!(count(k2.word()), 1);
+        assert_eq!(count("test_compact_keyword_1"), 1);
+        // the words are in the encoding, so it decodes in any process
+        let decoded = std::thread::spawn(move || from_bytes::<Vec<Keyword>>(&data).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(decoded, keywords);
+    }
+}
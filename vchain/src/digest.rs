@@ -5,6 +5,8 @@ use serde::{
     Deserialize, Serialize,
 };
 
+pub use vchain_derive::Digestible;
+
 pub const DIGEST_LEN: usize = 32;
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Default)]
@@ -31,6 +33,8 @@ impl Serialize for Digest {
     {
         if serializer.is_human_readable() {
             serializer.serialize_str(&hex::encode(&self.0))
+        } else if let Some(idx) = crate::compact::intern_digest(self) {
+            serializer.serialize_u32(idx)
         } else {
             let mut state = serializer.serialize_tuple_struct("Digest", 1)?;
             state.serialize_field(&self.0)?;
@@ -89,6 +93,9 @@ impl<'de> Deserialize<'de> for Digest {
 
         if deserializer.is_human_readable() {
             deserializer.deserialize_str(HexVisitor)
+        } else if crate::compact::is_decoding() {
+            let idx = u32::deserialize(deserializer)?;
+            crate::compact::digest_at(idx).ok_or_else(|| DeError::custom("invalid digest index"))
         } else {
             deserializer.deserialize_tuple_struct("Digest", 1, BytesVisitor)
         }
@@ -111,6 +118,15 @@ pub fn blake2() -> blake2b_simd::Params {
     params
 }
 
+pub const MAX_KEY_LEN: usize = blake2b_simd::KEYBYTES;
+
+// blake2 in keyed mode, an empty key is the same as blake2().
+pub fn keyed_blake2(key: &[u8]) -> blake2b_simd::Params {
+    let mut params = blake2();
+    params.key(key);
+    params
+}
+
 pub trait Digestible {
     fn to_digest(&self) -> Digest;
 }
@@ -148,6 +164,48 @@ impl_digestable_for_numeric!(i8, i16, i32, i64, i128);
 impl_digestable_for_numeric!(u8, u16, u32, u64, u128);
 impl_digestable_for_numeric!(f32, f64);
 
+impl<T: Digestible + ?Sized> Digestible for &T {
+    fn to_digest(&self) -> Digest {
+        (**self).to_digest()
+    }
+}
+
+// The digests of the elements are concatenated, unlike [u8] which hashes the
+// bytes directly.
+impl<T: Digestible> Digestible for Vec<T> {
+    fn to_digest(&self) -> Digest {
+        concat_digest(self.iter().map(|x| x.to_digest()))
+    }
+}
+
+impl<T: Digestible> Digestible for Option<T> {
+    fn to_digest(&self) -> Digest {
+        match self {
+            Some(x) => concat_digest([1u8.to_digest(), x.to_digest()].iter().copied()),
+            None => concat_digest([0u8.to_digest()].iter().copied()),
+        }
+    }
+}
+
+macro_rules! impl_digestable_for_tuple {
+    ($($name: ident)+) => {
+        impl<$($name: Digestible),+> Digestible for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn to_digest(&self) -> Digest {
+                let ($($name,)+) = self;
+                concat_digest([$($name.to_digest()),+].iter().copied())
+            }
+        }
+    };
+}
+
+impl_digestable_for_tuple!(A);
+impl_digestable_for_tuple!(A B);
+impl_digestable_for_tuple!(A B C);
+impl_digestable_for_tuple!(A B C D);
+impl_digestable_for_tuple!(A B C D E);
+impl_digestable_for_tuple!(A B C D E F);
+
 pub fn concat_digest_ref<'a>(input: impl Iterator<Item = &'a Digest>) -> Digest {
     let mut state = blake2().to_state();
     for d in input {
@@ -189,6 +247,70 @@ mod tests {
         assert_eq!(concat_digest(input.into_iter()), expect);
     }
 
+    #[test]
+    fn test_collection_digest() {
+        let a = "a".to_digest();
+        let b = "b".to_digest();
+        let v = vec!["a".to_owned(), "b".to_owned()];
+        assert_eq!(v.to_digest(), concat_digest_ref([a, b].iter()));
+        assert_eq!(("a", "b").to_digest(), v.to_digest());
+        assert_eq!(
+            ("a", 1u32).to_digest(),
+            concat_digest_ref([a, 1u32.to_digest()].iter())
+        );
+        assert_ne!(Some("a").to_digest(), None::<&str>.to_digest());
+        assert_ne!(Some(0u8).to_digest(), None::<u8>.to_digest());
+    }
+
+    #[derive(Digestible)]
+    struct Payload {
+        name: String,
+        tags: Vec<u32>,
+    }
+
+    #[derive(Digestible)]
+    struct Wrapper<T>(T, u64);
+
+    #[derive(Digestible)]
+    enum Kind {
+        A,
+        B(u32),
+        C { x: u32, y: Option<String> },
+    }
+
+    #[test]
+    fn test_derive_digestible() {
+        let p = Payload {
+            name: "a".to_owned(),
+            tags: vec![1, 2],
+        };
+        assert_eq!(
+            p.to_digest(),
+            concat_digest_ref([p.name.to_digest(), p.tags.to_digest()].iter())
+        );
+        let w = Wrapper(p, 3);
+        assert_eq!(
+            w.to_digest(),
+            concat_digest_ref([w.0.to_digest(), 3u64.to_digest()].iter())
+        );
+        assert_eq!(
+            Kind::A.to_digest(),
+            concat_digest_ref([0u32.to_digest()].iter())
+        );
+        assert_eq!(
+            Kind::B(1).to_digest(),
+            concat_digest_ref([1u32.to_digest(), 1u32.to_digest()].iter())
+        );
+        assert_ne!(
+            Kind::C { x: 1, y: None }.to_digest(),
+            Kind::C {
+                x: 1,
+                y: Some("a".to_owned())
+            }
+            .to_digest()
+        );
+    }
+
     #[test]
     fn test_serde() {
         let digest = "hello".to_digest();
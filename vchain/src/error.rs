@@ -0,0 +1,61 @@
+// The kinds of the failures, for the callers to tell them apart, e.g. for the
+// status codes of the servers. The functions still return anyhow::Result, with
+// the Error raised inside and any context added on top, see Error::find.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    // the data asked for is not in the chain
+    #[error("{0}")]
+    NotFound(String),
+    // the data of the chain cannot be read, e.g. it is corrupt
+    #[error("{0}")]
+    Storage(String),
+    // an invalid parameter of the chain, or an invalid query or request
+    #[error("{0}")]
+    Parameter(String),
+    // the result cannot be proved, e.g. over the pruned blocks
+    #[error("{0}")]
+    Proof(String),
+    // the result cannot be verified, e.g. it is malformed or its blocks are
+    // not trusted, unlike a result failing the verification, see VerifyResult
+    #[error("{0}")]
+    Verification(String),
+}
+
+impl Error {
+    // The first Error in the chain of the causes of `e`, if any.
+    pub fn find(e: &anyhow::Error) -> Option<&Self> {
+        e.chain().find_map(|e| e.downcast_ref::<Self>())
+    }
+
+    // The HTTP status code of the responses failed with the error.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::NotFound(_) => 404,
+            Self::Storage(_) => 500,
+            Self::Parameter(_) => 400,
+            Self::Proof(_) | Self::Verification(_) => 422,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, Result};
+
+    fn read() -> Result<()> {
+        Err(Error::NotFound("no blk #1".to_owned()).into())
+    }
+
+    #[test]
+    fn test_find() {
+        let err = read().context("failed to query").unwrap_err();
+        assert_eq!(
+            Error::find(&err),
+            Some(&Error::NotFound("no blk #1".to_owned()))
+        );
+        assert_eq!(Error::find(&err).unwrap().status_code(), 404);
+        assert_eq!(err.root_cause().to_string(), "no blk #1");
+        assert_eq!(Error::find(&anyhow::anyhow!("no blk #1")), None);
+    }
+}
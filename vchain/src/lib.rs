@@ -1,8 +1,26 @@
+// allow #[derive(Digestible)] to be used inside this crate
+extern crate self as vchain;
+
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
+// The verification of the results needs no threads, no clock and no files, so
+// that it builds for the browsers with `default-features = false`, i.e.
+// `cargo build -p vchain --no-default-features --target wasm32-unknown-unknown`.
+// There rand falls back to a getrandom that always fails, which is fine since
+// the verifiers draw no randomness.
+#[cfg(all(
+    target_arch = "wasm32",
+    target_os = "unknown",
+    any(feature = "parallel", feature = "storage")
+))]
+compile_error!("vchain builds for wasm32-unknown-unknown only with default-features = false");
+
+mod par;
+mod timer;
+
 pub mod digest;
 pub use digest::*;
 
@@ -14,3 +32,11 @@ pub use acc::*;
 
 pub mod chain;
 pub use chain::*;
+
+pub mod compact;
+
+pub mod error;
+pub use error::Error;
+
+#[cfg(feature = "storage")]
+pub mod openapi;
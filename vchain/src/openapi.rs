@@ -0,0 +1,273 @@
+use serde_json::{json, Map, Value};
+
+// A route of the HTTP API of a server, listed next to where it is registered
+// so that the OpenAPI document describes what the server serves.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiRoute {
+    // `get` or `post`
+    pub method: &'static str,
+    // in the syntax of actix, with `{id}` for the ids of the blocks, the nodes
+    // or the objects
+    pub path: &'static str,
+    pub summary: &'static str,
+    pub query_params: &'static [ApiParam],
+    // the names of the schemas, see schemas
+    pub request: Option<&'static str>,
+    pub response: &'static str,
+}
+
+impl ApiRoute {
+    pub const fn get(path: &'static str, summary: &'static str, response: &'static str) -> Self {
+        Self {
+            method: "get",
+            path,
+            summary,
+            query_params: &[],
+            request: None,
+            response,
+        }
+    }
+
+    pub const fn post(
+        path: &'static str,
+        summary: &'static str,
+        request: &'static str,
+        response: &'static str,
+    ) -> Self {
+        Self {
+            method: "post",
+            path,
+            summary,
+            query_params: &[],
+            request: Some(request),
+            response,
+        }
+    }
+
+    pub const fn with_query_params(self, query_params: &'static [ApiParam]) -> Self {
+        Self {
+            query_params,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ApiParam {
+    pub name: &'static str,
+    pub description: &'static str,
+    // a JSON type, e.g. `integer`
+    pub ty: &'static str,
+}
+
+// The OpenAPI 3.0 document of the routes, served by the servers at
+// `/openapi.json`.
+pub fn openapi_document(title: &str, routes: &[ApiRoute]) -> Value {
+    let mut paths = Map::new();
+    for route in routes {
+        let mut params = Vec::new();
+        for seg in route.path.split('/') {
+            if let Some(name) = seg.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                params.push(json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer", "minimum": 0 },
+                }));
+            }
+        }
+        for p in route.query_params {
+            params.push(json!({
+                "name": p.name,
+                "in": "query",
+                "required": false,
+                "description": p.description,
+                "schema": { "type": p.ty },
+            }));
+        }
+        let mut op = json!({
+            "summary": route.summary,
+            "parameters": params,
+            "responses": {
+                "200": {
+                    "description": "OK",
+                    "content": { "application/json": { "schema": schema_ref(route.response) } },
+                },
+                "500": { "description": "the error message" },
+            },
+        });
+        if let Some(request) = route.request {
+            op["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema_ref(request) } },
+            });
+        }
+        paths
+            .entry(route.path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("an object")
+            .insert(route.method.to_string(), op);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": title, "version": env!("CARGO_PKG_VERSION") },
+        "paths": paths,
+        "components": { "schemas": schemas() },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    match name.strip_suffix("[]") {
+        Some(item) => json!({ "type": "array", "items": schema_ref(item) }),
+        None => json!({ "$ref": format!("#/components/schemas/{}", name) }),
+    }
+}
+
+fn opaque(type_name: &str) -> Value {
+    json!({
+        "type": "object",
+        "description": format!("`{}` in serde JSON, refer to the source code", type_name),
+    })
+}
+
+// The types with simple JSON forms are described in full, and the others,
+// e.g. those holding the acc values and proofs, are left opaque.
+fn schemas() -> Value {
+    let id = json!({ "type": "integer", "minimum": 0 });
+    let digest = json!({ "$ref": "#/components/schemas/Digest" });
+    let nullable_digest = json!({ "allOf": [digest], "nullable": true });
+    json!({
+        "Digest": { "type": "string", "description": "hex of 32 bytes" },
+        "Parameter": {
+            "type": "object",
+            "required": ["v_bit_len", "acc_type", "use_sk", "intra_index", "skip_list_max_level"],
+            "properties": {
+                "v_bit_len": { "type": "array", "items": { "type": "integer" } },
+                "acc_type": { "type": "string", "enum": ["ACC1", "ACC2", "ACC3"] },
+                "use_sk": { "type": "boolean" },
+                "intra_index": { "type": "boolean" },
+                "skip_list_max_level": { "type": "integer" },
+                "chain_id": { "type": "string" },
+                "max_prefix_len": { "type": "integer" },
+                "intra_index_fanout": { "type": "integer" },
+                "btree_max_level": { "type": "integer" },
+                "max_objs_per_block": { "type": "integer" },
+            },
+        },
+        "BlockHeader": {
+            "type": "object",
+            "required": ["block_id", "prev_hash", "data_root"],
+            "properties": {
+                "block_id": id,
+                "prev_hash": digest,
+                "data_root": digest,
+                "skip_list_root": nullable_digest,
+                "timestamp": { "type": "integer" },
+                "tombstone_root": nullable_digest,
+            },
+        },
+        "RawObject": {
+            "type": "object",
+            "required": ["block_id", "v_data", "w_data"],
+            "properties": {
+                "block_id": id,
+                "v_data": { "type": "array", "items": { "type": "integer" } },
+                "w_data": { "type": "array", "items": { "type": "string" } },
+            },
+        },
+        "Query": {
+            "type": "object",
+            "required": ["start_block", "end_block"],
+            "properties": {
+                "start_block": id,
+                "end_block": id,
+                "range": {
+                    "type": "array",
+                    "nullable": true,
+                    "description": "the start and the end of each dimension, see the README",
+                    "items": { "type": "array" },
+                },
+                "bool": {
+                    "type": "array",
+                    "nullable": true,
+                    "description": "a conjunction of disjunctions of keywords",
+                    "items": { "type": "array", "items": { "type": "string" } },
+                },
+                "expr": { "type": "object", "nullable": true },
+                "start_time": { "type": "integer", "nullable": true },
+                "end_time": { "type": "integer", "nullable": true },
+                "intervals": {
+                    "type": "array",
+                    "nullable": true,
+                    "items": { "type": "array", "items": id, "minItems": 2, "maxItems": 2 },
+                },
+            },
+        },
+        "VerifyResponse": {
+            "type": "object",
+            "required": ["pass", "detail", "verify_time_in_ms"],
+            "properties": {
+                "pass": { "type": "boolean" },
+                "detail": { "type": "array", "items": { "type": "object" } },
+                "verify_time_in_ms": { "type": "integer" },
+                "soundness": { "type": "number", "nullable": true },
+            },
+        },
+        "BlockData": opaque("BlockData"),
+        "IntraIndexNode": opaque("IntraIndexNode"),
+        "SkipListNode": opaque("SkipListNode"),
+        "IndexNode": opaque("IntraIndexNode or {\"SkipListNode\": SkipListNode}"),
+        "Object": opaque("Object"),
+        "ObjectProof": opaque("ObjectProof"),
+        "PointQueryResult": opaque("PointQueryResult"),
+        "OverallResult": opaque("OverallResult"),
+        "QueryStreamLine": opaque("a {\"fragment\": ...} or {\"rest\": ...} line"),
+        "ChainStats": opaque("ChainStats"),
+        "JobStatus": {
+            "type": "object",
+            "required": ["status"],
+            "properties": {
+                "status": { "type": "string", "enum": ["pending", "running", "done", "failed"] },
+                "result": { "$ref": "#/components/schemas/OverallResult" },
+                "error": { "type": "string" },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_document() {
+        let routes = [
+            ApiRoute::get("/get/blk_header/{id}", "a header", "BlockHeader"),
+            ApiRoute::post("/query_batch", "queries", "Query[]", "OverallResult[]")
+                .with_query_params(&[ApiParam {
+                    name: "level",
+                    description: "the level",
+                    ty: "string",
+                }]),
+        ];
+        let doc = openapi_document("test", &routes);
+        let op = &doc["paths"]["/get/blk_header/{id}"]["get"];
+        assert_eq!(op["parameters"][0]["name"], "id");
+        assert_eq!(op["parameters"][0]["in"], "path");
+        let op = &doc["paths"]["/query_batch"]["post"];
+        assert_eq!(op["parameters"][0]["in"], "query");
+        assert_eq!(
+            op["requestBody"]["content"]["application/json"]["schema"]["items"]["$ref"],
+            "#/components/schemas/Query"
+        );
+
+        // every schema referred to is defined
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        let text = doc.to_string();
+        for part in text.split("#/components/schemas/").skip(1) {
+            let name = &part[..part.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "{}", name);
+        }
+    }
+}
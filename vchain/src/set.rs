@@ -1,6 +1,6 @@
 use crate::digest::Digestible;
 use core::iter::FromIterator;
-use core::ops::{Add, BitAnd, BitOr, Deref};
+use core::ops::{Add, BitAnd, BitOr, Deref, Sub};
 use serde::{
     de::Deserializer,
     ser::{SerializeSeq, SerializeStruct, Serializer},
@@ -15,9 +15,42 @@ impl<T> SetElement for T where
 {
 }
 
+// A 256-bit Bloom filter of the elements of a set. Two sets can only intersect
+// if their filters have a common bit.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub struct SetFilter([u64; 4]);
+
+impl SetFilter {
+    const NUM_HASHES: usize = 2;
+
+    pub fn new<T: SetElement>(set: &MultiSet<T>) -> Self {
+        let mut bits = [0u64; 4];
+        for k in set.keys() {
+            let d = k.to_digest();
+            for &b in &d.0[..Self::NUM_HASHES] {
+                bits[(b >> 6) as usize] |= 1 << (b & 63);
+            }
+        }
+        Self(bits)
+    }
+
+    pub fn may_intersect(&self, other: &Self) -> bool {
+        self.0.iter().zip(other.0.iter()).any(|(a, b)| a & b != 0)
+    }
+
+    // for the storage formats other than serde
+    pub fn from_bits(bits: [u64; 4]) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> [u64; 4] {
+        self.0
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 pub struct MultiSet<T: SetElement> {
-    pub(crate) inner: HashMap<T, u32>,
+    pub(crate) inner: HashMap<T, u64>,
 }
 
 impl<T: SetElement> MultiSet<T> {
@@ -31,7 +64,7 @@ impl<T: SetElement> MultiSet<T> {
         Self::from_iter(input.into_iter())
     }
 
-    pub fn from_tuple_vec(input: Vec<(T, u32)>) -> Self {
+    pub fn from_tuple_vec(input: Vec<(T, u64)>) -> Self {
         Self::from_iter(input.into_iter())
     }
 
@@ -43,10 +76,33 @@ impl<T: SetElement> MultiSet<T> {
         };
         a.keys().any(|v| b.contains_key(v))
     }
+
+    pub fn filter(&self) -> SetFilter {
+        SetFilter::new(self)
+    }
+
+    // Iterate in the order of the element digests, which is stable across runs
+    // unlike the order of the underlying HashMap.
+    pub fn sorted_iter(&self) -> impl Iterator<Item = (&T, &u64)> {
+        let mut items: Vec<_> = self.iter().map(|(k, v)| (k.to_digest(), k, v)).collect();
+        items.sort_unstable_by_key(|item| item.0 .0);
+        items.into_iter().map(|(_, k, v)| (k, v))
+    }
+
+    // distinct elements in self but not in other, like BitOr and BitAnd.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut data = HashMap::new();
+        for k in self.keys() {
+            if !other.contains_key(k) {
+                data.insert(k.clone(), 1);
+            }
+        }
+        Self { inner: data }
+    }
 }
 
 impl<T: SetElement> Deref for MultiSet<T> {
-    type Target = HashMap<T, u32>;
+    type Target = HashMap<T, u64>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
@@ -65,6 +121,22 @@ impl<'a, 'b, T: SetElement> Add<&'a MultiSet<T>> for &'b MultiSet<T> {
     }
 }
 
+// multiset subtraction, elements with non-positive multiplicity are removed.
+impl<'a, 'b, T: SetElement> Sub<&'a MultiSet<T>> for &'b MultiSet<T> {
+    type Output = MultiSet<T>;
+
+    fn sub(self, other: &'a MultiSet<T>) -> MultiSet<T> {
+        let mut data = HashMap::new();
+        for (k, v) in self.iter() {
+            let v = v.saturating_sub(other.get(k).copied().unwrap_or(0));
+            if v > 0 {
+                data.insert(k.clone(), v);
+            }
+        }
+        MultiSet { inner: data }
+    }
+}
+
 impl<'a, 'b, T: SetElement> BitOr<&'a MultiSet<T>> for &'b MultiSet<T> {
     type Output = MultiSet<T>;
 
@@ -101,8 +173,8 @@ impl<T: SetElement> FromIterator<T> for MultiSet<T> {
     }
 }
 
-impl<T: SetElement> FromIterator<(T, u32)> for MultiSet<T> {
-    fn from_iter<I: IntoIterator<Item = (T, u32)>>(iter: I) -> Self {
+impl<T: SetElement> FromIterator<(T, u64)> for MultiSet<T> {
+    fn from_iter<I: IntoIterator<Item = (T, u64)>>(iter: I) -> Self {
         let mut data = HashMap::new();
         for (k, v) in iter {
             *data.entry(k).or_insert(0) += v;
@@ -114,7 +186,27 @@ impl<T: SetElement> FromIterator<(T, u32)> for MultiSet<T> {
 #[derive(Serialize, Deserialize)]
 struct ElementTuple<T> {
     obj: T,
-    cnt: u32,
+    cnt: u64,
+}
+
+// serialized in the same format as HashMap, but in a deterministic order.
+struct SortedMap<'a, T: SetElement>(&'a MultiSet<T>);
+
+impl<'a, T: SetElement + Serialize> Serialize for SortedMap<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.sorted_iter())
+    }
+}
+
+// the counterpart of the struct serialized in the binary formats, which is the
+// same as the HashMap alone in bincode
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: SetElement + Deserialize<'de>"))]
+struct MultiSetStruct<T: SetElement> {
+    inner: HashMap<T, u64>,
 }
 
 impl<T: SetElement + Serialize> Serialize for MultiSet<T> {
@@ -124,7 +216,7 @@ impl<T: SetElement + Serialize> Serialize for MultiSet<T> {
     {
         if serializer.is_human_readable() {
             let mut seq = serializer.serialize_seq(Some(self.len()))?;
-            for (k, v) in self.iter() {
+            for (k, v) in self.sorted_iter() {
                 seq.serialize_element(&ElementTuple {
                     obj: k.clone(),
                     cnt: *v,
@@ -133,7 +225,7 @@ impl<T: SetElement + Serialize> Serialize for MultiSet<T> {
             seq.end()
         } else {
             let mut state = serializer.serialize_struct("MultiSet", 1)?;
-            state.serialize_field("inner", &self.inner)?;
+            state.serialize_field("inner", &SortedMap(self))?;
             state.end()
         }
     }
@@ -148,7 +240,7 @@ impl<'de, T: SetElement + Deserialize<'de>> Deserialize<'de> for MultiSet<T> {
             let inner: Vec<ElementTuple<T>> = Deserialize::deserialize(deserializer)?;
             Ok(Self::from_iter(inner.into_iter().map(|v| (v.obj, v.cnt))))
         } else {
-            let inner: HashMap<T, u32> = Deserialize::deserialize(deserializer)?;
+            let MultiSetStruct { inner } = Deserialize::deserialize(deserializer)?;
             Ok(Self { inner })
         }
     }
@@ -167,6 +259,20 @@ mod tests {
         assert!(!s1.is_intersected_with(&s3));
     }
 
+    #[test]
+    fn test_set_filter() {
+        let s1 = MultiSet::from_vec((0..10).collect());
+        let s2 = MultiSet::from_vec((9..20).collect());
+        assert!(s1.filter().may_intersect(&s2.filter()));
+        assert!(!MultiSet::<i32>::new().filter().may_intersect(&s1.filter()));
+        for i in 20..100 {
+            let s3 = MultiSet::from_vec(vec![i]);
+            if !s1.filter().may_intersect(&s3.filter()) {
+                assert!(!s1.is_intersected_with(&s3));
+            }
+        }
+    }
+
     #[test]
     fn test_set_sum() {
         let s1 = MultiSet::from_vec(vec![1, 1, 2]);
@@ -175,6 +281,25 @@ mod tests {
         assert_eq!(&s1 + &s2, s3);
     }
 
+    #[test]
+    fn test_set_sub() {
+        let s1 = MultiSet::from_vec(vec![1, 1, 1, 2, 3]);
+        let s2 = MultiSet::from_vec(vec![1, 2, 2, 4]);
+        let s3 = MultiSet::from_tuple_vec(vec![(1, 2), (3, 1)]);
+        assert_eq!(&s1 - &s2, s3);
+        assert_eq!(&(&s1 + &s2) - &s2, s1);
+        assert_eq!(&s1 - &s1, MultiSet::new());
+    }
+
+    #[test]
+    fn test_set_difference() {
+        let s1 = MultiSet::from_vec(vec![1, 1, 2, 3]);
+        let s2 = MultiSet::from_vec(vec![2, 2, 4]);
+        let s3 = MultiSet::from_tuple_vec(vec![(1, 1), (3, 1)]);
+        assert_eq!(s1.difference(&s2), s3);
+        assert_eq!(s2.difference(&s1), MultiSet::from_vec(vec![4]));
+    }
+
     #[test]
     fn test_set_union() {
         let s1 = MultiSet::from_vec(vec![1, 1, 2]);
@@ -199,4 +324,18 @@ mod tests {
         assert_eq!(serde_json::from_str::<MultiSet<i32>>(&json).unwrap(), s);
         assert_eq!(bincode::deserialize::<MultiSet<i32>>(&bin[..]).unwrap(), s);
     }
+
+    #[test]
+    fn test_serde_deterministic() {
+        let s1 = MultiSet::from_vec((0..100).collect());
+        let s2 = MultiSet::from_vec((0..100).rev().collect());
+        assert_eq!(
+            bincode::serialize(&s1).unwrap(),
+            bincode::serialize(&s2).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_string(&s1).unwrap(),
+            serde_json::to_string(&s2).unwrap()
+        );
+    }
 }
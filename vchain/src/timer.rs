@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+// The wall clock timer of the builds, the queries and the verifications. There
+// is no clock on wasm32-unknown-unknown, e.g. for the verifiers in the
+// browsers, where the timers read zero.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timer {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    start: std::time::Instant,
+}
+
+impl Timer {
+    pub(crate) fn new() -> Self {
+        Self {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        return self.start.elapsed();
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        Duration::ZERO
+    }
+}